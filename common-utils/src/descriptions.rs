@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+
+use serde_json::{Map, Value};
+
+/// Descriptions keyed by schema coordinates: `Type`, `Type.field`, `Type.field(argument:)`
+/// or `Enum.VALUE`
+pub type Descriptions = HashMap<String, String>;
+
+/// Translated descriptions by locale
+pub type Translations = HashMap<String, Descriptions>;
+
+/// Parses translations, e.g. `{"de": {"Planet": "Ein Planet", "Planet.name": "Name des Planeten"}}`
+pub fn parse_translations(json: &str) -> Result<Translations, String> {
+    Ok(serde_json::from_str::<Translations>(json)
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|(locale, descriptions)| (locale.to_lowercase(), descriptions))
+        .collect())
+}
+
+/// Rough check to not traverse responses of regular queries
+pub fn is_introspection(query: &str) -> bool {
+    query.contains("__schema") || query.contains("__type")
+}
+
+/// The available locale preferred by the client: a language range matches the same locale or,
+/// failing that, the locale of its primary language (`de-CH` matches `de`). `None` if there
+/// is no match, so the default descriptions are served
+pub fn negotiate_locale<'a>(
+    accept_language: &str,
+    locales: impl Iterator<Item = &'a str> + Clone,
+) -> Option<&'a str> {
+    let mut ranges: Vec<(String, f32)> = accept_language
+        .split(',')
+        .filter_map(|range| {
+            let mut parts = range.split(';');
+            let tag = parts.next()?.trim().to_lowercase();
+            let quality = parts
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.parse().ok())
+                .unwrap_or(1.0);
+            (!tag.is_empty() && quality > 0.0).then_some((tag, quality))
+        })
+        .collect();
+    // stable, so ranges of the same quality keep their order
+    ranges.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    ranges.iter().find_map(|(tag, _)| {
+        let primary = tag.split('-').next().unwrap_or(tag);
+        locales
+            .clone()
+            .find(|locale| locale == tag)
+            .or_else(|| locales.clone().find(|locale| *locale == primary))
+    })
+}
+
+/// Replaces descriptions in the data of an introspection response (only if `name` and
+/// `description` are requested without aliases)
+pub fn enrich(value: &mut Value, descriptions: &Descriptions) {
+    match value {
+        Value::Object(object) => {
+            if let Some(Value::String(type_name)) = object.get("name").cloned() {
+                set_description(object, &type_name, descriptions);
+                for key in ["fields", "inputFields", "enumValues"] {
+                    enrich_items(object, key, &type_name, descriptions);
+                }
+            }
+            object
+                .values_mut()
+                .for_each(|value| enrich(value, descriptions));
+        }
+        Value::Array(values) => values
+            .iter_mut()
+            .for_each(|value| enrich(value, descriptions)),
+        _ => {}
+    }
+}
+
+/// Sets descriptions of the named items (fields, enum values or arguments) listed under `key`
+fn enrich_items(
+    object: &mut Map<String, Value>,
+    key: &str,
+    parent: &str,
+    descriptions: &Descriptions,
+) {
+    let Some(Value::Array(items)) = object.get_mut(key) else {
+        return;
+    };
+    for item in items {
+        let Value::Object(item) = item else {
+            continue;
+        };
+        let Some(Value::String(name)) = item.get("name").cloned() else {
+            continue;
+        };
+        let coordinate = match key {
+            "args" => format!("{}({}:)", parent, name),
+            _ => format!("{}.{}", parent, name),
+        };
+        set_description(item, &coordinate, descriptions);
+        enrich_items(item, "args", &coordinate, descriptions);
+    }
+}
+
+fn set_description(object: &mut Map<String, Value>, coordinate: &str, descriptions: &Descriptions) {
+    if let (Some(current), Some(description)) =
+        (object.get_mut("description"), descriptions.get(coordinate))
+    {
+        *current = Value::String(description.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_enrich_introspection() {
+        let descriptions = Descriptions::from([
+            ("Planet".to_string(), "Ein Planet".to_string()),
+            ("Planet.name".to_string(), "Name des Planeten".to_string()),
+            (
+                "Query.getPlanet(id:)".to_string(),
+                "ID des Planeten".to_string(),
+            ),
+            ("PlanetType.GAS_GIANT".to_string(), "Gasriese".to_string()),
+        ]);
+
+        let mut data = json!({
+            "__schema": {
+                "types": [
+                    {
+                        "name": "Planet",
+                        "description": null,
+                        "fields": [{ "name": "name", "description": null }],
+                    },
+                    {
+                        "name": "Query",
+                        "description": null,
+                        "fields": [{
+                            "name": "getPlanet",
+                            "description": "Returns a planet",
+                            "args": [{ "name": "id", "description": null }],
+                        }],
+                    },
+                    {
+                        "name": "PlanetType",
+                        "enumValues": [{ "name": "GAS_GIANT", "description": null }],
+                    },
+                ],
+            },
+        });
+        enrich(&mut data, &descriptions);
+
+        assert_eq!(
+            json!({
+                "__schema": {
+                    "types": [
+                        {
+                            "name": "Planet",
+                            "description": "Ein Planet",
+                            "fields": [{ "name": "name", "description": "Name des Planeten" }],
+                        },
+                        {
+                            "name": "Query",
+                            "description": null,
+                            "fields": [{
+                                "name": "getPlanet",
+                                "description": "Returns a planet",
+                                "args": [{ "name": "id", "description": "ID des Planeten" }],
+                            }],
+                        },
+                        {
+                            // the description isn't requested
+                            "name": "PlanetType",
+                            "enumValues": [{ "name": "GAS_GIANT", "description": "Gasriese" }],
+                        },
+                    ],
+                },
+            }),
+            data
+        );
+    }
+
+    #[test]
+    fn test_negotiate_locale() {
+        let locales = ["de", "fr-ca"];
+        let negotiate = |accept_language| negotiate_locale(accept_language, locales.into_iter());
+        assert_eq!(Some("de"), negotiate("de"));
+        assert_eq!(Some("de"), negotiate("de-CH, en;q=0.5"));
+        assert_eq!(Some("fr-ca"), negotiate("en;q=0.5, fr-CA;q=0.8, de;q=0.7"));
+        assert_eq!(None, negotiate("fr"));
+        assert_eq!(None, negotiate("en, de;q=0"));
+        assert_eq!(None, negotiate("*"));
+    }
+}
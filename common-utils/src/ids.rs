@@ -0,0 +1,139 @@
+use std::env;
+
+use lazy_static::lazy_static;
+
+lazy_static! {
+    static ref ID_CODEC: Box<dyn IdCodec> = create_id_codec();
+}
+
+/// Converts integer primary keys to IDs exposed in the API and back
+pub trait IdCodec: Send + Sync {
+    fn encode(&self, id: i32) -> String;
+
+    fn decode(&self, id: &str) -> Option<i32>;
+}
+
+/// Exposes primary keys as is
+pub struct PlainIdCodec;
+
+impl IdCodec for PlainIdCodec {
+    fn encode(&self, id: i32) -> String {
+        id.to_string()
+    }
+
+    fn decode(&self, id: &str) -> Option<i32> {
+        id.parse().ok()
+    }
+}
+
+const ALPHABET: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+const ROUNDS: usize = 4;
+// enough for any u32
+const LENGTH: usize = 6;
+
+/// Exposes primary keys as opaque strings (like hashids) to prevent enumeration of entities:
+/// a key is shuffled by a Feistel network keyed with the secret and then encoded in base62.
+/// This is obfuscation, not encryption
+pub struct ObfuscatedIdCodec {
+    round_keys: [u32; ROUNDS],
+}
+
+impl ObfuscatedIdCodec {
+    pub fn new(secret: &str) -> Self {
+        let mut round_keys = [0; ROUNDS];
+        for (round, key) in round_keys.iter_mut().enumerate() {
+            // FNV-1a
+            *key = secret
+                .bytes()
+                .chain([round as u8])
+                .fold(0x811c_9dc5_u32, |hash, byte| {
+                    (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+                });
+        }
+        Self { round_keys }
+    }
+
+    fn permute(&self, value: u32, inverse: bool) -> u32 {
+        fn round(half: u32, key: u32) -> u32 {
+            let mixed = (half ^ key).wrapping_mul(0x045d_9f3b);
+            (mixed ^ (mixed >> 16)) & 0xffff
+        }
+
+        let (mut left, mut right) = (value >> 16, value & 0xffff);
+        if inverse {
+            for key in self.round_keys.iter().rev() {
+                (left, right) = (right ^ round(left, *key), left);
+            }
+        } else {
+            for key in self.round_keys.iter() {
+                (left, right) = (right, left ^ round(right, *key));
+            }
+        }
+        (left << 16) | right
+    }
+}
+
+impl IdCodec for ObfuscatedIdCodec {
+    fn encode(&self, id: i32) -> String {
+        let mut value = self.permute(id as u32, false);
+        let mut encoded = Vec::with_capacity(LENGTH);
+        for _ in 0..LENGTH {
+            encoded.push(ALPHABET[(value % ALPHABET.len() as u32) as usize]);
+            value /= ALPHABET.len() as u32;
+        }
+        encoded.reverse();
+        String::from_utf8(encoded).expect("Can't encode id")
+    }
+
+    fn decode(&self, id: &str) -> Option<i32> {
+        if id.len() != LENGTH {
+            return None;
+        }
+        let mut value = 0_u32;
+        for byte in id.bytes() {
+            let digit = ALPHABET.iter().position(|c| *c == byte)? as u32;
+            value = value
+                .checked_mul(ALPHABET.len() as u32)?
+                .checked_add(digit)?;
+        }
+        i32::try_from(self.permute(value, true)).ok()
+    }
+}
+
+/// Uses [ObfuscatedIdCodec] if `ID_SECRET` is set; it should be the same for all services,
+/// because they reference each other's entities
+fn create_id_codec() -> Box<dyn IdCodec> {
+    match env::var("ID_SECRET") {
+        Ok(secret) => Box::new(ObfuscatedIdCodec::new(&secret)),
+        Err(_) => Box::new(PlainIdCodec),
+    }
+}
+
+pub fn encode_id(id: i32) -> String {
+    ID_CODEC.encode(id)
+}
+
+pub fn decode_id(id: &str) -> Option<i32> {
+    ID_CODEC.decode(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_obfuscated_id_codec() {
+        let codec = ObfuscatedIdCodec::new("secret");
+
+        for id in [0, 1, 2, 3, 1000, i32::MAX] {
+            let encoded = codec.encode(id);
+            assert_eq!(LENGTH, encoded.len());
+            assert_eq!(Some(id), codec.decode(&encoded));
+        }
+        assert_ne!(codec.encode(1), ObfuscatedIdCodec::new("other").encode(1));
+
+        assert_eq!(None, codec.decode("1"));
+        assert_eq!(None, codec.decode("abc-de"));
+        assert_eq!(None, codec.decode("zzzzzz"));
+    }
+}
@@ -1,12 +1,17 @@
 // WARNING: THIS IS ONLY FOR DEMO! PLEASE DO MORE RESEARCH FOR PRODUCTION USE.
 use std::str::FromStr;
 
+#[cfg(feature = "actix")]
 use actix_web::http::header::ToStrError;
+#[cfg(feature = "actix")]
 use actix_web::HttpRequest;
 use serde::{Deserialize, Serialize};
 use strum::ParseError;
 use strum_macros::{Display, EnumString};
 
+pub mod descriptions;
+pub mod ids;
+
 pub const FORBIDDEN_MESSAGE: &str = "Forbidden";
 
 #[derive(Deserialize, Serialize)]
@@ -16,25 +21,69 @@ pub struct Claims {
     pub role: String,
 }
 
-#[derive(Eq, PartialEq, Display, EnumString)]
+#[derive(Copy, Clone, Eq, PartialEq, Display, EnumString)]
 #[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
 pub enum Role {
     Admin,
     User,
 }
 
+#[cfg(feature = "actix")]
 pub fn get_role(http_request: HttpRequest) -> Result<Option<Role>, CustomError> {
     let role_header_value = http_request.headers().get("role");
 
     match role_header_value {
-        Some(header_value) => {
-            let header_str = header_value.to_str()?;
-            Ok(Some(Role::from_str(header_str)?))
-        }
+        Some(header_value) => parse_role(Some(header_value.to_str()?)),
+        None => Ok(None),
+    }
+}
+
+/// Same as [get_role] for transports other than HTTP
+pub fn parse_role(role: Option<&str>) -> Result<Option<Role>, CustomError> {
+    match role {
+        Some(role) => Ok(Some(Role::from_str(role)?)),
         None => Ok(None),
     }
 }
 
+/// The user authenticated by the gateway (`sub` of the JWT), if any
+#[cfg(feature = "actix")]
+pub fn get_user(http_request: &HttpRequest) -> Option<String> {
+    http_request
+        .headers()
+        .get("user")
+        .and_then(|header_value| header_value.to_str().ok())
+        .map(String::from)
+}
+
+/// Identifies a client for per-client limits: by the user authenticated by the gateway, then by
+/// the API key if `is_known_api_key` accepts it, otherwise by the IP address of the peer. Unknown
+/// API keys and forwarding headers are ignored, since a client can send new ones with every request
+#[cfg(feature = "actix")]
+pub fn get_principal(
+    http_request: &HttpRequest,
+    is_known_api_key: impl Fn(&str) -> bool,
+) -> String {
+    if let Some(user) = get_user(http_request) {
+        return format!("user:{}", user);
+    }
+    match http_request
+        .headers()
+        .get("x-api-key")
+        .and_then(|header_value| header_value.to_str().ok())
+        .filter(|api_key| is_known_api_key(api_key))
+    {
+        Some(api_key) => format!("key:{}", api_key),
+        None => format!(
+            "ip:{}",
+            http_request
+                .peer_addr()
+                .map(|address| address.ip().to_string())
+                .unwrap_or_else(|| "unknown".to_string())
+        ),
+    }
+}
+
 pub fn check_user_role_is_allowed(
     getting_role_result: &Result<Option<Role>, CustomError>,
     allowed_role: &Role,
@@ -65,6 +114,7 @@ pub struct CustomError {
     pub message: String,
 }
 
+#[cfg(feature = "actix")]
 impl From<ToStrError> for CustomError {
     fn from(source: ToStrError) -> Self {
         Self {
@@ -88,3 +138,44 @@ impl From<&str> for CustomError {
         }
     }
 }
+
+#[cfg(all(test, feature = "actix"))]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    fn get_test_principal(headers: &[(&str, &str)]) -> String {
+        let mut request = TestRequest::default().peer_addr(([192, 0, 2, 1], 50000).into());
+        for header in headers {
+            request = request.insert_header(*header);
+        }
+        get_principal(&request.to_http_request(), |api_key| api_key == "known")
+    }
+
+    #[test]
+    fn test_random_api_keys_share_the_limit_of_the_address() {
+        assert_eq!("ip:192.0.2.1", get_test_principal(&[]));
+        assert_eq!(
+            "ip:192.0.2.1",
+            get_test_principal(&[("x-api-key", "random-1")])
+        );
+        assert_eq!(
+            "ip:192.0.2.1",
+            get_test_principal(&[("x-api-key", "random-2")])
+        );
+        assert_eq!(
+            "ip:192.0.2.1",
+            get_test_principal(&[("x-forwarded-for", "198.51.100.7")])
+        );
+    }
+
+    #[test]
+    fn test_users_and_known_api_keys_have_own_limits() {
+        assert_eq!("key:known", get_test_principal(&[("x-api-key", "known")]));
+        assert_eq!(
+            "user:alice",
+            get_test_principal(&[("user", "alice"), ("x-api-key", "known")])
+        );
+    }
+}
@@ -21,6 +21,7 @@ use tracing::debug;
 use common_utils::Claims;
 
 const ROLE_CONTEXT_PARAM_NAME: &str = "user_role";
+const USER_CONTEXT_PARAM_NAME: &str = "user_name";
 
 #[derive(Deserialize, JsonSchema)]
 struct JwtValidationConfig {
@@ -119,6 +120,16 @@ impl Plugin for JwtValidation {
                             StatusCode::INTERNAL_SERVER_ERROR,
                         );
                     }
+                    if let Err(error) = request
+                        .context
+                        .insert(USER_CONTEXT_PARAM_NAME, token_data.claims.sub)
+                    {
+                        return failure_message(
+                            request.context,
+                            format!("Failed to pass a user's name: {}", error),
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                        );
+                    }
 
                     Ok(ControlFlow::Continue(request))
                 }
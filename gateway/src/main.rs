@@ -2,6 +2,7 @@ use anyhow::Result;
 use dotenv::dotenv;
 
 mod jwt_validation;
+mod schema_translations;
 
 fn main() -> Result<()> {
     dotenv().ok();
@@ -0,0 +1,99 @@
+//! The router answers introspection queries from the supergraph, so they never reach the
+//! subgraphs, which translate descriptions by `Accept-Language`. The plugin translates the
+//! descriptions of the router's introspection responses with the same translations as
+//! planets-service (see its `SCHEMA_TRANSLATIONS_FILE`)
+use std::fs;
+use std::sync::Arc;
+
+use apollo_router::{
+    layers::ServiceBuilderExt,
+    plugin::{Plugin, PluginInit},
+    register_plugin,
+    services::supergraph,
+};
+use http::header::ACCEPT_LANGUAGE;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tower::{BoxError, ServiceBuilder, ServiceExt};
+
+use common_utils::descriptions::{self, Translations};
+
+#[derive(Deserialize, JsonSchema)]
+struct SchemaTranslationsConfig {
+    /// Path to a JSON file with descriptions by locale and schema coordinate; empty disables
+    /// the plugin
+    translations_file: String,
+}
+
+struct SchemaTranslations {
+    translations: Arc<Translations>,
+}
+
+#[async_trait::async_trait]
+impl Plugin for SchemaTranslations {
+    type Config = SchemaTranslationsConfig;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        let translations = if init.config.translations_file.is_empty() {
+            Translations::new()
+        } else {
+            descriptions::parse_translations(&fs::read_to_string(init.config.translations_file)?)?
+        };
+        Ok(SchemaTranslations {
+            translations: Arc::new(translations),
+        })
+    }
+
+    fn supergraph_service(&self, service: supergraph::BoxService) -> supergraph::BoxService {
+        if self.translations.is_empty() {
+            return service;
+        }
+        let translations = Arc::clone(&self.translations);
+        let request_translations = Arc::clone(&self.translations);
+
+        // the locale preferred by the client if the request is an introspection one
+        let get_locale = move |request: &supergraph::Request| {
+            let query = request.supergraph_request.body().query.as_deref()?;
+            if !descriptions::is_introspection(query) {
+                return None;
+            }
+            let accept_language = request
+                .supergraph_request
+                .headers()
+                .get(ACCEPT_LANGUAGE)?
+                .to_str()
+                .ok()?;
+            descriptions::negotiate_locale(
+                accept_language,
+                request_translations.keys().map(String::as_str),
+            )
+            .map(String::from)
+        };
+
+        ServiceBuilder::new()
+            .map_future_with_request_data(get_locale, move |locale: Option<String>, response| {
+                let translations = Arc::clone(&translations);
+                async move {
+                    let response: supergraph::Response = response.await?;
+                    let Some(locale) = locale else {
+                        return Ok(response);
+                    };
+                    Ok::<_, BoxError>(response.map_stream(move |mut response| {
+                        if let Some(data) = response.data.take() {
+                            let mut json =
+                                serde_json::to_value(data).expect("Can't convert response data");
+                            descriptions::enrich(&mut json, &translations[&locale]);
+                            response.data = Some(
+                                serde_json::from_value(json).expect("Can't convert response data"),
+                            );
+                        }
+                        response
+                    }))
+                }
+            })
+            .service(service)
+            .boxed()
+    }
+}
+
+register_plugin!("demo", "schema_translations", SchemaTranslations);
@@ -0,0 +1,220 @@
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+use futures::{stream, Stream, StreamExt};
+use graphql_client::{GraphQLQuery, QueryBody, Response};
+use reqwest::{Client, RequestBuilder};
+use url::Url;
+
+mod sse;
+
+// custom scalars of the schema; numbers are passed as strings, so that precision isn't lost
+pub type BigDecimal = String;
+pub type BigInt = String;
+pub type DateTime = String;
+pub type JSON = serde_json::Value;
+
+/// Operations and response structs are generated from `schema.graphql`, which should be updated
+/// along with the schema of planets-service (see `planets_service::schema_sdl`)
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.graphql",
+    query_path = "queries/planets.graphql",
+    response_derives = "Debug, Clone, PartialEq"
+)]
+pub struct GetPlanets;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.graphql",
+    query_path = "queries/planets.graphql",
+    response_derives = "Debug, Clone, PartialEq"
+)]
+pub struct GetPlanet;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.graphql",
+    query_path = "queries/planets.graphql",
+    response_derives = "Debug, Clone, PartialEq",
+    variables_derives = "Debug, Clone"
+)]
+pub struct CreatePlanet;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.graphql",
+    query_path = "queries/planets.graphql",
+    response_derives = "Debug, Clone, PartialEq"
+)]
+pub struct PlanetEvents;
+
+const SSE_PATH: &str = "subscriptions/sse";
+
+#[derive(Debug)]
+pub enum ClientError {
+    Http(reqwest::Error),
+    /// Errors returned by the server
+    GraphQL(Vec<graphql_client::Error>),
+    /// A malformed response
+    Response(String),
+}
+
+impl Display for ClientError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Http(e) => write!(f, "HTTP error: {}", e),
+            ClientError::GraphQL(errors) => {
+                let messages: Vec<_> = errors.iter().map(|e| e.message.as_str()).collect();
+                write!(f, "GraphQL errors: {}", messages.join("; "))
+            }
+            ClientError::Response(message) => write!(f, "Invalid response: {}", message),
+        }
+    }
+}
+
+impl Error for ClientError {}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(source: reqwest::Error) -> Self {
+        ClientError::Http(source)
+    }
+}
+
+/// Client of planets-service: queries and mutations are sent to its GraphQL endpoint,
+/// subscriptions are received over SSE
+#[derive(Clone)]
+pub struct PlanetsClient {
+    http: Client,
+    url: Url,
+    headers: Vec<(String, String)>,
+}
+
+impl PlanetsClient {
+    /// `url` of the GraphQL endpoint, e.g. `http://localhost:8001/`
+    pub fn new(url: &str) -> Result<Self, url::ParseError> {
+        Ok(Self {
+            http: Client::new(),
+            url: Url::parse(url)?,
+            headers: vec![],
+        })
+    }
+
+    /// Sends the header with each request, e.g. `role` or `Authorization`
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub async fn get_planets(&self) -> Result<Vec<get_planets::PlanetFields>, ClientError> {
+        let data = self.execute::<GetPlanets>(get_planets::Variables).await?;
+        Ok(data.get_planets)
+    }
+
+    pub async fn get_planet(
+        &self,
+        id: &str,
+    ) -> Result<Option<get_planet::PlanetFields>, ClientError> {
+        let variables = get_planet::Variables { id: id.to_string() };
+        let data = self.execute::<GetPlanet>(variables).await?;
+        Ok(data.get_planet)
+    }
+
+    pub async fn create_planet(
+        &self,
+        planet: create_planet::PlanetInput,
+    ) -> Result<create_planet::PlanetFields, ClientError> {
+        let data = self
+            .execute::<CreatePlanet>(create_planet::Variables { planet })
+            .await?;
+        Ok(data.create_planet)
+    }
+
+    /// Changes of planets; pass the token of the last received event to resume after it
+    pub async fn planet_events(
+        &self,
+        resume_from: Option<i64>,
+    ) -> Result<
+        impl Stream<Item = Result<planet_events::PlanetEventsPlanetEvents, ClientError>>,
+        ClientError,
+    > {
+        let events = self
+            .subscribe::<PlanetEvents>(planet_events::Variables { resume_from })
+            .await?;
+        Ok(events.map(|data| data.map(|data| data.planet_events)))
+    }
+
+    /// Executes a query or a mutation
+    pub async fn execute<Q: GraphQLQuery>(
+        &self,
+        variables: Q::Variables,
+    ) -> Result<Q::ResponseData, ClientError> {
+        let response: Response<Q::ResponseData> = self
+            .post(self.url.clone(), &Q::build_query(variables))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        get_data(response)
+    }
+
+    /// Executes a subscription; the stream ends when the server completes it
+    pub async fn subscribe<Q: GraphQLQuery>(
+        &self,
+        variables: Q::Variables,
+    ) -> Result<impl Stream<Item = Result<Q::ResponseData, ClientError>>, ClientError> {
+        let url = self
+            .url
+            .join(SSE_PATH)
+            .map_err(|e| ClientError::Response(e.to_string()))?;
+        let bytes = self
+            .post(url, &Q::build_query(variables))
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes_stream();
+
+        let events = stream::unfold(
+            (Box::pin(bytes), Vec::new()),
+            |(mut bytes, mut buffer)| async move {
+                loop {
+                    if let Some(event) = sse::take_event(&mut buffer) {
+                        return match event.name.as_str() {
+                            "complete" => None,
+                            _ => Some((to_data::<Q>(&event.data), (bytes, buffer))),
+                        };
+                    }
+                    match bytes.next().await? {
+                        Ok(chunk) => buffer.extend_from_slice(&chunk),
+                        Err(e) => return Some((Err(e.into()), (bytes, buffer))),
+                    }
+                }
+            },
+        );
+        Ok(events)
+    }
+
+    fn post<V: serde::Serialize>(&self, url: Url, body: &QueryBody<V>) -> RequestBuilder {
+        self.headers
+            .iter()
+            .fold(self.http.post(url), |request, (name, value)| {
+                request.header(name, value)
+            })
+            .json(body)
+    }
+}
+
+fn to_data<Q: GraphQLQuery>(json: &str) -> Result<Q::ResponseData, ClientError> {
+    let response: Response<Q::ResponseData> =
+        serde_json::from_str(json).map_err(|e| ClientError::Response(e.to_string()))?;
+    get_data(response)
+}
+
+fn get_data<T>(response: Response<T>) -> Result<T, ClientError> {
+    match (response.data, response.errors) {
+        (_, Some(errors)) if !errors.is_empty() => Err(ClientError::GraphQL(errors)),
+        (Some(data), _) => Ok(data),
+        (None, _) => Err(ClientError::Response("No data".to_string())),
+    }
+}
@@ -0,0 +1,90 @@
+/// An event of a `text/event-stream` response
+#[derive(Debug, PartialEq)]
+pub struct Event {
+    pub id: Option<String>,
+    /// `message` if not specified
+    pub name: String,
+    pub data: String,
+}
+
+/// Removes the first complete event from the received bytes; comments (e.g. heartbeats) are
+/// skipped. Bytes are decoded by whole events, since a chunk may end in the middle of a character
+pub fn take_event(buffer: &mut Vec<u8>) -> Option<Event> {
+    loop {
+        let end = buffer.windows(2).position(|window| window == b"\n\n")?;
+        let block: Vec<u8> = buffer.drain(..end + 2).collect();
+        let block = String::from_utf8_lossy(&block);
+
+        let mut id = None;
+        let mut name = None;
+        let mut data: Option<String> = None;
+        for line in block.lines() {
+            let (field, value) = line.split_once(':').unwrap_or((line, ""));
+            let value = value.strip_prefix(' ').unwrap_or(value);
+            match field {
+                "id" => id = Some(value.to_string()),
+                "event" => name = Some(value.to_string()),
+                "data" => match &mut data {
+                    Some(data) => {
+                        data.push('\n');
+                        data.push_str(value);
+                    }
+                    None => data = Some(value.to_string()),
+                },
+                _ => {}
+            }
+        }
+
+        if name.is_some() || data.is_some() {
+            return Some(Event {
+                id,
+                name: name.unwrap_or_else(|| "message".to_string()),
+                data: data.unwrap_or_default(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events() {
+        let mut buffer = b":\n\nid: 7\nevent: next\ndata: {\"data\":\n".to_vec();
+        assert_eq!(None, take_event(&mut buffer));
+
+        buffer.extend_from_slice(b"data: null}\n\nevent: complete\ndata:\n\n");
+        assert_eq!(
+            Some(Event {
+                id: Some("7".to_string()),
+                name: "next".to_string(),
+                data: "{\"data\":\nnull}".to_string(),
+            }),
+            take_event(&mut buffer)
+        );
+        assert_eq!(
+            Some(Event {
+                id: None,
+                name: "complete".to_string(),
+                data: String::new(),
+            }),
+            take_event(&mut buffer)
+        );
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn character_split_between_chunks() {
+        let event = "event: next\ndata: Io's moon\u{2019}\n\n".as_bytes();
+        let (first, second) = event.split_at(event.len() - 4);
+
+        let mut buffer = first.to_vec();
+        assert_eq!(None, take_event(&mut buffer));
+        buffer.extend_from_slice(second);
+        assert_eq!(
+            "Io's moon\u{2019}",
+            take_event(&mut buffer).expect("Can't get event").data
+        );
+    }
+}
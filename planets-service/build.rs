@@ -0,0 +1,36 @@
+use std::env;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Embeds the commit and the time of the build, see `build_info`
+fn main() {
+    // images are built without the repository, so the commit is passed as a build argument
+    let git_sha = env::var("GIT_SHA").ok().or_else(|| {
+        Command::new("git")
+            .args(["rev-parse", "--short", "HEAD"])
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .and_then(|output| String::from_utf8(output.stdout).ok())
+            .map(|sha| sha.trim().to_string())
+    });
+    println!(
+        "cargo:rustc-env=BUILD_GIT_SHA={}",
+        git_sha.unwrap_or_else(|| "unknown".to_string())
+    );
+
+    // reproducible builds set the time explicitly
+    let timestamp = env::var("SOURCE_DATE_EPOCH").unwrap_or_else(|_| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Can't get current time")
+            .as_secs()
+            .to_string()
+    });
+    println!("cargo:rustc-env=BUILD_TIMESTAMP={}", timestamp);
+
+    println!("cargo:rerun-if-env-changed=GIT_SHA");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    println!("cargo:rerun-if-changed=../.git/HEAD");
+    println!("cargo:rerun-if-changed=../.git/refs/heads");
+}
@@ -0,0 +1,31 @@
+//! AWS Lambda function executing GraphQL requests passed as the invocation payload, e.g. by other
+//! services invoking it directly, like `{"query": "{ getPlanets { name } }", "role": "ADMIN"}`.
+//! It is built without actix: `cargo build --example lambda --no-default-features`
+use async_graphql::Request;
+use lambda_runtime::{service_fn, Error, LambdaEvent};
+use serde::Deserialize;
+
+use planets_service::create_schema_with_context;
+use planets_service::persistence::connection::create_connection_pool;
+
+#[derive(Deserialize)]
+struct Invocation {
+    #[serde(flatten)]
+    request: Request,
+    role: Option<String>,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    let schema = create_schema_with_context(create_connection_pool());
+    let schema = &schema;
+
+    lambda_runtime::run(service_fn(
+        move |event: LambdaEvent<Invocation>| async move {
+            let Invocation { request, role } = event.payload;
+            let response = planets_service::execute(schema, request, role.as_deref()).await;
+            serde_json::to_value(response).map_err(Error::from)
+        },
+    ))
+    .await
+}
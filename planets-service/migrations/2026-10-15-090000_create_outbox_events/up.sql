@@ -0,0 +1,9 @@
+create table outbox_events (
+    id bigserial primary key,
+    topic varchar(50) not null,
+    kind varchar(20) not null,
+    payload jsonb not null,
+    created_at timestamptz not null default now()
+);
+
+create index outbox_events_topic_id_idx on outbox_events (topic, id);
@@ -0,0 +1,5 @@
+comment on column planets.name is null;
+comment on column planets.type is null;
+comment on column details.mean_radius is null;
+comment on column details.mass is null;
+comment on column details.population is null;
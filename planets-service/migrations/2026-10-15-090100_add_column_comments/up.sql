@@ -0,0 +1,5 @@
+comment on column planets.name is 'Name of the planet';
+comment on column planets.type is 'Type of the planet from an astronomical point of view';
+comment on column details.mean_radius is 'Mean radius in kilometers';
+comment on column details.mass is 'Mass in kilograms';
+comment on column details.population is 'Population in billions';
@@ -0,0 +1,4 @@
+-- gen_random_uuid() is built in only since PostgreSQL 13
+create extension if not exists pgcrypto;
+
+alter table planets add column uuid uuid not null unique default gen_random_uuid();
@@ -0,0 +1 @@
+drop table classification_rules;
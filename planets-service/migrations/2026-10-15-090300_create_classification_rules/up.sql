@@ -0,0 +1,15 @@
+-- lower bounds are inclusive, upper bounds are exclusive; the first matching rule in order of priority wins
+create table classification_rules (
+    id serial primary key,
+    planet_type varchar(20) not null,
+    min_mass numeric(30),
+    max_mass numeric(30),
+    min_mean_radius numeric(10,1),
+    max_mean_radius numeric(10,1),
+    priority integer not null
+);
+
+insert into classification_rules(planet_type, min_mass, max_mass, priority) values ('DWARF_PLANET', null, 1 * power(10, 23), 1);
+insert into classification_rules(planet_type, min_mass, max_mass, priority) values ('TERRESTRIAL_PLANET', 1 * power(10, 23), 1 * power(10, 25), 2);
+insert into classification_rules(planet_type, min_mass, max_mass, priority) values ('ICE_GIANT', 1 * power(10, 25), 2 * power(10, 26), 3);
+insert into classification_rules(planet_type, min_mass, max_mass, priority) values ('GAS_GIANT', 2 * power(10, 26), null, 4);
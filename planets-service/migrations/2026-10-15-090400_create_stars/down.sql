@@ -0,0 +1,2 @@
+alter table planets drop column star_id;
+drop table stars;
@@ -0,0 +1,16 @@
+create table stars (
+    id serial primary key,
+    name varchar not null unique,
+    spectral_class varchar(20) not null,
+    mass numeric(40) not null
+);
+
+comment on column stars.name is 'Name of the star';
+comment on column stars.spectral_class is 'Spectral class in the Morgan-Keenan system, e.g. G2V';
+comment on column stars.mass is 'Mass in kilograms';
+
+insert into stars(name, spectral_class, mass) values ('Sun', 'G2V', 1.989 * power(10, 30));
+
+-- planets created before stars were modeled are the Solar System ones
+alter table planets add column star_id integer references stars;
+update planets set star_id = (select id from stars where name = 'Sun');
@@ -0,0 +1,23 @@
+-- Keplerian elements at the J2000 epoch; inclinations are neglected
+create table orbits (
+    planet_id integer primary key references planets,
+    semi_major_axis numeric(20,10) not null,
+    eccentricity numeric(10,8) not null,
+    orbital_period numeric(20,6) not null,
+    longitude_of_perihelion numeric(12,8) not null,
+    mean_longitude numeric(12,8) not null
+);
+
+comment on column orbits.semi_major_axis is 'Semi-major axis in astronomical units';
+comment on column orbits.orbital_period is 'Sidereal orbital period in days';
+comment on column orbits.longitude_of_perihelion is 'Longitude of perihelion in degrees';
+comment on column orbits.mean_longitude is 'Mean longitude at the J2000 epoch in degrees';
+
+insert into orbits select id, 0.38709927, 0.20563593, 87.969, 77.45779628, 252.25032350 from planets where name = 'Mercury';
+insert into orbits select id, 0.72333566, 0.00677672, 224.701, 131.60246718, 181.97909950 from planets where name = 'Venus';
+insert into orbits select id, 1.00000261, 0.01671123, 365.256, 102.93768193, 100.46457166 from planets where name = 'Earth';
+insert into orbits select id, 1.52371034, 0.09339410, 686.980, 336.05637041, 355.44656795 from planets where name = 'Mars';
+insert into orbits select id, 5.20288700, 0.04838624, 4332.589, 14.72847983, 34.39644051 from planets where name = 'Jupiter';
+insert into orbits select id, 9.53667594, 0.05386179, 10759.22, 92.59887831, 49.95424423 from planets where name = 'Saturn';
+insert into orbits select id, 19.18916464, 0.04725744, 30685.4, 170.95427630, 313.23810451 from planets where name = 'Uranus';
+insert into orbits select id, 30.06992276, 0.00859048, 60189.0, 44.96476227, 304.87997031 from planets where name = 'Neptune';
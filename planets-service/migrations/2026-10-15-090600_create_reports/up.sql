@@ -0,0 +1,9 @@
+-- named queries saved by users
+create table reports (
+    id serial primary key,
+    owner varchar not null,
+    name varchar not null,
+    query text not null,
+    variables jsonb not null,
+    unique (owner, name)
+);
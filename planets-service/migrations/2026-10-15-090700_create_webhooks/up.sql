@@ -0,0 +1,24 @@
+create table webhooks (
+    id serial primary key,
+    url varchar not null,
+    -- kinds of planet events to deliver, all if empty
+    event_kinds varchar[] not null,
+    -- payloads are signed with it, so it's stored as is
+    secret varchar not null,
+    -- the last outbox event for which deliveries were created
+    last_event_id bigint not null
+);
+
+create table webhook_deliveries (
+    id bigserial primary key,
+    webhook_id integer not null references webhooks,
+    event_id bigint not null references outbox_events,
+    status varchar(20) not null,
+    attempts integer not null,
+    last_error varchar,
+    next_attempt_at timestamptz not null,
+    delivered_at timestamptz,
+    unique (webhook_id, event_id)
+);
+
+create index webhook_deliveries_due_idx on webhook_deliveries (next_attempt_at) where status = 'PENDING';
@@ -0,0 +1 @@
+alter table planets drop column status;
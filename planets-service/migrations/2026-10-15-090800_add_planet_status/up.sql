@@ -0,0 +1,4 @@
+-- existing planets stay visible
+alter table planets add column status varchar(20) not null default 'PUBLISHED';
+
+comment on column planets.status is 'Only published planets are visible to everyone; drafts are prepared by editors and archived planets are withdrawn';
@@ -0,0 +1 @@
+drop table atmosphere_components;
@@ -0,0 +1,30 @@
+create table atmosphere_components (
+    planet_id integer not null references planets,
+    gas varchar(20) not null,
+    percentage numeric(7,4) not null check (percentage > 0 and percentage <= 100),
+    primary key (planet_id, gas)
+);
+
+comment on column atmosphere_components.gas is 'Chemical formula of the gas, e.g. N2';
+comment on column atmosphere_components.percentage is 'Share by volume in percent';
+
+-- main components, so shares don't add up to 100 exactly; Mercury has no substantial atmosphere
+insert into atmosphere_components select id, 'CO2', 96.5 from planets where name = 'Venus';
+insert into atmosphere_components select id, 'N2', 3.5 from planets where name = 'Venus';
+insert into atmosphere_components select id, 'N2', 78.08 from planets where name = 'Earth';
+insert into atmosphere_components select id, 'O2', 20.95 from planets where name = 'Earth';
+insert into atmosphere_components select id, 'Ar', 0.93 from planets where name = 'Earth';
+insert into atmosphere_components select id, 'CO2', 0.04 from planets where name = 'Earth';
+insert into atmosphere_components select id, 'CO2', 95.32 from planets where name = 'Mars';
+insert into atmosphere_components select id, 'N2', 2.6 from planets where name = 'Mars';
+insert into atmosphere_components select id, 'Ar', 1.9 from planets where name = 'Mars';
+insert into atmosphere_components select id, 'H2', 89.8 from planets where name = 'Jupiter';
+insert into atmosphere_components select id, 'He', 10.2 from planets where name = 'Jupiter';
+insert into atmosphere_components select id, 'H2', 96.3 from planets where name = 'Saturn';
+insert into atmosphere_components select id, 'He', 3.25 from planets where name = 'Saturn';
+insert into atmosphere_components select id, 'H2', 82.5 from planets where name = 'Uranus';
+insert into atmosphere_components select id, 'He', 15.2 from planets where name = 'Uranus';
+insert into atmosphere_components select id, 'CH4', 2.3 from planets where name = 'Uranus';
+insert into atmosphere_components select id, 'H2', 80.0 from planets where name = 'Neptune';
+insert into atmosphere_components select id, 'He', 19.0 from planets where name = 'Neptune';
+insert into atmosphere_components select id, 'CH4', 1.5 from planets where name = 'Neptune';
@@ -0,0 +1,2 @@
+alter table classification_rules drop constraint classification_rules_planet_type_check;
+alter table planets drop constraint planets_type_check;
@@ -0,0 +1,13 @@
+-- types could have been written in other casings, e.g. 'GasGiant' or 'gas_giant'
+update planets
+set type = upper(regexp_replace(type, '([a-z])([A-Z])', '\1_\2', 'g'))
+where type <> upper(regexp_replace(type, '([a-z])([A-Z])', '\1_\2', 'g'));
+update classification_rules
+set planet_type = upper(regexp_replace(planet_type, '([a-z])([A-Z])', '\1_\2', 'g'))
+where planet_type <> upper(regexp_replace(planet_type, '([a-z])([A-Z])', '\1_\2', 'g'));
+
+-- see model::PlanetType
+alter table planets add constraint planets_type_check
+    check (type in ('TERRESTRIAL_PLANET', 'GAS_GIANT', 'ICE_GIANT', 'DWARF_PLANET'));
+alter table classification_rules add constraint classification_rules_planet_type_check
+    check (planet_type in ('TERRESTRIAL_PLANET', 'GAS_GIANT', 'ICE_GIANT', 'DWARF_PLANET'));
@@ -0,0 +1,6 @@
+drop trigger details_history on details;
+drop trigger planets_history on planets;
+drop function record_details_version();
+drop function record_planet_version();
+drop table details_history;
+drop table planets_history;
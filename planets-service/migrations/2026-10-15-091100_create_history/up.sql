@@ -0,0 +1,70 @@
+-- Versions of planets and their details maintained by triggers, so that every change is recorded
+-- no matter how it's made. A version is valid from `valid_from` inclusive to `valid_to` exclusive;
+-- `valid_to` is null for the current version and the last version of a deleted row is closed
+create table planets_history (
+    planet_id integer not null,
+    name varchar not null,
+    type varchar(20) not null,
+    uuid uuid not null,
+    star_id integer,
+    status varchar(20) not null,
+    valid_from timestamptz not null,
+    valid_to timestamptz,
+    primary key (planet_id, valid_from)
+);
+
+create table details_history (
+    details_id integer not null,
+    mean_radius numeric(10,1) not null,
+    mass numeric(30) not null,
+    population numeric(10,2),
+    planet_id integer not null,
+    valid_from timestamptz not null,
+    valid_to timestamptz,
+    primary key (details_id, valid_from)
+);
+
+create index details_history_planet_id_idx on details_history (planet_id, valid_from);
+
+-- versions start at the start time of the transaction (now() is the same within it), and a version
+-- replaced within the same transaction is dropped, since it has never been visible
+create function record_planet_version() returns trigger as $$
+begin
+    if tg_op in ('UPDATE', 'DELETE') then
+        delete from planets_history
+        where planet_id = old.id and valid_from = now() and valid_to is null;
+        update planets_history set valid_to = now() where planet_id = old.id and valid_to is null;
+    end if;
+    if tg_op in ('INSERT', 'UPDATE') then
+        insert into planets_history
+        values (new.id, new.name, new.type, new.uuid, new.star_id, new.status, now(), null);
+        return new;
+    end if;
+    return old;
+end;
+$$ language plpgsql;
+
+create function record_details_version() returns trigger as $$
+begin
+    if tg_op in ('UPDATE', 'DELETE') then
+        delete from details_history
+        where details_id = old.id and valid_from = now() and valid_to is null;
+        update details_history set valid_to = now() where details_id = old.id and valid_to is null;
+    end if;
+    if tg_op in ('INSERT', 'UPDATE') then
+        insert into details_history
+        values (new.id, new.mean_radius, new.mass, new.population, new.planet_id, now(), null);
+        return new;
+    end if;
+    return old;
+end;
+$$ language plpgsql;
+
+create trigger planets_history after insert or update or delete on planets
+    for each row execute procedure record_planet_version();
+create trigger details_history after insert or update or delete on details
+    for each row execute procedure record_details_version();
+
+-- earlier versions are unknown, so the history starts with the current state
+insert into planets_history select id, name, type, uuid, star_id, status, now(), null from planets;
+insert into details_history select id, mean_radius, mass, population, planet_id, now(), null from details;
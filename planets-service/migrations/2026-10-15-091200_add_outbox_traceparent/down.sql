@@ -0,0 +1 @@
+alter table outbox_events drop column traceparent;
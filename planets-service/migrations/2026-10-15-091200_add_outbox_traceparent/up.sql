@@ -0,0 +1,2 @@
+-- trace context of the request which caused the event, so that deliveries join its trace
+alter table outbox_events add column traceparent varchar(55);
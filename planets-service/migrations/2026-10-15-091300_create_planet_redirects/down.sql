@@ -0,0 +1 @@
+drop table planet_redirects;
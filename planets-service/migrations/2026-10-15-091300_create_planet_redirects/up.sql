@@ -0,0 +1,9 @@
+-- planets merged into another one; references to them resolve to the planet they were merged into
+create table planet_redirects (
+    old_id integer primary key,
+    old_uuid uuid not null unique,
+    planet_id integer not null references planets (id) on delete cascade,
+    created_at timestamptz not null default now()
+);
+
+create index planet_redirects_planet_id_idx on planet_redirects (planet_id);
@@ -0,0 +1,12 @@
+alter table details alter column mean_radius type numeric(10,1) using mean_radius / 1000;
+alter table details_history alter column mean_radius type numeric(10,1) using mean_radius / 1000;
+alter table classification_rules
+    alter column min_mean_radius type numeric(10,1) using min_mean_radius / 1000,
+    alter column max_mean_radius type numeric(10,1) using max_mean_radius / 1000;
+alter table orbits
+    alter column semi_major_axis type numeric(20,10) using semi_major_axis / 149597870700,
+    alter column orbital_period type numeric(20,6) using orbital_period / 86400;
+
+comment on column details.mean_radius is 'Mean radius in kilometers';
+comment on column orbits.semi_major_axis is 'Semi-major axis in astronomical units';
+comment on column orbits.orbital_period is 'Sidereal orbital period in days';
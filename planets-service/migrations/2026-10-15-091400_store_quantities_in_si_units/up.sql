@@ -0,0 +1,14 @@
+-- Physical quantities are stored in SI units: radii and semi-major axes in meters, periods in
+-- seconds; the schema keeps exposing kilometers, astronomical units and days
+alter table details alter column mean_radius type numeric(12) using round(mean_radius * 1000);
+alter table details_history alter column mean_radius type numeric(12) using round(mean_radius * 1000);
+alter table classification_rules
+    alter column min_mean_radius type numeric(12) using round(min_mean_radius * 1000),
+    alter column max_mean_radius type numeric(12) using round(max_mean_radius * 1000);
+alter table orbits
+    alter column semi_major_axis type numeric(20) using round(semi_major_axis * 149597870700),
+    alter column orbital_period type numeric(20,3) using round(orbital_period * 86400, 3);
+
+comment on column details.mean_radius is 'Mean radius in meters';
+comment on column orbits.semi_major_axis is 'Semi-major axis in meters';
+comment on column orbits.orbital_period is 'Sidereal orbital period in seconds';
@@ -0,0 +1,2 @@
+alter table planets_history drop constraint planets_history_status_check;
+alter table planets drop constraint planets_status_check;
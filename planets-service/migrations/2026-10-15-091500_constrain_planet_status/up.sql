@@ -0,0 +1,5 @@
+-- see model::PlanetStatus
+alter table planets add constraint planets_status_check
+    check (status in ('DRAFT', 'PUBLISHED', 'ARCHIVED'));
+alter table planets_history add constraint planets_history_status_check
+    check (status in ('DRAFT', 'PUBLISHED', 'ARCHIVED'));
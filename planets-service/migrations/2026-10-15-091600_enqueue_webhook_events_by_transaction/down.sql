@@ -0,0 +1,6 @@
+alter table webhooks add column last_event_id bigint not null default 0;
+update webhooks set last_event_id = coalesce((select max(id) from outbox_events), 0);
+alter table webhooks alter column last_event_id drop default;
+alter table webhooks drop column next_transaction_id;
+drop index outbox_events_topic_transaction_id_idx;
+alter table outbox_events drop column transaction_id;
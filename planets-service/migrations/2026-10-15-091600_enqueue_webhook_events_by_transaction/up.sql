@@ -0,0 +1,20 @@
+-- outbox event IDs are assigned on insert, so a transaction committing late stores events with
+-- lower IDs than the ones already enqueued; events are enqueued by the IDs of their transactions
+-- instead, since the events of transactions older than all running ones can't change anymore
+alter table outbox_events add column transaction_id bigint not null default 0;
+alter table outbox_events alter column transaction_id set default txid_current();
+create index outbox_events_topic_transaction_id_idx on outbox_events (topic, transaction_id);
+
+-- the transactions of the events stored before are unknown, so they are enqueued here
+insert into webhook_deliveries (webhook_id, event_id, status, attempts, next_attempt_at)
+select webhooks.id, outbox_events.id, 'PENDING', 0, now()
+from webhooks
+join outbox_events on outbox_events.topic = 'planets'
+    and outbox_events.id > webhooks.last_event_id
+    and (cardinality(webhooks.event_kinds) = 0 or outbox_events.kind = any(webhooks.event_kinds))
+on conflict do nothing;
+
+-- events of this transaction and the later ones aren't enqueued yet
+alter table webhooks add column next_transaction_id bigint not null default 1;
+alter table webhooks alter column next_transaction_id drop default;
+alter table webhooks drop column last_event_id;
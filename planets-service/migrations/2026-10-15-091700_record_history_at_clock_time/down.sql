@@ -0,0 +1,37 @@
+create or replace function record_planet_version() returns trigger as $$
+begin
+    if tg_op in ('UPDATE', 'DELETE') then
+        delete from planets_history
+        where planet_id = old.id and valid_from = now() and valid_to is null;
+        update planets_history set valid_to = now() where planet_id = old.id and valid_to is null;
+    end if;
+    if tg_op in ('INSERT', 'UPDATE') then
+        insert into planets_history
+        values (new.id, new.name, new.type, new.uuid, new.star_id, new.status, now(), null);
+        return new;
+    end if;
+    return old;
+end;
+$$ language plpgsql;
+
+create or replace function record_details_version() returns trigger as $$
+begin
+    if tg_op in ('UPDATE', 'DELETE') then
+        delete from details_history
+        where details_id = old.id and valid_from = now() and valid_to is null;
+        update details_history set valid_to = now() where details_id = old.id and valid_to is null;
+    end if;
+    if tg_op in ('INSERT', 'UPDATE') then
+        insert into details_history
+        values (new.id, new.mean_radius, new.mass, new.population, new.planet_id, now(), null);
+        return new;
+    end if;
+    return old;
+end;
+$$ language plpgsql;
+
+alter table details_history drop constraint details_history_valid_check;
+alter table planets_history drop constraint planets_history_valid_check;
+
+alter table details_history drop column transaction_id;
+alter table planets_history drop column transaction_id;
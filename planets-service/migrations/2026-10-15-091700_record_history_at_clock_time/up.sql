@@ -0,0 +1,63 @@
+-- now() is the start time of the transaction, so a transaction that started earlier but changed a
+-- row later closed the version of the other one before it started; versions start at the time of
+-- the change instead, and the versions of the current transaction are found by its ID
+alter table planets_history add column transaction_id bigint not null default 0;
+alter table details_history add column transaction_id bigint not null default 0;
+
+-- versions closed before they started have never been visible
+update planets_history set valid_to = valid_from where valid_to < valid_from;
+update details_history set valid_to = valid_from where valid_to < valid_from;
+
+alter table planets_history add constraint planets_history_valid_check
+    check (valid_to >= valid_from);
+alter table details_history add constraint details_history_valid_check
+    check (valid_to >= valid_from);
+
+-- a version replaced within the same transaction is dropped, since it has never been visible, and
+-- the new version starts where the dropped one did
+create or replace function record_planet_version() returns trigger as $$
+declare
+    changed_at timestamptz;
+begin
+    if tg_op in ('UPDATE', 'DELETE') then
+        delete from planets_history
+        where planet_id = old.id and transaction_id = txid_current() and valid_to is null
+        returning valid_from into changed_at;
+        changed_at := coalesce(changed_at, clock_timestamp());
+        update planets_history set valid_to = changed_at
+        where planet_id = old.id and valid_to is null;
+    end if;
+    if tg_op in ('INSERT', 'UPDATE') then
+        insert into planets_history
+            (planet_id, name, type, uuid, star_id, status, valid_from, valid_to, transaction_id)
+        values (new.id, new.name, new.type, new.uuid, new.star_id, new.status,
+            coalesce(changed_at, clock_timestamp()), null, txid_current());
+        return new;
+    end if;
+    return old;
+end;
+$$ language plpgsql;
+
+create or replace function record_details_version() returns trigger as $$
+declare
+    changed_at timestamptz;
+begin
+    if tg_op in ('UPDATE', 'DELETE') then
+        delete from details_history
+        where details_id = old.id and transaction_id = txid_current() and valid_to is null
+        returning valid_from into changed_at;
+        changed_at := coalesce(changed_at, clock_timestamp());
+        update details_history set valid_to = changed_at
+        where details_id = old.id and valid_to is null;
+    end if;
+    if tg_op in ('INSERT', 'UPDATE') then
+        insert into details_history
+            (details_id, mean_radius, mass, population, planet_id, valid_from, valid_to,
+            transaction_id)
+        values (new.id, new.mean_radius, new.mass, new.population, new.planet_id,
+            coalesce(changed_at, clock_timestamp()), null, txid_current());
+        return new;
+    end if;
+    return old;
+end;
+$$ language plpgsql;
@@ -0,0 +1,8 @@
+update details_history set planet_id = merged_from where merged_from is not null;
+update planets_history set planet_id = merged_from where merged_from is not null;
+
+alter table planets_history drop constraint planets_history_pkey;
+alter table planets_history add primary key (planet_id, valid_from);
+
+alter table details_history drop column merged_from;
+alter table planets_history drop column merged_from;
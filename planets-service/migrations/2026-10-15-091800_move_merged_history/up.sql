@@ -0,0 +1,14 @@
+-- versions of planets merged into another one move to it; `merged_from` is the ID of the planet
+-- they were versions of, and is null for the versions of the planet itself
+alter table planets_history add column merged_from integer;
+alter table details_history add column merged_from integer;
+
+-- versions of the merged planets may start at the same time as the ones of the target
+alter table planets_history drop constraint planets_history_pkey;
+alter table planets_history add primary key (planet_id, valid_from, uuid);
+
+-- the history of planets merged before stayed under their IDs
+update planets_history set planet_id = planet_redirects.planet_id, merged_from = old_id
+from planet_redirects where planets_history.planet_id = old_id;
+update details_history set planet_id = planet_redirects.planet_id, merged_from = old_id
+from planet_redirects where details_history.planet_id = old_id;
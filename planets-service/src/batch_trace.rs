@@ -0,0 +1,179 @@
+//! Spans of data loader batches. Loaders are shared by operations, so a batch loads the keys of
+//! several of them and can't run in the trace context of the one which happened to start it; each
+//! batch runs in a span of its own, a child of the first operation's span linked to the spans of
+//! all operations which enqueued its keys
+use std::any;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use async_graphql::dataloader::{DataLoader, Loader};
+use serde_json::json;
+
+use crate::http_client::{self, RequestContext};
+use crate::operation_log;
+use crate::trace_context::TraceContext;
+
+/// A data loader which records the trace context of the operations enqueuing keys
+pub struct TracedDataLoader<K, L> {
+    data_loader: DataLoader<TracedLoader<K, L>>,
+}
+
+impl<K, L> TracedDataLoader<K, L>
+where
+    K: Send + Sync + Hash + Eq + Clone + 'static,
+    L: Loader<K>,
+{
+    pub fn new(loader: L) -> Self {
+        let loader = TracedLoader {
+            loader,
+            contexts: Mutex::new(HashMap::new()),
+            enqueued: AtomicU64::new(0),
+        };
+        TracedDataLoader {
+            data_loader: DataLoader::new(loader, tokio::spawn),
+        }
+    }
+
+    pub fn max_batch_size(self, max_batch_size: usize) -> Self {
+        TracedDataLoader {
+            data_loader: self.data_loader.max_batch_size(max_batch_size),
+        }
+    }
+
+    pub async fn load_one(&self, key: K) -> Result<Option<L::Value>, L::Error> {
+        if let Some(trace) = http_client::current_context().trace {
+            let loader = self.data_loader.loader();
+            let order = loader.enqueued.fetch_add(1, Ordering::Relaxed);
+            loader
+                .contexts
+                .lock()
+                .expect("Can't lock trace contexts")
+                .entry(key.clone())
+                .or_default()
+                .push((order, trace));
+        }
+        self.data_loader.load_one(key).await
+    }
+}
+
+struct TracedLoader<K, L> {
+    loader: L,
+    /// Spans of the operations waiting for the keys, in the order of enqueuing
+    contexts: Mutex<HashMap<K, Vec<(u64, TraceContext)>>>,
+    enqueued: AtomicU64,
+}
+
+#[async_trait::async_trait]
+impl<K, L> Loader<K> for TracedLoader<K, L>
+where
+    K: Send + Sync + Hash + Eq + Clone + 'static,
+    L: Loader<K>,
+{
+    type Value = L::Value;
+    type Error = L::Error;
+
+    async fn load(&self, keys: &[K]) -> Result<HashMap<K, Self::Value>, Self::Error> {
+        let links = {
+            let mut contexts = self.contexts.lock().expect("Can't lock trace contexts");
+            let mut enqueued: Vec<(u64, TraceContext)> = keys
+                .iter()
+                .filter_map(|key| contexts.remove(key))
+                .flatten()
+                .collect();
+            enqueued.sort_by_key(|(order, _)| *order);
+            let mut links: Vec<TraceContext> = vec![];
+            for (_, trace) in enqueued {
+                if !links.contains(&trace) {
+                    links.push(trace);
+                }
+            }
+            links
+        };
+        let trace =
+            TraceContext::continue_from(links.first().map(|parent| parent.to_string()).as_deref());
+        let context = RequestContext {
+            request_id: None,
+            trace: Some(trace.clone()),
+        };
+
+        let start = Instant::now();
+        let values = http_client::with_context(context, self.loader.load(keys)).await;
+        if operation_log::is_sampled() {
+            println!(
+                "{}",
+                json!({
+                    "batch": any::type_name::<L>(),
+                    "keys": keys.len(),
+                    "traceId": trace.trace_id,
+                    "spanId": trace.span_id,
+                    "parentSpanId": links.first().map(|parent| &parent.span_id),
+                    "links": links
+                        .iter()
+                        .map(|link| json!({ "traceId": link.trace_id, "spanId": link.span_id }))
+                        .collect::<Vec<_>>(),
+                    "durationMs": start.elapsed().as_millis() as u64,
+                })
+            );
+        }
+        values
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct EchoLoader;
+
+    #[async_trait::async_trait]
+    impl Loader<i32> for EchoLoader {
+        type Value = Option<TraceContext>;
+        type Error = String;
+
+        async fn load(&self, keys: &[i32]) -> Result<HashMap<i32, Self::Value>, Self::Error> {
+            let trace = http_client::current_context().trace;
+            Ok(keys.iter().map(|key| (*key, trace.clone())).collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn batch_runs_in_span_of_its_own() {
+        let data_loader = TracedDataLoader::new(EchoLoader);
+        let operations: Vec<TraceContext> =
+            (0..2).map(|_| TraceContext::continue_from(None)).collect();
+
+        let load = |key: i32, trace: &TraceContext| {
+            let context = RequestContext {
+                request_id: None,
+                trace: Some(trace.clone()),
+            };
+            http_client::with_context(context, data_loader.load_one(key))
+        };
+        let (first, second) = futures::join!(load(1, &operations[0]), load(2, &operations[1]));
+        let first = first
+            .expect("Can't load")
+            .flatten()
+            .expect("Can't get trace");
+        let second = second
+            .expect("Can't load")
+            .flatten()
+            .expect("Can't get trace");
+
+        // both keys are loaded by one batch in the trace of the first operation
+        assert_eq!(first, second);
+        assert_eq!(operations[0].trace_id, first.trace_id);
+        assert!(operations
+            .iter()
+            .all(|trace| trace.span_id != first.span_id));
+        assert!(data_loader
+            .data_loader
+            .loader()
+            .contexts
+            .lock()
+            .expect("Can't lock trace contexts")
+            .is_empty());
+    }
+}
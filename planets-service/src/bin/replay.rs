@@ -0,0 +1,137 @@
+//! Replays operations captured by the operation log (the service's stdout with
+//! `OPERATION_LOG_SAMPLE_RATE` set) against a target environment and prints, as JSON lines,
+//! the differences of latencies and errors per operation. Captured latencies are execution times,
+//! while replayed ones are measured by the client and include the network and HTTP handling, so
+//! diffs are best compared between replays against different environments.
+//!
+//! Usage: `replay <captures file> <target URL>`. Settings:
+//! - `REPLAY_RATE`: requests per second, 10 by default; requests are sent at this rate
+//!   regardless of how fast the target responds
+//! - `REPLAY_MIN_DURATION_MS`: only operations captured as at least this slow are replayed
+//! - `REPLAY_INCLUDE_MUTATIONS`: mutations change the data of the target, so they are skipped
+//!   unless this is `true`
+//! - `REPLAY_ROLE`: sent as the `role` header
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+use std::{env, fs, process};
+
+use lazy_static::lazy_static;
+use serde_json::{json, Value};
+
+use planets_service::replay::{self, Capture, Outcome};
+
+lazy_static! {
+    static ref RATE: f64 = env::var("REPLAY_RATE")
+        .map(|rate| rate.parse().expect("Can't parse REPLAY_RATE"))
+        .unwrap_or(10.0);
+    static ref MIN_DURATION_MS: u64 = env::var("REPLAY_MIN_DURATION_MS")
+        .map(|duration| duration
+            .parse()
+            .expect("Can't parse REPLAY_MIN_DURATION_MS"))
+        .unwrap_or(0);
+    static ref INCLUDE_MUTATIONS: bool = env::var("REPLAY_INCLUDE_MUTATIONS")
+        .map(|include| include
+            .parse()
+            .expect("Can't parse REPLAY_INCLUDE_MUTATIONS"))
+        .unwrap_or(false);
+    static ref ROLE: Option<String> = env::var("REPLAY_ROLE").ok();
+}
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let [captures_path, target] = args.as_slice() else {
+        eprintln!("Usage: replay <captures file> <target URL>");
+        process::exit(2);
+    };
+    assert!(*RATE > 0.0, "REPLAY_RATE must be positive");
+
+    let log = fs::read_to_string(captures_path).expect("Can't read captures file");
+    let mut skipped: BTreeMap<&str, usize> = BTreeMap::new();
+    let captures: Vec<Capture> = log
+        .lines()
+        .filter_map(replay::parse_capture)
+        .filter(|capture| capture.duration_ms >= *MIN_DURATION_MS)
+        .filter(
+            |capture| match replay::skip_reason(capture, *INCLUDE_MUTATIONS) {
+                Some(reason) => {
+                    *skipped.entry(reason).or_default() += 1;
+                    false
+                }
+                None => true,
+            },
+        )
+        .collect();
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("Can't create runtime");
+    let start = Instant::now();
+    let outcomes = runtime.block_on(replay_all(captures, target));
+
+    for report in replay::report(&outcomes) {
+        println!(
+            "{}",
+            serde_json::to_string(&report).expect("Can't serialize report")
+        );
+    }
+    println!(
+        "{}",
+        json!({
+            "replayed": outcomes.len(),
+            "skipped": skipped,
+            "durationMs": start.elapsed().as_millis() as u64,
+        })
+    );
+}
+
+/// Requests are sent concurrently at `REPLAY_RATE`, so that the load has the captured shape
+async fn replay_all(captures: Vec<Capture>, target: &str) -> Vec<Outcome> {
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .expect("Can't create HTTP client");
+    let mut ticks = tokio::time::interval(Duration::from_secs_f64(1.0 / *RATE));
+    let mut requests = vec![];
+    for capture in captures {
+        ticks.tick().await;
+        let client = client.clone();
+        let target = target.to_string();
+        requests.push(tokio::spawn(async move {
+            let start = Instant::now();
+            let errors = send(&client, &target, &capture).await;
+            Outcome {
+                capture,
+                duration_ms: start.elapsed().as_millis() as u64,
+                errors,
+            }
+        }));
+    }
+
+    let mut outcomes = vec![];
+    for request in requests {
+        outcomes.push(request.await.expect("Replay task failed"));
+    }
+    outcomes
+}
+
+/// Number of errors of the response; a failed request counts as one
+async fn send(client: &reqwest::Client, target: &str, capture: &Capture) -> usize {
+    let mut request = client.post(target).json(&json!({
+        "query": capture.query,
+        "operationName": capture.operation,
+    }));
+    if let Some(role) = ROLE.as_ref() {
+        request = request.header("role", role);
+    }
+    let response = match request.send().await {
+        Ok(response) if response.status().is_success() => response,
+        _ => return 1,
+    };
+    match response.json::<Value>().await {
+        Ok(body) => body["errors"].as_array().map(Vec::len).unwrap_or(0),
+        Err(_) => 1,
+    }
+}
@@ -0,0 +1,117 @@
+//! Serves the schema without actix for low-traffic environments: on AWS Lambda (behind API
+//! Gateway or a function URL) if `AWS_LAMBDA_RUNTIME_API` is set, otherwise as an HTTP server on
+//! `PORT` (as on Cloud Run). Subscriptions aren't supported, and migrations are expected to be
+//! run by a regular deployment of the service
+use std::convert::Infallible;
+use std::env;
+use std::net::SocketAddr;
+
+use async_graphql::http::{parse_query_string, receive_body, MultipartOptions};
+use hyper::header::{HeaderValue, CONTENT_TYPE};
+use hyper::http::{Method, Request, Response, StatusCode};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Server};
+use tokio::sync::OnceCell;
+
+use planets_service::build_info;
+use planets_service::create_schema_with_context;
+use planets_service::graphql::AppSchema;
+use planets_service::persistence::connection::create_lazy_connection_pool;
+
+static SCHEMA: OnceCell<AppSchema> = OnceCell::const_new();
+
+/// The schema is created on the first request, so that cold starts don't wait for the DB
+async fn get_schema() -> &'static AppSchema {
+    SCHEMA
+        .get_or_init(|| async { create_schema_with_context(create_lazy_connection_pool()) })
+        .await
+}
+
+/// Translates an HTTP request, with the operation in the query string (GET) or in the body,
+/// into a GraphQL one
+async fn handle(request: Request<Vec<u8>>) -> Response<String> {
+    let (parts, body) = request.into_parts();
+    let graphql_request = match parts.method {
+        Method::GET => parse_query_string(parts.uri.query().unwrap_or_default()),
+        Method::POST => {
+            let content_type = parts
+                .headers
+                .get(CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok());
+            receive_body(content_type, body.as_slice(), MultipartOptions::default()).await
+        }
+        _ => return create_response(StatusCode::METHOD_NOT_ALLOWED, String::new()),
+    };
+    let graphql_request = match graphql_request {
+        Ok(graphql_request) => graphql_request,
+        Err(e) => return create_response(StatusCode::BAD_REQUEST, e.to_string()),
+    };
+
+    let role = parts
+        .headers
+        .get("role")
+        .and_then(|value| value.to_str().ok());
+    let response = planets_service::execute(get_schema().await, graphql_request, role).await;
+
+    let mut http_response = create_response(
+        StatusCode::OK,
+        serde_json::to_string(&response).expect("Can't serialize GraphQL response"),
+    );
+    http_response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    http_response
+}
+
+fn create_response(status: StatusCode, body: String) -> Response<String> {
+    let mut response = Response::new(body);
+    *response.status_mut() = status;
+    response
+}
+
+async fn run_lambda() -> Result<(), lambda_http::Error> {
+    lambda_http::run(lambda_http::service_fn(
+        |request: lambda_http::Request| async move {
+            let (parts, body) = request.into_parts();
+            let response = handle(Request::from_parts(parts, body.to_vec())).await;
+            Ok::<_, Infallible>(response)
+        },
+    ))
+    .await
+}
+
+async fn run_server() -> Result<(), lambda_http::Error> {
+    let port = env::var("PORT")
+        .unwrap_or_else(|_| "8080".to_string())
+        .parse::<u16>()
+        .expect("Can't parse port");
+
+    let make_service = make_service_fn(|_| async {
+        Ok::<_, Infallible>(service_fn(|request: Request<Body>| async move {
+            let (parts, body) = request.into_parts();
+            let body = hyper::body::to_bytes(body).await?;
+            let response = handle(Request::from_parts(parts, body.to_vec())).await;
+            Ok::<_, hyper::Error>(response.map(Body::from))
+        }))
+    });
+
+    Server::bind(&SocketAddr::from(([0, 0, 0, 0], port)))
+        .serve(make_service)
+        .await?;
+    Ok(())
+}
+
+#[cfg(feature = "jemalloc")]
+#[global_allocator]
+static GLOBAL: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
+
+#[tokio::main]
+async fn main() -> Result<(), lambda_http::Error> {
+    dotenv::dotenv().ok();
+    println!("{}", build_info::banner());
+    if env::var("AWS_LAMBDA_RUNTIME_API").is_ok() {
+        run_lambda().await
+    } else {
+        run_server().await
+    }
+}
@@ -0,0 +1,332 @@
+use std::env;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures::stream::{self, BoxStream};
+use futures::{future, Stream, StreamExt};
+use lazy_static::lazy_static;
+use tokio::sync::broadcast::{self, error::RecvError, Sender};
+use tokio::time::{self, MissedTickBehavior};
+
+use crate::metrics;
+
+lazy_static! {
+    /// Messages of a topic kept for subscribers which haven't received them yet
+    static ref CAPACITY: usize = env::var("BROKER_CAPACITY")
+        .map(|capacity| capacity.parse().expect("Can't parse BROKER_CAPACITY"))
+        .unwrap_or(1024);
+}
+
+/// A subscriber fell behind by more than the capacity, so the oldest messages were dropped for it
+#[derive(Debug, PartialEq)]
+pub struct Lagged(pub u64);
+
+/// Delivery of messages of one type to subscribers; the in-process [Topic] can be replaced with
+/// an external broker
+pub trait Broker<T>: Send + Sync {
+    fn publish(&self, message: T);
+
+    /// Messages published after the call; if the subscriber lags, it gets [Lagged] with the number
+    /// of skipped messages and then continues from the oldest kept one. Dropping the stream
+    /// unsubscribes
+    fn subscribe(&self) -> Subscription<T>;
+
+    fn stats(&self) -> TopicStats;
+}
+
+pub struct TopicStats {
+    pub name: &'static str,
+    pub subscribers: usize,
+    /// Since the start of the instance, including messages published without subscribers
+    pub published: u64,
+    /// Messages skipped by lagging subscribers
+    pub skipped: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    topic: &'static str,
+    subscribers: AtomicUsize,
+    published: AtomicU64,
+    skipped: AtomicU64,
+}
+
+/// In-process topic: each subscriber gets every message. Messages are stored once in a bounded
+/// buffer, so a slow subscriber can't make memory grow unboundedly
+pub struct Topic<T> {
+    name: &'static str,
+    sender: Sender<T>,
+    counters: Arc<Counters>,
+}
+
+impl<T: Clone + Send + 'static> Topic<T> {
+    pub fn new(name: &'static str) -> Self {
+        Self::with_capacity(name, *CAPACITY)
+    }
+
+    pub fn with_capacity(name: &'static str, capacity: usize) -> Self {
+        Topic {
+            name,
+            sender: broadcast::channel(capacity).0,
+            counters: Arc::new(Counters {
+                topic: name,
+                ..Default::default()
+            }),
+        }
+    }
+}
+
+impl<T: Clone + Send + 'static> Broker<T> for Topic<T> {
+    fn publish(&self, message: T) {
+        self.counters.published.fetch_add(1, Ordering::Relaxed);
+        metrics::BROKER_PUBLISHED.increment(&[self.name], 1);
+        // fails only if there are no subscribers
+        self.sender.send(message).ok();
+    }
+
+    fn subscribe(&self) -> Subscription<T> {
+        let counters = Arc::clone(&self.counters);
+        counters.subscribers.fetch_add(1, Ordering::Relaxed);
+        metrics::BROKER_SUBSCRIBERS.increase(&[counters.topic], 1.0);
+        let receiver = self.sender.subscribe();
+        let messages = stream::unfold(
+            (receiver, Arc::clone(&counters)),
+            |(mut receiver, counters)| async move {
+                let message = match receiver.recv().await {
+                    Ok(message) => Ok(message),
+                    Err(RecvError::Lagged(skipped)) => {
+                        counters.skipped.fetch_add(skipped, Ordering::Relaxed);
+                        metrics::BROKER_SKIPPED.increment(&[counters.topic], skipped);
+                        Err(Lagged(skipped))
+                    }
+                    Err(RecvError::Closed) => return None,
+                };
+                Some((message, (receiver, counters)))
+            },
+        );
+        Subscription {
+            messages: messages.boxed(),
+            counters,
+        }
+    }
+
+    fn stats(&self) -> TopicStats {
+        TopicStats {
+            name: self.name,
+            subscribers: self.counters.subscribers.load(Ordering::Relaxed),
+            published: self.counters.published.load(Ordering::Relaxed),
+            skipped: self.counters.skipped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Messages of a topic for one subscriber
+pub struct Subscription<T> {
+    messages: BoxStream<'static, Result<T, Lagged>>,
+    counters: Arc<Counters>,
+}
+
+impl<T> Stream for Subscription<T> {
+    type Item = Result<T, Lagged>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.messages.poll_next_unpin(cx)
+    }
+}
+
+impl<T> Drop for Subscription<T> {
+    fn drop(&mut self) {
+        self.counters.subscribers.fetch_sub(1, Ordering::Relaxed);
+        metrics::BROKER_SUBSCRIBERS.decrease(&[self.counters.topic], 1.0);
+    }
+}
+
+enum BatchInput<T> {
+    Message(T),
+    Tick,
+    End,
+}
+
+/// Collects messages of a subscription into batches emitted every `interval`; intervals without
+/// messages give no batch. A batch is emitted early once it has `max_size` messages, so that
+/// a burst doesn't make it grow unboundedly. An error is passed through right after the batch of
+/// the messages received before it, so that the messages after it are never delivered earlier
+pub fn batches<T, E>(
+    messages: impl Stream<Item = Result<T, E>> + Send + 'static,
+    interval: Duration,
+    max_size: usize,
+) -> impl Stream<Item = Result<Vec<T>, E>> + Send + 'static
+where
+    T: Send + 'static,
+    E: Send + 'static,
+{
+    let mut ticks = time::interval_at(time::Instant::now() + interval, interval);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let ticks = stream::unfold(ticks, |mut ticks| async move {
+        ticks.tick().await;
+        Some((BatchInput::Tick, ticks))
+    })
+    .boxed();
+    let messages = messages
+        .map(BatchInput::Message)
+        .chain(stream::once(future::ready(BatchInput::End)))
+        .boxed();
+
+    async_stream::stream! {
+        let mut inputs = stream::select(messages, ticks);
+        let mut batch = vec![];
+        while let Some(input) = inputs.next().await {
+            match input {
+                BatchInput::Message(Ok(message)) => {
+                    batch.push(message);
+                    if batch.len() >= max_size {
+                        yield Ok(std::mem::take(&mut batch));
+                    }
+                }
+                BatchInput::Message(Err(e)) => {
+                    if !batch.is_empty() {
+                        yield Ok(std::mem::take(&mut batch));
+                    }
+                    yield Err(e);
+                }
+                BatchInput::Tick => {
+                    if !batch.is_empty() {
+                        yield Ok(std::mem::take(&mut batch));
+                    }
+                }
+                BatchInput::End => {
+                    if !batch.is_empty() {
+                        yield Ok(std::mem::take(&mut batch));
+                    }
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use futures::future;
+
+    use super::*;
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Message(usize);
+
+    #[tokio::test]
+    async fn thousand_subscribers() {
+        let topic = Topic::new("messages");
+        let subscribers: Vec<_> = (0..1000).map(|_| topic.subscribe()).collect();
+        for i in 0..100 {
+            topic.publish(Message(i));
+        }
+        let received = future::join_all(subscribers.into_iter().map(|subscriber| {
+            subscriber
+                .take(100)
+                .map(|message| message.map(|message| message.0))
+                .collect::<Vec<_>>()
+        }))
+        .await;
+
+        let expected: Vec<_> = (0..100).map(Ok).collect();
+        assert!(received.iter().all(|messages| *messages == expected));
+    }
+
+    #[tokio::test]
+    async fn slow_subscriber_lags() {
+        let topic = Topic::with_capacity("messages", 16);
+        let mut fast = topic.subscribe();
+        let mut slow = topic.subscribe();
+        for i in 0..26 {
+            topic.publish(Message(i));
+            assert_eq!(Some(Ok(Message(i))), fast.next().await);
+        }
+
+        assert_eq!(Some(Err(Lagged(10))), slow.next().await);
+        assert_eq!(Some(Ok(Message(10))), slow.next().await);
+        assert_eq!(10, topic.stats().skipped);
+    }
+
+    #[tokio::test]
+    async fn messages_before_subscription_are_not_received() {
+        let topic = Topic::new("messages");
+        topic.publish(Message(1));
+        let mut subscriber = topic.subscribe();
+        topic.publish(Message(2));
+        assert_eq!(Some(Ok(Message(2))), subscriber.next().await);
+    }
+
+    #[tokio::test]
+    async fn topics_are_independent() {
+        let first = Topic::new("first");
+        let second = Topic::new("second");
+        let mut subscriber = second.subscribe();
+        first.publish(Message(1));
+        second.publish(Message(2));
+        assert_eq!(Some(Ok(Message(2))), subscriber.next().await);
+    }
+
+    #[tokio::test]
+    async fn messages_are_batched_per_interval() {
+        let topic = Topic::new("messages");
+        let interval = Duration::from_millis(200);
+        let mut batches = Box::pin(batches(topic.subscribe(), interval, 3));
+        for i in 0..5 {
+            topic.publish(Message(i));
+        }
+        // the first batch is full before the interval passes
+        let start = Instant::now();
+        assert_eq!(
+            Some(Ok(vec![Message(0), Message(1), Message(2)])),
+            batches.next().await
+        );
+        assert!(start.elapsed() < interval / 2);
+        assert_eq!(Some(Ok(vec![Message(3), Message(4)])), batches.next().await);
+
+        topic.publish(Message(5));
+        assert_eq!(Some(Ok(vec![Message(5)])), batches.next().await);
+    }
+
+    #[tokio::test]
+    async fn errors_are_passed_through_after_the_batch_before_them() {
+        let messages = stream::iter(vec![
+            Ok(Message(1)),
+            Err(Lagged(10)),
+            Ok(Message(12)),
+            Ok(Message(13)),
+        ]);
+        let batches: Vec<_> = batches(messages, Duration::from_secs(60), 10)
+            .collect()
+            .await;
+        assert_eq!(
+            vec![
+                Ok(vec![Message(1)]),
+                Err(Lagged(10)),
+                Ok(vec![Message(12), Message(13)])
+            ],
+            batches
+        );
+    }
+
+    #[test]
+    fn dropped_subscription_unsubscribes() {
+        let topic = Topic::<Message>::new("messages");
+        let first = topic.subscribe();
+        let second = topic.subscribe();
+        assert_eq!(2, topic.stats().subscribers);
+
+        drop(first);
+        assert_eq!(1, topic.stats().subscribers);
+        drop(second);
+        assert_eq!(0, topic.stats().subscribers);
+
+        topic.publish(Message(1));
+        assert_eq!(1, topic.stats().published);
+    }
+}
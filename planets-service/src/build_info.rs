@@ -0,0 +1,81 @@
+//! What a running instance is built from; the commit and the time are embedded by build.rs
+use chrono::{DateTime, TimeZone, Utc};
+use lazy_static::lazy_static;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+lazy_static! {
+    pub static ref BUILD_INFO: BuildInfo = BuildInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        git_sha: env!("BUILD_GIT_SHA"),
+        built_at: Utc
+            .timestamp_opt(
+                env!("BUILD_TIMESTAMP")
+                    .parse()
+                    .expect("Can't parse build timestamp"),
+                0,
+            )
+            .single()
+            .expect("Can't convert build timestamp"),
+        features: get_features(),
+        schema_hash: hex::encode(Sha256::digest(crate::schema_sdl())),
+    };
+}
+
+pub struct BuildInfo {
+    pub version: &'static str,
+    pub git_sha: &'static str,
+    pub built_at: DateTime<Utc>,
+    /// Cargo features the service is compiled with
+    pub features: Vec<&'static str>,
+    /// Hex-encoded SHA-256 of the SDL from which clients are generated
+    pub schema_hash: String,
+}
+
+fn get_features() -> Vec<&'static str> {
+    [
+        ("actix", cfg!(feature = "actix")),
+        ("kafka", cfg!(feature = "kafka")),
+        ("nats", cfg!(feature = "nats")),
+        ("aws-secrets-manager", cfg!(feature = "aws-secrets-manager")),
+        ("serverless", cfg!(feature = "serverless")),
+        ("jemalloc", cfg!(feature = "jemalloc")),
+        ("chaos", cfg!(feature = "chaos")),
+    ]
+    .into_iter()
+    .filter_map(|(feature, enabled)| enabled.then_some(feature))
+    .collect()
+}
+
+/// A JSON line logged at startup, so that logs tell which build and schema an instance serves
+pub fn banner() -> String {
+    json!({
+        "event": "startup",
+        "version": BUILD_INFO.version,
+        "gitSha": BUILD_INFO.git_sha,
+        "builtAt": BUILD_INFO.built_at.to_rfc3339(),
+        "features": BUILD_INFO.features,
+        "schemaHash": BUILD_INFO.schema_hash,
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn banner_is_json() {
+        let banner: serde_json::Value =
+            serde_json::from_str(&banner()).expect("Can't parse banner");
+        assert_eq!(env!("CARGO_PKG_VERSION"), banner["version"]);
+        assert_eq!(
+            64,
+            banner["schemaHash"]
+                .as_str()
+                .expect("Can't get schema hash")
+                .len()
+        );
+        assert_eq!(json!(get_features()), banner["features"]);
+    }
+}
@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::env;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_graphql::extensions::{
+    Extension, ExtensionContext, ExtensionFactory, NextPrepareRequest, NextResolve, ResolveInfo,
+};
+use async_graphql::{Request, ServerError, ServerResult, Value};
+use lazy_static::lazy_static;
+
+/// Faults of a request replacing the ones from `CHAOS_FAULTS`, in the same format
+pub const FAULTS_HEADER: &str = "chaos-faults";
+
+lazy_static! {
+    static ref ENV_FAULTS: Arc<Faults> = Arc::new(
+        env::var("CHAOS_FAULTS")
+            .map(|faults| faults.parse().expect("Can't parse chaos faults"))
+            .unwrap_or_default()
+    );
+}
+
+tokio::task_local! {
+    static BROKER_FAILURE: bool;
+}
+
+/// Faults injected into a resolver
+#[derive(Clone, Debug, PartialEq)]
+pub enum Fault {
+    /// Delays the resolver
+    Latency(Duration),
+    /// Fails the resolver as if the database were unavailable
+    DbError,
+    /// Fails messages sent by the resolver to Kafka and to subscribers
+    BrokerError,
+}
+
+/// Faults by resolvers, e.g. `Query.getPlanets=latency:500;Mutation.createPlanet=broker_error`.
+/// Latency is in milliseconds, a resolver may be listed more than once
+#[derive(Debug, Default, PartialEq)]
+pub struct Faults(HashMap<String, Vec<Fault>>);
+
+impl Faults {
+    fn get(&self, parent_type: &str, field: &str) -> &[Fault] {
+        self.0
+            .get(&format!("{}.{}", parent_type, field))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl FromStr for Faults {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut faults = HashMap::<String, Vec<Fault>>::new();
+        for entry in s
+            .split(';')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+        {
+            let (resolver, fault) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("Fault should be set as Type.field=fault: {}", entry))?;
+            let fault = match fault.split_once(':') {
+                Some(("latency", millis)) => Fault::Latency(Duration::from_millis(
+                    millis
+                        .parse()
+                        .map_err(|_| format!("Can't parse latency: {}", millis))?,
+                )),
+                None if fault == "db_error" => Fault::DbError,
+                None if fault == "broker_error" => Fault::BrokerError,
+                _ => return Err(format!("Unknown fault: {}", fault)),
+            };
+            faults
+                .entry(resolver.trim().to_string())
+                .or_default()
+                .push(fault);
+        }
+        Ok(Faults(faults))
+    }
+}
+
+/// Value of [FAULTS_HEADER], passed by a transport
+pub struct FaultsHeader(pub String);
+
+/// Whether faults may be injected into the request; other extensions may act differently then
+pub fn is_enabled(ctx: &ExtensionContext<'_>) -> bool {
+    ctx.data_opt::<FaultsHeader>().is_some() || !ENV_FAULTS.is_empty()
+}
+
+/// Whether messages sent by the current resolver should fail
+pub fn is_broker_failing() -> bool {
+    BROKER_FAILURE.try_with(|failing| *failing).unwrap_or(false)
+}
+
+/// Injects faults configured in `CHAOS_FAULTS` or per request in [FAULTS_HEADER], so that the
+/// behavior under failures can be tested. Only built with the `chaos` feature
+pub struct FaultInjector;
+
+impl ExtensionFactory for FaultInjector {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(FaultInjectorExtension::default())
+    }
+}
+
+#[derive(Default)]
+struct FaultInjectorExtension {
+    faults: Mutex<Arc<Faults>>,
+}
+
+#[async_trait::async_trait]
+impl Extension for FaultInjectorExtension {
+    async fn prepare_request(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        request: Request,
+        next: NextPrepareRequest<'_>,
+    ) -> ServerResult<Request> {
+        let faults = match ctx.data_opt::<FaultsHeader>() {
+            Some(header) => Arc::new(
+                header
+                    .0
+                    .parse::<Faults>()
+                    .map_err(|e| ServerError::new(e, None))?,
+            ),
+            None => Arc::clone(&ENV_FAULTS),
+        };
+        *self.faults.lock().expect("Can't lock faults") = faults;
+        next.run(ctx, request).await
+    }
+
+    async fn resolve(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        info: ResolveInfo<'_>,
+        next: NextResolve<'_>,
+    ) -> ServerResult<Option<Value>> {
+        let faults = Arc::clone(&self.faults.lock().expect("Can't lock faults"));
+        let mut broker_failure = false;
+        for fault in faults.get(info.parent_type, info.name) {
+            match fault {
+                Fault::Latency(latency) => tokio::time::sleep(*latency).await,
+                Fault::DbError => {
+                    return Err(ServerError::new(
+                        "Can't get DB connection: injected fault",
+                        None,
+                    ))
+                }
+                Fault::BrokerError => broker_failure = true,
+            }
+        }
+        BROKER_FAILURE
+            .scope(broker_failure, next.run(ctx, info))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_faults() {
+        let faults: Faults =
+            "Query.getPlanets=latency:250; Query.getPlanets=db_error;Mutation.createPlanet=broker_error"
+                .parse()
+                .expect("Can't parse faults");
+
+        assert_eq!(
+            [Fault::Latency(Duration::from_millis(250)), Fault::DbError],
+            faults.get("Query", "getPlanets")
+        );
+        assert_eq!([Fault::BrokerError], faults.get("Mutation", "createPlanet"));
+        assert!(faults.get("Planet", "details").is_empty());
+        assert!("".parse::<Faults>().expect("Can't parse faults").is_empty());
+    }
+
+    #[test]
+    fn invalid_faults() {
+        assert!("Query.getPlanets".parse::<Faults>().is_err());
+        assert!("Query.getPlanets=latency:soon".parse::<Faults>().is_err());
+        assert!("Query.getPlanets=meteor".parse::<Faults>().is_err());
+    }
+
+    #[tokio::test]
+    async fn broker_failure_is_scoped_to_resolver() {
+        assert!(!is_broker_failing());
+        assert!(
+            BROKER_FAILURE
+                .scope(true, async { is_broker_failing() })
+                .await
+        );
+    }
+}
@@ -0,0 +1,140 @@
+use bigdecimal::BigDecimal;
+
+use crate::persistence::model::{ClassificationRuleEntity, PlanetType};
+
+/// Returns the type of the first rule (in order of priority) the planet satisfies. Lower bounds
+/// of rules are inclusive, upper bounds are exclusive, and absent bounds are not checked
+pub fn classify(
+    rules: &[ClassificationRuleEntity],
+    mass: &BigDecimal,
+    mean_radius: &BigDecimal,
+) -> Option<PlanetType> {
+    let mut rules = rules.iter().collect::<Vec<_>>();
+    rules.sort_by_key(|rule| rule.priority);
+
+    rules
+        .into_iter()
+        .find(|rule| {
+            is_in_range(mass, &rule.min_mass, &rule.max_mass)
+                && is_in_range(mean_radius, &rule.min_mean_radius, &rule.max_mean_radius)
+        })
+        .map(|rule| rule.planet_type)
+}
+
+fn is_in_range(value: &BigDecimal, min: &Option<BigDecimal>, max: &Option<BigDecimal>) -> bool {
+    let below_min = matches!(min, Some(min) if value < min);
+    let above_max = matches!(max, Some(max) if value >= max);
+    !below_min && !above_max
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn rule(
+        planet_type: &str,
+        min_mass: Option<&str>,
+        max_mass: Option<&str>,
+        min_mean_radius: Option<&str>,
+        priority: i32,
+    ) -> ClassificationRuleEntity {
+        let parse = |value: Option<&str>| {
+            value.map(|value| BigDecimal::from_str(value).expect("Can't parse"))
+        };
+        ClassificationRuleEntity {
+            id: priority,
+            planet_type: PlanetType::from_str(planet_type).expect("Can't parse planet type"),
+            min_mass: parse(min_mass),
+            max_mass: parse(max_mass),
+            min_mean_radius: parse(min_mean_radius),
+            max_mean_radius: None,
+            priority,
+        }
+    }
+
+    fn classify_str(
+        rules: &[ClassificationRuleEntity],
+        mass: &str,
+        mean_radius: &str,
+    ) -> Option<String> {
+        classify(
+            rules,
+            &BigDecimal::from_str(mass).expect("Can't parse"),
+            &BigDecimal::from_str(mean_radius).expect("Can't parse"),
+        )
+        .map(|planet_type| planet_type.to_string())
+    }
+
+    #[test]
+    fn test_mass_boundaries() {
+        // deliberately not sorted by priority
+        let rules = vec![
+            rule("GAS_GIANT", Some("2e26"), None, None, 4),
+            rule("DWARF_PLANET", None, Some("1e23"), None, 1),
+            rule("TERRESTRIAL_PLANET", Some("1e23"), Some("1e25"), None, 2),
+            rule("ICE_GIANT", Some("1e25"), Some("2e26"), None, 3),
+        ];
+
+        assert_eq!(
+            Some("DWARF_PLANET".to_string()),
+            classify_str(&rules, "0", "1")
+        );
+        assert_eq!(
+            Some("DWARF_PLANET".to_string()),
+            classify_str(&rules, "99999999999999999999999", "1")
+        );
+        assert_eq!(
+            Some("TERRESTRIAL_PLANET".to_string()),
+            classify_str(&rules, "1e23", "1")
+        );
+        assert_eq!(
+            Some("TERRESTRIAL_PLANET".to_string()),
+            classify_str(&rules, "9999999999999999999999999", "1")
+        );
+        assert_eq!(
+            Some("ICE_GIANT".to_string()),
+            classify_str(&rules, "1e25", "1")
+        );
+        assert_eq!(
+            Some("ICE_GIANT".to_string()),
+            classify_str(&rules, "199999999999999999999999999", "1")
+        );
+        assert_eq!(
+            Some("GAS_GIANT".to_string()),
+            classify_str(&rules, "2e26", "1")
+        );
+        assert_eq!(
+            Some("GAS_GIANT".to_string()),
+            classify_str(&rules, "1e30", "1")
+        );
+    }
+
+    #[test]
+    fn test_priority_and_radius() {
+        let rules = vec![
+            rule("TERRESTRIAL_PLANET", None, None, None, 2),
+            rule("DWARF_PLANET", None, None, Some("0"), 3),
+            rule("GAS_GIANT", None, None, Some("20000"), 1),
+        ];
+
+        assert_eq!(
+            Some("GAS_GIANT".to_string()),
+            classify_str(&rules, "1", "20000")
+        );
+        assert_eq!(
+            Some("TERRESTRIAL_PLANET".to_string()),
+            classify_str(&rules, "1", "19999.9")
+        );
+        assert_eq!(None, classify_str(&[], "1", "1"));
+        assert_eq!(
+            None,
+            classify_str(
+                &[rule("DWARF_PLANET", None, Some("1e23"), None, 1)],
+                "1e23",
+                "1"
+            )
+        );
+    }
+}
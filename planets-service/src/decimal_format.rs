@@ -0,0 +1,152 @@
+use std::env;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use async_graphql::extensions::{
+    Extension, ExtensionContext, ExtensionFactory, NextResolve, ResolveInfo,
+};
+use async_graphql::{Number, ServerResult, Value};
+use bigdecimal::{BigDecimal, ToPrimitive};
+use lazy_static::lazy_static;
+use strum_macros::{Display, EnumString};
+
+/// Format of a request replacing the one from `BIG_DECIMAL_FORMAT`
+pub const FORMAT_HEADER: &str = "big-decimal-format";
+
+lazy_static! {
+    static ref DEFAULT_FORMAT: BigDecimalFormat = env::var("BIG_DECIMAL_FORMAT")
+        .map(|format| BigDecimalFormat::from_str(&format).expect("Can't parse BigDecimal format"))
+        .unwrap_or(BigDecimalFormat::String);
+}
+
+// JSON numbers are read as doubles by most clients, so larger integers lose precision
+const MAX_SAFE_INTEGER: i64 = (1 << f64::MANTISSA_DIGITS) - 1;
+
+/// How values of the `BigDecimal` scalar are represented in responses
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Display, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum BigDecimalFormat {
+    String,
+    /// Numbers if they can be read as doubles without precision loss, strings otherwise
+    Number,
+}
+
+impl BigDecimalFormat {
+    /// The format requested by a transport or the default one
+    pub fn get(ctx: &ExtensionContext<'_>) -> BigDecimalFormat {
+        ctx.data_opt::<BigDecimalFormat>()
+            .copied()
+            .unwrap_or(*DEFAULT_FORMAT)
+    }
+}
+
+/// Represents `BigDecimal` values in the requested [BigDecimalFormat]; the scalar itself always
+/// produces strings, since it doesn't know about the request
+pub struct DecimalFormatter;
+
+impl ExtensionFactory for DecimalFormatter {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(DecimalFormatterExtension)
+    }
+}
+
+struct DecimalFormatterExtension;
+
+#[async_trait::async_trait]
+impl Extension for DecimalFormatterExtension {
+    async fn resolve(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        info: ResolveInfo<'_>,
+        next: NextResolve<'_>,
+    ) -> ServerResult<Option<Value>> {
+        let is_big_decimal = info
+            .return_type
+            .trim_matches(|c| c == '[' || c == ']' || c == '!')
+            == "BigDecimal";
+        let value = next.run(ctx, info).await?;
+        if !is_big_decimal || BigDecimalFormat::get(ctx) == BigDecimalFormat::String {
+            return Ok(value);
+        }
+        Ok(value.map(to_number))
+    }
+}
+
+fn to_number(value: Value) -> Value {
+    match value {
+        Value::String(s) => match to_lossless_number(&s) {
+            Some(number) => Value::Number(number),
+            None => Value::String(s),
+        },
+        Value::List(values) => Value::List(values.into_iter().map(to_number).collect()),
+        value => value,
+    }
+}
+
+/// `None` if the decimal can't be read back from a double exactly
+fn to_lossless_number(s: &str) -> Option<Number> {
+    let decimal = BigDecimal::from_str(s).ok()?;
+    if decimal.is_integer() {
+        if let Some(integer) = decimal.to_i64() {
+            return (integer.abs() <= MAX_SAFE_INTEGER).then(|| Number::from(integer));
+        }
+    }
+
+    // correctly rounded, unlike the conversion of BigDecimal
+    let double = s.parse::<f64>().ok()?;
+    // the shortest representation which is read back as the same double
+    let read_back = BigDecimal::from_str(&double.to_string()).ok()?;
+    if read_back == decimal {
+        Number::from_f64(double)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(s: &str) -> Value {
+        to_number(Value::String(s.to_string()))
+    }
+
+    #[test]
+    fn exact_values_become_numbers() {
+        assert_eq!(Value::from(6371), format("6371.0"));
+        assert_eq!(Value::from(2439.7), format("2439.7"));
+        assert_eq!(Value::from(0.1), format("0.1"));
+        assert_eq!(Value::from(-7.5), format("-7.5"));
+        assert_eq!(Value::from(MAX_SAFE_INTEGER), format("9007199254740991"));
+        assert_eq!(Value::from(1e300), format("1e300"));
+    }
+
+    #[test]
+    fn inexact_values_stay_strings() {
+        // 2^53 + 1
+        assert_eq!(Value::from("9007199254740993"), format("9007199254740993"));
+        assert_eq!(
+            Value::from("0.12345678901234567890"),
+            format("0.12345678901234567890")
+        );
+        assert_eq!(Value::from("1e400"), format("1e400"));
+        assert_eq!(Value::from("1e-400"), format("1e-400"));
+    }
+
+    #[test]
+    fn lists_and_nulls() {
+        assert_eq!(
+            Value::List(vec![Value::from(1), Value::Null]),
+            to_number(Value::List(vec![Value::from("1"), Value::Null]))
+        );
+    }
+
+    #[test]
+    fn parse_format() {
+        assert_eq!(
+            BigDecimalFormat::Number,
+            BigDecimalFormat::from_str("number").expect("Can't parse format")
+        );
+        assert!(BigDecimalFormat::from_str("float").is_err());
+    }
+}
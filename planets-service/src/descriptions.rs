@@ -0,0 +1,300 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use async_graphql::extensions::{
+    Extension, ExtensionContext, ExtensionFactory, NextExecute, NextPrepareRequest,
+};
+use async_graphql::{Request, Response, ServerResult, Value};
+use common_utils::descriptions::{self, Descriptions, Translations};
+use heck::ToLowerCamelCase;
+
+use crate::persistence::model::ColumnCommentEntity;
+use crate::renames::RENAMED_FIELDS;
+use crate::schema_coordinates::Coordinates;
+
+/// GraphQL types whose fields are backed by columns of a table
+const TABLE_TYPES: &[(&str, &[&str])] = &[
+    ("planets", &["Planet", "PlanetInput"]),
+    ("stars", &["Star", "StarInput"]),
+    (
+        "details",
+        &[
+            "Details",
+            "InhabitedPlanetDetails",
+            "UninhabitedPlanetDetails",
+            "DetailsInput",
+        ],
+    ),
+    (
+        "atmosphere_components",
+        &["GasComponent", "GasComponentInput"],
+    ),
+];
+
+/// Columns stored in other units than the fields backed by them expose, so their comments
+/// don't describe the fields
+const CONVERTED_COLUMNS: &[(&str, &str)] = &[("details", "mean_radius")];
+
+/// Field descriptions keyed by type and field names
+pub type FieldDescriptions = HashMap<(String, String), String>;
+
+/// Value of the `Accept-Language` header
+pub struct AcceptLanguage(pub String);
+
+/// Reads translations from `SCHEMA_TRANSLATIONS_FILE` (path to a JSON file), e.g.
+/// `{"de": {"Planet": "Ein Planet", "Planet.name": "Name des Planeten"}}`; the keys are
+/// validated against the schema's coordinates
+pub fn load_translations(coordinates: &Coordinates) -> Translations {
+    let Ok(path) = env::var("SCHEMA_TRANSLATIONS_FILE") else {
+        return Translations::new();
+    };
+    let json = fs::read_to_string(path).expect("Can't read schema translations file");
+    let translations =
+        descriptions::parse_translations(&json).expect("Can't parse schema translations");
+    check_translations(&translations, coordinates).expect("Invalid schema translations");
+    translations
+}
+
+fn check_translations(
+    translations: &Translations,
+    coordinates: &Coordinates,
+) -> Result<(), String> {
+    for (locale, descriptions) in translations {
+        if let Some(coordinate) = descriptions
+            .keys()
+            .find(|coordinate| !coordinates.contains_key(*coordinate))
+        {
+            return Err(format!(
+                "Unknown schema coordinate {} in translations of {}",
+                coordinate, locale
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Maps comments of the DB columns to the fields backed by them (a column `mean_radius`
+/// backs the field `meanRadius`); a comment also applies to the new name of a renamed field
+pub fn from_column_comments(comments: &[ColumnCommentEntity]) -> FieldDescriptions {
+    let mut descriptions: FieldDescriptions = comments
+        .iter()
+        .filter(|comment| {
+            !CONVERTED_COLUMNS
+                .contains(&(comment.table_name.as_str(), comment.column_name.as_str()))
+        })
+        .flat_map(|comment| {
+            let type_names = TABLE_TYPES
+                .iter()
+                .find(|(table, _)| *table == comment.table_name)
+                .map(|(_, type_names)| *type_names)
+                .unwrap_or_default();
+            let field_name = comment.column_name.to_lower_camel_case();
+            type_names.iter().map(move |type_name| {
+                (
+                    (type_name.to_string(), field_name.clone()),
+                    comment.comment.clone(),
+                )
+            })
+        })
+        .collect();
+    for field in RENAMED_FIELDS.iter() {
+        let key = (field.type_name.to_string(), field.old_name.to_string());
+        if let Some(description) = descriptions.get(&key).cloned() {
+            descriptions.insert(
+                (field.type_name.to_string(), field.new_name.to_string()),
+                description,
+            );
+        }
+    }
+    descriptions
+}
+
+/// Replaces descriptions in introspection responses with the ones maintained in the database
+/// and, if the client's `Accept-Language` matches a translated locale, with the translated
+/// ones. Async-graphql's registry is immutable once the schema is built, so results of
+/// `__schema` and `__type` queries are rewritten instead (only if `name` and `description`
+/// are requested without aliases)
+pub struct DescriptionEnricher {
+    /// Default descriptions
+    descriptions: Arc<Descriptions>,
+    /// The default descriptions overridden by the translated ones, per locale
+    variants: Arc<HashMap<String, Arc<Descriptions>>>,
+}
+
+impl DescriptionEnricher {
+    pub fn new(field_descriptions: FieldDescriptions, translations: Translations) -> Self {
+        let descriptions: Descriptions = field_descriptions
+            .into_iter()
+            .map(|((type_name, field_name), description)| {
+                (format!("{}.{}", type_name, field_name), description)
+            })
+            .collect();
+        let variants = translations
+            .into_iter()
+            .map(|(locale, translated)| {
+                let mut variant = descriptions.clone();
+                variant.extend(translated);
+                (locale, Arc::new(variant))
+            })
+            .collect();
+        Self {
+            descriptions: Arc::new(descriptions),
+            variants: Arc::new(variants),
+        }
+    }
+}
+
+impl ExtensionFactory for DescriptionEnricher {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(DescriptionEnricherExtension {
+            descriptions: Arc::clone(&self.descriptions),
+            variants: Arc::clone(&self.variants),
+            introspection: AtomicBool::new(false),
+        })
+    }
+}
+
+struct DescriptionEnricherExtension {
+    descriptions: Arc<Descriptions>,
+    variants: Arc<HashMap<String, Arc<Descriptions>>>,
+    introspection: AtomicBool,
+}
+
+#[async_trait::async_trait]
+impl Extension for DescriptionEnricherExtension {
+    async fn prepare_request(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        request: Request,
+        next: NextPrepareRequest<'_>,
+    ) -> ServerResult<Request> {
+        self.introspection.store(
+            descriptions::is_introspection(&request.query),
+            Ordering::Relaxed,
+        );
+        next.run(ctx, request).await
+    }
+
+    async fn execute(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        operation_name: Option<&str>,
+        next: NextExecute<'_>,
+    ) -> Response {
+        let mut response = next.run(ctx, operation_name).await;
+        if !self.introspection.load(Ordering::Relaxed) {
+            return response;
+        }
+        let descriptions = ctx
+            .data_opt::<AcceptLanguage>()
+            .and_then(|accept_language| {
+                descriptions::negotiate_locale(
+                    &accept_language.0,
+                    self.variants.keys().map(String::as_str),
+                )
+            })
+            .and_then(|locale| self.variants.get(locale))
+            .unwrap_or(&self.descriptions);
+        if !descriptions.is_empty() {
+            let mut data = response
+                .data
+                .into_json()
+                .expect("Can't convert response data");
+            descriptions::enrich(&mut data, descriptions);
+            response.data = Value::from_json(data).expect("Can't convert response data");
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema_coordinates;
+
+    #[test]
+    fn test_descriptions_from_column_comments() {
+        let comments = vec![
+            ColumnCommentEntity {
+                table_name: "details".to_string(),
+                column_name: "mean_radius".to_string(),
+                comment: "Mean radius in meters".to_string(),
+            },
+            ColumnCommentEntity {
+                table_name: "details".to_string(),
+                column_name: "mass".to_string(),
+                comment: "Mass in kilograms".to_string(),
+            },
+        ];
+        let enricher =
+            DescriptionEnricher::new(from_column_comments(&comments), Translations::new());
+
+        assert_eq!(
+            Some("Mass in kilograms"),
+            enricher
+                .descriptions
+                .get("UninhabitedPlanetDetails.mass")
+                .map(String::as_str)
+        );
+        // the radius is stored in other units than it's exposed in
+        assert!(!enricher
+            .descriptions
+            .contains_key("UninhabitedPlanetDetails.meanRadius"));
+    }
+
+    #[test]
+    fn translations_override_default_descriptions() {
+        let comments = vec![ColumnCommentEntity {
+            table_name: "planets".to_string(),
+            column_name: "name".to_string(),
+            comment: "Name of the planet".to_string(),
+        }];
+        let translations = HashMap::from([(
+            "de".to_string(),
+            HashMap::from([
+                ("Planet".to_string(), "Ein Planet".to_string()),
+                (
+                    "PlanetInput.name".to_string(),
+                    "Name des Planeten".to_string(),
+                ),
+            ]),
+        )]);
+        let enricher = DescriptionEnricher::new(from_column_comments(&comments), translations);
+
+        let german = &enricher.variants["de"];
+        assert_eq!("Ein Planet", german["Planet"]);
+        assert_eq!("Name of the planet", german["Planet.name"]);
+        assert_eq!("Name des Planeten", german["PlanetInput.name"]);
+        assert!(!enricher.descriptions.contains_key("Planet"));
+    }
+
+    #[test]
+    fn translations_are_checked_against_schema() {
+        let coordinates = schema_coordinates::from_sdl(
+            "
+            type Planet { name: String! }
+            type Query { getPlanet(id: ID!): Planet }
+            ",
+        );
+        let translations = |coordinate: &str| {
+            HashMap::from([(
+                "de".to_string(),
+                HashMap::from([(coordinate.to_string(), "Übersetzung".to_string())]),
+            )])
+        };
+
+        for coordinate in ["Planet", "Planet.name", "Query.getPlanet(id:)"] {
+            assert_eq!(
+                Ok(()),
+                check_translations(&translations(coordinate), &coordinates)
+            );
+        }
+        assert_eq!(
+            Err("Unknown schema coordinate Planet.mass in translations of de".to_string()),
+            check_translations(&translations("Planet.mass"), &coordinates)
+        );
+    }
+}
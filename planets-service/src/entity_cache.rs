@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+use std::env;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+
+use crate::metrics;
+
+lazy_static! {
+    static ref TTL: Duration = Duration::from_millis(
+        env::var("ENTITY_CACHE_TTL_MILLIS")
+            .map(|value| value.parse().expect("Can't parse entity cache TTL"))
+            .unwrap_or(500)
+    );
+    static ref CAPACITY: usize = env::var("ENTITY_CACHE_CAPACITY")
+        .map(|value| value.parse().expect("Can't parse entity cache capacity"))
+        .unwrap_or(10000);
+}
+
+/// Keeps resolved entity representations for a short time, so that the gateway fanning out
+/// `_entities` requests for the same keys within milliseconds doesn't hit the database each time.
+/// Entries are keyed by the key and its version: invalidating a key bumps the version, so that
+/// a value loaded before the invalidation is never stored after it. Only mutations of this
+/// instance invalidate entries, changes made elsewhere are seen once the TTL
+/// (`ENTITY_CACHE_TTL_MILLIS`) passes; set it or `ENTITY_CACHE_CAPACITY` to 0 to disable caching
+pub struct EntityCache<K, V> {
+    ttl: Duration,
+    capacity: usize,
+    state: Arc<Mutex<CacheState<K, V>>>,
+}
+
+struct CacheState<K, V> {
+    /// Versions of the keys invalidated at least once; absent keys have version 0
+    versions: HashMap<K, u64>,
+    entries: HashMap<K, Entry<V>>,
+}
+
+struct Entry<V> {
+    value: V,
+    version: u64,
+    expires_at: Instant,
+}
+
+impl<K, V> Clone for EntityCache<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            ttl: self.ttl,
+            capacity: self.capacity,
+            state: Arc::clone(&self.state),
+        }
+    }
+}
+
+impl<K: Copy + Eq + Hash, V: Clone> EntityCache<K, V> {
+    pub fn from_env() -> Self {
+        Self::new(*TTL, *CAPACITY)
+    }
+
+    fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            ttl,
+            capacity,
+            state: Arc::new(Mutex::new(CacheState {
+                versions: HashMap::new(),
+                entries: HashMap::new(),
+            })),
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        !self.ttl.is_zero() && self.capacity > 0
+    }
+
+    fn lock_state(&self) -> MutexGuard<'_, CacheState<K, V>> {
+        self.state.lock().expect("Can't lock entity cache")
+    }
+
+    pub fn get(&self, key: K, now: Instant) -> Option<V> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let state = self.lock_state();
+        let version = state.versions.get(&key).copied().unwrap_or_default();
+        let value = state
+            .entries
+            .get(&key)
+            .filter(|entry| entry.version == version && now < entry.expires_at)
+            .map(|entry| entry.value.clone());
+        let result = if value.is_some() { "hit" } else { "miss" };
+        metrics::CACHE_REQUESTS.increment(&["entity", result], 1);
+        value
+    }
+
+    /// To be read before loading a value, which is then stored with it
+    pub fn version(&self, key: K) -> u64 {
+        self.lock_state()
+            .versions
+            .get(&key)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Ignored if the key was invalidated since its version was read
+    pub fn put(&self, key: K, version: u64, value: V, now: Instant) {
+        if !self.is_enabled() {
+            return;
+        }
+        let mut state = self.lock_state();
+        if state.versions.get(&key).copied().unwrap_or_default() != version {
+            return;
+        }
+        if state.entries.len() >= self.capacity && !state.entries.contains_key(&key) {
+            state.entries.retain(|_, entry| now < entry.expires_at);
+            if state.entries.len() >= self.capacity {
+                return;
+            }
+        }
+        state.entries.insert(
+            key,
+            Entry {
+                value,
+                version,
+                expires_at: now + self.ttl,
+            },
+        );
+        metrics::CACHE_ENTRIES.set(&["entity"], state.entries.len() as f64);
+    }
+
+    pub fn invalidate(&self, keys: &[K]) {
+        let mut state = self.lock_state();
+        for key in keys {
+            *state.versions.entry(*key).or_default() += 1;
+            state.entries.remove(key);
+        }
+        metrics::CACHE_ENTRIES.set(&["entity"], state.entries.len() as f64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TTL: Duration = Duration::from_millis(100);
+
+    #[test]
+    fn entries_expire() {
+        let cache = EntityCache::new(TTL, 10);
+        let now = Instant::now();
+        cache.put(1, cache.version(1), "Mercury", now);
+        assert_eq!(
+            Some("Mercury"),
+            cache.get(1, now + TTL - Duration::from_millis(1))
+        );
+        assert_eq!(None, cache.get(1, now + TTL));
+    }
+
+    #[test]
+    fn invalidation_discards_values_loaded_before_it() {
+        let cache = EntityCache::new(TTL, 10);
+        let now = Instant::now();
+        cache.put(1, cache.version(1), "Mercury", now);
+        cache.invalidate(&[1]);
+        assert_eq!(None, cache.get(1, now));
+
+        // loaded concurrently with a mutation
+        let version = cache.version(1);
+        cache.invalidate(&[1]);
+        cache.put(1, version, "Stale Mercury", now);
+        assert_eq!(None, cache.get(1, now));
+
+        cache.put(1, cache.version(1), "Renamed Mercury", now);
+        assert_eq!(Some("Renamed Mercury"), cache.get(1, now));
+    }
+
+    #[test]
+    fn full_cache_drops_expired_entries() {
+        let cache = EntityCache::new(TTL, 2);
+        let now = Instant::now();
+        cache.put(1, 0, "Mercury", now);
+        cache.put(2, 0, "Venus", now + TTL / 2);
+        cache.put(3, 0, "Earth", now + TTL / 2);
+        assert_eq!(None, cache.get(3, now + TTL / 2));
+
+        cache.put(3, 0, "Earth", now + TTL);
+        assert_eq!(Some("Earth"), cache.get(3, now + TTL));
+        assert_eq!(Some("Venus"), cache.get(2, now + TTL));
+    }
+
+    #[test]
+    fn disabled_with_zero_ttl() {
+        let cache = EntityCache::new(Duration::ZERO, 10);
+        let now = Instant::now();
+        cache.put(1, 0, "Mercury", now);
+        assert_eq!(None, cache.get(1, now));
+    }
+}
@@ -0,0 +1,102 @@
+use std::collections::HashSet;
+
+use common_utils::Role;
+use futures::stream::{self, BoxStream};
+use futures::{future, StreamExt};
+
+use crate::broker::{Broker, Lagged};
+use crate::graphql::{PlanetEvent, PlanetEventKind};
+
+const USER_EVENT_KINDS: &[PlanetEventKind] = &[
+    PlanetEventKind::Created,
+    PlanetEventKind::Updated,
+    PlanetEventKind::Published,
+    PlanetEventKind::Archived,
+];
+const PUBLIC_EVENT_KINDS: &[PlanetEventKind] = &[
+    PlanetEventKind::Created,
+    PlanetEventKind::Published,
+    PlanetEventKind::Archived,
+];
+
+/// Whether a subscriber with the role gets an event of the kind about a planet which is
+/// published (or not) after the change. Admins get all events; others get only events about
+/// published planets and archivings, which withdraw published planets, so that they can drop
+/// them. A subscriber without a role (or with an invalid one) is public
+pub fn is_event_allowed(role: Option<&Role>, kind: PlanetEventKind, is_published: bool) -> bool {
+    let is_visible = is_published || kind == PlanetEventKind::Archived;
+    match role {
+        Some(Role::Admin) => true,
+        Some(Role::User) => is_visible && USER_EVENT_KINDS.contains(&kind),
+        None => is_visible && PUBLIC_EVENT_KINDS.contains(&kind),
+    }
+}
+
+/// Events which `replay` reads (from the outbox) followed by the live ones of the broker, only
+/// those the subscriber with the role is allowed to get. The policy is applied here, where broker
+/// messages become a subscription's stream, so that every subscription of planet events and every
+/// transport delivers the same events
+pub fn subscribe<E>(
+    broker: &dyn Broker<PlanetEvent>,
+    role: Option<Role>,
+    replay: impl FnOnce() -> Result<Vec<PlanetEvent>, E>,
+) -> Result<BoxStream<'static, Result<PlanetEvent, Lagged>>, E> {
+    // subscribe before reading the outbox, so that events created in between are not lost
+    let live_events = broker.subscribe();
+    let replayed_events = replay()?;
+    // tokens are assigned on insert, not on commit, so an event committed after the replay can
+    // have a lower token than the replayed ones; only the replayed events are skipped when live
+    let replayed_tokens: HashSet<i64> = replayed_events.iter().map(|event| event.token).collect();
+
+    let is_allowed = move |event: &PlanetEvent| {
+        is_event_allowed(role.as_ref(), event.kind, event.is_planet_published())
+    };
+    let replayed_events: Vec<_> = replayed_events
+        .into_iter()
+        .filter(is_allowed)
+        .map(Ok)
+        .collect();
+    let live_events = live_events.filter(move |event| {
+        future::ready(match event {
+            Ok(event) => !replayed_tokens.contains(&event.token) && is_allowed(event),
+            Err(Lagged(_)) => true,
+        })
+    });
+    Ok(stream::iter(replayed_events).chain(live_events).boxed())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn receivers(kind: PlanetEventKind, is_published: bool) -> usize {
+        [Some(&Role::Admin), Some(&Role::User), None]
+            .into_iter()
+            .filter(|role| is_event_allowed(*role, kind, is_published))
+            .count()
+    }
+
+    #[test]
+    fn only_admins_get_deleted_events() {
+        assert_eq!(3, receivers(PlanetEventKind::Created, true));
+        assert_eq!(2, receivers(PlanetEventKind::Updated, true));
+        assert_eq!(1, receivers(PlanetEventKind::Deleted, true));
+        assert!(is_event_allowed(
+            Some(&Role::Admin),
+            PlanetEventKind::Deleted,
+            true
+        ));
+    }
+
+    #[test]
+    fn only_admins_get_events_about_unpublished_planets() {
+        assert_eq!(1, receivers(PlanetEventKind::Created, false));
+        assert_eq!(1, receivers(PlanetEventKind::Updated, false));
+        assert_eq!(3, receivers(PlanetEventKind::Published, true));
+    }
+
+    #[test]
+    fn everyone_gets_archived_events() {
+        assert_eq!(3, receivers(PlanetEventKind::Archived, false));
+    }
+}
@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use async_graphql::{Context, Guard, Result};
+use serde::Deserialize;
+use strum_macros::{Display, EnumIter, EnumString};
+
+use common_utils::{CustomError, Role};
+
+/// Experimental functionality that can be switched on without a redeploy
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Display, EnumString, EnumIter)]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+pub enum Feature {
+    HabitabilityScore,
+}
+
+#[derive(Clone, Default, Deserialize)]
+pub struct FeatureFlag {
+    /// Enabled for everyone
+    #[serde(default)]
+    pub enabled: bool,
+    /// Enabled only for these roles if the flag isn't enabled for everyone
+    #[serde(default)]
+    pub roles: Vec<String>,
+}
+
+impl FeatureFlag {
+    fn is_enabled_for(&self, role: Option<&Role>) -> bool {
+        self.enabled
+            || role.is_some_and(|role| {
+                self.roles
+                    .iter()
+                    .any(|allowed| *allowed == role.to_string())
+            })
+    }
+}
+
+/// Source of feature flags; the config-based one can be replaced with a remote flag service
+pub trait FeatureFlagProvider: Send + Sync {
+    fn get_flag(&self, feature: Feature) -> Option<FeatureFlag>;
+}
+
+/// Flags read once at startup from `FEATURE_FLAGS_FILE` (path to a JSON file) or from
+/// `FEATURE_FLAGS` (inline JSON), e.g. `{"HABITABILITY_SCORE": {"roles": ["ADMIN"]}}`
+pub struct ConfigFeatureFlags {
+    flags: HashMap<Feature, FeatureFlag>,
+}
+
+impl ConfigFeatureFlags {
+    pub fn from_env() -> Self {
+        let json = if let Ok(path) = env::var("FEATURE_FLAGS_FILE") {
+            fs::read_to_string(path).expect("Can't read feature flags file")
+        } else if let Ok(json) = env::var("FEATURE_FLAGS") {
+            json
+        } else {
+            return Self::new(HashMap::new());
+        };
+        Self::from_json(&json)
+    }
+
+    pub fn from_json(json: &str) -> Self {
+        let flags = serde_json::from_str::<HashMap<String, FeatureFlag>>(json)
+            .expect("Can't parse feature flags")
+            .into_iter()
+            .map(|(name, flag)| {
+                let feature = Feature::from_str(&name).expect("Unknown feature");
+                (feature, flag)
+            })
+            .collect();
+        Self::new(flags)
+    }
+
+    pub fn new(flags: HashMap<Feature, FeatureFlag>) -> Self {
+        Self { flags }
+    }
+}
+
+impl FeatureFlagProvider for ConfigFeatureFlags {
+    fn get_flag(&self, feature: Feature) -> Option<FeatureFlag> {
+        self.flags.get(&feature).cloned()
+    }
+}
+
+pub fn is_enabled(ctx: &Context<'_>, feature: Feature) -> bool {
+    let role = match ctx.data_opt::<Result<Option<Role>, CustomError>>() {
+        Some(Ok(maybe_role)) => maybe_role.as_ref(),
+        _ => None,
+    };
+    ctx.data::<Arc<dyn FeatureFlagProvider>>()
+        .expect("Can't get feature flags")
+        .get_flag(feature)
+        .is_some_and(|flag| flag.is_enabled_for(role))
+}
+
+/// Hides a field behind a feature flag: the field is rejected when the feature is disabled
+/// for the current principal
+pub struct FeatureGuard {
+    feature: Feature,
+}
+
+impl FeatureGuard {
+    pub fn new(feature: Feature) -> Self {
+        Self { feature }
+    }
+}
+
+#[async_trait::async_trait]
+impl Guard for FeatureGuard {
+    async fn check(&self, ctx: &Context<'_>) -> Result<()> {
+        if is_enabled(ctx, self.feature) {
+            Ok(())
+        } else {
+            Err(format!("Feature {} is not enabled", self.feature).into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flag_targeting() {
+        let flags =
+            ConfigFeatureFlags::from_json(r#"{"HABITABILITY_SCORE": {"roles": ["ADMIN"]}}"#);
+        let flag = flags
+            .get_flag(Feature::HabitabilityScore)
+            .expect("Can't get flag");
+
+        assert!(flag.is_enabled_for(Some(&Role::Admin)));
+        assert!(!flag.is_enabled_for(Some(&Role::User)));
+        assert!(!flag.is_enabled_for(None));
+
+        let flags = ConfigFeatureFlags::from_json(r#"{"HABITABILITY_SCORE": {"enabled": true}}"#);
+        assert!(flags
+            .get_flag(Feature::HabitabilityScore)
+            .expect("Can't get flag")
+            .is_enabled_for(None));
+
+        let flags = ConfigFeatureFlags::from_json("{}");
+        assert!(flags.get_flag(Feature::HabitabilityScore).is_none());
+    }
+}
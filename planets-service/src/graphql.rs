@@ -3,89 +3,955 @@ use std::env;
 use std::fmt::{self, Formatter, LowerExp};
 use std::iter::Iterator;
 use std::str::FromStr;
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
+#[cfg(feature = "kafka")]
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
-use async_graphql::dataloader::{DataLoader, Loader};
+use async_graphql::dataloader::{DataLoader, HashMapCache, Loader};
+use async_graphql::parser::types::{ExecutableDocument, OperationType};
 use async_graphql::*;
 use bigdecimal::{BigDecimal, ToPrimitive};
-use futures::{Stream, StreamExt};
+use chrono::{DateTime, Utc};
+use diesel::result::Error::NotFound;
+use diesel::{OptionalExtension, PgConnection, QueryResult};
+use futures::{future, Stream, StreamExt};
+use lazy_static::lazy_static;
+#[cfg(feature = "kafka")]
 use rdkafka::{producer::FutureProducer, Message};
 use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
 use strum_macros::{Display, EnumString};
+use uuid::Uuid;
 
+use common_utils::ids;
 use common_utils::{CustomError, Role, FORBIDDEN_MESSAGE};
 
+use crate::batch_trace::TracedDataLoader;
+use crate::broker::{self, Broker, Lagged};
+use crate::build_info::BUILD_INFO;
+use crate::entity_cache::EntityCache;
+use crate::event_policy;
+use crate::feature_flags::{self, Feature, FeatureFlagProvider, FeatureGuard};
 use crate::get_conn_from_ctx;
+#[cfg(feature = "kafka")]
 use crate::kafka;
-use crate::persistence::connection::PgPool;
-use crate::persistence::model::{DetailsEntity, NewDetailsEntity, NewPlanetEntity, PlanetEntity};
-use crate::persistence::repository;
+use crate::mapping;
+use crate::memory;
+use crate::operation_variables;
+use crate::orbits::{
+    self, OrbitalElements, ASTRONOMICAL_UNIT_KILOMETERS, SPEED_OF_LIGHT_KILOMETERS_PER_SECOND,
+};
+use crate::persistence::connection::ReloadablePool;
+use crate::persistence::model::{
+    self, ClassificationRuleEntity, DeliveryStatus, EventKind, GasComponentEntity, HistoryRange,
+    NewClassificationRuleEntity, NewDetailsEntity, NewReportEntity, NewStarEntity,
+    NewWebhookEntity, OutboxEventEntity, PlanetEntity, PlanetWrite, PlanetsFilter, PlanetsOrder,
+    PlanetsOrderColumn, ReportEntity, StarEntity, WebhookDeliveryEntity, WebhookEntity,
+};
+use crate::persistence::repository::{self, PLANETS_TOPIC};
+use crate::persistence::units;
+use crate::redirects::ResolvedRedirects;
+use crate::renames;
+use crate::response_cache::ResponseCache;
+use crate::snapshot;
+use crate::validation::ValidateOnly;
 
 pub type AppSchema = Schema<Query, Mutation, Subscription>;
 
+/// The user authenticated by the gateway, passed by a transport
+pub struct CurrentUser(pub String);
+
+/// `User-Agent` of the client, passed by a transport
+pub struct UserAgent(pub String);
+
+/// The schema itself passed with a request by a transport, so that resolvers can validate and run
+/// saved operations. Operations run this way don't get it, so reports can't run each other
+pub struct OperationRunner(pub AppSchema);
+
+lazy_static! {
+    static ref ID_FORMAT: IdFormat = env::var("ID_FORMAT")
+        .map(|format| IdFormat::from_str(&format).expect("Can't parse ID format"))
+        .unwrap_or(IdFormat::Integer);
+}
+
+const DELETE_BATCH_SIZE: i64 = 100;
+const MAX_SAMPLE_SIZE: i32 = 100;
+const MAX_PLANETS_BY_IDS: usize = 100;
+const UNCLASSIFIED_PLANET_MESSAGE: &str =
+    "Can't determine the type of the planet, specify it explicitly";
+
+const EARTH_MEAN_RADIUS: f64 = 6371.0;
+const EARTH_MASS: f64 = 5.972e24;
+
 pub struct Query;
 
 #[Object]
 impl Query {
-    async fn get_planets(&self, ctx: &Context<'_>) -> Vec<Planet> {
-        repository::get_all(&mut get_conn_from_ctx(ctx))
-            .expect("Can't get planets")
+    /// Published planets unless another status is specified, which is allowed only to admins.
+    /// Planets are ordered by ID unless another order is specified. With `asOf` planets and their
+    /// details are as they were at the moment (the status too), while other data is current
+    async fn get_planets(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] status: PlanetStatus,
+        #[graphql(default)] order_by: PlanetOrder,
+        as_of: Option<DateTime<Utc>>,
+    ) -> Result<Vec<Planet>> {
+        if status != PlanetStatus::Published && !is_admin(ctx).await {
+            return Err(FORBIDDEN_MESSAGE.into());
+        }
+        let conn = &mut get_conn_from_ctx(ctx);
+        let planets = match as_of {
+            Some(as_of) => {
+                check_as_of(as_of)?;
+                repository::get_all_as_of(status.into(), &order_by.into(), as_of, conn)?
+                    .iter()
+                    .map(|planet| Planet::from(planet).at(as_of))
+                    .collect()
+            }
+            None => repository::get_all(status.into(), &order_by.into(), conn)?
+                .iter()
+                .map(Planet::from)
+                .collect(),
+        };
+        Ok(planets)
+    }
+
+    /// With `asOf` the planet is as it was at the moment, see `getPlanets`
+    async fn get_planet(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        as_of: Option<DateTime<Utc>>,
+    ) -> Result<Option<Planet>> {
+        match as_of {
+            Some(as_of) => {
+                check_as_of(as_of)?;
+                find_planet_as_of(ctx, &id, as_of).await
+            }
+            None => find_planet_by_id_internal(ctx, id).await,
+        }
+    }
+
+    /// Planets in the order of the IDs, with nulls for the ones which are not found; resolves
+    /// many references with one request instead of a `getPlanet` per ID
+    async fn planets_by_ids(&self, ctx: &Context<'_>, ids: Vec<ID>) -> Result<Vec<Option<Planet>>> {
+        if ids.len() > MAX_PLANETS_BY_IDS {
+            return Err(format!("At most {} IDs can be requested", MAX_PLANETS_BY_IDS).into());
+        }
+        let keys: Vec<Option<PlanetKey>> = ids.iter().map(parse_planet_key).collect();
+
+        let planets = ctx
+            .data::<PlanetIdentityMap>()
+            .expect("Can't get planet identity map")
+            .load_many(keys.iter().flatten().copied())
+            .await?;
+
+        let is_admin = is_admin(ctx).await;
+        Ok(keys
+            .into_iter()
+            .map(|key| {
+                let planet = planets.get(&key?)?;
+                (planet.status == PlanetStatus::Published || is_admin).then(|| planet.clone())
+            })
+            .collect())
+    }
+
+    /// A random planet; the same seed gives the same planet
+    async fn random_planet(&self, ctx: &Context<'_>, seed: Option<i32>) -> Result<Option<Planet>> {
+        Ok(sample_planets_internal(ctx, 1, seed)?.into_iter().next())
+    }
+
+    /// Random planets without repetitions; the same seed gives the same planets in the same order
+    async fn sample_planets(
+        &self,
+        ctx: &Context<'_>,
+        n: i32,
+        seed: Option<i32>,
+    ) -> Result<Vec<Planet>> {
+        if !(1..=MAX_SAMPLE_SIZE).contains(&n) {
+            return Err(format!("n should be from 1 to {}", MAX_SAMPLE_SIZE).into());
+        }
+        sample_planets_internal(ctx, n, seed)
+    }
+
+    /// Approximate distance between planets of the same star at the moment
+    async fn distance(
+        &self,
+        ctx: &Context<'_>,
+        from: ID,
+        to: ID,
+        at: DateTime<Utc>,
+    ) -> Result<PlanetDistance> {
+        let conn = &mut get_conn_from_ctx(ctx);
+        let from = get_planet_entity(&from, conn)?;
+        let to = get_planet_entity(&to, conn)?;
+        // drafts and archived planets are visible only to admins
+        let published = model::PlanetStatus::Published.to_string();
+        if (from.status != published || to.status != published) && !is_admin(ctx).await {
+            return Err(NotFound.into());
+        }
+        if from.star_id.is_none() || from.star_id != to.star_id {
+            return Err("Planets should orbit the same star".into());
+        }
+
+        let orbits = repository::get_orbits(&[from.id, to.id], conn)?;
+        let get_elements = |planet: &PlanetEntity| {
+            orbits
+                .iter()
+                .find(|orbit| orbit.planet_id == planet.id)
+                .map(OrbitalElements::from)
+                .ok_or_else(|| format!("Orbit of {} is unknown", planet.name))
+        };
+        let astronomical_units = orbits::distance(&get_elements(&from)?, &get_elements(&to)?, at);
+
+        Ok(PlanetDistance::from_astronomical_units(astronomical_units))
+    }
+
+    /// Published planets found by their own key are kept in [PlanetEntityCache] for a while
+    #[graphql(entity)]
+    async fn find_planet_by_id(&self, ctx: &Context<'_>, id: ID) -> Result<Option<Planet>> {
+        let entity_cache = ctx
+            .data::<PlanetEntityCache>()
+            .expect("Can't get entity cache");
+        let Some(key) = parse_planet_key(&id) else {
+            return Ok(None);
+        };
+        if let Some(planet) = entity_cache.get(key, Instant::now()) {
+            return Ok(Some(planet));
+        }
+        let version = entity_cache.version(key);
+        let Some(planet) = find_planet_by_id_internal(ctx, id).await? else {
+            return Ok(None);
+        };
+        // drafts are visible only to admins, redirects are reported per response
+        if planet.status == PlanetStatus::Published && planet.has_key(key) {
+            entity_cache.put(key, version, planet.clone(), Instant::now());
+        }
+        Ok(Some(planet))
+    }
+
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn classification_rules(&self, ctx: &Context<'_>) -> Result<Vec<ClassificationRule>> {
+        let rules = repository::get_classification_rules(&mut get_conn_from_ctx(ctx))?
             .iter()
-            .map(Planet::from)
+            .map(ClassificationRule::from)
+            .collect();
+        Ok(rules)
+    }
+
+    /// The build and the schema served by this instance
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn server_info(&self) -> ServerInfo {
+        ServerInfo {
+            version: BUILD_INFO.version,
+            git_sha: BUILD_INFO.git_sha,
+            built_at: BUILD_INFO.built_at,
+            features: BUILD_INFO.features.clone(),
+            schema_hash: &BUILD_INFO.schema_hash,
+        }
+    }
+
+    /// Features which are switched on, with the roles they are limited to (null means everyone)
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn enabled_features(&self, ctx: &Context<'_>) -> Vec<EnabledFeature> {
+        let feature_flags = ctx
+            .data::<Arc<dyn FeatureFlagProvider>>()
+            .expect("Can't get feature flags");
+        Feature::iter()
+            .filter_map(|feature| {
+                let flag = feature_flags.get_flag(feature)?;
+                if flag.enabled {
+                    Some(EnabledFeature {
+                        name: feature.to_string(),
+                        roles: None,
+                    })
+                } else if !flag.roles.is_empty() {
+                    Some(EnabledFeature {
+                        name: feature.to_string(),
+                        roles: Some(flag.roles),
+                    })
+                } else {
+                    None
+                }
+            })
             .collect()
     }
 
-    async fn get_planet(&self, ctx: &Context<'_>, id: ID) -> Option<Planet> {
-        find_planet_by_id_internal(ctx, id)
+    /// Usage of old names of renamed fields since the start of the instance, with the clients
+    /// still using them
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn renamed_field_usage(&self) -> Vec<RenamedFieldUsage> {
+        renames::get_usages()
+            .into_iter()
+            .map(|usage| RenamedFieldUsage {
+                type_name: usage.field.type_name.to_string(),
+                old_name: usage.field.old_name.to_string(),
+                new_name: usage.field.new_name.to_string(),
+                operations: usage.operations,
+                last_used_at: usage.last_used_at,
+                user_agents: usage
+                    .user_agents
+                    .into_iter()
+                    .map(|usage| UserAgentUsage {
+                        user_agent: usage.user_agent,
+                        operations: usage.operations,
+                        last_used_at: usage.last_used_at,
+                    })
+                    .collect(),
+            })
+            .collect()
     }
 
-    #[graphql(entity)]
-    async fn find_planet_by_id(&self, ctx: &Context<'_>, id: ID) -> Option<Planet> {
-        find_planet_by_id_internal(ctx, id)
+    /// Effectiveness of the response cache since the start of the instance
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn response_cache_stats(&self, ctx: &Context<'_>) -> ResponseCacheStats {
+        let stats = ctx
+            .data::<ResponseCache>()
+            .expect("Can't get response cache")
+            .stats();
+        ResponseCacheStats {
+            hits: stats.hits,
+            misses: stats.misses,
+            hit_rate: stats.hit_rate(),
+            entries: stats.entries as u64,
+        }
     }
+
+    /// Subscribers and messages of the topics of subscriptions since the start of the instance
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn broker_stats(&self, ctx: &Context<'_>) -> Vec<BrokerTopicStats> {
+        [
+            get_broker::<PlanetEvent>(ctx).stats(),
+            get_broker::<DeletionProgress>(ctx).stats(),
+        ]
+        .into_iter()
+        .map(|stats| BrokerTopicStats {
+            topic: stats.name.to_string(),
+            subscribers: stats.subscribers as u64,
+            published: stats.published,
+            skipped: stats.skipped,
+        })
+        .collect()
+    }
+
+    /// Allocator statistics (if jemalloc is used) and estimated sizes of in-memory caches
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn memory_stats(&self, ctx: &Context<'_>) -> MemoryStats {
+        let response_cache = ctx
+            .data::<ResponseCache>()
+            .expect("Can't get response cache");
+        MemoryStats {
+            allocator: memory::get_allocator_stats().map(|stats| AllocatorStats {
+                allocated: stats.allocated as u64,
+                active: stats.active as u64,
+                resident: stats.resident as u64,
+                mapped: stats.mapped as u64,
+                retained: stats.retained as u64,
+            }),
+            heap_profiling: memory::is_heap_profiling_active(),
+            caches: [response_cache.memory(), renames::get_user_agents_memory()]
+                .into_iter()
+                .map(|cache| CacheMemory {
+                    name: cache.name.to_string(),
+                    entries: cache.entries as u64,
+                    estimated_bytes: cache.estimated_bytes as u64,
+                })
+                .collect(),
+        }
+    }
+
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn webhooks(&self, ctx: &Context<'_>) -> Result<Vec<Webhook>> {
+        let webhooks = repository::get_webhooks(&mut get_conn_from_ctx(ctx))?;
+        Ok(webhooks.iter().map(Webhook::from).collect())
+    }
+
+    /// Anomalies of the planets data, the most severe first
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn data_quality_report(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(
+            default = 365,
+            desc = "Planets which haven't changed for more days are reported as stale"
+        )]
+        stale_after_days: u32,
+    ) -> Result<DataQualityReport> {
+        let generated_at = Utc::now();
+        let stale_before = generated_at - chrono::Duration::days(stale_after_days.into());
+        let mut findings =
+            repository::get_data_quality_findings(stale_before, &mut get_conn_from_ctx(ctx))?
+                .into_iter()
+                .map(DataQualityFinding::try_from)
+                .collect::<Result<Vec<_>>>()?;
+        // stable, so findings of the same severity stay ordered by planet
+        findings.sort_by_key(|finding| finding.severity);
+        Ok(DataQualityReport {
+            generated_at,
+            findings,
+        })
+    }
+
+    /// Reports saved by the current user
+    async fn my_reports(&self, ctx: &Context<'_>) -> Result<Vec<Report>> {
+        let user = get_current_user(ctx)?;
+        let reports = repository::get_reports(&user.0, &mut get_conn_from_ctx(ctx))?;
+        Ok(reports.iter().map(Report::from).collect())
+    }
+
+    /// Runs a report of the current user on their behalf and returns its data; `variables`
+    /// override the saved ones and are checked against the variable definitions of the report
+    async fn run_report(
+        &self,
+        ctx: &Context<'_>,
+        name: String,
+        variables: Option<Json<serde_json::Map<String, serde_json::Value>>>,
+    ) -> Result<Json<Value>> {
+        let user = get_current_user(ctx)?;
+        let runner = get_operation_runner(ctx)?;
+        let mut report = repository::get_report(&user.0, &name, &mut get_conn_from_ctx(ctx))
+            .optional()?
+            .ok_or_else(|| format!("Report {} is not found", name))?;
+
+        if let Some(variables) = variables {
+            if let serde_json::Value::Object(saved) = &mut report.variables {
+                saved.extend(variables.0);
+            }
+            if let serde_json::Value::Object(variables) = &report.variables {
+                check_operation_variables(&parser::parse_query(&report.query)?, variables)?;
+            }
+        }
+
+        let role = match ctx.data_opt::<Result<Option<Role>, CustomError>>() {
+            Some(Ok(Some(role))) => Some(role.to_string()),
+            _ => None,
+        };
+        let request = Request::new(report.query)
+            .variables(Variables::from_json(report.variables))
+            .data(common_utils::parse_role(role.as_deref()))
+            .data(CurrentUser(user.0.clone()));
+        let response = runner.0.execute(request).await;
+        if response.is_err() {
+            return Err(format!("Report failed: {}", join_error_messages(&response)).into());
+        }
+        Ok(Json(response.data))
+    }
+}
+
+fn get_broker<'a, T: 'static>(ctx: &Context<'a>) -> &'a Arc<dyn Broker<T>> {
+    ctx.data::<Arc<dyn Broker<T>>>().expect("Can't get broker")
+}
+
+/// Publishes the event of a mutation after evicting the planet from [PlanetEntityCache]
+fn publish_planet_event(ctx: &Context<'_>, event: PlanetEvent) {
+    ctx.data::<PlanetEntityCache>()
+        .expect("Can't get entity cache")
+        .invalidate(&[
+            PlanetKey::Id(event.planet.id),
+            PlanetKey::Uuid(event.planet.uuid),
+        ]);
+    // the event is kept in the outbox, so subscribers can still get it by resuming
+    #[cfg(feature = "chaos")]
+    if crate::chaos::is_broker_failing() {
+        println!("Event wasn't published: injected fault");
+        return;
+    }
+    get_broker::<PlanetEvent>(ctx).publish(event);
+}
+
+fn get_current_user<'a>(ctx: &Context<'a>) -> Result<&'a CurrentUser> {
+    ctx.data_opt::<CurrentUser>()
+        .ok_or_else(|| FORBIDDEN_MESSAGE.into())
+}
+
+fn get_operation_runner<'a>(ctx: &Context<'a>) -> Result<&'a OperationRunner> {
+    ctx.data_opt::<OperationRunner>()
+        .ok_or_else(|| "Reports aren't supported here".into())
+}
+
+/// Documents with several operations are left to the execution, which requires an operation name
+fn check_operation_variables(
+    document: &ExecutableDocument,
+    variables: &serde_json::Map<String, serde_json::Value>,
+) -> Result<()> {
+    let mut operations = document.operations.iter();
+    if let (Some((_, operation)), None) = (operations.next(), operations.next()) {
+        operation_variables::check(&operation.node.variable_definitions, variables)
+            .map_err(|errors| format!("Invalid variables: {}", errors.join("; ")))?;
+    }
+    Ok(())
+}
+
+fn join_error_messages(response: &Response) -> String {
+    response
+        .errors
+        .iter()
+        .map(|error| error.message.as_str())
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Planets which aren't published are found only for admins; a merged planet resolves to the
+/// planet it was merged into
+async fn find_planet_by_id_internal(ctx: &Context<'_>, id: ID) -> Result<Option<Planet>> {
+    let Some(key) = parse_planet_key(&id) else {
+        return Ok(None);
+    };
+    let planet = match load_planet(ctx, key).await? {
+        Some(planet) => planet,
+        None => {
+            let conn = &mut get_conn_from_ctx(ctx);
+            let target_id = match key {
+                PlanetKey::Id(id) => repository::get_redirect(id, conn),
+                PlanetKey::Uuid(uuid) => repository::get_redirect_by_uuid(uuid, conn),
+            }
+            .optional()?;
+            let Some(target_id) = target_id else {
+                return Ok(None);
+            };
+            let Some(planet) = load_planet(ctx, PlanetKey::Id(target_id)).await? else {
+                return Ok(None);
+            };
+            if let Some(redirects) = ctx.data_opt::<Arc<ResolvedRedirects>>() {
+                redirects.add(id, planet.get_id());
+            }
+            planet
+        }
+    };
+    if planet.status == PlanetStatus::Published || is_admin(ctx).await {
+        Ok(Some(planet))
+    } else {
+        Ok(None)
+    }
+}
+
+async fn load_planet(ctx: &Context<'_>, key: PlanetKey) -> Result<Option<Planet>> {
+    let planet = ctx
+        .data::<PlanetIdentityMap>()
+        .expect("Can't get planet identity map")
+        .load_one(key)
+        .await?;
+    Ok(planet)
+}
+
+async fn find_planet_as_of(
+    ctx: &Context<'_>,
+    id: &ID,
+    as_of: DateTime<Utc>,
+) -> Result<Option<Planet>> {
+    let Some(key) = parse_planet_key(id) else {
+        return Ok(None);
+    };
+    let conn = &mut get_conn_from_ctx(ctx);
+    let planet = match key {
+        PlanetKey::Id(id) => repository::get_as_of(id, as_of, conn),
+        PlanetKey::Uuid(uuid) => repository::get_by_uuid_as_of(uuid, as_of, conn),
+    }
+    .optional()?
+    .map(|planet| Planet::from(&planet).at(as_of));
+    match planet {
+        Some(planet) if planet.status == PlanetStatus::Published || is_admin(ctx).await => {
+            Ok(Some(planet))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// The moment to read planets as of; the history doesn't know the future
+fn check_as_of(as_of: DateTime<Utc>) -> Result<()> {
+    if as_of > Utc::now() {
+        return Err("asOf can't be in the future".into());
+    }
+    Ok(())
+}
+
+async fn is_admin(ctx: &Context<'_>) -> bool {
+    RoleGuard::new(Role::Admin).check(ctx).await.is_ok()
+}
+
+fn sample_planets_internal(ctx: &Context<'_>, n: i32, seed: Option<i32>) -> Result<Vec<Planet>> {
+    let seed = seed.unwrap_or_else(rand::random);
+    let planets = repository::sample(n.into(), seed.into(), &mut get_conn_from_ctx(ctx))?
+        .iter()
+        .map(Planet::from)
+        .collect();
+    Ok(planets)
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Hash)]
+pub(crate) enum PlanetKey {
+    Id(i32),
+    Uuid(Uuid),
 }
 
-fn find_planet_by_id_internal(ctx: &Context<'_>, id: ID) -> Option<Planet> {
-    let id = id
-        .to_string()
-        .parse::<i32>()
-        .expect("Can't get id from String");
-    repository::get(id, &mut get_conn_from_ctx(ctx))
-        .ok()
-        .map(|p| Planet::from(&p))
+fn parse_planet_key(id: &ID) -> Option<PlanetKey> {
+    let uuid = || Uuid::parse_str(id).ok().map(PlanetKey::Uuid);
+    let int_id = || ids::decode_id(id).map(PlanetKey::Id);
+    match *ID_FORMAT {
+        IdFormat::Integer => int_id(),
+        IdFormat::Uuid => uuid(),
+        IdFormat::Compat => uuid().or_else(int_id),
+    }
+}
+
+async fn set_planet_status(
+    ctx: &Context<'_>,
+    id: &ID,
+    status: PlanetStatus,
+    event_kind: EventKind,
+) -> Result<Planet> {
+    let planet = get_planet_entity(id, &mut get_conn_from_ctx(ctx))?;
+    if planet.status == status.to_string() {
+        return Err(format!("Planet is already {}", status.to_string().to_lowercase()).into());
+    }
+    // subscribers get archivings of the planets they could see
+    if status == PlanetStatus::Archived && planet.status != PlanetStatus::Published.to_string() {
+        return Err("Only published planets can be archived".into());
+    }
+
+    let (updated_planet_entity, event) = write_blocking(ctx, move |conn| {
+        repository::set_status(planet.id, status.into(), event_kind, conn)
+    })
+    .await?;
+    publish_planet_event(ctx, PlanetEvent::from(&event));
+
+    Ok(Planet::from(&updated_planet_entity))
+}
+
+fn get_planet_entity(id: &ID, conn: &mut PgConnection) -> QueryResult<PlanetEntity> {
+    match parse_planet_key(id).ok_or(NotFound)? {
+        PlanetKey::Id(id) => repository::get(id, conn),
+        PlanetKey::Uuid(uuid) => repository::get_by_uuid(uuid, conn),
+    }
 }
 
 pub struct Mutation;
 
 #[Object]
 impl Mutation {
+    /// A draft is visible only to admins until it's published
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn create_planet(
+        &self,
+        ctx: &Context<'_>,
+        planet: PlanetInput,
+        #[graphql(default)] draft: bool,
+    ) -> Result<Planet> {
+        let (mut new_planet, new_planet_details, atmosphere) = to_new_entities(planet)?;
+        if draft {
+            new_planet.status = Some(model::PlanetStatus::Draft.to_string());
+        }
+
+        let (created_planet_entity, event) = write_blocking(ctx, move |conn| {
+            repository::create(
+                new_planet,
+                new_planet_details,
+                &atmosphere.unwrap_or_default(),
+                conn,
+            )
+        })
+        .await?
+        .map_err(|_| UNCLASSIFIED_PLANET_MESSAGE)?;
+        publish_planet_event(ctx, PlanetEvent::from(&event));
+
+        #[cfg(feature = "kafka")]
+        {
+            let producer = ctx
+                .data::<FutureProducer>()
+                .expect("Can't get Kafka producer");
+            let message = serde_json::to_string(&Planet::from(&created_planet_entity))
+                .expect("Can't serialize a planet");
+            kafka::send_message(producer, &message).await;
+        }
+
+        Ok(Planet::from(&created_planet_entity))
+    }
+
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn update_planet(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        planet: PlanetInput,
+    ) -> Result<Planet> {
+        let id = get_planet_entity(&id, &mut get_conn_from_ctx(ctx))?.id;
+        let (planet, planet_details, atmosphere) = to_new_entities(planet)?;
+
+        let (updated_planet_entity, event) = write_blocking(ctx, move |conn| {
+            repository::update(id, planet, planet_details, atmosphere.as_deref(), conn)
+        })
+        .await?
+        .map_err(|_| UNCLASSIFIED_PLANET_MESSAGE)?;
+        publish_planet_event(ctx, PlanetEvent::from(&event));
+
+        Ok(Planet::from(&updated_planet_entity))
+    }
+
+    /// Makes a draft or an archived planet visible to everyone
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn publish_planet(&self, ctx: &Context<'_>, id: ID) -> Result<Planet> {
+        set_planet_status(ctx, &id, PlanetStatus::Published, EventKind::Published).await
+    }
+
+    /// Withdraws a published planet from everyone except admins, who get it back by publishing it;
+    /// only published planets can be archived, and drafts are removed with `deletePlanets`
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn archive_planet(&self, ctx: &Context<'_>, id: ID) -> Result<Planet> {
+        set_planet_status(ctx, &id, PlanetStatus::Archived, EventKind::Archived).await
+    }
+
+    /// Merges a duplicate (e.g. created by an import) into the target planet, which keeps its own
+    /// data and takes the star, the orbit and the atmosphere of the duplicate only if it lacks
+    /// them. The duplicate is deleted, its IDs redirect to the target, and its history moves to the
+    /// target
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn merge_planets(
+        &self,
+        ctx: &Context<'_>,
+        source_id: ID,
+        target_id: ID,
+    ) -> Result<Planet> {
+        let (source_id, target_id) = {
+            let conn = &mut get_conn_from_ctx(ctx);
+            (
+                get_planet_entity(&source_id, conn)?.id,
+                get_planet_entity(&target_id, conn)?.id,
+            )
+        };
+        if source_id == target_id {
+            return Err("Can't merge a planet into itself".into());
+        }
+
+        let (merged_planet_entity, events) = write_blocking(ctx, move |conn| {
+            repository::merge(source_id, target_id, conn)
+        })
+        .await?;
+        for event in &events {
+            publish_planet_event(ctx, PlanetEvent::from(event));
+        }
+
+        Ok(Planet::from(&merged_planet_entity))
+    }
+
+    /// Sets the orbit used by `distance`, replacing the current one; null removes the orbit
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn set_planet_orbit(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        orbit: Option<OrbitInput>,
+    ) -> Result<Planet> {
+        let id = get_planet_entity(&id, &mut get_conn_from_ctx(ctx))?.id;
+        let orbit = orbit
+            .map(|orbit| {
+                mapping::to_stored_orbit(
+                    &orbit.semi_major_axis.0,
+                    &orbit.eccentricity.0,
+                    &orbit.orbital_period.0,
+                    &orbit.longitude_of_perihelion.0,
+                    &orbit.mean_longitude.0,
+                )
+            })
+            .transpose()?;
+
+        let (planet_entity, event) =
+            write_blocking(ctx, move |conn| repository::set_orbit(id, orbit, conn)).await?;
+        publish_planet_event(ctx, PlanetEvent::from(&event));
+
+        Ok(Planet::from(&planet_entity))
+    }
+
+    /// All stars and planets (in any status) with their details and orbits as gzipped NDJSON
+    /// encoded in Base64, which can be imported into another instance
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn export_snapshot(&self, ctx: &Context<'_>) -> Result<String> {
+        let entities = repository::get_snapshot(&mut get_conn_from_ctx(ctx))?;
+        Ok(snapshot::encode(&snapshot::to_records(entities)))
+    }
+
+    /// Restores a snapshot made by `exportSnapshot` in a transaction; importing the same
+    /// snapshot again changes nothing
     #[graphql(guard = "RoleGuard::new(Role::Admin)")]
-    async fn create_planet(&self, ctx: &Context<'_>, planet: PlanetInput) -> Result<Planet> {
-        let new_planet = NewPlanetEntity {
-            name: planet.name,
-            type_: planet.type_.to_string(),
+    async fn import_snapshot(
+        &self,
+        ctx: &Context<'_>,
+        snapshot: String,
+        #[graphql(default)] on_conflict: ConflictPolicy,
+    ) -> Result<SnapshotImport> {
+        let (stars, planets) = snapshot::from_records(snapshot::decode(&snapshot)?);
+        let imported = write_blocking(ctx, move |conn| {
+            repository::import_snapshot(&stars, &planets, on_conflict.into(), conn)
+        })
+        .await?
+        .map_err(|existing| format!("{} planets of the snapshot already exist", existing.0))?;
+        let mut result = SnapshotImport {
+            created: 0,
+            updated: 0,
+            skipped: imported.skipped,
         };
+        for event in imported.events.iter().map(PlanetEvent::from) {
+            match event.kind {
+                PlanetEventKind::Created => result.created += 1,
+                _ => result.updated += 1,
+            }
+            publish_planet_event(ctx, event);
+        }
+        Ok(result)
+    }
+
+    /// Saves a query under a name for the current user replacing their report with the same name.
+    /// The query is validated against the current schema
+    async fn save_report(
+        &self,
+        ctx: &Context<'_>,
+        name: String,
+        query: String,
+        variables: Option<Json<serde_json::Map<String, serde_json::Value>>>,
+    ) -> Result<Report> {
+        let user = get_current_user(ctx)?;
+        let runner = get_operation_runner(ctx)?;
+
+        let document = parser::parse_query(&query)?;
+        if document
+            .operations
+            .iter()
+            .any(|(_, operation)| operation.node.ty != OperationType::Query)
+        {
+            return Err("Only queries can be saved as reports".into());
+        }
+
+        let variables = variables.map(|json| json.0).unwrap_or_default();
+        let validation = runner
+            .0
+            .execute(
+                Request::new(query.as_str())
+                    .variables(Variables::from_json(serde_json::Value::Object(
+                        variables.clone(),
+                    )))
+                    .data(ValidateOnly),
+            )
+            .await;
+        if validation.is_err() {
+            return Err(format!("Invalid report: {}", join_error_messages(&validation)).into());
+        }
+        check_operation_variables(&document, &variables)?;
+        let variables = serde_json::Value::Object(variables);
 
-        let details = planet.details;
-        let new_planet_details = NewDetailsEntity {
-            mean_radius: details.mean_radius.0,
-            mass: BigDecimal::from_str(&details.mass.0.to_string())
-                .expect("Can't get BigDecimal from string"),
-            population: details.population.map(|wrapper| wrapper.0),
-            planet_id: 0,
+        let new_report = NewReportEntity {
+            owner: user.0.clone(),
+            name,
+            query,
+            variables,
         };
+        let report = repository::save_report(new_report, &mut get_conn_from_ctx(ctx))?;
+        Ok(Report::from(&report))
+    }
 
-        let created_planet_entity =
-            repository::create(new_planet, new_planet_details, &mut get_conn_from_ctx(ctx))?;
+    /// Registers a URL to which planet events of the kinds (all if not specified) are POSTed as
+    /// CloudEvents signed with the secret, see the `x-webhook-signature` header
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn register_webhook(
+        &self,
+        ctx: &Context<'_>,
+        url: String,
+        event_kinds: Option<Vec<PlanetEventKind>>,
+        #[graphql(secret)] secret: String,
+    ) -> Result<Webhook> {
+        let parsed_url = url::Url::parse(&url)?;
+        if !["http", "https"].contains(&parsed_url.scheme()) {
+            return Err("Webhook URL should be HTTP(S)".into());
+        }
+        if secret.is_empty() {
+            return Err("Secret should not be empty".into());
+        }
 
-        let producer = ctx
-            .data::<FutureProducer>()
-            .expect("Can't get Kafka producer");
-        let message = serde_json::to_string(&Planet::from(&created_planet_entity))
-            .expect("Can't serialize a planet");
-        kafka::send_message(producer, &message).await;
+        let new_webhook = NewWebhookEntity {
+            url,
+            event_kinds: event_kinds
+                .unwrap_or_default()
+                .iter()
+                .map(PlanetEventKind::to_string)
+                .collect(),
+            secret,
+        };
+        let webhook = write_blocking(ctx, move |conn| {
+            repository::create_webhook(new_webhook, conn)
+        })
+        .await?;
+        Ok(Webhook::from(&webhook))
+    }
 
-        Ok(Planet::from(&created_planet_entity))
+    /// Starts or stops sampling allocations for heap profiles, returns whether it is active
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn set_heap_profiling(&self, active: bool) -> Result<bool> {
+        memory::set_heap_profiling(active)?;
+        Ok(active)
+    }
+
+    /// Replaces the rules used to determine the type of a planet created without it
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn set_classification_rules(
+        &self,
+        ctx: &Context<'_>,
+        rules: Vec<ClassificationRuleInput>,
+    ) -> Result<Vec<ClassificationRule>> {
+        let new_rules = rules
+            .into_iter()
+            .map(NewClassificationRuleEntity::from)
+            .collect();
+        let rules = write_blocking(ctx, move |conn| {
+            repository::replace_classification_rules(new_rules, conn)
+        })
+        .await?;
+        Ok(rules.iter().map(ClassificationRule::from).collect())
+    }
+
+    /// Deletes planets matching the filter in batches; progress is published to `deletionProgress`.
+    /// With `dryRun` only reports how many planets would be deleted
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn delete_planets(
+        &self,
+        ctx: &Context<'_>,
+        filter: PlanetFilter,
+        #[graphql(default)] dry_run: bool,
+    ) -> Result<DeletePlanetsResult> {
+        let filter = PlanetsFilter::from(filter);
+        if filter.is_empty() {
+            return Err("Filter should contain at least one condition".into());
+        }
+
+        let total = repository::count(&filter, &mut get_conn_from_ctx(ctx))?;
+        if dry_run {
+            return Ok(DeletePlanetsResult {
+                affected: total,
+                dry_run,
+            });
+        }
+
+        let deletion_progress = get_broker::<DeletionProgress>(ctx);
+        let mut deleted = 0;
+        loop {
+            let batch_filter = filter.clone();
+            let events = write_blocking(ctx, move |conn| {
+                repository::delete_batch(&batch_filter, DELETE_BATCH_SIZE, conn)
+            })
+            .await?;
+            if events.is_empty() {
+                break;
+            }
+            for event in &events {
+                publish_planet_event(ctx, PlanetEvent::from(event));
+            }
+            deleted += events.len() as i64;
+            deletion_progress.publish(DeletionProgress { deleted, total });
+        }
+
+        Ok(DeletePlanetsResult {
+            affected: deleted,
+            dry_run,
+        })
     }
 }
 
@@ -93,6 +959,7 @@ pub struct Subscription;
 
 #[Subscription]
 impl Subscription {
+    #[cfg(feature = "kafka")]
     async fn latest_planet<'ctx>(
         &self,
         ctx: &'ctx Context<'_>,
@@ -121,19 +988,154 @@ impl Subscription {
             }
         }
     }
+
+    /// Changes of planets. Pass the token of the last received event as `resumeFrom` to get
+    /// the events that happened after it before the live ones; it's also the way to get the events
+    /// skipped if a subscriber falls too far behind, which it's notified about with an error. Only
+    /// admins get deletions and changes of unpublished planets, and subscribers without a role get
+    /// only creations, publications and archivings
+    async fn planet_events(
+        &self,
+        ctx: &Context<'_>,
+        resume_from: Option<i64>,
+    ) -> Result<impl Stream<Item = Result<PlanetEvent>>> {
+        get_planet_events(ctx, resume_from)
+    }
+
+    /// Events of `planetEvents` coalesced into a digest every `batchIntervalSeconds` (from 1 to
+    /// 60), so that bulk changes don't flood clients which only need periodic updates. Intervals
+    /// without events give no digest, and a digest is sent early once it has 1000 events
+    async fn planet_event_digests(
+        &self,
+        ctx: &Context<'_>,
+        batch_interval_seconds: i32,
+        resume_from: Option<i64>,
+    ) -> Result<impl Stream<Item = Result<PlanetEventDigest>>> {
+        if !(1..=MAX_DIGEST_INTERVAL_SECONDS).contains(&batch_interval_seconds) {
+            return Err(format!(
+                "batchIntervalSeconds must be from 1 to {}",
+                MAX_DIGEST_INTERVAL_SECONDS
+            )
+            .into());
+        }
+        let events = get_planet_events(ctx, resume_from)?;
+        let interval = Duration::from_secs(batch_interval_seconds as u64);
+        Ok(broker::batches(events, interval, MAX_DIGEST_EVENTS)
+            .map(|events| events.map(PlanetEventDigest::new)))
+    }
+
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn deletion_progress(&self, ctx: &Context<'_>) -> impl Stream<Item = DeletionProgress> {
+        // only the latest progress matters, so skipped updates are ignored
+        get_broker::<DeletionProgress>(ctx)
+            .subscribe()
+            .filter_map(|progress| future::ready(progress.ok()))
+    }
 }
 
-#[derive(Serialize, Deserialize)]
-struct Planet {
-    id: ID,
+const MAX_DIGEST_INTERVAL_SECONDS: i32 = 60;
+const MAX_DIGEST_EVENTS: usize = 1000;
+
+/// Subscriptions which can be resumed after the token of an event, see [ResumeFrom]
+pub const RESUMABLE_SUBSCRIPTIONS: &[&str] = &["planetEvents", "planetEventDigests"];
+
+/// The token of the last event a reconnecting client received, passed by a transport (e.g.
+/// `Last-Event-ID` of SSE); takes precedence over `resumeFrom`
+pub struct ResumeFrom(pub i64);
+
+/// Replayed events after `resume_from` followed by the live ones, which the subscriber is allowed
+/// to get
+fn get_planet_events(
+    ctx: &Context<'_>,
+    resume_from: Option<i64>,
+) -> Result<impl Stream<Item = Result<PlanetEvent>> + Send + 'static> {
+    let resume_from = ctx
+        .data_opt::<ResumeFrom>()
+        .map(|resume_from| resume_from.0)
+        .or(resume_from);
+    let role = if is_auth_disabled() {
+        Some(Role::Admin)
+    } else {
+        match ctx.data_opt::<Result<Option<Role>, CustomError>>() {
+            Some(Ok(role)) => *role,
+            _ => None,
+        }
+    };
+
+    let events = event_policy::subscribe(get_broker::<PlanetEvent>(ctx).as_ref(), role, || {
+        let replayed_events = match resume_from {
+            Some(token) => {
+                repository::get_events_after(PLANETS_TOPIC, token, &mut get_conn_from_ctx(ctx))?
+                    .iter()
+                    .map(PlanetEvent::from)
+                    .collect()
+            }
+            None => vec![],
+        };
+        Ok::<_, Error>(replayed_events)
+    })?;
+    Ok(events.map(|event| {
+        // the skipped events can be got from the outbox
+        event.map_err(|Lagged(skipped)| {
+            format!(
+                "{} events were skipped, since the subscriber is too slow; \
+                    resubscribe with the last received token as `resumeFrom`",
+                skipped
+            )
+            .into()
+        })
+    }))
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Planet {
+    id: i32,
+    uuid: Uuid,
     name: String,
     type_: PlanetType,
+    // planets serialized before stars were modeled don't contain it
+    #[serde(default)]
+    star_id: Option<i32>,
+    #[serde(default)]
+    status: PlanetStatus,
+    /// Set if the planet is read as of the moment, so that its details are of the same moment
+    #[serde(skip)]
+    as_of: Option<DateTime<Utc>>,
+}
+
+/// The ID of a planet exposed to clients
+pub(crate) fn to_planet_id(id: i32, uuid: Uuid) -> ID {
+    match *ID_FORMAT {
+        IdFormat::Integer => ids::encode_id(id).into(),
+        IdFormat::Uuid | IdFormat::Compat => uuid.into(),
+    }
+}
+
+impl Planet {
+    fn get_id(&self) -> ID {
+        to_planet_id(self.id, self.uuid)
+    }
+
+    fn has_key(&self, key: PlanetKey) -> bool {
+        match key {
+            PlanetKey::Id(id) => id == self.id,
+            PlanetKey::Uuid(uuid) => uuid == self.uuid,
+        }
+    }
+
+    /// The planet as it was at the moment
+    fn at(self, as_of: DateTime<Utc>) -> Self {
+        Planet {
+            as_of: Some(as_of),
+            ..self
+        }
+    }
 }
 
 #[Object]
 impl Planet {
-    async fn id(&self) -> &ID {
-        &self.id
+    async fn id(&self) -> ID {
+        self.get_id()
     }
 
     async fn name(&self) -> &String {
@@ -141,32 +1143,215 @@ impl Planet {
     }
 
     /// From an astronomical point of view
-    #[graphql(name = "type")]
-    async fn type_(&self) -> &PlanetType {
+    async fn planet_type(&self) -> &PlanetType {
         &self.type_
     }
 
+    /// Old name of `planetType`, see [crate::renames]
+    // the deprecation reason names the new field, which makes the old name tracked
+    #[graphql(name = "type", deprecation = "Use `planetType` instead")]
+    async fn type_(&self, ctx: &Context<'_>) -> Result<&PlanetType> {
+        self.planet_type(ctx).await
+    }
+
+    async fn status(&self) -> PlanetStatus {
+        self.status
+    }
+
+    /// The star the planet orbits; absent for rogue planets
+    async fn star(&self, ctx: &Context<'_>) -> Result<Option<Star>> {
+        let Some(star_id) = self.star_id else {
+            return Ok(None);
+        };
+        let data_loader = ctx
+            .data::<TracedDataLoader<i32, StarLoader>>()
+            .expect("Can't get data loader");
+        data_loader.load_one(star_id).await
+    }
+
     #[graphql(deprecation = "Now it is not in doubt. Do not use this field")]
     async fn is_rotating_around_sun(&self) -> bool {
         true
     }
 
     async fn details(&self, ctx: &Context<'_>) -> Result<Details> {
-        let data_loader = ctx
-            .data::<DataLoader<DetailsLoader>>()
-            .expect("Can't get data loader");
-        let planet_id = self
-            .id
-            .to_string()
-            .parse::<i32>()
-            .expect("Can't convert id");
-        let details = data_loader.load_one(planet_id).await?;
+        let details = match self.as_of {
+            Some(as_of) => {
+                ctx.data::<TracedDataLoader<(i32, DateTime<Utc>), DetailsAsOfLoader>>()
+                    .expect("Can't get data loader")
+                    .load_one((self.id, as_of))
+                    .await?
+            }
+            None => {
+                ctx.data::<TracedDataLoader<i32, DetailsLoader>>()
+                    .expect("Can't get data loader")
+                    .load_one(self.id)
+                    .await?
+            }
+        };
         details.ok_or_else(|| "Not found".into())
     }
+
+    /// Versions of the planet which started from `from` inclusive to `to` exclusive, e.g. the
+    /// changes of a month; pass `endCursor` of a page as `after` to get the next one. Users
+    /// other than admins get only the published versions
+    async fn history(
+        &self,
+        ctx: &Context<'_>,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        #[graphql(default)] direction: OrderDirection,
+        #[graphql(default = 20)] limit: i64,
+        after: Option<DateTime<Utc>>,
+    ) -> Result<HistoryPage<PlanetVersion>> {
+        let published_only = !is_admin(ctx).await;
+        let range = to_history_range(from, to, direction, limit, after, published_only)?;
+        let versions =
+            repository::get_planet_history(self.id, &range, &mut get_conn_from_ctx(ctx))?;
+        HistoryPage::new(versions, limit, |(planet, (valid_from, valid_to))| {
+            let planet = Planet::from(&planet);
+            Ok(PlanetVersion {
+                name: planet.name,
+                planet_type: planet.type_,
+                status: planet.status,
+                valid_from,
+                valid_to,
+            })
+        })
+    }
+
+    /// Versions of the details of the planet, see `history`
+    async fn details_history(
+        &self,
+        ctx: &Context<'_>,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        #[graphql(default)] direction: OrderDirection,
+        #[graphql(default = 20)] limit: i64,
+        after: Option<DateTime<Utc>>,
+    ) -> Result<HistoryPage<DetailsVersion>> {
+        let published_only = !is_admin(ctx).await;
+        let range = to_history_range(from, to, direction, limit, after, published_only)?;
+        let versions =
+            repository::get_details_history(self.id, &range, &mut get_conn_from_ctx(ctx))?;
+        HistoryPage::new(versions, limit, |(details, (valid_from, valid_to))| {
+            Ok(DetailsVersion {
+                mean_radius: CustomBigDecimal(units::meters_to_kilometers(&details.mean_radius)),
+                mass: CustomBigInt(details.mass),
+                population: details.population.map(CustomBigDecimal),
+                valid_from,
+                valid_to,
+            })
+        })
+    }
+
+    /// Earth Similarity Index based on the mean radius and the density: from 0 to 1, where 1 is the Earth
+    #[graphql(
+        guard = "FeatureGuard::new(Feature::HabitabilityScore)",
+        visible = "is_habitability_score_visible"
+    )]
+    async fn habitability_score(&self, ctx: &Context<'_>) -> Result<f64> {
+        let (mean_radius, mass) = match self.details(ctx).await? {
+            Details::InhabitedPlanetDetails(details) => (details.mean_radius, details.mass),
+            Details::UninhabitedPlanetDetails(details) => (details.mean_radius, details.mass),
+        };
+        let relative_radius =
+            mean_radius.0.to_f64().expect("Can't get mean radius") / EARTH_MEAN_RADIUS;
+        let relative_mass = mass.0.to_f64().expect("Can't get mass") / EARTH_MASS;
+        let relative_density = relative_mass / relative_radius.powi(3);
+
+        Ok(get_similarity(relative_radius, 0.57) * get_similarity(relative_density, 1.07))
+    }
 }
 
-#[derive(Copy, Clone, Eq, PartialEq, Serialize, Deserialize, Enum, Display, EnumString)]
-#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+const MAX_HISTORY_PAGE_SIZE: i64 = 100;
+
+/// A page of versions ordered by the start
+#[derive(SimpleObject)]
+#[graphql(concrete(name = "PlanetVersionPage", params(PlanetVersion)))]
+#[graphql(concrete(name = "DetailsVersionPage", params(DetailsVersion)))]
+struct HistoryPage<T: OutputType> {
+    versions: Vec<T>,
+    /// Start of the last version of the page
+    end_cursor: Option<DateTime<Utc>>,
+    has_next_page: bool,
+}
+
+impl<T: OutputType> HistoryPage<T> {
+    /// Versions are loaded with one extra to know if there is a next page
+    fn new<E>(
+        mut entities: Vec<(E, model::Validity)>,
+        limit: i64,
+        to_version: impl Fn((E, model::Validity)) -> Result<T>,
+    ) -> Result<Self> {
+        let has_next_page = entities.len() as i64 > limit;
+        entities.truncate(limit as usize);
+        let end_cursor = entities.last().map(|(_, (valid_from, _))| *valid_from);
+        Ok(HistoryPage {
+            versions: entities
+                .into_iter()
+                .map(to_version)
+                .collect::<Result<_>>()?,
+            end_cursor,
+            has_next_page,
+        })
+    }
+}
+
+fn to_history_range(
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    direction: OrderDirection,
+    limit: i64,
+    after: Option<DateTime<Utc>>,
+    published_only: bool,
+) -> Result<HistoryRange> {
+    if !(1..=MAX_HISTORY_PAGE_SIZE).contains(&limit) {
+        return Err(format!("limit must be from 1 to {}", MAX_HISTORY_PAGE_SIZE).into());
+    }
+    Ok(HistoryRange {
+        from,
+        to,
+        after,
+        descending: direction == OrderDirection::Desc,
+        limit: limit + 1,
+        published_only,
+    })
+}
+
+/// A version of a planet, valid from `validFrom` inclusive to `validTo` exclusive
+#[derive(SimpleObject)]
+struct PlanetVersion {
+    name: String,
+    planet_type: PlanetType,
+    status: PlanetStatus,
+    valid_from: DateTime<Utc>,
+    /// Absent for the current version
+    valid_to: Option<DateTime<Utc>>,
+}
+
+/// A version of the details of a planet, see `PlanetVersion`
+#[derive(SimpleObject)]
+struct DetailsVersion {
+    /// In kilometers
+    mean_radius: CustomBigDecimal,
+    mass: CustomBigInt,
+    /// In billions; absent for uninhabited planets
+    population: Option<CustomBigDecimal>,
+    valid_from: DateTime<Utc>,
+    valid_to: Option<DateTime<Utc>>,
+}
+
+fn is_habitability_score_visible(ctx: &Context<'_>) -> bool {
+    feature_flags::is_enabled(ctx, Feature::HabitabilityScore)
+}
+
+/// Weighted similarity of a property given relative to the Earth's one (of two properties in total)
+fn get_similarity(relative_value: f64, weight: f64) -> f64 {
+    (1.0 - ((relative_value - 1.0) / (relative_value + 1.0)).abs()).powf(weight / 2.0)
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Serialize, Deserialize, Enum)]
 enum PlanetType {
     TerrestrialPlanet,
     GasGiant,
@@ -174,10 +1359,82 @@ enum PlanetType {
     DwarfPlanet,
 }
 
+#[derive(
+    Copy, Clone, Default, Eq, PartialEq, Serialize, Deserialize, Enum, Display, EnumString,
+)]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+enum PlanetStatus {
+    Draft,
+    #[default]
+    Published,
+    Archived,
+}
+
+/// Planets with equal values of the field are ordered by ID ascending, so the order is stable
+#[derive(InputObject, Default)]
+struct PlanetOrder {
+    #[graphql(default)]
+    field: PlanetOrderField,
+    #[graphql(default)]
+    direction: OrderDirection,
+}
+
+#[derive(Copy, Clone, Default, Eq, PartialEq, Enum)]
+enum PlanetOrderField {
+    #[default]
+    Id,
+    Name,
+    Type,
+}
+
+#[derive(Copy, Clone, Default, Eq, PartialEq, Enum)]
+enum OrderDirection {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// What happens to planets of a snapshot which already exist (with the same name or UUID)
+#[derive(Copy, Clone, Default, Eq, PartialEq, Enum)]
+enum ConflictPolicy {
+    /// Existing planets are kept as is
+    #[default]
+    Skip,
+    Overwrite,
+    /// Nothing is imported if any planet exists
+    Fail,
+}
+
+#[derive(SimpleObject)]
+struct SnapshotImport {
+    created: usize,
+    updated: usize,
+    skipped: usize,
+}
+
+/// How planets are identified in the API. `COMPAT` is for the transition to UUIDs: planets are
+/// exposed by UUIDs, but can still be found by integer IDs
+#[derive(Copy, Clone, Eq, PartialEq, EnumString)]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+enum IdFormat {
+    Integer,
+    Uuid,
+    Compat,
+}
+
+#[derive(SimpleObject, Clone)]
+pub struct Star {
+    id: ID,
+    name: String,
+    spectral_class: String,
+    mass: CustomBigInt,
+}
+
 #[derive(Interface, Clone)]
 #[graphql(
-    field(name = "mean_radius", ty = "&CustomBigDecimal"),
-    field(name = "mass", ty = "&CustomBigInt")
+    field(name = "mean_radius", ty = "&CustomBigDecimal", desc = "In kilometers"),
+    field(name = "mass", ty = "&CustomBigInt"),
+    field(name = "atmosphere", ty = "Vec<GasComponent>")
 )]
 pub enum Details {
     InhabitedPlanetDetails(InhabitedPlanetDetails),
@@ -185,21 +1442,58 @@ pub enum Details {
 }
 
 #[derive(SimpleObject, Clone)]
+#[graphql(complex)]
 pub struct InhabitedPlanetDetails {
-    mean_radius: CustomBigDecimal,
-    mass: CustomBigInt,
+    #[graphql(skip)]
+    pub(crate) planet_id: i32,
+    /// In kilometers
+    pub(crate) mean_radius: CustomBigDecimal,
+    pub(crate) mass: CustomBigInt,
     /// In billions
-    population: CustomBigDecimal,
+    pub(crate) population: CustomBigDecimal,
+}
+
+#[ComplexObject]
+impl InhabitedPlanetDetails {
+    /// Gases from the most abundant; empty if the planet has no substantial atmosphere
+    async fn atmosphere(&self, ctx: &Context<'_>) -> Result<Vec<GasComponent>> {
+        get_atmosphere(ctx, self.planet_id).await
+    }
 }
 
 #[derive(SimpleObject, Clone)]
+#[graphql(complex)]
 pub struct UninhabitedPlanetDetails {
-    mean_radius: CustomBigDecimal,
-    mass: CustomBigInt,
+    #[graphql(skip)]
+    pub(crate) planet_id: i32,
+    /// In kilometers
+    pub(crate) mean_radius: CustomBigDecimal,
+    pub(crate) mass: CustomBigInt,
+}
+
+#[ComplexObject]
+impl UninhabitedPlanetDetails {
+    /// Gases from the most abundant; empty if the planet has no substantial atmosphere
+    async fn atmosphere(&self, ctx: &Context<'_>) -> Result<Vec<GasComponent>> {
+        get_atmosphere(ctx, self.planet_id).await
+    }
+}
+
+async fn get_atmosphere(ctx: &Context<'_>, planet_id: i32) -> Result<Vec<GasComponent>> {
+    let data_loader = ctx
+        .data::<TracedDataLoader<i32, AtmosphereLoader>>()
+        .expect("Can't get data loader");
+    Ok(data_loader.load_one(planet_id).await?.unwrap_or_default())
+}
+
+#[derive(SimpleObject, Clone)]
+pub struct GasComponent {
+    gas: String,
+    percentage: CustomBigDecimal,
 }
 
 #[derive(Clone)]
-pub struct CustomBigInt(BigDecimal);
+pub struct CustomBigInt(pub(crate) BigDecimal);
 
 #[Scalar(name = "BigInt")]
 impl ScalarType for CustomBigInt {
@@ -226,7 +1520,7 @@ impl LowerExp for CustomBigInt {
 }
 
 #[derive(Clone)]
-pub struct CustomBigDecimal(BigDecimal);
+pub struct CustomBigDecimal(pub(crate) BigDecimal);
 
 #[Scalar(name = "BigDecimal")]
 impl ScalarType for CustomBigDecimal {
@@ -236,6 +1530,11 @@ impl ScalarType for CustomBigDecimal {
                 let parsed_value = BigDecimal::from_str(&s)?;
                 Ok(CustomBigDecimal(parsed_value))
             }
+            // values received in the number format can be sent back as is
+            Value::Number(n) => {
+                let parsed_value = BigDecimal::from_str(&n.to_string())?;
+                Ok(CustomBigDecimal(parsed_value))
+            }
             _ => Err(InputValueError::expected_type(value)),
         }
     }
@@ -248,9 +1547,362 @@ impl ScalarType for CustomBigDecimal {
 #[derive(InputObject)]
 struct PlanetInput {
     name: String,
+    /// Determined by the classification rules if not specified
     #[graphql(name = "type")]
-    type_: PlanetType,
+    type_: Option<PlanetType>,
     details: DetailsInput,
+    /// An existing star with the same name is used as is; on update the star isn't changed if
+    /// absent, and null detaches the planet from its star
+    star: MaybeUndefined<StarInput>,
+}
+
+#[derive(InputObject)]
+struct StarInput {
+    name: String,
+    spectral_class: String,
+    /// In kilograms
+    mass: CustomBigInt,
+}
+
+#[derive(SimpleObject, Clone)]
+pub struct PlanetEvent {
+    /// Increases with each event
+    pub(crate) token: i64,
+    pub(crate) kind: PlanetEventKind,
+    /// State of the planet after the change
+    planet: Planet,
+}
+
+impl PlanetEvent {
+    pub(crate) fn is_planet_published(&self) -> bool {
+        self.planet.status == PlanetStatus::Published
+    }
+}
+
+/// Events received within an interval
+#[derive(SimpleObject)]
+pub struct PlanetEventDigest {
+    /// In order of tokens
+    events: Vec<PlanetEvent>,
+    /// Numbers of the events by kind, for the kinds which occurred
+    counts: Vec<PlanetEventKindCount>,
+    /// Token of the last event, to be passed as `resumeFrom`
+    last_token: i64,
+}
+
+#[derive(SimpleObject)]
+struct PlanetEventKindCount {
+    kind: PlanetEventKind,
+    count: i32,
+}
+
+impl PlanetEventDigest {
+    /// `events` aren't empty
+    fn new(events: Vec<PlanetEvent>) -> Self {
+        let mut counts: Vec<PlanetEventKindCount> = vec![];
+        for event in &events {
+            match counts.iter_mut().find(|count| count.kind == event.kind) {
+                Some(count) => count.count += 1,
+                None => counts.push(PlanetEventKindCount {
+                    kind: event.kind,
+                    count: 1,
+                }),
+            }
+        }
+        PlanetEventDigest {
+            last_token: events.last().map(|event| event.token).unwrap_or_default(),
+            events,
+            counts,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Enum, Display, EnumString)]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+pub enum PlanetEventKind {
+    Created,
+    Updated,
+    Deleted,
+    Published,
+    Archived,
+}
+
+#[derive(InputObject)]
+struct PlanetFilter {
+    /// Case-insensitive part of a name
+    name_contains: Option<String>,
+    #[graphql(name = "type")]
+    type_: Option<PlanetType>,
+}
+
+/// Lower bounds are inclusive, upper bounds are exclusive, and absent bounds are not checked;
+/// the first rule in order of priority which a planet satisfies determines its type
+#[derive(SimpleObject)]
+struct ClassificationRule {
+    planet_type: PlanetType,
+    min_mass: Option<CustomBigInt>,
+    max_mass: Option<CustomBigInt>,
+    /// In kilometers
+    min_mean_radius: Option<CustomBigDecimal>,
+    max_mean_radius: Option<CustomBigDecimal>,
+    priority: i32,
+}
+
+#[derive(InputObject)]
+struct ClassificationRuleInput {
+    planet_type: PlanetType,
+    /// In kilograms
+    min_mass: Option<CustomBigInt>,
+    max_mass: Option<CustomBigInt>,
+    /// In kilometers
+    min_mean_radius: Option<CustomBigDecimal>,
+    max_mean_radius: Option<CustomBigDecimal>,
+    /// The lower, the earlier the rule is checked
+    priority: i32,
+}
+
+#[derive(SimpleObject)]
+struct ServerInfo {
+    version: &'static str,
+    /// Commit the service is built from
+    git_sha: &'static str,
+    built_at: DateTime<Utc>,
+    /// Cargo features the service is compiled with; see `enabledFeatures` for feature flags
+    features: Vec<&'static str>,
+    /// Hex-encoded SHA-256 of the SDL from which clients are generated
+    schema_hash: &'static str,
+}
+
+#[derive(SimpleObject)]
+struct EnabledFeature {
+    name: String,
+    roles: Option<Vec<String>>,
+}
+
+#[derive(SimpleObject)]
+struct RenamedFieldUsage {
+    type_name: String,
+    old_name: String,
+    new_name: String,
+    /// Number of operations that requested the old name
+    operations: u64,
+    /// Seconds since the Unix epoch
+    last_used_at: Option<u64>,
+    /// Clients which still request the old name, the most recent first
+    user_agents: Vec<UserAgentUsage>,
+}
+
+#[derive(SimpleObject)]
+struct UserAgentUsage {
+    user_agent: String,
+    operations: u64,
+    /// Seconds since the Unix epoch
+    last_used_at: u64,
+}
+
+#[derive(SimpleObject)]
+struct BrokerTopicStats {
+    topic: String,
+    subscribers: u64,
+    /// Including messages published without subscribers
+    published: u64,
+    /// Messages skipped by subscribers which fell too far behind
+    skipped: u64,
+}
+
+#[derive(SimpleObject)]
+struct DataQualityReport {
+    generated_at: DateTime<Utc>,
+    findings: Vec<DataQualityFinding>,
+}
+
+#[derive(SimpleObject)]
+struct DataQualityFinding {
+    check: DataQualityCheck,
+    severity: Severity,
+    planet_id: ID,
+    planet_name: String,
+    message: String,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Enum, EnumString)]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+enum DataQualityCheck {
+    /// Details are required by the schema, so the planet can't be fully queried
+    MissingDetails,
+    NonPositiveMass,
+    NonPositiveMeanRadius,
+    /// The type differs from the one given by the classification rules
+    TypeMismatch,
+    /// The planet and its details haven't changed for a long time, or no change was recorded
+    Stale,
+}
+
+impl DataQualityCheck {
+    fn severity(&self) -> Severity {
+        match self {
+            DataQualityCheck::MissingDetails
+            | DataQualityCheck::NonPositiveMass
+            | DataQualityCheck::NonPositiveMeanRadius => Severity::Error,
+            DataQualityCheck::TypeMismatch => Severity::Warning,
+            DataQualityCheck::Stale => Severity::Info,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Enum)]
+enum Severity {
+    Error,
+    Warning,
+    Info,
+}
+
+impl TryFrom<model::DataQualityFindingEntity> for DataQualityFinding {
+    type Error = Error;
+
+    fn try_from(entity: model::DataQualityFindingEntity) -> Result<Self> {
+        let check = DataQualityCheck::from_str(&entity.check_name)?;
+        Ok(DataQualityFinding {
+            check,
+            severity: check.severity(),
+            planet_id: to_planet_id(entity.planet_id, entity.planet_uuid),
+            planet_name: entity.planet_name,
+            message: entity.message,
+        })
+    }
+}
+
+#[derive(SimpleObject)]
+struct ResponseCacheStats {
+    /// Queries served from the cache
+    hits: u64,
+    /// Queries executed
+    misses: u64,
+    hit_rate: f64,
+    entries: u64,
+}
+
+#[derive(SimpleObject)]
+struct MemoryStats {
+    /// Null unless jemalloc is the allocator
+    allocator: Option<AllocatorStats>,
+    /// Null if heap profiling isn't available
+    heap_profiling: Option<bool>,
+    caches: Vec<CacheMemory>,
+}
+
+/// In bytes
+#[derive(SimpleObject)]
+struct AllocatorStats {
+    allocated: u64,
+    active: u64,
+    resident: u64,
+    mapped: u64,
+    retained: u64,
+}
+
+#[derive(SimpleObject)]
+struct CacheMemory {
+    name: String,
+    entries: u64,
+    estimated_bytes: u64,
+}
+
+#[derive(SimpleObject)]
+#[graphql(complex)]
+struct Webhook {
+    #[graphql(skip)]
+    id: i32,
+    url: String,
+    /// Empty if events of all kinds are delivered
+    event_kinds: Vec<PlanetEventKind>,
+}
+
+#[ComplexObject]
+impl Webhook {
+    async fn id(&self) -> ID {
+        self.id.into()
+    }
+
+    /// The latest first
+    async fn deliveries(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 20)] limit: i64,
+    ) -> Result<Vec<WebhookDelivery>> {
+        let deliveries =
+            repository::get_webhook_deliveries(self.id, limit, &mut get_conn_from_ctx(ctx))?;
+        Ok(deliveries.iter().map(WebhookDelivery::from).collect())
+    }
+}
+
+#[derive(SimpleObject)]
+struct WebhookDelivery {
+    /// Token of the delivered event, as in `PlanetEvent`
+    event_token: i64,
+    status: DeliveryStatus,
+    attempts: i32,
+    last_error: Option<String>,
+    /// Only for pending deliveries
+    next_attempt_at: Option<DateTime<Utc>>,
+    delivered_at: Option<DateTime<Utc>>,
+}
+
+/// A named query saved by a user
+#[derive(SimpleObject)]
+struct Report {
+    name: String,
+    query: String,
+    variables: Json<serde_json::Value>,
+}
+
+/// Orbits are considered to lie in the same plane, so the distance is approximate
+#[derive(SimpleObject)]
+struct PlanetDistance {
+    distance: Distance,
+    light_travel_time: TravelTime,
+}
+
+impl PlanetDistance {
+    fn from_astronomical_units(astronomical_units: f64) -> Self {
+        let kilometers = astronomical_units * ASTRONOMICAL_UNIT_KILOMETERS;
+        let seconds = kilometers / SPEED_OF_LIGHT_KILOMETERS_PER_SECOND;
+        PlanetDistance {
+            distance: Distance {
+                kilometers,
+                astronomical_units,
+            },
+            light_travel_time: TravelTime {
+                seconds,
+                minutes: seconds / 60.0,
+            },
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+struct Distance {
+    kilometers: f64,
+    astronomical_units: f64,
+}
+
+#[derive(SimpleObject)]
+struct TravelTime {
+    seconds: f64,
+    minutes: f64,
+}
+
+#[derive(SimpleObject)]
+struct DeletePlanetsResult {
+    /// Number of deleted planets or, in case of dry run, of planets that would be deleted
+    affected: i64,
+    dry_run: bool,
+}
+
+#[derive(SimpleObject, Clone)]
+pub struct DeletionProgress {
+    deleted: i64,
+    total: i64,
 }
 
 #[derive(InputObject)]
@@ -261,46 +1913,328 @@ struct DetailsInput {
     mass: CustomBigInt,
     /// In billions
     population: Option<CustomBigDecimal>,
+    /// On update the atmosphere isn't changed if absent
+    atmosphere: Option<Vec<GasComponentInput>>,
+}
+
+/// Keplerian elements at the J2000 epoch; inclinations are neglected
+#[derive(InputObject)]
+struct OrbitInput {
+    /// In astronomical units
+    semi_major_axis: CustomBigDecimal,
+    /// From 0 (a circle) to 1, exclusive
+    eccentricity: CustomBigDecimal,
+    /// Sidereal, in days
+    orbital_period: CustomBigDecimal,
+    /// In degrees, from 0 to 360
+    longitude_of_perihelion: CustomBigDecimal,
+    /// In degrees, from 0 to 360
+    mean_longitude: CustomBigDecimal,
+}
+
+#[derive(InputObject)]
+struct GasComponentInput {
+    gas: String,
+    percentage: CustomBigDecimal,
+}
+
+/// The atmosphere is absent if it's not specified
+fn to_new_entities(
+    planet: PlanetInput,
+) -> Result<(
+    PlanetWrite,
+    NewDetailsEntity,
+    Option<Vec<GasComponentEntity>>,
+)> {
+    let details = planet.details;
+    let atmosphere = details
+        .atmosphere
+        .map(|components| {
+            mapping::to_atmosphere(
+                components
+                    .into_iter()
+                    .map(|component| (component.gas, component.percentage.0))
+                    .collect(),
+            )
+        })
+        .transpose()?;
+    let new_planet_details = NewDetailsEntity {
+        mean_radius: mapping::to_stored_mean_radius(&details.mean_radius.0)?,
+        mass: mapping::to_stored_mass(&details.mass.0)?,
+        population: details.population.map(|wrapper| wrapper.0),
+        planet_id: 0,
+    };
+
+    let star = match planet.star {
+        MaybeUndefined::Value(star) => Some(Some(NewStarEntity::from(star))),
+        MaybeUndefined::Null => Some(None),
+        MaybeUndefined::Undefined => None,
+    };
+
+    let new_planet = PlanetWrite {
+        name: planet.name,
+        type_: planet.type_.map(PlanetType::into),
+        star,
+        status: None,
+    };
+
+    Ok((new_planet, new_planet_details, atmosphere))
 }
 
 impl From<&PlanetEntity> for Planet {
     fn from(entity: &PlanetEntity) -> Self {
         Planet {
-            id: entity.id.into(),
+            id: entity.id,
+            uuid: entity.uuid,
             name: entity.name.clone(),
-            type_: PlanetType::from_str(entity.type_.as_str())
-                .expect("Can't convert &str to PlanetType"),
+            type_: entity.type_.into(),
+            star_id: entity.star_id,
+            status: PlanetStatus::from_str(entity.status.as_str())
+                .expect("Can't convert &str to PlanetStatus"),
+            as_of: None,
         }
     }
 }
 
-impl From<&DetailsEntity> for Details {
-    fn from(entity: &DetailsEntity) -> Self {
-        if entity.population.is_some() {
-            InhabitedPlanetDetails {
-                mean_radius: CustomBigDecimal(entity.mean_radius.clone()),
-                mass: CustomBigInt(entity.mass.clone()),
-                population: CustomBigDecimal(
-                    entity
-                        .population
-                        .as_ref()
-                        .expect("Can't get population")
-                        .clone(),
-                ),
-            }
-            .into()
-        } else {
-            UninhabitedPlanetDetails {
-                mean_radius: CustomBigDecimal(entity.mean_radius.clone()),
-                mass: CustomBigInt(entity.mass.clone()),
-            }
-            .into()
+impl From<PlanetType> for model::PlanetType {
+    fn from(type_: PlanetType) -> Self {
+        match type_ {
+            PlanetType::TerrestrialPlanet => model::PlanetType::TerrestrialPlanet,
+            PlanetType::GasGiant => model::PlanetType::GasGiant,
+            PlanetType::IceGiant => model::PlanetType::IceGiant,
+            PlanetType::DwarfPlanet => model::PlanetType::DwarfPlanet,
+        }
+    }
+}
+
+impl From<model::PlanetType> for PlanetType {
+    fn from(type_: model::PlanetType) -> Self {
+        match type_ {
+            model::PlanetType::TerrestrialPlanet => PlanetType::TerrestrialPlanet,
+            model::PlanetType::GasGiant => PlanetType::GasGiant,
+            model::PlanetType::IceGiant => PlanetType::IceGiant,
+            model::PlanetType::DwarfPlanet => PlanetType::DwarfPlanet,
+        }
+    }
+}
+
+impl From<PlanetStatus> for model::PlanetStatus {
+    fn from(status: PlanetStatus) -> Self {
+        match status {
+            PlanetStatus::Draft => model::PlanetStatus::Draft,
+            PlanetStatus::Published => model::PlanetStatus::Published,
+            PlanetStatus::Archived => model::PlanetStatus::Archived,
+        }
+    }
+}
+
+impl From<PlanetOrder> for PlanetsOrder {
+    fn from(order: PlanetOrder) -> Self {
+        PlanetsOrder {
+            column: match order.field {
+                PlanetOrderField::Id => PlanetsOrderColumn::Id,
+                PlanetOrderField::Name => PlanetsOrderColumn::Name,
+                PlanetOrderField::Type => PlanetsOrderColumn::Type,
+            },
+            descending: order.direction == OrderDirection::Desc,
+        }
+    }
+}
+
+impl From<ConflictPolicy> for model::ConflictPolicy {
+    fn from(policy: ConflictPolicy) -> Self {
+        match policy {
+            ConflictPolicy::Skip => model::ConflictPolicy::Skip,
+            ConflictPolicy::Overwrite => model::ConflictPolicy::Overwrite,
+            ConflictPolicy::Fail => model::ConflictPolicy::Fail,
+        }
+    }
+}
+
+impl From<&WebhookEntity> for Webhook {
+    fn from(entity: &WebhookEntity) -> Self {
+        Webhook {
+            id: entity.id,
+            url: entity.url.clone(),
+            event_kinds: entity
+                .event_kinds
+                .iter()
+                .map(|kind| {
+                    PlanetEventKind::from_str(kind).expect("Can't convert &str to PlanetEventKind")
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<&WebhookDeliveryEntity> for WebhookDelivery {
+    fn from(entity: &WebhookDeliveryEntity) -> Self {
+        let status =
+            DeliveryStatus::from_str(&entity.status).expect("Can't convert &str to DeliveryStatus");
+        WebhookDelivery {
+            event_token: entity.event_id,
+            status,
+            attempts: entity.attempts,
+            last_error: entity.last_error.clone(),
+            next_attempt_at: (status == DeliveryStatus::Pending).then_some(entity.next_attempt_at),
+            delivered_at: entity.delivered_at,
+        }
+    }
+}
+
+impl From<&ReportEntity> for Report {
+    fn from(entity: &ReportEntity) -> Self {
+        Report {
+            name: entity.name.clone(),
+            query: entity.query.clone(),
+            variables: Json(entity.variables.clone()),
+        }
+    }
+}
+
+impl From<&StarEntity> for Star {
+    fn from(entity: &StarEntity) -> Self {
+        Star {
+            id: ids::encode_id(entity.id).into(),
+            name: entity.name.clone(),
+            spectral_class: entity.spectral_class.clone(),
+            mass: CustomBigInt(entity.mass.clone()),
+        }
+    }
+}
+
+impl From<StarInput> for NewStarEntity {
+    fn from(input: StarInput) -> Self {
+        NewStarEntity {
+            name: input.name,
+            spectral_class: input.spectral_class,
+            mass: input.mass.0,
+        }
+    }
+}
+
+impl From<&ClassificationRuleEntity> for ClassificationRule {
+    fn from(entity: &ClassificationRuleEntity) -> Self {
+        ClassificationRule {
+            planet_type: entity.planet_type.into(),
+            min_mass: entity.min_mass.clone().map(CustomBigInt),
+            max_mass: entity.max_mass.clone().map(CustomBigInt),
+            min_mean_radius: entity
+                .min_mean_radius
+                .as_ref()
+                .map(|radius| CustomBigDecimal(units::meters_to_kilometers(radius))),
+            max_mean_radius: entity
+                .max_mean_radius
+                .as_ref()
+                .map(|radius| CustomBigDecimal(units::meters_to_kilometers(radius))),
+            priority: entity.priority,
+        }
+    }
+}
+
+impl From<ClassificationRuleInput> for NewClassificationRuleEntity {
+    fn from(input: ClassificationRuleInput) -> Self {
+        NewClassificationRuleEntity {
+            planet_type: input.planet_type.into(),
+            min_mass: input.min_mass.map(|wrapper| wrapper.0),
+            max_mass: input.max_mass.map(|wrapper| wrapper.0),
+            min_mean_radius: input
+                .min_mean_radius
+                .map(|wrapper| units::kilometers_to_meters(&wrapper.0)),
+            max_mean_radius: input
+                .max_mean_radius
+                .map(|wrapper| units::kilometers_to_meters(&wrapper.0)),
+            priority: input.priority,
+        }
+    }
+}
+
+impl From<&OutboxEventEntity> for PlanetEvent {
+    fn from(entity: &OutboxEventEntity) -> Self {
+        let planet: PlanetEntity =
+            serde_json::from_value(entity.payload.clone()).expect("Can't deserialize a planet");
+        PlanetEvent {
+            token: entity.id,
+            kind: PlanetEventKind::from_str(entity.kind.as_str())
+                .expect("Can't convert &str to PlanetEventKind"),
+            planet: Planet::from(&planet),
         }
     }
 }
 
+impl From<PlanetFilter> for PlanetsFilter {
+    fn from(filter: PlanetFilter) -> Self {
+        PlanetsFilter {
+            name_contains: filter.name_contains,
+            type_: filter.type_.map(PlanetType::into),
+        }
+    }
+}
+
+/// Request-scoped: each planet is loaded at most once per operation by its ID and once by its
+/// UUID, no matter how many times it's referenced by queries and entity representations
+pub type PlanetIdentityMap = DataLoader<PlanetLoader, HashMapCache>;
+
+/// Shared by requests, see [find_planet_by_id](Query::find_planet_by_id)
+pub(crate) type PlanetEntityCache = EntityCache<PlanetKey, Planet>;
+
+pub struct PlanetLoader {
+    pub pool: Arc<ReloadablePool>,
+}
+
+#[async_trait::async_trait]
+impl Loader<PlanetKey> for PlanetLoader {
+    type Value = Planet;
+    type Error = Error;
+
+    async fn load(
+        &self,
+        keys: &[PlanetKey],
+    ) -> Result<HashMap<PlanetKey, Self::Value>, Self::Error> {
+        let ids: Vec<i32> = keys
+            .iter()
+            .filter_map(|key| match key {
+                PlanetKey::Id(id) => Some(*id),
+                _ => None,
+            })
+            .collect();
+        let uuids: Vec<Uuid> = keys
+            .iter()
+            .filter_map(|key| match key {
+                PlanetKey::Uuid(uuid) => Some(*uuid),
+                _ => None,
+            })
+            .collect();
+        let (planets_by_id, planets_by_uuid) = load_blocking(&self.pool, move |conn| {
+            let planets_by_id = if ids.is_empty() {
+                vec![]
+            } else {
+                repository::get_by_ids(&ids, conn)?
+            };
+            let planets_by_uuid = if uuids.is_empty() {
+                vec![]
+            } else {
+                repository::get_by_uuids(&uuids, conn)?
+            };
+            Ok((planets_by_id, planets_by_uuid))
+        })
+        .await?;
+
+        let planets_by_id = planets_by_id
+            .iter()
+            .map(|planet_entity| (PlanetKey::Id(planet_entity.id), Planet::from(planet_entity)));
+        let planets_by_uuid = planets_by_uuid.iter().map(|planet_entity| {
+            (
+                PlanetKey::Uuid(planet_entity.uuid),
+                Planet::from(planet_entity),
+            )
+        });
+        Ok(planets_by_id.chain(planets_by_uuid).collect())
+    }
+}
+
 pub struct DetailsLoader {
-    pub pool: Arc<PgPool>,
+    pub pool: Arc<ReloadablePool>,
 }
 
 #[async_trait::async_trait]
@@ -309,16 +2243,146 @@ impl Loader<i32> for DetailsLoader {
     type Error = Error;
 
     async fn load(&self, keys: &[i32]) -> Result<HashMap<i32, Self::Value>, Self::Error> {
-        let mut conn = self.pool.get()?;
-        let details = repository::get_details(keys, &mut conn)?;
+        let keys = keys.to_vec();
+        let details =
+            load_blocking(&self.pool, move |conn| repository::get_details(&keys, conn)).await?;
+
+        let details = details
+            .iter()
+            .map(|details_entity| {
+                Ok((
+                    details_entity.planet_id,
+                    mapping::to_details(details_entity)?,
+                ))
+            })
+            .collect::<Result<HashMap<_, _>, String>>()?;
+        Ok(details)
+    }
+}
+
+/// Details of planets as of moments, see `details_history`
+pub struct DetailsAsOfLoader {
+    pub pool: Arc<ReloadablePool>,
+}
+
+#[async_trait::async_trait]
+impl Loader<(i32, DateTime<Utc>)> for DetailsAsOfLoader {
+    type Value = Details;
+    type Error = Error;
+
+    async fn load(
+        &self,
+        keys: &[(i32, DateTime<Utc>)],
+    ) -> Result<HashMap<(i32, DateTime<Utc>), Self::Value>, Self::Error> {
+        // usually all planets of an operation are read as of the same moment
+        let mut planet_ids_by_moment: HashMap<DateTime<Utc>, Vec<i32>> = HashMap::new();
+        for (planet_id, as_of) in keys {
+            planet_ids_by_moment
+                .entry(*as_of)
+                .or_default()
+                .push(*planet_id);
+        }
+
+        let mut details = HashMap::new();
+        for (as_of, planet_ids) in planet_ids_by_moment {
+            let entities = load_blocking(&self.pool, move |conn| {
+                repository::get_details_as_of(&planet_ids, as_of, conn)
+            })
+            .await?;
+            for entity in &entities {
+                details.insert((entity.planet_id, as_of), mapping::to_details(entity)?);
+            }
+        }
+        Ok(details)
+    }
+}
+
+pub struct AtmosphereLoader {
+    pub pool: Arc<ReloadablePool>,
+}
+
+#[async_trait::async_trait]
+impl Loader<i32> for AtmosphereLoader {
+    type Value = Vec<GasComponent>;
+    type Error = Error;
+
+    async fn load(&self, keys: &[i32]) -> Result<HashMap<i32, Self::Value>, Self::Error> {
+        let keys = keys.to_vec();
+        let components = load_blocking(&self.pool, move |conn| {
+            repository::get_atmospheres(&keys, conn)
+        })
+        .await?;
+
+        let mut atmospheres: HashMap<i32, Vec<GasComponent>> = HashMap::new();
+        for component in components {
+            atmospheres
+                .entry(component.planet_id)
+                .or_default()
+                .push(GasComponent {
+                    gas: component.gas,
+                    percentage: CustomBigDecimal(component.percentage),
+                });
+        }
+        Ok(atmospheres)
+    }
+}
+
+pub struct StarLoader {
+    pub pool: Arc<ReloadablePool>,
+}
+
+#[async_trait::async_trait]
+impl Loader<i32> for StarLoader {
+    type Value = Star;
+    type Error = Error;
+
+    async fn load(&self, keys: &[i32]) -> Result<HashMap<i32, Self::Value>, Self::Error> {
+        let keys = keys.to_vec();
+        let stars =
+            load_blocking(&self.pool, move |conn| repository::get_stars(&keys, conn)).await?;
 
-        Ok(details
+        Ok(stars
             .iter()
-            .map(|details_entity| (details_entity.planet_id, Details::from(details_entity)))
+            .map(|star_entity| (star_entity.id, Star::from(star_entity)))
             .collect::<HashMap<_, _>>())
     }
 }
 
+/// Diesel calls block, so loaders run them on the blocking thread pool: otherwise batches
+/// (e.g. details of many planets) would be loaded one after another, blocking the executor
+async fn load_blocking<T, F>(pool: &Arc<ReloadablePool>, load: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&mut PgConnection) -> QueryResult<T> + Send + 'static,
+{
+    let pool = Arc::clone(pool);
+    tokio::task::spawn_blocking(move || {
+        let mut conn = pool.get()?;
+        Ok(load(&mut conn)?)
+    })
+    .await?
+}
+
+/// Serializable transactions sleep between attempts, so they run on the blocking pool
+async fn write_blocking<T, F>(ctx: &Context<'_>, write: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&mut PgConnection) -> QueryResult<T> + Send + 'static,
+{
+    load_blocking(
+        ctx.data::<Arc<ReloadablePool>>().expect("Can't get pool"),
+        write,
+    )
+    .await
+}
+
+// TODO: auth disabling is needed for tests. try to reimplement when https://github.com/rust-lang/rust/issues/45599 will be resolved (using cfg(test))
+fn is_auth_disabled() -> bool {
+    env::var("DISABLE_AUTH")
+        .map(|boolean| bool::from_str(boolean.as_str()).expect("Can't parse bool"))
+        .unwrap_or(false)
+}
+
 struct RoleGuard {
     role: Role,
 }
@@ -332,13 +2396,9 @@ impl RoleGuard {
 #[async_trait::async_trait]
 impl Guard for RoleGuard {
     async fn check(&self, ctx: &Context<'_>) -> Result<()> {
-        // TODO: auth disabling is needed for tests. try to reimplement when https://github.com/rust-lang/rust/issues/45599 will be resolved (using cfg(test))
-        if let Ok(boolean) = env::var("DISABLE_AUTH") {
-            let disable_auth = bool::from_str(boolean.as_str()).expect("Can't parse bool");
-            if disable_auth {
-                return Ok(());
-            }
-        };
+        if is_auth_disabled() {
+            return Ok(());
+        }
 
         let maybe_getting_role_result = ctx.data_opt::<Result<Option<Role>, CustomError>>();
         match maybe_getting_role_result {
@@ -0,0 +1,352 @@
+use actix_web::body::BoxBody;
+use actix_web::http::header::{ACCEPT, ACCEPT_LANGUAGE, CACHE_CONTROL, CONTENT_TYPE, USER_AGENT};
+use actix_web::{error, guard, web, HttpRequest, HttpResponse, Responder, Result};
+use async_graphql::http::{playground_source, GraphQLPlaygroundConfig};
+use async_graphql::{Request, Response, Schema, Variables};
+use async_graphql_actix_web::{GraphQLRequest, GraphQLResponse};
+use serde::Deserialize;
+
+#[cfg(feature = "chaos")]
+use crate::chaos::{FaultsHeader, FAULTS_HEADER};
+use crate::decimal_format::{BigDecimalFormat, FORMAT_HEADER};
+use crate::descriptions::AcceptLanguage;
+use crate::graphql::{AppSchema, CurrentUser, OperationRunner, UserAgent};
+use crate::http_client::{self, RequestContext, REQUEST_ID_HEADER, TRACEPARENT_HEADER};
+use crate::masking::{self, ApiKey};
+use crate::metrics;
+use crate::sse;
+use crate::subscription;
+use crate::validation::ValidateOnly;
+
+pub fn configure_service(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/")
+            .route(
+                web::post()
+                    .guard(guard::fn_guard(is_graphql_body))
+                    .to(index_graphql_body),
+            )
+            .route(web::post().to(index))
+            .route(
+                web::get()
+                    .guard(guard::Header("upgrade", "websocket"))
+                    .to(index_ws),
+            )
+            .route(web::get().to(index_playground)),
+    )
+    .service(web::resource("/validate").route(web::post().to(validate)))
+    .service(
+        web::resource("/subscriptions/sse")
+            .route(web::get().to(index_sse_get))
+            .route(web::post().to(index_sse_post)),
+    );
+}
+
+/// Endpoints for operators, served on a port which isn't exposed publicly
+pub fn configure_admin_service(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/metrics").route(web::get().to(index_metrics)));
+}
+
+async fn index(
+    schema: web::Data<AppSchema>,
+    http_req: HttpRequest,
+    req: GraphQLRequest,
+) -> NegotiatedResponse {
+    let context = get_request_context(&http_req);
+    let query = with_request_data(req.into_inner(), &schema, http_req);
+    NegotiatedResponse(http_client::with_context(context, schema.execute(query)).await)
+}
+
+/// Identifiers passed by the caller, which outbound calls made during execution carry on
+pub(crate) fn get_request_context(http_req: &HttpRequest) -> RequestContext {
+    let get_header = |name| {
+        http_req
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+    };
+    RequestContext::continue_from(
+        get_header(REQUEST_ID_HEADER).map(String::from),
+        get_header(TRACEPARENT_HEADER),
+    )
+}
+
+/// Data of the HTTP request needed to execute an operation
+fn with_request_data(request: Request, schema: &AppSchema, http_req: HttpRequest) -> Request {
+    let mut request = request.data(OperationRunner(Schema::clone(schema)));
+    if let Some(user) = common_utils::get_user(&http_req) {
+        request = request.data(CurrentUser(user));
+    }
+    if let Some(user_agent) = http_req
+        .headers()
+        .get(USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+    {
+        request = request.data(UserAgent(user_agent.to_string()));
+    }
+    if let Some(api_key) = http_req
+        .headers()
+        .get("x-api-key")
+        .and_then(|value| value.to_str().ok())
+    {
+        request = request.data(ApiKey(api_key.to_string()));
+    }
+    if let Some(accept_language) = http_req
+        .headers()
+        .get(ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok())
+    {
+        request = request.data(AcceptLanguage(accept_language.to_string()));
+    }
+    // an unknown format falls back to the default one
+    if let Some(format) = http_req
+        .headers()
+        .get(FORMAT_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<BigDecimalFormat>().ok())
+    {
+        request = request.data(format);
+    }
+    #[cfg(feature = "chaos")]
+    if let Some(faults) = http_req
+        .headers()
+        .get(FAULTS_HEADER)
+        .and_then(|value| value.to_str().ok())
+    {
+        request = request.data(FaultsHeader(faults.to_string()));
+    }
+    request.data(common_utils::get_role(http_req))
+}
+
+fn is_graphql_body(ctx: &guard::GuardContext<'_>) -> bool {
+    ctx.head()
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/graphql"))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphQLBodyParams {
+    operation_name: Option<String>,
+    /// JSON object
+    variables: Option<String>,
+}
+
+/// Serves `application/graphql` requests: the body is the query itself, while the variables and
+/// the operation name are passed as query parameters
+async fn index_graphql_body(
+    schema: web::Data<AppSchema>,
+    http_req: HttpRequest,
+    params: web::Query<GraphQLBodyParams>,
+    body: String,
+) -> Result<NegotiatedResponse> {
+    let context = get_request_context(&http_req);
+    let query = to_request(body, params.into_inner())?;
+    let query = with_request_data(query, &schema, http_req);
+    Ok(NegotiatedResponse(
+        http_client::with_context(context, schema.execute(query)).await,
+    ))
+}
+
+fn to_request(query: String, params: GraphQLBodyParams) -> Result<Request> {
+    let mut request = Request::new(query);
+    if let Some(operation_name) = params.operation_name {
+        request = request.operation_name(operation_name);
+    }
+    if let Some(variables) = params.variables {
+        let variables = serde_json::from_str::<Variables>(&variables)
+            .map_err(|e| error::ErrorBadRequest(format!("Invalid variables: {}", e)))?;
+        request = request.variables(variables);
+    }
+    Ok(request)
+}
+
+#[derive(Deserialize)]
+struct SseParams {
+    query: String,
+    #[serde(flatten)]
+    params: GraphQLBodyParams,
+}
+
+/// GraphQL over SSE, a firewall-friendly alternative to WebSocket subscriptions; with GET the
+/// operation is passed in query parameters
+async fn index_sse_get(
+    schema: web::Data<AppSchema>,
+    http_req: HttpRequest,
+    params: web::Query<SseParams>,
+) -> Result<HttpResponse> {
+    let params = params.into_inner();
+    let request = to_request(params.query, params.params)?;
+    Ok(start_sse(&schema, request, http_req))
+}
+
+async fn index_sse_post(
+    schema: web::Data<AppSchema>,
+    http_req: HttpRequest,
+    req: GraphQLRequest,
+) -> HttpResponse {
+    start_sse(&schema, req.into_inner(), http_req)
+}
+
+fn start_sse(schema: &AppSchema, request: Request, http_req: HttpRequest) -> HttpResponse {
+    let last_event_id = http_req
+        .headers()
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .map(String::from);
+    let principal = common_utils::get_principal(&http_req, masking::is_configured_api_key);
+    let context = get_request_context(&http_req);
+    let request = with_request_data(request, schema, http_req);
+    sse::stream_response(
+        schema,
+        request,
+        principal,
+        context,
+        last_event_id.as_deref(),
+    )
+}
+
+/// Parses and validates an operation without executing it, so clients can check the operation's
+/// complexity and depth against the server's limits
+async fn validate(
+    schema: web::Data<AppSchema>,
+    http_req: HttpRequest,
+    req: GraphQLRequest,
+) -> NegotiatedResponse {
+    let mut query = req.into_inner();
+    let getting_role_result = common_utils::get_role(http_req);
+    query = query.data(getting_role_result).data(ValidateOnly);
+    NegotiatedResponse(schema.execute(query).await)
+}
+
+async fn index_ws(
+    schema: web::Data<AppSchema>,
+    req: HttpRequest,
+    payload: web::Payload,
+) -> Result<HttpResponse> {
+    let principal = common_utils::get_principal(&req, masking::is_configured_api_key);
+    subscription::start_connection(Schema::clone(&*schema), principal, &req, payload)
+}
+
+/// Scrape endpoint of the Prometheus metrics backend
+async fn index_metrics() -> HttpResponse {
+    match metrics::render() {
+        Some(metrics) => HttpResponse::Ok()
+            .content_type("text/plain; version=0.0.4; charset=utf-8")
+            .body(metrics),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+async fn index_playground() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(playground_source(
+            GraphQLPlaygroundConfig::new("/").subscription_endpoint("/"),
+        ))
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum BinaryFormat {
+    MessagePack,
+    Cbor,
+}
+
+/// The binary format preferred by `Accept`, if any. Media ranges are ordered by their quality
+/// (ranges of the same quality keep their order); `q=0` excludes a format, and JSON is served if
+/// it or a wildcard is preferred
+fn negotiate_format(accept: Option<&str>) -> Option<BinaryFormat> {
+    let mut formats: Vec<(Option<BinaryFormat>, f32)> = accept?
+        .split(',')
+        .filter_map(|media_range| {
+            let mut parts = media_range.split(';');
+            let format = match parts.next()?.trim() {
+                "application/msgpack" | "application/x-msgpack" => Some(BinaryFormat::MessagePack),
+                "application/cbor" => Some(BinaryFormat::Cbor),
+                "application/json"
+                | "application/graphql-response+json"
+                | "application/*"
+                | "*/*" => None,
+                _ => return None,
+            };
+            let quality = parts
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.parse().ok())
+                .unwrap_or(1.0);
+            (quality > 0.0).then_some((format, quality))
+        })
+        .collect();
+    formats.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    formats.first()?.0
+}
+
+/// Serializes a response to MessagePack or CBOR for clients that accept it, otherwise to JSON
+struct NegotiatedResponse(Response);
+
+impl Responder for NegotiatedResponse {
+    type Body = BoxBody;
+
+    fn respond_to(self, req: &HttpRequest) -> HttpResponse {
+        let accept = req
+            .headers()
+            .get(ACCEPT)
+            .and_then(|value| value.to_str().ok());
+        let encoded = match negotiate_format(accept) {
+            Some(BinaryFormat::MessagePack) => rmp_serde::to_vec_named(&self.0)
+                .map(|body| ("application/msgpack", body))
+                .map_err(|e| e.to_string()),
+            Some(BinaryFormat::Cbor) => {
+                let mut body = Vec::new();
+                ciborium::into_writer(&self.0, &mut body)
+                    .map(|_| ("application/cbor", body))
+                    .map_err(|e| e.to_string())
+            }
+            None => return GraphQLResponse::from(self.0).respond_to(req),
+        };
+        let (content_type, body) = match encoded {
+            Ok(encoded) => encoded,
+            Err(e) => return HttpResponse::InternalServerError().body(e),
+        };
+
+        let mut response = HttpResponse::Ok();
+        if self.0.is_ok() {
+            if let Some(cache_control) = self.0.cache_control.value() {
+                response.insert_header((CACHE_CONTROL, cache_control));
+            }
+        }
+        for (name, value) in self.0.http_headers.iter() {
+            response.append_header((name.clone(), value.clone()));
+        }
+        response.content_type(content_type).body(body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiate_format() {
+        assert_eq!(None, negotiate_format(None));
+        assert_eq!(None, negotiate_format(Some("application/json, */*")));
+        assert_eq!(
+            Some(BinaryFormat::MessagePack),
+            negotiate_format(Some("application/msgpack"))
+        );
+        assert_eq!(
+            Some(BinaryFormat::Cbor),
+            negotiate_format(Some("application/json;q=0.5, application/cbor;q=0.9"))
+        );
+        assert_eq!(
+            None,
+            negotiate_format(Some("application/msgpack;q=0.5, application/json"))
+        );
+        assert_eq!(
+            Some(BinaryFormat::Cbor),
+            negotiate_format(Some("application/msgpack;q=0, text/html, application/cbor"))
+        );
+        assert_eq!(None, negotiate_format(Some("application/cbor; q=0")));
+    }
+}
@@ -0,0 +1,259 @@
+//! The HTTP client shared by outbound integrations, so that they reuse connections and behave
+//! the same way on failures
+use std::env;
+use std::future::Future;
+use std::time::Duration;
+
+use futures::{stream, Stream, StreamExt};
+use lazy_static::lazy_static;
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use tokio::task::JoinHandle;
+
+use crate::trace_context::TraceContext;
+
+/// Identifies the operation which caused a call, so that logs of both sides can be correlated
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+/// W3C Trace Context
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+
+lazy_static! {
+    static ref TIMEOUT: Duration = Duration::from_secs(
+        env::var("HTTP_CLIENT_TIMEOUT_SECONDS")
+            .map(|timeout| timeout
+                .parse()
+                .expect("Can't parse HTTP_CLIENT_TIMEOUT_SECONDS"))
+            .unwrap_or(30)
+    );
+    static ref MAX_IDLE_CONNECTIONS_PER_HOST: usize =
+        env::var("HTTP_CLIENT_MAX_IDLE_CONNECTIONS_PER_HOST")
+            .map(|max| max
+                .parse()
+                .expect("Can't parse HTTP_CLIENT_MAX_IDLE_CONNECTIONS_PER_HOST"))
+            .unwrap_or(16);
+    static ref CLIENT: Client = Client::builder()
+        .user_agent(concat!("planets-service/", env!("CARGO_PKG_VERSION")))
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(*TIMEOUT)
+        .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+        .pool_max_idle_per_host(*MAX_IDLE_CONNECTIONS_PER_HOST)
+        .build()
+        .expect("Can't create HTTP client");
+}
+
+tokio::task_local! {
+    static REQUEST_CONTEXT: RequestContext;
+}
+
+/// Pooled client with the default timeout; a request can override the timeout
+pub fn client() -> &'static Client {
+    &CLIENT
+}
+
+/// Identifiers of the incoming request, propagated to calls made while it's handled
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RequestContext {
+    pub request_id: Option<String>,
+    pub trace: Option<TraceContext>,
+}
+
+impl RequestContext {
+    /// Identifiers passed by the caller, e.g. in headers; the service joins the caller's trace or
+    /// starts a new one
+    pub fn continue_from(request_id: Option<String>, traceparent: Option<&str>) -> Self {
+        RequestContext {
+            request_id,
+            trace: Some(TraceContext::continue_from(traceparent)),
+        }
+    }
+}
+
+/// Runs `f` so that calls made by [send] in the same task carry the context
+pub async fn with_context<F: Future>(context: RequestContext, f: F) -> F::Output {
+    REQUEST_CONTEXT.scope(context, f).await
+}
+
+/// Like [with_context] for a stream, e.g. of a subscription, which is polled by another task
+pub fn with_stream_context<S>(context: RequestContext, stream: S) -> impl Stream<Item = S::Item>
+where
+    S: Stream + Unpin,
+{
+    stream::unfold(stream, move |mut stream| {
+        with_context(context.clone(), async move {
+            let item = stream.next().await?;
+            Some((item, stream))
+        })
+    })
+}
+
+/// The context of the current task; empty outside of a request
+pub fn current_context() -> RequestContext {
+    REQUEST_CONTEXT
+        .try_with(RequestContext::clone)
+        .unwrap_or_default()
+}
+
+/// Spawns a task which inherits the context of the current one, e.g. a batch of a data loader
+pub fn spawn<F>(f: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    tokio::spawn(with_context(current_context(), f))
+}
+
+/// Exponential backoff between attempts
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// For callers which retry themselves or can't repeat a request
+    pub const NONE: RetryPolicy = RetryPolicy {
+        max_attempts: 1,
+        base_delay: Duration::ZERO,
+        max_delay: Duration::ZERO,
+    };
+
+    fn get_delay(&self, attempt: u32) -> Duration {
+        self.base_delay
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_delay)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(200),
+            max_delay: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Sends a request with the headers of the current [RequestContext], repeating it on connection
+/// failures, timeouts, 429 and 5xx responses. The response of the last attempt is returned as is,
+/// so the caller decides whether a status is an error. A request with a streamed body is sent once
+pub async fn send(request: RequestBuilder, policy: &RetryPolicy) -> reqwest::Result<Response> {
+    let request = with_context_headers(request);
+    let mut attempt = 1;
+    loop {
+        let retry = if attempt < policy.max_attempts {
+            request.try_clone()
+        } else {
+            None
+        };
+        let Some(retry) = retry else {
+            return request.send().await;
+        };
+        match retry.send().await {
+            Ok(response) if !is_retryable_status(response.status()) => return Ok(response),
+            Err(e) if !is_retryable_error(&e) => return Err(e),
+            Ok(response) => println!(
+                "HTTP call failed (attempt {} of {}): {}",
+                attempt,
+                policy.max_attempts,
+                response.status()
+            ),
+            Err(e) => println!(
+                "HTTP call failed (attempt {} of {}): {}",
+                attempt, policy.max_attempts, e
+            ),
+        }
+        tokio::time::sleep(policy.get_delay(attempt)).await;
+        attempt += 1;
+    }
+}
+
+fn with_context_headers(mut request: RequestBuilder) -> RequestBuilder {
+    let context = current_context();
+    if let Some(request_id) = context.request_id {
+        request = request.header(REQUEST_ID_HEADER, request_id);
+    }
+    if let Some(trace) = context.trace {
+        request = request.header(TRACEPARENT_HEADER, trace.to_string());
+    }
+    request
+}
+
+fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS
+        || (status.is_server_error() && status != StatusCode::NOT_IMPLEMENTED)
+}
+
+fn is_retryable_error(error: &reqwest::Error) -> bool {
+    error.is_connect() || error.is_timeout()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_delay_grows_until_limit() {
+        let policy = RetryPolicy::default();
+        assert_eq!(Duration::from_millis(200), policy.get_delay(1));
+        assert_eq!(Duration::from_millis(400), policy.get_delay(2));
+        assert_eq!(Duration::from_millis(800), policy.get_delay(3));
+        assert_eq!(Duration::from_secs(5), policy.get_delay(10));
+        assert_eq!(Duration::from_secs(5), policy.get_delay(100));
+    }
+
+    #[test]
+    fn retryable_statuses() {
+        assert!(is_retryable_status(StatusCode::TOO_MANY_REQUESTS));
+        assert!(is_retryable_status(StatusCode::BAD_GATEWAY));
+        assert!(is_retryable_status(StatusCode::SERVICE_UNAVAILABLE));
+        assert!(!is_retryable_status(StatusCode::NOT_IMPLEMENTED));
+        assert!(!is_retryable_status(StatusCode::BAD_REQUEST));
+        assert!(!is_retryable_status(StatusCode::OK));
+    }
+
+    #[tokio::test]
+    async fn context_headers_are_propagated() {
+        let context = RequestContext {
+            request_id: Some("42".to_string()),
+            trace: Some(TraceContext::continue_from(Some(
+                "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            ))),
+        };
+        let request = with_context(context, async {
+            with_context_headers(client().get("http://localhost/"))
+                .build()
+                .expect("Can't build request")
+        })
+        .await;
+        assert_eq!("42", request.headers()[REQUEST_ID_HEADER]);
+        let traceparent = request.headers()[TRACEPARENT_HEADER]
+            .to_str()
+            .expect("Can't read traceparent");
+        assert!(traceparent.starts_with("00-4bf92f3577b34da6a3ce929d0e0e4736-"));
+        assert!(!traceparent.contains("00f067aa0ba902b7"));
+
+        let request = with_context_headers(client().get("http://localhost/"))
+            .build()
+            .expect("Can't build request");
+        assert!(request.headers().get(REQUEST_ID_HEADER).is_none());
+    }
+
+    #[tokio::test]
+    async fn spawned_tasks_inherit_context() {
+        let context = RequestContext {
+            request_id: Some("42".to_string()),
+            trace: None,
+        };
+        let request_id = with_context(context, async {
+            spawn(async { current_context().request_id })
+                .await
+                .expect("Can't join task")
+        })
+        .await;
+        assert_eq!(Some("42".to_string()), request_id);
+    }
+}
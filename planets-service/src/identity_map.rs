@@ -0,0 +1,52 @@
+use std::sync::Arc;
+
+use async_graphql::dataloader::{DataLoader, HashMapCache};
+use async_graphql::extensions::{
+    Extension, ExtensionContext, ExtensionFactory, NextPrepareRequest,
+};
+use async_graphql::{Request, ServerResult};
+
+use crate::graphql::PlanetLoader;
+use crate::http_client;
+use crate::persistence::connection::ReloadablePool;
+
+/// Adds a new [crate::graphql::PlanetIdentityMap] to each request, so that repeated entity
+/// representations and identical lookups within an operation hit the database once
+pub struct IdentityMap {
+    pool: Arc<ReloadablePool>,
+}
+
+impl IdentityMap {
+    pub fn new(pool: Arc<ReloadablePool>) -> Self {
+        Self { pool }
+    }
+}
+
+impl ExtensionFactory for IdentityMap {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(IdentityMapExtension {
+            pool: Arc::clone(&self.pool),
+        })
+    }
+}
+
+struct IdentityMapExtension {
+    pool: Arc<ReloadablePool>,
+}
+
+#[async_trait::async_trait]
+impl Extension for IdentityMapExtension {
+    async fn prepare_request(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        request: Request,
+        next: NextPrepareRequest<'_>,
+    ) -> ServerResult<Request> {
+        let planet_loader = PlanetLoader {
+            pool: Arc::clone(&self.pool),
+        };
+        let identity_map =
+            DataLoader::with_cache(planet_loader, http_client::spawn, HashMapCache::default());
+        next.run(ctx, request.data(identity_map)).await
+    }
+}
@@ -4,10 +4,13 @@ use std::time::Duration;
 use lazy_static::lazy_static;
 use rdkafka::config::RDKafkaLogLevel;
 use rdkafka::consumer::{Consumer, StreamConsumer};
+use rdkafka::message::{Header, OwnedHeaders};
 use rdkafka::producer::{FutureProducer, FutureRecord};
 use rdkafka::util::Timeout;
 use rdkafka::ClientConfig;
 
+use crate::http_client::{self, TRACEPARENT_HEADER};
+
 lazy_static! {
     static ref KAFKA_BROKER: String =
         std::env::var("KAFKA_BROKER").expect("Can't read Kafka broker address");
@@ -49,13 +52,24 @@ pub fn get_kafka_consumer_group_id(kafka_consumer_counter: &Mutex<i32>) -> Strin
 
 // TODO: send without caller blocking
 pub async fn send_message(producer: &FutureProducer, message: &str) {
+    #[cfg(feature = "chaos")]
+    if crate::chaos::is_broker_failing() {
+        println!("Message wasn't sent: injected fault");
+        return;
+    }
+
+    let mut record = FutureRecord::to(&KAFKA_TOPIC)
+        .payload(message)
+        .key("new_planet");
+    // consumers join the trace of the request which produced the message
+    if let Some(trace) = http_client::current_context().trace {
+        record = record.headers(OwnedHeaders::new().insert(Header {
+            key: TRACEPARENT_HEADER,
+            value: Some(&trace.to_string()),
+        }));
+    }
     let delivery_status = producer
-        .send(
-            FutureRecord::to(&KAFKA_TOPIC)
-                .payload(message)
-                .key("new_planet"),
-            Timeout::After(Duration::from_secs(0)),
-        )
+        .send(record, Timeout::After(Duration::from_secs(0)))
         .await;
 
     match delivery_status {
@@ -1,82 +1,201 @@
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
+#[cfg(feature = "kafka")]
+use std::sync::Mutex;
 
-use actix_web::{guard, web, HttpRequest, HttpResponse, Result};
-use async_graphql::dataloader::DataLoader;
-use async_graphql::http::{playground_source, GraphQLPlaygroundConfig};
-use async_graphql::{Context, Schema};
-use async_graphql_actix_web::{GraphQLRequest, GraphQLResponse, GraphQLSubscription};
+use async_graphql::{Context, Request, Response, Schema};
 use diesel::r2d2::{ConnectionManager, PooledConnection};
 use diesel::PgConnection;
 use diesel_migrations::MigrationHarness;
 
-use crate::graphql::{AppSchema, DetailsLoader, Mutation, Query, Subscription};
-use crate::persistence::connection::PgPool;
+use crate::batch_trace::TracedDataLoader;
+use crate::broker::{Broker, Topic};
+use crate::decimal_format::DecimalFormatter;
+use crate::descriptions::DescriptionEnricher;
+use crate::feature_flags::{ConfigFeatureFlags, FeatureFlagProvider};
+use crate::graphql::{
+    AppSchema, AtmosphereLoader, DeletionProgress, DetailsAsOfLoader, DetailsLoader, Mutation,
+    PlanetEntityCache, PlanetEvent, Query, StarLoader, Subscription,
+};
+use crate::identity_map::IdentityMap;
+use crate::load_shedding::LoadShedder;
+use crate::masking::{ConsumerProfiles, FieldMasker};
+use crate::metrics::OperationMetrics;
+use crate::operation_log::OperationLogger;
+use crate::persistence::connection::{PgPool, ReloadablePool};
+use crate::persistence::repository;
+use crate::persistence::schema_check;
+use crate::redirects::RedirectTracker;
+use crate::renames::RenamedFieldTracker;
+use crate::response_cache::ResponseCache;
+use crate::validation::{OperationLimiter, Validator, QUERY_LIMITS};
 
+#[cfg(feature = "actix")]
+pub use crate::http::{configure_admin_service, configure_service};
+
+mod batch_trace;
+mod broker;
+pub mod build_info;
+#[cfg(feature = "chaos")]
+mod chaos;
+mod classification;
+pub mod decimal_format;
+mod descriptions;
+mod entity_cache;
+mod event_policy;
+pub mod feature_flags;
 pub mod graphql;
+#[cfg(feature = "actix")]
+mod http;
+pub mod http_client;
+mod identity_map;
+#[cfg(feature = "kafka")]
 mod kafka;
+mod load_shedding;
+mod mapping;
+mod masking;
+pub mod memory;
+pub mod metrics;
+#[cfg(feature = "nats")]
+pub mod nats;
+mod operation_log;
+mod operation_variables;
+mod orbits;
 pub mod persistence;
+mod redirects;
+mod renames;
+pub mod replay;
+pub mod response_cache;
+mod schema_coordinates;
+pub mod schema_registry;
+pub mod secrets;
+pub mod smoke;
+mod snapshot;
+#[cfg(feature = "actix")]
+mod sse;
+#[cfg(feature = "actix")]
+mod subscription;
+mod trace_context;
+mod validation;
+pub mod webhooks;
+
+/// Planets whose details are read by one query
+pub const DETAILS_BATCH_SIZE: usize = 10;
 
 const MIGRATIONS: diesel_migrations::EmbeddedMigrations =
     diesel_migrations::embed_migrations!("./migrations");
 
-pub fn configure_service(cfg: &mut web::ServiceConfig) {
-    cfg.service(
-        web::resource("/")
-            .route(web::post().to(index))
-            .route(
-                web::get()
-                    .guard(guard::Header("upgrade", "websocket"))
-                    .to(index_ws),
-            )
-            .route(web::get().to(index_playground)),
-    );
+/// Executes an operation on behalf of a role passed by a transport (e.g. in a header); unlike
+/// the HTTP transport it doesn't depend on actix, so the schema can be embedded elsewhere
+pub async fn execute(schema: &AppSchema, request: Request, role: Option<&str>) -> Response {
+    schema
+        .execute(request.data(common_utils::parse_role(role)))
+        .await
 }
 
-async fn index(
-    schema: web::Data<AppSchema>,
-    http_req: HttpRequest,
-    req: GraphQLRequest,
-) -> GraphQLResponse {
-    let mut query = req.into_inner();
-    let getting_role_result = common_utils::get_role(http_req);
-    query = query.data(getting_role_result);
-    schema.execute(query).await.into()
+pub fn create_schema_with_context(pool: PgPool) -> Schema<Query, Mutation, Subscription> {
+    create_schema(Arc::new(ReloadablePool::new(pool)))
 }
 
-async fn index_ws(
-    schema: web::Data<AppSchema>,
-    req: HttpRequest,
-    payload: web::Payload,
-) -> Result<HttpResponse> {
-    GraphQLSubscription::new(Schema::clone(&*schema)).start(&req, payload)
-}
+/// The caller can keep a reference to the pool to replace it at runtime
+pub fn create_schema(arc_pool: Arc<ReloadablePool>) -> Schema<Query, Mutation, Subscription> {
+    let column_comments =
+        repository::get_column_comments(&mut arc_pool.get().expect("Can't get DB connection"))
+            .expect("Can't get column comments");
+    let field_descriptions = descriptions::from_column_comments(&column_comments);
 
-async fn index_playground() -> HttpResponse {
-    HttpResponse::Ok()
-        .content_type("text/html; charset=utf-8")
-        .body(playground_source(
-            GraphQLPlaygroundConfig::new("/").subscription_endpoint("/"),
-        ))
-}
+    // batches run in spans of their own, since they load keys of several operations
+    let details_data_loader = TracedDataLoader::new(DetailsLoader {
+        pool: Arc::clone(&arc_pool),
+    })
+    .max_batch_size(DETAILS_BATCH_SIZE);
+    let details_as_of_data_loader = TracedDataLoader::new(DetailsAsOfLoader {
+        pool: Arc::clone(&arc_pool),
+    });
+    let atmosphere_data_loader = TracedDataLoader::new(AtmosphereLoader {
+        pool: Arc::clone(&arc_pool),
+    });
+    let star_data_loader = TracedDataLoader::new(StarLoader {
+        pool: Arc::clone(&arc_pool),
+    });
 
-pub fn create_schema_with_context(pool: PgPool) -> Schema<Query, Mutation, Subscription> {
-    let arc_pool = Arc::new(pool);
-    let cloned_pool = Arc::clone(&arc_pool);
-    let details_data_loader =
-        DataLoader::new(DetailsLoader { pool: cloned_pool }, actix_rt::spawn).max_batch_size(10);
+    let identity_map = IdentityMap::new(Arc::clone(&arc_pool));
 
-    let kafka_consumer_counter = Mutex::new(0);
+    let schema_coordinates = schema_coordinates::from_sdl(&schema_sdl());
 
-    Schema::build(Query, Mutation, Subscription)
-        // limits are commented out, because otherwise introspection query won't work
-        // .limit_depth(3)
-        // .limit_complexity(15)
+    let feature_flags: Arc<dyn FeatureFlagProvider> = Arc::new(ConfigFeatureFlags::from_env());
+
+    let response_cache = ResponseCache::from_env();
+
+    let planet_events: Arc<dyn Broker<PlanetEvent>> = Arc::new(Topic::new("planetEvents"));
+    let deletion_progress: Arc<dyn Broker<DeletionProgress>> =
+        Arc::new(Topic::new("deletionProgress"));
+
+    let mut schema_builder = Schema::build(Query, Mutation, Subscription)
         .data(arc_pool)
         .data(details_data_loader)
-        .data(kafka::create_producer())
-        .data(kafka_consumer_counter)
+        .data(details_as_of_data_loader)
+        .data(atmosphere_data_loader)
+        .data(star_data_loader)
+        .data(feature_flags)
+        .data(ConsumerProfiles::from_env(&schema_coordinates))
+        .data(planet_events)
+        .data(deletion_progress)
+        .data(response_cache.clone())
+        .data(PlanetEntityCache::from_env())
+        // the outermost, so that the duration includes the other extensions
+        .extension(OperationLogger)
+        .extension(OperationMetrics)
+        .extension(LoadShedder)
+        .extension(Validator)
+        .extension(OperationLimiter)
+        .extension(DescriptionEnricher::new(
+            field_descriptions,
+            descriptions::load_translations(&schema_coordinates),
+        ))
+        .extension(identity_map)
+        .extension(RenamedFieldTracker)
+        .extension(DecimalFormatter);
+
+    #[cfg(feature = "kafka")]
+    {
+        let kafka_consumer_counter = Mutex::new(0);
+        schema_builder = schema_builder
+            .data(kafka::create_producer())
+            .data(kafka_consumer_counter);
+    }
+
+    #[cfg(feature = "chaos")]
+    {
+        schema_builder = schema_builder.extension(chaos::FaultInjector);
+    }
+
+    schema_builder = schema_builder
+        // so that cache hits skip only the execution
+        .extension(response_cache)
+        // inside the cache, which doesn't keep responses with extensions
+        .extension(RedirectTracker)
+        // inside the cache, which keeps masked responses per profile
+        .extension(FieldMasker)
+        .enable_subscription_in_federation();
+
+    // limits are not set by default, because otherwise introspection query won't work
+    if let Some(max_depth) = QUERY_LIMITS.max_depth {
+        schema_builder = schema_builder.limit_depth(max_depth);
+    }
+    if let Some(max_complexity) = QUERY_LIMITS.max_complexity {
+        schema_builder = schema_builder.limit_complexity(max_complexity);
+    }
+
+    schema_builder.finish()
+}
+
+/// SDL of the schema, from which clients are generated; unlike [create_schema] doesn't need
+/// a database, so column comments aren't included
+pub fn schema_sdl() -> String {
+    Schema::build(Query, Mutation, Subscription)
         .enable_subscription_in_federation()
         .finish()
+        .sdl()
 }
 
 pub fn run_migrations(conn: &mut PooledConnection<ConnectionManager<PgConnection>>) {
@@ -84,8 +203,15 @@ pub fn run_migrations(conn: &mut PooledConnection<ConnectionManager<PgConnection
         .expect("Failed to run database migrations");
 }
 
+pub fn check_database_schema(conn: &mut PooledConnection<ConnectionManager<PgConnection>>) {
+    if let Err(mismatches) = schema_check::check_schema(conn).expect("Can't check database schema")
+    {
+        panic!("{}", mismatches);
+    }
+}
+
 pub fn get_conn_from_ctx(ctx: &Context<'_>) -> PooledConnection<ConnectionManager<PgConnection>> {
-    ctx.data::<Arc<PgPool>>()
+    ctx.data::<Arc<ReloadablePool>>()
         .expect("Can't get pool")
         .get()
         .expect("Can't get DB connection")
@@ -0,0 +1,294 @@
+use std::any::{Any, TypeId};
+use std::collections::HashSet;
+use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_graphql::extensions::{
+    Extension, ExtensionContext, ExtensionFactory, NextPrepareRequest, NextRequest,
+};
+use async_graphql::{Error, ErrorExtensions, Request, Response, ServerError, ServerResult};
+use common_utils::{CustomError, Role};
+use lazy_static::lazy_static;
+
+use crate::masking::ApiKey;
+use crate::metrics;
+
+/// Pool pressure is evaluated over windows of this length; it's also the `Retry-After` hint
+const WINDOW: Duration = Duration::from_secs(5);
+/// Fewer checkouts in a window don't make a meaningful error rate
+const MIN_CHECKOUTS_FOR_ERROR_RATE: u32 = 10;
+
+lazy_static! {
+    static ref CONTROLLER: AdmissionController = AdmissionController::new(Thresholds {
+        max_wait: env::var("LOAD_SHEDDING_MAX_WAIT_MILLIS").ok().map(|millis| {
+            Duration::from_millis(
+                millis
+                    .parse()
+                    .expect("Can't parse LOAD_SHEDDING_MAX_WAIT_MILLIS"),
+            )
+        }),
+        max_error_rate: env::var("LOAD_SHEDDING_MAX_ERROR_RATE").ok().map(|rate| {
+            rate.parse()
+                .expect("Can't parse LOAD_SHEDDING_MAX_ERROR_RATE")
+        }),
+    });
+    /// Roles whose operations are never shed; the role is set by the gateway from the validated
+    /// JWT, unlike operation names which clients choose
+    static ref PRIORITY_ROLES: HashSet<String> = get_list("LOAD_SHEDDING_PRIORITY_ROLES");
+    /// API keys of the priority tier, whose operations are never shed; the keys are secrets given
+    /// to the clients of the tier
+    static ref PRIORITY_API_KEYS: HashSet<String> = get_list("LOAD_SHEDDING_PRIORITY_API_KEYS");
+}
+
+fn get_list(name: &str) -> HashSet<String> {
+    env::var(name)
+        .map(|value| {
+            value
+                .split(',')
+                .map(|item| item.trim().to_string())
+                .filter(|item| !item.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Shedding is disabled if neither threshold is set
+pub struct Thresholds {
+    /// Average wait for a connection from the pool
+    pub max_wait: Option<Duration>,
+    /// Share of checkouts failed, e.g. timed out
+    pub max_error_rate: Option<f64>,
+}
+
+struct Window {
+    started_at: Instant,
+    checkouts: u32,
+    failures: u32,
+    total_wait: Duration,
+}
+
+impl Window {
+    fn new(started_at: Instant) -> Self {
+        Window {
+            started_at,
+            checkouts: 0,
+            failures: 0,
+            total_wait: Duration::ZERO,
+        }
+    }
+}
+
+/// Decides whether the pool is overloaded by checkouts of the last complete window, so shedding
+/// stops after a window without pressure (including one without checkouts at all)
+pub struct AdmissionController {
+    thresholds: Thresholds,
+    window: Mutex<Window>,
+    overloaded: AtomicBool,
+}
+
+impl AdmissionController {
+    pub fn new(thresholds: Thresholds) -> Self {
+        AdmissionController {
+            thresholds,
+            window: Mutex::new(Window::new(Instant::now())),
+            overloaded: AtomicBool::new(false),
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.thresholds.max_wait.is_some() || self.thresholds.max_error_rate.is_some()
+    }
+
+    pub fn record_checkout(&self, wait: Duration, succeeded: bool, now: Instant) {
+        if !self.is_enabled() {
+            return;
+        }
+        let mut window = self.lock_window();
+        self.roll(&mut window, now);
+        window.checkouts += 1;
+        window.total_wait += wait;
+        if !succeeded {
+            window.failures += 1;
+        }
+    }
+
+    pub fn is_overloaded(&self, now: Instant) -> bool {
+        if !self.is_enabled() {
+            return false;
+        }
+        self.roll(&mut self.lock_window(), now);
+        self.overloaded.load(Ordering::Relaxed)
+    }
+
+    fn lock_window(&self) -> std::sync::MutexGuard<'_, Window> {
+        self.window.lock().expect("Can't lock load shedding window")
+    }
+
+    fn roll(&self, window: &mut Window, now: Instant) {
+        if now.duration_since(window.started_at) < WINDOW {
+            return;
+        }
+        let slow = match (self.thresholds.max_wait, window.checkouts) {
+            (Some(max_wait), checkouts) if checkouts > 0 => {
+                window.total_wait / checkouts > max_wait
+            }
+            _ => false,
+        };
+        let failing = match self.thresholds.max_error_rate {
+            Some(max_error_rate) if window.checkouts >= MIN_CHECKOUTS_FOR_ERROR_RATE => {
+                window.failures as f64 / window.checkouts as f64 > max_error_rate
+            }
+            _ => false,
+        };
+        let overloaded = slow || failing;
+        if overloaded != self.overloaded.swap(overloaded, Ordering::Relaxed) {
+            println!(
+                "Load shedding {}: {} checkouts, {} failed, {:?} average wait",
+                if overloaded { "started" } else { "stopped" },
+                window.checkouts,
+                window.failures,
+                window.total_wait / window.checkouts.max(1)
+            );
+        }
+        *window = Window::new(now);
+    }
+}
+
+/// Feeds the controller; called for every checkout from the pool
+pub fn record_checkout(wait: Duration, succeeded: bool) {
+    CONTROLLER.record_checkout(wait, succeeded, Instant::now());
+}
+
+/// Rejects operations which are neither made by a role of `LOAD_SHEDDING_PRIORITY_ROLES` nor sent
+/// with an API key of `LOAD_SHEDDING_PRIORITY_API_KEYS` while the pool is overloaded, i.e. the
+/// average wait for a connection exceeds `LOAD_SHEDDING_MAX_WAIT_MILLIS` or the share of failed
+/// checkouts exceeds `LOAD_SHEDDING_MAX_ERROR_RATE`. Rejected operations get the `RETRY_LATER`
+/// error code and the `Retry-After` header
+pub struct LoadShedder;
+
+impl ExtensionFactory for LoadShedder {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(LoadShedderExtension {
+            rejected: AtomicBool::new(false),
+        })
+    }
+}
+
+struct LoadShedderExtension {
+    rejected: AtomicBool,
+}
+
+#[async_trait::async_trait]
+impl Extension for LoadShedderExtension {
+    async fn request(&self, ctx: &ExtensionContext<'_>, next: NextRequest<'_>) -> Response {
+        let mut response = next.run(ctx).await;
+        if self.rejected.load(Ordering::Relaxed) {
+            response.http_headers.insert(
+                "retry-after",
+                WINDOW
+                    .as_secs()
+                    .to_string()
+                    .parse()
+                    .expect("Can't create header value"),
+            );
+        }
+        response
+    }
+
+    async fn prepare_request(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        request: Request,
+        next: NextPrepareRequest<'_>,
+    ) -> ServerResult<Request> {
+        let is_priority = get_role(ctx, &request)
+            .is_some_and(|role| PRIORITY_ROLES.contains(&role.to_string()))
+            || get_data::<ApiKey>(ctx, &request)
+                .is_some_and(|api_key| PRIORITY_API_KEYS.contains(&api_key.0));
+        if !is_priority && CONTROLLER.is_overloaded(Instant::now()) {
+            self.rejected.store(true, Ordering::Relaxed);
+            metrics::OPERATIONS_SHED.increment(&[], 1);
+            let mut error = ServerError::new("Server is overloaded, retry later", None);
+            error.extensions = Error::new(error.message.clone())
+                .extend_with(|_, e| {
+                    e.set("code", "RETRY_LATER");
+                    e.set("retryAfter", WINDOW.as_secs());
+                })
+                .extensions;
+            return Err(error);
+        }
+        next.run(ctx, request).await
+    }
+}
+
+/// Data of an HTTP request is in the request data, which isn't available to the context before
+/// the request is prepared; the one of a WebSocket connection is in the connection data
+fn get_data<'a, T: Any + Send + Sync>(
+    ctx: &'a ExtensionContext<'_>,
+    request: &'a Request,
+) -> Option<&'a T> {
+    request
+        .data
+        .get(&TypeId::of::<T>())
+        .and_then(|data| data.downcast_ref::<T>())
+        .or_else(|| ctx.data_opt::<T>())
+}
+
+fn get_role(ctx: &ExtensionContext<'_>, request: &Request) -> Option<Role> {
+    match get_data::<Result<Option<Role>, CustomError>>(ctx, request) {
+        Some(Ok(role)) => *role,
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slow_checkouts_overload_until_window_without_pressure() {
+        let controller = AdmissionController::new(Thresholds {
+            max_wait: Some(Duration::from_millis(100)),
+            max_error_rate: None,
+        });
+        let start = Instant::now();
+        controller.record_checkout(Duration::from_millis(50), true, start);
+        controller.record_checkout(Duration::from_millis(250), true, start);
+        assert!(!controller.is_overloaded(start));
+        assert!(controller.is_overloaded(start + WINDOW));
+
+        controller.record_checkout(Duration::from_millis(10), true, start + WINDOW);
+        assert!(controller.is_overloaded(start + WINDOW * 2 - Duration::from_millis(1)));
+        assert!(!controller.is_overloaded(start + WINDOW * 2));
+    }
+
+    #[test]
+    fn failed_checkouts_overload() {
+        let controller = AdmissionController::new(Thresholds {
+            max_wait: None,
+            max_error_rate: Some(0.2),
+        });
+        let start = Instant::now();
+        for i in 0..MIN_CHECKOUTS_FOR_ERROR_RATE {
+            controller.record_checkout(Duration::ZERO, i % 3 != 0, start);
+        }
+        assert!(controller.is_overloaded(start + WINDOW));
+
+        // too few checkouts to judge
+        controller.record_checkout(Duration::ZERO, false, start + WINDOW);
+        assert!(!controller.is_overloaded(start + WINDOW * 2));
+    }
+
+    #[test]
+    fn disabled_without_thresholds() {
+        let controller = AdmissionController::new(Thresholds {
+            max_wait: None,
+            max_error_rate: None,
+        });
+        let start = Instant::now();
+        controller.record_checkout(Duration::from_secs(30), false, start);
+        assert!(!controller.is_overloaded(start + WINDOW));
+    }
+}
@@ -1,29 +1,102 @@
 extern crate planets_service;
 
 use std::env;
+use std::sync::Arc;
 
 use actix_web::{web, App, HttpServer};
+use async_graphql::SDLExportOptions;
 use dotenv::dotenv;
 
-use planets_service::persistence::connection::create_connection_pool;
-use planets_service::{configure_service, create_schema_with_context, run_migrations};
+#[cfg(feature = "nats")]
+use planets_service::nats;
+use planets_service::persistence::connection::{self, DisposableSchema, ReloadablePool};
+use planets_service::schema_registry::{self, RegistryConfig};
+use planets_service::secrets::SecretCache;
+use planets_service::{build_info, metrics, smoke, webhooks};
+use planets_service::{
+    check_database_schema, configure_admin_service, configure_service, create_schema,
+    run_migrations,
+};
+
+// set by the binaries, so that the library doesn't impose the allocator on its users
+#[cfg(feature = "jemalloc")]
+#[global_allocator]
+static GLOBAL: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
 
 #[actix_rt::main]
 async fn main() -> std::io::Result<()> {
     dotenv().ok();
-    let pool = create_connection_pool();
-    run_migrations(&mut pool.get().expect("Can't get DB connection"));
+    println!("{}", build_info::banner());
+    let secrets = SecretCache::from_env().await.map(Arc::new);
+
+    if env::args().any(|arg| arg == "--smoke") {
+        run_smoke_test(secrets.as_deref()).await;
+    }
+
+    let pool = connection::create_configured_pool(secrets.as_deref()).await;
+    let mut conn = pool.get().expect("Can't get DB connection");
+    run_migrations(&mut conn);
+    check_database_schema(&mut conn);
+    drop(conn);
+
+    let pool = Arc::new(ReloadablePool::new(pool));
+    actix_rt::spawn(connection::watch_config(Arc::clone(&pool), secrets));
+    actix_rt::spawn(webhooks::run(Arc::clone(&pool)));
+
+    let schema = web::Data::new(create_schema(pool));
 
-    let schema = web::Data::new(create_schema_with_context(pool));
+    if let Some(registry_config) = RegistryConfig::from_env() {
+        let sdl = schema.sdl_with_options(SDLExportOptions::new().federation());
+        actix_rt::spawn(schema_registry::publish(registry_config, sdl));
+    }
+
+    #[cfg(feature = "nats")]
+    actix_rt::spawn(nats::serve(schema.get_ref().clone()));
+    actix_rt::spawn(metrics::run_exporter());
 
     let server_port = env::var("SERVER_PORT").expect("Can't get server port");
 
-    HttpServer::new(move || {
+    let server = HttpServer::new(move || {
         App::new()
             .configure(configure_service)
             .app_data(schema.clone())
     })
     .bind(format!("0.0.0.0:{}", server_port))?
-    .run()
-    .await
+    .run();
+
+    match env::var("ADMIN_PORT") {
+        Ok(admin_port) => {
+            let admin_server = HttpServer::new(|| App::new().configure(configure_admin_service))
+                .bind(format!("0.0.0.0:{}", admin_port))?
+                .workers(1)
+                .run();
+            futures::try_join!(server, admin_server).map(|_| ())
+        }
+        Err(_) => server.await,
+    }
+}
+
+/// Migrations, the schema check and canary operations run in a schema which is dropped afterwards,
+/// so an image can be checked against the production database before rollout
+async fn run_smoke_test(secrets: Option<&SecretCache>) -> ! {
+    let disposable_schema = DisposableSchema::create(secrets).await;
+    let mut conn = disposable_schema
+        .pool
+        .get()
+        .expect("Can't get DB connection");
+    run_migrations(&mut conn);
+    check_database_schema(&mut conn);
+    drop(conn);
+
+    let schema = create_schema(Arc::new(ReloadablePool::new(
+        disposable_schema.pool.clone(),
+    )));
+    let passed = smoke::run(&schema).await;
+    disposable_schema.discard();
+    if passed {
+        println!("Smoke test passed");
+        std::process::exit(0);
+    }
+    println!("Smoke test failed");
+    std::process::exit(1);
 }
@@ -0,0 +1,374 @@
+//! Conversions between planet details as they are stored and as they are exposed in the schema
+use std::collections::HashSet;
+
+use bigdecimal::{BigDecimal, Signed, Zero};
+
+use crate::graphql::{
+    CustomBigDecimal, CustomBigInt, Details, InhabitedPlanetDetails, UninhabitedPlanetDetails,
+};
+use crate::persistence::model::{DetailsEntity, GasComponentEntity, OrbitEntity};
+use crate::persistence::units;
+
+/// Precision of `details.mass`
+const MAX_MASS_DIGITS: i64 = 30;
+/// Precision of `details.mean_radius` in meters
+const MAX_MEAN_RADIUS_DIGITS: i64 = 12;
+/// Length of `atmosphere_components.gas`
+const MAX_GAS_LENGTH: usize = 20;
+/// Precision of `orbits.semi_major_axis` in meters
+const MAX_SEMI_MAJOR_AXIS_DIGITS: i64 = 20;
+/// Precision of `orbits.orbital_period` in seconds
+const MAX_ORBITAL_PERIOD_DIGITS: i64 = 17;
+
+/// Details of a planet are inhabited if the population is known, even if it's zero
+pub fn to_details(entity: &DetailsEntity) -> Result<Details, String> {
+    check_mass(&entity.mass).map_err(|e| format!("Invalid details {}: {}", entity.id, e))?;
+    let planet_id = entity.planet_id;
+    let mean_radius = CustomBigDecimal(units::meters_to_kilometers(&entity.mean_radius));
+    let mass = CustomBigInt(entity.mass.clone());
+    let details = match &entity.population {
+        Some(population) => InhabitedPlanetDetails {
+            planet_id,
+            mean_radius,
+            mass,
+            population: CustomBigDecimal(population.clone()),
+        }
+        .into(),
+        None => UninhabitedPlanetDetails {
+            planet_id,
+            mean_radius,
+            mass,
+        }
+        .into(),
+    };
+    Ok(details)
+}
+
+/// A mass in kilograms as it's stored: rounded to an integer, which must be positive and fit
+/// into the column
+pub fn to_stored_mass(mass: &BigDecimal) -> Result<BigDecimal, String> {
+    // the number of digits is checked first, since a huge exponent would be expanded by rounding
+    check_mass_digits(mass)?;
+    let mass = mass.round(0);
+    check_mass(&mass)?;
+    Ok(mass)
+}
+
+/// A mean radius in kilometers as it's stored: in meters, rounded to an integer, which must fit
+/// into the column. Non-positive radii are accepted, as they used to be
+pub fn to_stored_mean_radius(mean_radius: &BigDecimal) -> Result<BigDecimal, String> {
+    let mean_radius = units::kilometers_to_meters(mean_radius);
+    if integer_digits(&mean_radius) > MAX_MEAN_RADIUS_DIGITS {
+        return Err(format!(
+            "Mean radius must have at most {} digits in kilometers",
+            MAX_MEAN_RADIUS_DIGITS - 3
+        ));
+    }
+    Ok(mean_radius.round(0))
+}
+
+/// Gases with their shares in percent; the planet ID is set on saving
+pub fn to_atmosphere(
+    components: Vec<(String, BigDecimal)>,
+) -> Result<Vec<GasComponentEntity>, String> {
+    let mut gases = HashSet::new();
+    let mut total = BigDecimal::zero();
+    for (gas, percentage) in &components {
+        let gas = gas.trim();
+        if gas.is_empty() || gas.len() > MAX_GAS_LENGTH {
+            return Err(format!(
+                "Gas must be a formula of 1 to {} characters",
+                MAX_GAS_LENGTH
+            ));
+        }
+        if !gases.insert(gas) {
+            return Err(format!("Gas {} is specified more than once", gas));
+        }
+        if !percentage.is_positive() || *percentage > BigDecimal::from(100) {
+            return Err(format!("Percentage of {} must be from 0 to 100", gas));
+        }
+        total += percentage;
+    }
+    if total > BigDecimal::from(100) {
+        return Err("Percentages of gases add up to more than 100".to_string());
+    }
+
+    Ok(components
+        .into_iter()
+        .map(|(gas, percentage)| GasComponentEntity {
+            planet_id: 0,
+            gas: gas.trim().to_string(),
+            percentage,
+        })
+        .collect())
+}
+
+/// Keplerian elements as they are stored: the semi-major axis in meters and the period in
+/// seconds. The orbit must be elliptic and the angles from 0 to 360 degrees; the planet ID is set
+/// on saving
+pub fn to_stored_orbit(
+    semi_major_axis: &BigDecimal,
+    eccentricity: &BigDecimal,
+    orbital_period: &BigDecimal,
+    longitude_of_perihelion: &BigDecimal,
+    mean_longitude: &BigDecimal,
+) -> Result<OrbitEntity, String> {
+    // the numbers of digits are checked first, since a huge exponent would be expanded by the
+    // conversion and rounding
+    let invalid_semi_major_axis = "Semi-major axis must be positive and fit into the column";
+    if exceeds_digits(
+        semi_major_axis,
+        units::METERS_PER_ASTRONOMICAL_UNIT,
+        MAX_SEMI_MAJOR_AXIS_DIGITS,
+    ) {
+        return Err(invalid_semi_major_axis.to_string());
+    }
+    let semi_major_axis = units::astronomical_units_to_meters(semi_major_axis);
+    if !semi_major_axis.is_positive()
+        || integer_digits(&semi_major_axis) > MAX_SEMI_MAJOR_AXIS_DIGITS
+    {
+        return Err(invalid_semi_major_axis.to_string());
+    }
+    let invalid_orbital_period = "Orbital period must be positive and fit into the column";
+    if exceeds_digits(
+        orbital_period,
+        units::SECONDS_PER_DAY,
+        MAX_ORBITAL_PERIOD_DIGITS,
+    ) {
+        return Err(invalid_orbital_period.to_string());
+    }
+    let orbital_period = units::days_to_seconds(orbital_period).round(3);
+    if !orbital_period.is_positive() || integer_digits(&orbital_period) > MAX_ORBITAL_PERIOD_DIGITS
+    {
+        return Err(invalid_orbital_period.to_string());
+    }
+    if eccentricity.is_negative() || *eccentricity >= BigDecimal::from(1) {
+        return Err("Eccentricity of an elliptic orbit must be from 0 to 1".to_string());
+    }
+    for (name, angle) in [
+        ("Longitude of perihelion", longitude_of_perihelion),
+        ("Mean longitude", mean_longitude),
+    ] {
+        if angle.is_negative() || *angle >= BigDecimal::from(360) {
+            return Err(format!("{} must be from 0 to 360 degrees", name));
+        }
+    }
+
+    Ok(OrbitEntity {
+        planet_id: 0,
+        semi_major_axis,
+        eccentricity: eccentricity.round(8),
+        orbital_period,
+        longitude_of_perihelion: longitude_of_perihelion.round(8),
+        mean_longitude: mean_longitude.round(8),
+    })
+}
+
+fn check_mass(mass: &BigDecimal) -> Result<(), String> {
+    if !mass.is_positive() {
+        return Err("Mass must be positive".to_string());
+    }
+    check_mass_digits(mass)
+}
+
+fn check_mass_digits(mass: &BigDecimal) -> Result<(), String> {
+    if integer_digits(mass) > MAX_MASS_DIGITS {
+        return Err(format!("Mass must have at most {} digits", MAX_MASS_DIGITS));
+    }
+    Ok(())
+}
+
+/// Digits before the point (negative for fractions below 0.1), counted without expanding the
+/// exponent
+/// Whether `value` multiplied by `factor` surely has more integer digits than `max_digits`; the
+/// product has at least the digits of both numbers but one
+fn exceeds_digits(value: &BigDecimal, factor: i64, max_digits: i64) -> bool {
+    integer_digits(value) + integer_digits(&BigDecimal::from(factor)) - 1 > max_digits
+}
+
+fn integer_digits(value: &BigDecimal) -> i64 {
+    if value.is_zero() {
+        return 0;
+    }
+    let (unscaled, scale) = value.as_bigint_and_exponent();
+    unscaled.abs().to_string().len() as i64 - scale
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn decimal(value: &str) -> BigDecimal {
+        BigDecimal::from_str(value).expect("Can't parse decimal")
+    }
+
+    fn entity(mass: &str, population: Option<&str>) -> DetailsEntity {
+        DetailsEntity {
+            id: 1,
+            mean_radius: decimal("6371000"),
+            mass: decimal(mass),
+            population: population.map(decimal),
+            planet_id: 3,
+        }
+    }
+
+    #[test]
+    fn details_depend_on_population() {
+        assert!(matches!(
+            to_details(&entity("5.97e24", None)),
+            Ok(Details::UninhabitedPlanetDetails(_))
+        ));
+        assert!(matches!(
+            to_details(&entity("5.97e24", Some("7.53"))),
+            Ok(Details::InhabitedPlanetDetails(_))
+        ));
+        assert!(matches!(
+            to_details(&entity("5.97e24", Some("0"))),
+            Ok(Details::InhabitedPlanetDetails(_))
+        ));
+    }
+
+    #[test]
+    fn invalid_stored_details() {
+        assert_eq!(
+            Some("Invalid details 1: Mass must be positive".to_string()),
+            to_details(&entity("0", None)).err()
+        );
+        assert!(to_details(&entity("-5.97e24", Some("7.53"))).is_err());
+    }
+
+    fn atmosphere(components: &[(&str, &str)]) -> Result<Vec<GasComponentEntity>, String> {
+        to_atmosphere(
+            components
+                .iter()
+                .map(|(gas, percentage)| (gas.to_string(), decimal(percentage)))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn atmosphere_components() {
+        let components =
+            atmosphere(&[(" N2 ", "78.08"), ("O2", "20.95"), ("Ar", "0.97")]).expect("Invalid");
+        assert_eq!(
+            vec!["N2", "O2", "Ar"],
+            components
+                .iter()
+                .map(|component| component.gas.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert!(atmosphere(&[]).is_ok());
+        assert!(atmosphere(&[("CO2", "100")]).is_ok());
+    }
+
+    #[test]
+    fn invalid_atmosphere() {
+        assert_eq!(
+            Some("Gas N2 is specified more than once".to_string()),
+            atmosphere(&[("N2", "50"), ("N2 ", "10")]).err()
+        );
+        assert_eq!(
+            Some("Percentages of gases add up to more than 100".to_string()),
+            atmosphere(&[("N2", "78.08"), ("O2", "20.95"), ("Ar", "0.98")]).err()
+        );
+        for percentage in ["0", "-1", "100.01"] {
+            assert!(atmosphere(&[("N2", percentage)]).is_err(), "{}", percentage);
+        }
+        assert!(atmosphere(&[(" ", "1")]).is_err());
+        assert!(atmosphere(&[("C".repeat(21).as_str(), "1")]).is_err());
+    }
+
+    #[test]
+    fn mass_is_rounded() {
+        assert_eq!(
+            Ok(decimal("642000000000000000000000")),
+            to_stored_mass(&decimal("6.42e+23"))
+        );
+        assert_eq!(Ok(decimal("2")), to_stored_mass(&decimal("1.5")));
+        assert_eq!(Ok(decimal("1")), to_stored_mass(&decimal("0.5000001")));
+    }
+
+    #[test]
+    fn mass_must_be_positive() {
+        for mass in ["0", "0.0", "0.4", "-1", "-6.42e+23"] {
+            assert_eq!(
+                Err("Mass must be positive".to_string()),
+                to_stored_mass(&decimal(mass)),
+                "{}",
+                mass
+            );
+        }
+    }
+
+    #[test]
+    fn huge_exponents() {
+        assert!(to_stored_mass(&decimal("9.99e29")).is_ok());
+        assert!(to_stored_mass(&decimal("999999999999999999999999999999.4")).is_ok());
+        for mass in ["1e30", "1.5e31", "6.42e+1000000000", "-6.42e+1000000000"] {
+            assert!(to_stored_mass(&decimal(mass)).is_err(), "{}", mass);
+        }
+        // negative exponents are rounded to zero
+        assert_eq!(
+            Err("Mass must be positive".to_string()),
+            to_stored_mass(&decimal("1e-1000000000"))
+        );
+    }
+
+    #[test]
+    fn orbit_is_stored_in_si_units() {
+        let orbit = to_stored_orbit(
+            &decimal("1.00000261"),
+            &decimal("0.01671123"),
+            &decimal("365.256"),
+            &decimal("102.93768193"),
+            &decimal("100.46457166"),
+        )
+        .expect("Can't convert orbit");
+        assert_eq!(decimal("149598261150"), orbit.semi_major_axis);
+        assert_eq!(decimal("31558118.4"), orbit.orbital_period);
+
+        let to_orbit = |semi_major_axis, eccentricity, orbital_period, mean_longitude| {
+            to_stored_orbit(
+                &decimal(semi_major_axis),
+                &decimal(eccentricity),
+                &decimal(orbital_period),
+                &decimal("0"),
+                &decimal(mean_longitude),
+            )
+        };
+        assert!(to_orbit("0", "0", "1", "0").is_err());
+        assert!(to_orbit("1e9", "0", "1", "0").is_err());
+        assert!(to_orbit("1e1000000000", "0", "1", "0").is_err());
+        assert!(to_orbit("1", "0", "1e1000000000", "0").is_err());
+        assert!(to_orbit("1", "1", "1", "0").is_err());
+        assert!(to_orbit("1", "-0.1", "1", "0").is_err());
+        assert!(to_orbit("1", "0", "-1", "0").is_err());
+        assert!(to_orbit("1", "0", "1", "360").is_err());
+    }
+
+    #[test]
+    fn mean_radius_is_stored_in_meters() {
+        assert_eq!(
+            Ok(decimal("6371000")),
+            to_stored_mean_radius(&decimal("6371.0"))
+        );
+        assert_eq!(Ok(decimal("11")), to_stored_mean_radius(&decimal("0.0107")));
+        assert_eq!(Ok(decimal("0")), to_stored_mean_radius(&decimal("0")));
+        assert!(to_stored_mean_radius(&decimal("999999999.9")).is_ok());
+        for mean_radius in ["1e9", "6.4e+1000000000"] {
+            assert!(
+                to_stored_mean_radius(&decimal(mean_radius)).is_err(),
+                "{}",
+                mean_radius
+            );
+        }
+        match to_details(&entity("5.97e24", None)) {
+            Ok(Details::UninhabitedPlanetDetails(details)) => {
+                assert_eq!("6371.0", details.mean_radius.0.to_string())
+            }
+            _ => panic!("Details should be uninhabited"),
+        }
+    }
+}
@@ -0,0 +1,269 @@
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::sync::Arc;
+
+use async_graphql::extensions::{
+    Extension, ExtensionContext, ExtensionFactory, NextResolve, ResolveInfo,
+};
+use async_graphql::{ServerResult, Value};
+use lazy_static::lazy_static;
+use serde::Deserialize;
+
+use crate::schema_coordinates::{Coordinate, Coordinates};
+
+/// Value of the `x-api-key` header
+pub struct ApiKey(pub String);
+
+/// Masking keeps responses valid against the schema, so a masked field has to be nullable, and
+/// fields can't be stripped from responses
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum MaskAction {
+    /// The field is returned as `null`
+    Nullify,
+}
+
+/// Actions keyed by `Type.field`, where the type is an object type
+type MaskedFields = HashMap<String, MaskAction>;
+
+/// Profile of callers without a known API key, unless `defaultProfile` names another one; if it
+/// isn't configured, it masks the fields masked by any profile
+const DEFAULT_PROFILE: &str = "default";
+
+/// Read once at startup from `CONSUMER_PROFILES_FILE` (path to a JSON file) or from
+/// `CONSUMER_PROFILES` (inline JSON), e.g.
+/// `{"profiles": {"public": {"DetailsVersion.population": "NULLIFY"}, "internal": {}},
+/// "apiKeys": {"backoffice-key": "internal"}, "defaultProfile": "public"}`
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConsumerProfiles {
+    profiles: HashMap<String, MaskedFields>,
+    /// Profile names by API key
+    api_keys: HashMap<String, String>,
+    /// Profile of callers without a known API key, so that masking fails closed
+    #[serde(default = "default_profile")]
+    default_profile: String,
+}
+
+fn default_profile() -> String {
+    DEFAULT_PROFILE.to_string()
+}
+
+lazy_static! {
+    /// API keys of the configured profiles; invalid profiles fail [ConsumerProfiles::from_env]
+    static ref CONFIGURED_API_KEYS: HashSet<String> = read_json()
+        .and_then(|json| serde_json::from_str::<ConsumerProfiles>(&json).ok())
+        .map(|profiles| profiles.api_keys.into_keys().collect())
+        .unwrap_or_default();
+}
+
+/// Whether the API key is one of the consumer profiles; other keys don't identify clients, since
+/// anyone can make them up
+#[cfg(feature = "actix")]
+pub fn is_configured_api_key(api_key: &str) -> bool {
+    CONFIGURED_API_KEYS.contains(api_key)
+}
+
+fn read_json() -> Option<String> {
+    if let Ok(path) = env::var("CONSUMER_PROFILES_FILE") {
+        Some(fs::read_to_string(path).expect("Can't read consumer profiles file"))
+    } else {
+        env::var("CONSUMER_PROFILES").ok()
+    }
+}
+
+impl ConsumerProfiles {
+    /// Masked fields are validated against the schema's coordinates
+    pub fn from_env(coordinates: &Coordinates) -> Self {
+        match read_json() {
+            Some(json) => {
+                Self::from_json(&json, coordinates).expect("Can't parse consumer profiles")
+            }
+            None => Self::default(),
+        }
+    }
+
+    pub fn from_json(json: &str, coordinates: &Coordinates) -> Result<Self, String> {
+        let mut profiles: Self = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        for (profile, fields) in &profiles.profiles {
+            for field in fields.keys() {
+                match coordinates.get(field) {
+                    Some(Coordinate::ObjectField { nullable: true }) => {}
+                    Some(Coordinate::ObjectField { nullable: false }) => {
+                        return Err(format!(
+                            "Field {} of profile {} is non-null, so it can't be nullified",
+                            field, profile
+                        ))
+                    }
+                    _ => {
+                        return Err(format!(
+                            "Invalid field {} of profile {}, expected a field of an object type",
+                            field, profile
+                        ))
+                    }
+                }
+            }
+        }
+        if !profiles.profiles.contains_key(DEFAULT_PROFILE) {
+            let most_restrictive = profiles
+                .profiles
+                .values()
+                .flat_map(|fields| fields.clone())
+                .collect();
+            profiles
+                .profiles
+                .insert(DEFAULT_PROFILE.to_string(), most_restrictive);
+        }
+        if let Some(profile) = profiles
+            .api_keys
+            .values()
+            .chain([&profiles.default_profile])
+            .find(|profile| !profiles.profiles.contains_key(*profile))
+        {
+            return Err(format!("Unknown profile {}", profile));
+        }
+        Ok(profiles)
+    }
+
+    /// `None` only if no profiles are configured
+    fn get(&self, api_key: Option<&str>) -> Option<(&String, &MaskedFields)> {
+        let name = api_key
+            .and_then(|api_key| self.api_keys.get(api_key))
+            .unwrap_or(&self.default_profile);
+        self.profiles.get_key_value(name)
+    }
+}
+
+/// Name and masked fields of the profile of the caller's API key
+pub fn get_profile<'a>(ctx: &ExtensionContext<'a>) -> Option<(&'a String, &'a MaskedFields)> {
+    let api_key = ctx.data_opt::<ApiKey>().map(|api_key| api_key.0.as_str());
+    ctx.data_opt::<ConsumerProfiles>()?.get(api_key)
+}
+
+/// Applies the profile of the caller's API key: resolvers of masked fields aren't run and the
+/// fields are nullified, so that one schema can serve consumers allowed to see different data.
+/// HTTP requests, SSE and WebSocket connections carry the API key of `x-api-key`
+pub struct FieldMasker;
+
+impl ExtensionFactory for FieldMasker {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(FieldMaskerExtension)
+    }
+}
+
+struct FieldMaskerExtension;
+
+#[async_trait::async_trait]
+impl Extension for FieldMaskerExtension {
+    async fn resolve(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        info: ResolveInfo<'_>,
+        next: NextResolve<'_>,
+    ) -> ServerResult<Option<Value>> {
+        let action = get_profile(ctx).and_then(|(_, fields)| {
+            fields
+                .get(&format!("{}.{}", info.parent_type, info.name))
+                .copied()
+        });
+        match action {
+            None => next.run(ctx, info).await,
+            Some(MaskAction::Nullify) => Ok(Some(Value::Null)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema_coordinates;
+
+    const SDL: &str = "
+        type InhabitedPlanetDetails {
+            meanRadius: String!
+            population: String
+        }
+        type Planet { name: String! }
+    ";
+
+    fn from_json(json: &str) -> Result<ConsumerProfiles, String> {
+        ConsumerProfiles::from_json(json, &schema_coordinates::from_sdl(SDL))
+    }
+
+    #[test]
+    fn test_parse_profiles() {
+        let profiles = from_json(
+            r#"{
+                "profiles": { "public": { "InhabitedPlanetDetails.population": "NULLIFY" }, "internal": {} },
+                "apiKeys": { "website-key": "public", "backoffice-key": "internal" }
+            }"#,
+        )
+        .expect("Can't parse profiles");
+        let (name, fields) = profiles
+            .get(Some("website-key"))
+            .expect("Can't get profile");
+        assert_eq!("public", name);
+        assert_eq!(
+            Some(&MaskAction::Nullify),
+            fields.get("InhabitedPlanetDetails.population")
+        );
+        let (name, fields) = profiles
+            .get(Some("backoffice-key"))
+            .expect("Can't get profile");
+        assert_eq!("internal", name);
+        assert!(fields.is_empty());
+
+        assert_eq!(
+            Err("Unknown profile internal".to_string()),
+            from_json(r#"{ "profiles": {}, "apiKeys": { "k": "internal" } }"#).map(|_| ())
+        );
+        assert!(ConsumerProfiles::default().get(None).is_none());
+    }
+
+    #[test]
+    fn test_callers_without_known_key_get_default_profile() {
+        let profiles = from_json(
+            r#"{
+                "profiles": { "public": { "InhabitedPlanetDetails.population": "NULLIFY" }, "internal": {} },
+                "apiKeys": { "backoffice-key": "internal" }
+            }"#,
+        )
+        .expect("Can't parse profiles");
+        // the most restrictive one unless configured
+        for api_key in [None, Some("other-key")] {
+            let (name, fields) = profiles.get(api_key).expect("Can't get profile");
+            assert_eq!(DEFAULT_PROFILE, name);
+            assert!(fields.contains_key("InhabitedPlanetDetails.population"));
+        }
+
+        let profiles = from_json(
+            r#"{ "profiles": { "internal": {} }, "apiKeys": {}, "defaultProfile": "internal" }"#,
+        )
+        .expect("Can't parse profiles");
+        assert_eq!("internal", profiles.get(None).expect("Can't get profile").0);
+    }
+
+    #[test]
+    fn test_profiles_are_validated_against_schema() {
+        let profile = |field: &str, action: &str| {
+            from_json(&format!(
+                r#"{{ "profiles": {{ "public": {{ "{}": "{}" }} }}, "apiKeys": {{}} }}"#,
+                field, action
+            ))
+            .map(|_| ())
+        };
+
+        assert!(profile("InhabitedPlanetDetails.population", "NULLIFY").is_ok());
+        assert_eq!(
+            Err(
+                "Field Planet.name of profile public is non-null, so it can't be nullified"
+                    .to_string()
+            ),
+            profile("Planet.name", "NULLIFY")
+        );
+        assert!(profile("Planet.mass", "NULLIFY").is_err());
+        assert!(profile("Planet", "NULLIFY").is_err());
+        assert!(profile("InhabitedPlanetDetails.population", "STRIP").is_err());
+    }
+}
@@ -0,0 +1,122 @@
+use std::mem::size_of;
+
+use async_graphql::Value;
+
+/// Allocator statistics in bytes, see `stats.*` in the jemalloc manual
+pub struct AllocatorStats {
+    /// Allocated by the application
+    pub allocated: usize,
+    /// In active pages, a multiple of the page size
+    pub active: usize,
+    /// In physically resident pages
+    pub resident: usize,
+    /// In chunks mapped by the allocator
+    pub mapped: usize,
+    /// Retained for future reuse instead of being returned to the OS
+    pub retained: usize,
+}
+
+/// Memory taken by an in-memory cache
+pub struct CacheMemory {
+    pub name: &'static str,
+    pub entries: usize,
+    pub estimated_bytes: usize,
+}
+
+/// Statistics of jemalloc if it is the global allocator (the `jemalloc` feature)
+#[cfg(feature = "jemalloc")]
+pub fn get_allocator_stats() -> Option<AllocatorStats> {
+    use tikv_jemalloc_ctl::{epoch, stats};
+
+    // statistics are cached until the epoch is advanced
+    epoch::advance().ok()?;
+    Some(AllocatorStats {
+        allocated: stats::allocated::read().ok()?,
+        active: stats::active::read().ok()?,
+        resident: stats::resident::read().ok()?,
+        mapped: stats::mapped::read().ok()?,
+        retained: stats::retained::read().ok()?,
+    })
+}
+
+#[cfg(not(feature = "jemalloc"))]
+pub fn get_allocator_stats() -> Option<AllocatorStats> {
+    None
+}
+
+/// Whether allocations are sampled for heap profiles; `None` if profiling isn't available, which
+/// requires the `jemalloc` feature and `_RJEM_MALLOC_CONF=prof:true` at startup
+#[cfg(feature = "jemalloc")]
+pub fn is_heap_profiling_active() -> Option<bool> {
+    // SAFETY: both options are booleans
+    unsafe {
+        if !tikv_jemalloc_ctl::raw::read::<bool>(b"opt.prof\0").ok()? {
+            return None;
+        }
+        tikv_jemalloc_ctl::raw::read(b"prof.active\0").ok()
+    }
+}
+
+#[cfg(not(feature = "jemalloc"))]
+pub fn is_heap_profiling_active() -> Option<bool> {
+    None
+}
+
+/// Starts or stops sampling allocations for heap profiles
+#[cfg(feature = "jemalloc")]
+pub fn set_heap_profiling(active: bool) -> Result<(), String> {
+    if is_heap_profiling_active().is_none() {
+        return Err(HEAP_PROFILING_UNAVAILABLE.to_string());
+    }
+    // SAFETY: the option is a boolean
+    unsafe { tikv_jemalloc_ctl::raw::write(b"prof.active\0", active) }.map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "jemalloc"))]
+pub fn set_heap_profiling(_active: bool) -> Result<(), String> {
+    Err(HEAP_PROFILING_UNAVAILABLE.to_string())
+}
+
+const HEAP_PROFILING_UNAVAILABLE: &str =
+    "Heap profiling requires the jemalloc feature and _RJEM_MALLOC_CONF=prof:true";
+
+/// Approximate size of a value with its content on the heap
+pub fn estimate_value_size(value: &Value) -> usize {
+    size_of::<Value>()
+        + match value {
+            Value::String(string) => string.capacity(),
+            Value::Binary(bytes) => bytes.len(),
+            Value::Enum(name) => name.len(),
+            Value::List(values) => values.iter().map(estimate_value_size).sum(),
+            Value::Object(fields) => fields
+                .iter()
+                .map(|(name, value)| name.len() + estimate_value_size(value))
+                .sum(),
+            Value::Null | Value::Number(_) | Value::Boolean(_) => 0,
+        }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_graphql::value;
+
+    use super::*;
+
+    #[test]
+    fn value_size_includes_content() {
+        let scalar = estimate_value_size(&Value::Null);
+        assert_eq!(size_of::<Value>(), scalar);
+
+        let planet = value!({ "name": "Earth", "moons": ["Moon"] });
+        // the object, two fields with their names and the list item
+        assert!(estimate_value_size(&planet) >= 4 * scalar + "namemoons".len() + "EarthMoon".len());
+    }
+
+    #[cfg(not(feature = "jemalloc"))]
+    #[test]
+    fn profiling_is_unavailable_without_jemalloc() {
+        assert!(get_allocator_stats().is_none());
+        assert!(is_heap_profiling_active().is_none());
+        assert!(set_heap_profiling(true).is_err());
+    }
+}
@@ -0,0 +1,263 @@
+//! Metrics facade: instrumented code records through [Counter], [Gauge] and [Histogram] and
+//! doesn't depend on the backend, which is selected by `METRICS_BACKEND`:
+//! `prometheus` (scraped from `/metrics` on `ADMIN_PORT`), `statsd` (`STATSD_ADDRESS`), `otlp` (pushed to
+//! `OTEL_EXPORTER_OTLP_ENDPOINT`) or `none` (the default)
+use std::env;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_graphql::extensions::{
+    Extension, ExtensionContext, ExtensionFactory, NextExecute, NextParseQuery,
+};
+use async_graphql::parser::types::{ExecutableDocument, OperationType};
+use async_graphql::{Response, ServerResult, Variables};
+use lazy_static::lazy_static;
+use strum_macros::EnumString;
+
+mod otlp;
+mod prometheus;
+mod registry;
+mod statsd;
+
+/// Label values are passed in the order of [MetricDescription::labels]
+pub trait Counter: Send + Sync {
+    fn increment(&self, labels: &[&str], value: u64);
+}
+
+pub trait Gauge: Send + Sync {
+    fn set(&self, labels: &[&str], value: f64);
+
+    /// Changes the value atomically, so that concurrent updates aren't lost as with `set`
+    fn increase(&self, labels: &[&str], value: f64);
+
+    fn decrease(&self, labels: &[&str], value: f64);
+}
+
+pub trait Histogram: Send + Sync {
+    fn record(&self, labels: &[&str], value: f64);
+}
+
+#[derive(Clone, Copy)]
+pub struct MetricDescription {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub labels: &'static [&'static str],
+}
+
+pub trait MetricsBackend: Send + Sync {
+    fn counter(&self, description: MetricDescription) -> Arc<dyn Counter>;
+    fn gauge(&self, description: MetricDescription) -> Arc<dyn Gauge>;
+    fn histogram(&self, description: MetricDescription) -> Arc<dyn Histogram>;
+
+    /// Metrics in the Prometheus text format if the backend is scraped
+    fn render(&self) -> Option<String> {
+        None
+    }
+
+    /// Pushes metrics periodically if the backend needs it
+    fn export_interval(&self) -> Option<Duration> {
+        None
+    }
+
+    fn export(&self) -> Option<futures::future::BoxFuture<'static, Result<(), String>>> {
+        None
+    }
+}
+
+#[derive(EnumString)]
+#[strum(serialize_all = "lowercase")]
+enum BackendKind {
+    None,
+    Prometheus,
+    Statsd,
+    Otlp,
+}
+
+lazy_static! {
+    static ref BACKEND: Box<dyn MetricsBackend> = create_backend();
+    pub static ref OPERATIONS: Arc<dyn Counter> = BACKEND.counter(MetricDescription {
+        name: "graphql_operations_total",
+        help: "Executed GraphQL operations",
+        labels: &["type", "outcome"],
+    });
+    pub static ref OPERATION_DURATION: Arc<dyn Histogram> = BACKEND.histogram(MetricDescription {
+        name: "graphql_operation_duration_seconds",
+        help: "Duration of execution of GraphQL operations",
+        labels: &["type"],
+    });
+    pub static ref OPERATIONS_SHED: Arc<dyn Counter> = BACKEND.counter(MetricDescription {
+        name: "graphql_operations_shed_total",
+        help: "Operations rejected because the database pool is overloaded",
+        labels: &[],
+    });
+    pub static ref DB_CONNECTION_WAIT: Arc<dyn Histogram> = BACKEND.histogram(MetricDescription {
+        name: "db_connection_wait_seconds",
+        help: "Time spent waiting for a connection from the pool",
+        labels: &[],
+    });
+    pub static ref DB_CONNECTIONS: Arc<dyn Gauge> = BACKEND.gauge(MetricDescription {
+        name: "db_pool_connections",
+        help: "Connections of the pool",
+        labels: &["state"],
+    });
+    pub static ref BROKER_PUBLISHED: Arc<dyn Counter> = BACKEND.counter(MetricDescription {
+        name: "broker_messages_published_total",
+        help: "Messages published to a topic",
+        labels: &["topic"],
+    });
+    pub static ref BROKER_SKIPPED: Arc<dyn Counter> = BACKEND.counter(MetricDescription {
+        name: "broker_messages_skipped_total",
+        help: "Messages skipped by lagging subscribers of a topic",
+        labels: &["topic"],
+    });
+    pub static ref BROKER_SUBSCRIBERS: Arc<dyn Gauge> = BACKEND.gauge(MetricDescription {
+        name: "broker_subscribers",
+        help: "Subscribers of a topic",
+        labels: &["topic"],
+    });
+    pub static ref CACHE_REQUESTS: Arc<dyn Counter> = BACKEND.counter(MetricDescription {
+        name: "cache_requests_total",
+        help: "Lookups in an in-memory cache",
+        labels: &["cache", "result"],
+    });
+    pub static ref CACHE_ENTRIES: Arc<dyn Gauge> = BACKEND.gauge(MetricDescription {
+        name: "cache_entries",
+        help: "Entries of an in-memory cache",
+        labels: &["cache"],
+    });
+}
+
+fn create_backend() -> Box<dyn MetricsBackend> {
+    let kind = env::var("METRICS_BACKEND").unwrap_or_else(|_| "none".to_string());
+    match BackendKind::from_str(&kind).expect("Can't parse METRICS_BACKEND") {
+        BackendKind::None => Box::new(NoopBackend),
+        BackendKind::Prometheus => Box::<prometheus::PrometheusBackend>::default(),
+        BackendKind::Statsd => Box::new(statsd::StatsdBackend::from_env()),
+        BackendKind::Otlp => Box::new(otlp::OtlpBackend::from_env()),
+    }
+}
+
+/// Metrics in the Prometheus text format, if the Prometheus backend is selected
+pub fn render() -> Option<String> {
+    BACKEND.render()
+}
+
+/// Pushes metrics until the process stops if the backend needs it, otherwise returns at once
+pub async fn run_exporter() {
+    let Some(interval) = BACKEND.export_interval() else {
+        return;
+    };
+    loop {
+        tokio::time::sleep(interval).await;
+        if let Some(export) = BACKEND.export() {
+            if let Err(e) = export.await {
+                println!("Can't export metrics: {}", e);
+            }
+        }
+    }
+}
+
+struct NoopBackend;
+
+struct NoopMetric;
+
+impl Counter for NoopMetric {
+    fn increment(&self, _labels: &[&str], _value: u64) {}
+}
+
+impl Gauge for NoopMetric {
+    fn set(&self, _labels: &[&str], _value: f64) {}
+
+    fn increase(&self, _labels: &[&str], _value: f64) {}
+
+    fn decrease(&self, _labels: &[&str], _value: f64) {}
+}
+
+impl Histogram for NoopMetric {
+    fn record(&self, _labels: &[&str], _value: f64) {}
+}
+
+impl MetricsBackend for NoopBackend {
+    fn counter(&self, _description: MetricDescription) -> Arc<dyn Counter> {
+        Arc::new(NoopMetric)
+    }
+
+    fn gauge(&self, _description: MetricDescription) -> Arc<dyn Gauge> {
+        Arc::new(NoopMetric)
+    }
+
+    fn histogram(&self, _description: MetricDescription) -> Arc<dyn Histogram> {
+        Arc::new(NoopMetric)
+    }
+}
+
+/// Counts operations by type and outcome and measures their duration; subscriptions aren't
+/// counted
+pub struct OperationMetrics;
+
+impl ExtensionFactory for OperationMetrics {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(OperationMetricsExtension {
+            operation_types: Mutex::default(),
+        })
+    }
+}
+
+struct OperationMetricsExtension {
+    /// Types of the operations of the document by name
+    operation_types: Mutex<Vec<(Option<String>, OperationType)>>,
+}
+
+#[async_trait::async_trait]
+impl Extension for OperationMetricsExtension {
+    async fn parse_query(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        query: &str,
+        variables: &Variables,
+        next: NextParseQuery<'_>,
+    ) -> ServerResult<ExecutableDocument> {
+        let document = next.run(ctx, query, variables).await?;
+        *self
+            .operation_types
+            .lock()
+            .expect("Can't lock operation types") = document
+            .operations
+            .iter()
+            .map(|(name, operation)| (name.map(|name| name.to_string()), operation.node.ty))
+            .collect();
+        Ok(document)
+    }
+
+    async fn execute(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        operation_name: Option<&str>,
+        next: NextExecute<'_>,
+    ) -> Response {
+        let operation_type = {
+            let operation_types = self
+                .operation_types
+                .lock()
+                .expect("Can't lock operation types");
+            let operation_type = match operation_name {
+                Some(operation_name) => operation_types
+                    .iter()
+                    .find(|(name, _)| name.as_deref() == Some(operation_name)),
+                None => operation_types.first(),
+            };
+            match operation_type.map(|(_, operation_type)| operation_type) {
+                Some(OperationType::Mutation) => "mutation",
+                _ => "query",
+            }
+        };
+
+        let start = Instant::now();
+        let response = next.run(ctx, operation_name).await;
+        OPERATION_DURATION.record(&[operation_type], start.elapsed().as_secs_f64());
+        let outcome = if response.is_ok() { "ok" } else { "error" };
+        OPERATIONS.increment(&[operation_type, outcome], 1);
+        response
+    }
+}
@@ -0,0 +1,218 @@
+use std::env;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use serde_json::{json, Value as Json};
+
+use super::registry::{Kind, Registry, Snapshot, Value, BUCKETS};
+use super::{Counter, Gauge, Histogram, MetricDescription, MetricsBackend};
+use crate::http_client::{self, RetryPolicy};
+
+/// `AGGREGATION_TEMPORALITY_CUMULATIVE`: values are totals since [OtlpBackend::start_time]
+const CUMULATIVE: u8 = 2;
+
+/// Keeps metrics in memory and pushes them periodically to an OpenTelemetry collector
+/// as OTLP/HTTP JSON
+pub struct OtlpBackend {
+    registry: Registry,
+    endpoint: String,
+    interval: Duration,
+    start_time: u128,
+}
+
+impl OtlpBackend {
+    pub fn from_env() -> Self {
+        let endpoint = env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+            .unwrap_or_else(|_| "http://localhost:4318".to_string());
+        let interval = env::var("OTEL_METRIC_EXPORT_INTERVAL")
+            .map(|interval| {
+                interval
+                    .parse()
+                    .expect("Can't parse OTEL_METRIC_EXPORT_INTERVAL")
+            })
+            .unwrap_or(60000);
+        OtlpBackend {
+            registry: Registry::default(),
+            endpoint: format!("{}/v1/metrics", endpoint.trim_end_matches('/')),
+            interval: Duration::from_millis(interval),
+            start_time: now(),
+        }
+    }
+}
+
+impl MetricsBackend for OtlpBackend {
+    fn counter(&self, description: MetricDescription) -> Arc<dyn Counter> {
+        self.registry.register(description, Kind::Counter)
+    }
+
+    fn gauge(&self, description: MetricDescription) -> Arc<dyn Gauge> {
+        self.registry.register(description, Kind::Gauge)
+    }
+
+    fn histogram(&self, description: MetricDescription) -> Arc<dyn Histogram> {
+        self.registry.register(description, Kind::Histogram)
+    }
+
+    fn export_interval(&self) -> Option<Duration> {
+        Some(self.interval)
+    }
+
+    fn export(&self) -> Option<BoxFuture<'static, Result<(), String>>> {
+        let body = to_request(&self.registry.snapshot(), self.start_time, now());
+        let request = http_client::client().post(&self.endpoint).json(&body);
+        Some(
+            async move {
+                http_client::send(request, &RetryPolicy::NONE)
+                    .await
+                    .and_then(|response| response.error_for_status())
+                    .map(|_| ())
+                    .map_err(|e| e.to_string())
+            }
+            .boxed(),
+        )
+    }
+}
+
+fn now() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Can't get current time")
+        .as_nanos()
+}
+
+/// `ExportMetricsServiceRequest`; 64-bit integers are strings in the JSON encoding
+fn to_request(metrics: &[Snapshot], start_time: u128, time: u128) -> Json {
+    let metrics: Vec<Json> = metrics
+        .iter()
+        .filter(|metric| !metric.series.is_empty())
+        .map(|metric| {
+            let data_points: Vec<Json> = metric
+                .series
+                .iter()
+                .map(|(values, value)| {
+                    let attributes: Vec<Json> = metric
+                        .description
+                        .labels
+                        .iter()
+                        .zip(values)
+                        .map(|(name, value)| json!({ "key": name, "value": { "stringValue": value } }))
+                        .collect();
+                    let mut data_point = json!({
+                        "attributes": attributes,
+                        "startTimeUnixNano": start_time.to_string(),
+                        "timeUnixNano": time.to_string(),
+                    });
+                    let fields = match value {
+                        Value::Counter(value) => json!({ "asInt": value.to_string() }),
+                        Value::Gauge(value) => json!({ "asDouble": value }),
+                        Value::Histogram {
+                            buckets,
+                            sum,
+                            count,
+                        } => json!({
+                            "count": count.to_string(),
+                            "sum": sum,
+                            "bucketCounts": buckets.iter().map(u64::to_string).collect::<Vec<_>>(),
+                            "explicitBounds": BUCKETS,
+                        }),
+                    };
+                    if let (Json::Object(data_point), Json::Object(fields)) =
+                        (&mut data_point, fields)
+                    {
+                        data_point.extend(fields);
+                    }
+                    data_point
+                })
+                .collect();
+            let (key, data) = match metric.kind {
+                Kind::Counter => (
+                    "sum",
+                    json!({
+                        "dataPoints": data_points,
+                        "aggregationTemporality": CUMULATIVE,
+                        "isMonotonic": true,
+                    }),
+                ),
+                Kind::Gauge => ("gauge", json!({ "dataPoints": data_points })),
+                Kind::Histogram => (
+                    "histogram",
+                    json!({
+                        "dataPoints": data_points,
+                        "aggregationTemporality": CUMULATIVE,
+                    }),
+                ),
+            };
+            json!({
+                "name": metric.description.name,
+                "description": metric.description.help,
+                key: data,
+            })
+        })
+        .collect();
+    json!({
+        "resourceMetrics": [{
+            "resource": {
+                "attributes": [
+                    { "key": "service.name", "value": { "stringValue": "planets-service" } },
+                    {
+                        "key": "service.version",
+                        "value": { "stringValue": env!("CARGO_PKG_VERSION") }
+                    },
+                ]
+            },
+            "scopeMetrics": [{
+                "scope": { "name": "planets-service" },
+                "metrics": metrics,
+            }]
+        }]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counters_are_exported_as_cumulative_sums() {
+        let registry = Registry::default();
+        let counter = registry.register(
+            MetricDescription {
+                name: "requests_total",
+                help: "Requests",
+                labels: &["type"],
+            },
+            Kind::Counter,
+        );
+        registry.register(
+            MetricDescription {
+                name: "unused",
+                help: "Unused",
+                labels: &[],
+            },
+            Kind::Gauge,
+        );
+        counter.increment(&["query"], 3);
+
+        let request = to_request(&registry.snapshot(), 1, 2);
+        let metrics = &request["resourceMetrics"][0]["scopeMetrics"][0]["metrics"];
+        assert_eq!(
+            json!([{
+                "name": "requests_total",
+                "description": "Requests",
+                "sum": {
+                    "dataPoints": [{
+                        "attributes": [{ "key": "type", "value": { "stringValue": "query" } }],
+                        "startTimeUnixNano": "1",
+                        "timeUnixNano": "2",
+                        "asInt": "3",
+                    }],
+                    "aggregationTemporality": 2,
+                    "isMonotonic": true,
+                }
+            }]),
+            *metrics
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use std::fmt::Write;
+use std::sync::Arc;
+
+use super::registry::{Kind, Registry, Snapshot, Value, BUCKETS};
+use super::{Counter, Gauge, Histogram, MetricDescription, MetricsBackend};
+
+/// Keeps metrics in memory and renders them in the text exposition format on scrape
+#[derive(Default)]
+pub struct PrometheusBackend {
+    registry: Registry,
+}
+
+impl MetricsBackend for PrometheusBackend {
+    fn counter(&self, description: MetricDescription) -> Arc<dyn Counter> {
+        self.registry.register(description, Kind::Counter)
+    }
+
+    fn gauge(&self, description: MetricDescription) -> Arc<dyn Gauge> {
+        self.registry.register(description, Kind::Gauge)
+    }
+
+    fn histogram(&self, description: MetricDescription) -> Arc<dyn Histogram> {
+        self.registry.register(description, Kind::Histogram)
+    }
+
+    fn render(&self) -> Option<String> {
+        Some(render(&self.registry.snapshot()))
+    }
+}
+
+fn render(metrics: &[Snapshot]) -> String {
+    let mut output = String::new();
+    for metric in metrics {
+        let name = metric.description.name;
+        let kind = match metric.kind {
+            Kind::Counter => "counter",
+            Kind::Gauge => "gauge",
+            Kind::Histogram => "histogram",
+        };
+        writeln!(output, "# HELP {} {}", name, metric.description.help).expect("Can't write");
+        writeln!(output, "# TYPE {} {}", name, kind).expect("Can't write");
+        for (values, value) in &metric.series {
+            let labels: Vec<(&str, &str)> = metric
+                .description
+                .labels
+                .iter()
+                .copied()
+                .zip(values.iter().map(String::as_str))
+                .collect();
+            match value {
+                Value::Counter(value) => {
+                    writeln!(output, "{}{} {}", name, format_labels(&labels), value)
+                }
+                Value::Gauge(value) => {
+                    writeln!(output, "{}{} {}", name, format_labels(&labels), value)
+                }
+                Value::Histogram {
+                    buckets,
+                    sum,
+                    count,
+                } => {
+                    let mut cumulative = 0;
+                    for (bucket, bound) in buckets.iter().zip(
+                        BUCKETS
+                            .iter()
+                            .map(|bound| bound.to_string())
+                            .chain(["+Inf".to_string()]),
+                    ) {
+                        cumulative += bucket;
+                        let mut labels = labels.clone();
+                        labels.push(("le", &bound));
+                        writeln!(
+                            output,
+                            "{}_bucket{} {}",
+                            name,
+                            format_labels(&labels),
+                            cumulative
+                        )
+                        .expect("Can't write");
+                    }
+                    writeln!(output, "{}_sum{} {}", name, format_labels(&labels), sum)
+                        .expect("Can't write");
+                    writeln!(output, "{}_count{} {}", name, format_labels(&labels), count)
+                }
+            }
+            .expect("Can't write");
+        }
+    }
+    output
+}
+
+fn format_labels(labels: &[(&str, &str)]) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+    let labels: Vec<String> = labels
+        .iter()
+        .map(|(name, value)| {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{}=\"{}\"", name, value)
+        })
+        .collect();
+    format!("{{{}}}", labels.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metrics_are_rendered_in_text_format() {
+        let backend = PrometheusBackend::default();
+        let counter = backend.counter(MetricDescription {
+            name: "requests_total",
+            help: "Requests",
+            labels: &["path"],
+        });
+        let histogram = backend.histogram(MetricDescription {
+            name: "wait_seconds",
+            help: "Wait",
+            labels: &[],
+        });
+        counter.increment(&["/a\"b"], 2);
+        histogram.record(&[], 0.3);
+
+        let output = backend.render().expect("Can't render");
+        let expected_lines = [
+            "# HELP requests_total Requests",
+            "# TYPE requests_total counter",
+            "requests_total{path=\"/a\\\"b\"} 2",
+            "# TYPE wait_seconds histogram",
+            "wait_seconds_bucket{le=\"0.25\"} 0",
+            "wait_seconds_bucket{le=\"0.5\"} 1",
+            "wait_seconds_bucket{le=\"+Inf\"} 1",
+            "wait_seconds_sum 0.3",
+            "wait_seconds_count 1",
+        ];
+        for line in expected_lines {
+            assert!(output.lines().any(|l| l == line), "{} in {}", line, output);
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use super::{Counter, Gauge, Histogram, MetricDescription};
+
+/// Upper bounds of histogram buckets, in seconds
+pub const BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Aggregates metrics in memory for backends reading them all at once
+#[derive(Default)]
+pub struct Registry {
+    metrics: Mutex<Vec<Arc<Metric>>>,
+}
+
+pub struct Metric {
+    pub description: MetricDescription,
+    kind: Kind,
+    /// Values by label values
+    series: Mutex<BTreeMap<Vec<String>, Value>>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Kind {
+    Counter,
+    Gauge,
+    Histogram,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Value {
+    Counter(u64),
+    Gauge(f64),
+    /// Counts of values per bucket of [BUCKETS] (not cumulative) plus the overflow bucket
+    Histogram {
+        buckets: Vec<u64>,
+        sum: f64,
+        count: u64,
+    },
+}
+
+pub struct Snapshot {
+    pub description: MetricDescription,
+    pub kind: Kind,
+    pub series: Vec<(Vec<String>, Value)>,
+}
+
+impl Registry {
+    pub fn register(&self, description: MetricDescription, kind: Kind) -> Arc<Metric> {
+        let metric = Arc::new(Metric {
+            description,
+            kind,
+            series: Mutex::default(),
+        });
+        self.metrics
+            .lock()
+            .expect("Can't lock metrics")
+            .push(Arc::clone(&metric));
+        metric
+    }
+
+    pub fn snapshot(&self) -> Vec<Snapshot> {
+        self.metrics
+            .lock()
+            .expect("Can't lock metrics")
+            .iter()
+            .map(|metric| Snapshot {
+                description: metric.description,
+                kind: metric.kind,
+                series: metric
+                    .series
+                    .lock()
+                    .expect("Can't lock series")
+                    .iter()
+                    .map(|(labels, value)| (labels.clone(), value.clone()))
+                    .collect(),
+            })
+            .collect()
+    }
+}
+
+impl Metric {
+    fn update(&self, labels: &[&str], default: Value, update: impl FnOnce(&mut Value)) {
+        let labels = labels.iter().map(|label| label.to_string()).collect();
+        let mut series = self.series.lock().expect("Can't lock series");
+        update(series.entry(labels).or_insert(default));
+    }
+}
+
+impl Counter for Metric {
+    fn increment(&self, labels: &[&str], value: u64) {
+        self.update(labels, Value::Counter(0), |current| {
+            if let Value::Counter(current) = current {
+                *current += value;
+            }
+        });
+    }
+}
+
+impl Gauge for Metric {
+    fn set(&self, labels: &[&str], value: f64) {
+        self.update(labels, Value::Gauge(value), |current| {
+            *current = Value::Gauge(value)
+        });
+    }
+
+    fn increase(&self, labels: &[&str], value: f64) {
+        self.update(labels, Value::Gauge(0.0), |current| {
+            if let Value::Gauge(current) = current {
+                *current += value;
+            }
+        });
+    }
+
+    fn decrease(&self, labels: &[&str], value: f64) {
+        self.increase(labels, -value);
+    }
+}
+
+impl Histogram for Metric {
+    fn record(&self, labels: &[&str], value: f64) {
+        let empty = Value::Histogram {
+            buckets: vec![0; BUCKETS.len() + 1],
+            sum: 0.0,
+            count: 0,
+        };
+        self.update(labels, empty, |current| {
+            if let Value::Histogram {
+                buckets,
+                sum,
+                count,
+            } = current
+            {
+                let bucket = BUCKETS
+                    .iter()
+                    .position(|bound| value <= *bound)
+                    .unwrap_or(BUCKETS.len());
+                buckets[bucket] += 1;
+                *sum += value;
+                *count += 1;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DESCRIPTION: MetricDescription = MetricDescription {
+        name: "test",
+        help: "Test",
+        labels: &["label"],
+    };
+
+    #[test]
+    fn values_are_aggregated_per_labels() {
+        let registry = Registry::default();
+        let counter = registry.register(DESCRIPTION, Kind::Counter);
+        let histogram = registry.register(DESCRIPTION, Kind::Histogram);
+        counter.increment(&["a"], 1);
+        counter.increment(&["a"], 2);
+        counter.increment(&["b"], 1);
+        histogram.record(&["a"], 0.02);
+        histogram.record(&["a"], 20.0);
+
+        let snapshot = registry.snapshot();
+        assert_eq!(
+            vec![
+                (vec!["a".to_string()], Value::Counter(3)),
+                (vec!["b".to_string()], Value::Counter(1))
+            ],
+            snapshot[0].series
+        );
+        let mut buckets = vec![0; BUCKETS.len() + 1];
+        buckets[2] = 1;
+        buckets[BUCKETS.len()] = 1;
+        assert_eq!(
+            vec![(
+                vec!["a".to_string()],
+                Value::Histogram {
+                    buckets,
+                    sum: 20.02,
+                    count: 2
+                }
+            )],
+            snapshot[1].series
+        );
+    }
+
+    #[test]
+    fn gauges_are_changed_by_deltas() {
+        let registry = Registry::default();
+        let gauge = registry.register(DESCRIPTION, Kind::Gauge);
+        gauge.increase(&["a"], 2.0);
+        gauge.decrease(&["a"], 1.0);
+        gauge.decrease(&["b"], 1.0);
+
+        assert_eq!(
+            vec![
+                (vec!["a".to_string()], Value::Gauge(1.0)),
+                (vec!["b".to_string()], Value::Gauge(-1.0))
+            ],
+            registry.snapshot()[0].series
+        );
+    }
+}
@@ -0,0 +1,137 @@
+use std::env;
+use std::net::UdpSocket;
+use std::sync::Arc;
+
+use super::{Counter, Gauge, Histogram, MetricDescription, MetricsBackend};
+
+/// Sends every value to a StatsD agent over UDP, labels as DogStatsD tags; the agent does
+/// the aggregation
+pub struct StatsdBackend {
+    sender: Arc<Sender>,
+}
+
+struct Sender {
+    socket: UdpSocket,
+    prefix: String,
+}
+
+struct StatsdMetric {
+    sender: Arc<Sender>,
+    description: MetricDescription,
+}
+
+impl StatsdBackend {
+    pub fn from_env() -> Self {
+        let address = env::var("STATSD_ADDRESS").unwrap_or_else(|_| "127.0.0.1:8125".to_string());
+        let prefix = env::var("STATSD_PREFIX").unwrap_or_else(|_| "planets_service.".to_string());
+        let socket = UdpSocket::bind("0.0.0.0:0").expect("Can't bind StatsD socket");
+        socket
+            .connect(&address)
+            .expect("Can't connect to StatsD agent");
+        socket
+            .set_nonblocking(true)
+            .expect("Can't make StatsD socket non-blocking");
+        StatsdBackend {
+            sender: Arc::new(Sender { socket, prefix }),
+        }
+    }
+
+    fn metric(&self, description: MetricDescription) -> Arc<StatsdMetric> {
+        Arc::new(StatsdMetric {
+            sender: Arc::clone(&self.sender),
+            description,
+        })
+    }
+}
+
+impl MetricsBackend for StatsdBackend {
+    fn counter(&self, description: MetricDescription) -> Arc<dyn Counter> {
+        self.metric(description)
+    }
+
+    fn gauge(&self, description: MetricDescription) -> Arc<dyn Gauge> {
+        self.metric(description)
+    }
+
+    fn histogram(&self, description: MetricDescription) -> Arc<dyn Histogram> {
+        self.metric(description)
+    }
+}
+
+impl StatsdMetric {
+    fn send(&self, labels: &[&str], value: &str, kind: &str) {
+        let line = format_line(&self.sender.prefix, &self.description, labels, value, kind);
+        // metrics are best effort, a full buffer or a missing agent must not slow requests down
+        let _ = self.sender.socket.send(line.as_bytes());
+    }
+}
+
+impl Counter for StatsdMetric {
+    fn increment(&self, labels: &[&str], value: u64) {
+        self.send(labels, &value.to_string(), "c");
+    }
+}
+
+impl Gauge for StatsdMetric {
+    fn set(&self, labels: &[&str], value: f64) {
+        self.send(labels, &value.to_string(), "g");
+    }
+
+    // signed values change a gauge instead of setting it
+    fn increase(&self, labels: &[&str], value: f64) {
+        self.send(labels, &format!("+{}", value), "g");
+    }
+
+    fn decrease(&self, labels: &[&str], value: f64) {
+        self.send(labels, &format!("-{}", value), "g");
+    }
+}
+
+impl Histogram for StatsdMetric {
+    fn record(&self, labels: &[&str], value: f64) {
+        self.send(labels, &value.to_string(), "h");
+    }
+}
+
+fn format_line(
+    prefix: &str,
+    description: &MetricDescription,
+    labels: &[&str],
+    value: &str,
+    kind: &str,
+) -> String {
+    let mut line = format!("{}{}:{}|{}", prefix, description.name, value, kind);
+    if !labels.is_empty() {
+        let tags: Vec<String> = description
+            .labels
+            .iter()
+            .zip(labels)
+            .map(|(name, value)| format!("{}:{}", name, value.replace([',', '|', '#'], "_")))
+            .collect();
+        line.push_str("|#");
+        line.push_str(&tags.join(","));
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_have_tags() {
+        let description = MetricDescription {
+            name: "requests_total",
+            help: "Requests",
+            labels: &["type", "outcome"],
+        };
+        assert_eq!(
+            "app.requests_total:1|c|#type:query,outcome:o_k",
+            format_line("app.", &description, &["query", "o|k"], "1", "c")
+        );
+        assert_eq!(
+            "requests_total:0.5|h",
+            format_line("", &description, &[], "0.5", "h")
+        );
+    }
+}
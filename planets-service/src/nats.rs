@@ -0,0 +1,73 @@
+use std::env;
+
+use async_graphql::{Request, Response, ServerError};
+use async_nats::HeaderMap;
+use futures::StreamExt;
+
+use crate::graphql::AppSchema;
+use crate::http_client::{self, RequestContext, REQUEST_ID_HEADER, TRACEPARENT_HEADER};
+
+/// Instances of the service share messages of the subject
+const QUEUE_GROUP: &str = "planets-service";
+
+/// Serves GraphQL operations sent over NATS if `NATS_URL` is set: a message published to
+/// `NATS_SUBJECT` (`planets.graphql` by default) carries a JSON GraphQL request and optionally
+/// `role`, `x-request-id` and `traceparent` headers; the JSON response is published to the message's reply subject, so clients
+/// can use NATS request-reply
+pub async fn serve(schema: AppSchema) {
+    let url = match env::var("NATS_URL") {
+        Ok(url) => url,
+        Err(_) => return,
+    };
+    let subject = env::var("NATS_SUBJECT").unwrap_or_else(|_| "planets.graphql".to_string());
+
+    let client = async_nats::connect(url)
+        .await
+        .expect("Can't connect to NATS");
+    let mut subscriber = client
+        .queue_subscribe(subject, QUEUE_GROUP.to_string())
+        .await
+        .expect("Can't subscribe to NATS subject");
+
+    while let Some(message) = subscriber.next().await {
+        let reply = match message.reply {
+            Some(reply) => reply,
+            None => {
+                println!("NATS message without reply subject is skipped");
+                continue;
+            }
+        };
+        let schema = schema.clone();
+        let client = client.clone();
+        tokio::spawn(async move {
+            let response = execute(&schema, &message.payload, message.headers.as_ref()).await;
+            if let Err(e) = client.publish(reply, response.into()).await {
+                println!("NATS response wasn't sent: {}", e);
+            }
+        });
+    }
+}
+
+/// Executes a GraphQL request passed as a NATS message and returns the serialized response
+pub async fn execute(schema: &AppSchema, payload: &[u8], headers: Option<&HeaderMap>) -> Vec<u8> {
+    let response = match serde_json::from_slice::<Request>(payload) {
+        Ok(request) => {
+            let get_header = |name| {
+                headers
+                    .and_then(|headers| headers.get(name))
+                    .map(|value| value.as_str())
+            };
+            let context = RequestContext::continue_from(
+                get_header(REQUEST_ID_HEADER).map(String::from),
+                get_header(TRACEPARENT_HEADER),
+            );
+            let role = get_header("role");
+            http_client::with_context(context, crate::execute(schema, request, role)).await
+        }
+        Err(e) => Response::from_errors(vec![ServerError::new(
+            format!("Can't parse GraphQL request: {}", e),
+            None,
+        )]),
+    };
+    serde_json::to_vec(&response).expect("Can't serialize GraphQL response")
+}
@@ -0,0 +1,417 @@
+use std::env;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use async_graphql::extensions::{
+    Extension, ExtensionContext, ExtensionFactory, NextExecute, NextParseQuery,
+};
+use async_graphql::parser::types::{
+    DocumentOperations, ExecutableDocument, Selection, SelectionSet,
+};
+use async_graphql::{Name, Response, ServerResult, Value, Variables};
+use async_graphql_value::Value as InputValue;
+use lazy_static::lazy_static;
+use serde_json::json;
+
+use crate::http_client;
+
+pub const REDACTED: &str = "[REDACTED]";
+
+lazy_static! {
+    static ref SAMPLE_RATE: f64 = env::var("OPERATION_LOG_SAMPLE_RATE")
+        .map(|rate| rate.parse().expect("Can't parse OPERATION_LOG_SAMPLE_RATE"))
+        .unwrap_or(0.0);
+    static ref REDACTION_RULES: RedactionRules = RedactionRules::new(
+        &env::var("LOG_REDACTED_ARGUMENTS").unwrap_or_default(),
+        &env::var("LOG_REDACTED_NAMES").unwrap_or_else(|_| DEFAULT_REDACTED_NAMES.to_string()),
+    );
+}
+
+const DEFAULT_REDACTED_NAMES: &str = "password,secret,token,email";
+
+/// What is replaced with [REDACTED] before an operation leaves the service (e.g. in logs)
+pub struct RedactionRules {
+    /// A field name, then an argument and input fields, e.g. `registerWebhook.url`; a field name
+    /// alone covers all arguments of the field
+    argument_paths: Vec<Vec<String>>,
+    /// Arguments, input fields and variables with these names (case-insensitive) at any depth
+    names: Vec<String>,
+}
+
+impl RedactionRules {
+    /// Both lists are comma-separated
+    pub fn new(argument_paths: &str, names: &str) -> Self {
+        let split = |list: &str| -> Vec<String> {
+            list.split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(String::from)
+                .collect()
+        };
+        RedactionRules {
+            argument_paths: split(argument_paths)
+                .iter()
+                .map(|path| path.split('.').map(String::from).collect())
+                .collect(),
+            names: split(names),
+        }
+    }
+
+    /// Copies of the document and variables with redacted values; variables are redacted where
+    /// they are used, so they can be inlined (see `ExtensionContext::stringify_execute_doc`)
+    pub fn redact(
+        &self,
+        document: &ExecutableDocument,
+        variables: &Variables,
+    ) -> (ExecutableDocument, Variables) {
+        let mut document = document.clone();
+        let mut variable_paths = vec![];
+        match &mut document.operations {
+            DocumentOperations::Single(operation) => self
+                .redact_selection_set(&mut operation.node.selection_set.node, &mut variable_paths),
+            DocumentOperations::Multiple(operations) => {
+                for operation in operations.values_mut() {
+                    self.redact_selection_set(
+                        &mut operation.node.selection_set.node,
+                        &mut variable_paths,
+                    );
+                }
+            }
+        }
+        for fragment in document.fragments.values_mut() {
+            self.redact_selection_set(&mut fragment.node.selection_set.node, &mut variable_paths);
+        }
+
+        let mut variables = variables.clone();
+        for (name, value) in variables.iter_mut() {
+            if self.is_redacted_name(name) {
+                *value = Value::String(REDACTED.to_string());
+                continue;
+            }
+            self.redact_names(value);
+            for (_, path) in variable_paths
+                .iter()
+                .filter(|(variable, _)| variable == name)
+            {
+                redact_path(value, path);
+            }
+        }
+        (document, variables)
+    }
+
+    fn redact_selection_set(
+        &self,
+        selection_set: &mut SelectionSet,
+        variable_paths: &mut Vec<(Name, Vec<String>)>,
+    ) {
+        for selection in &mut selection_set.items {
+            match &mut selection.node {
+                Selection::Field(field) => {
+                    let field_name = field.node.name.node.to_string();
+                    for (name, value) in &mut field.node.arguments {
+                        let path = vec![field_name.clone(), name.node.to_string()];
+                        self.redact_argument(&mut value.node, path, variable_paths);
+                    }
+                    self.redact_selection_set(&mut field.node.selection_set.node, variable_paths);
+                }
+                Selection::InlineFragment(fragment) => {
+                    self.redact_selection_set(&mut fragment.node.selection_set.node, variable_paths)
+                }
+                Selection::FragmentSpread(_) => {}
+            }
+        }
+    }
+
+    fn redact_argument(
+        &self,
+        value: &mut InputValue,
+        path: Vec<String>,
+        variable_paths: &mut Vec<(Name, Vec<String>)>,
+    ) {
+        let name = path
+            .last()
+            .expect("Path should contain a field and an argument");
+        if self.is_redacted_name(name)
+            || self
+                .argument_paths
+                .iter()
+                .any(|rule| path.starts_with(rule))
+        {
+            if let InputValue::Variable(variable) = value {
+                variable_paths.push((variable.clone(), vec![]));
+            }
+            *value = InputValue::String(REDACTED.to_string());
+            return;
+        }
+
+        match value {
+            InputValue::Variable(variable) => {
+                // the rest of the rules applies to the value of the variable
+                for rule in &self.argument_paths {
+                    if rule.len() > path.len() && rule.starts_with(&path) {
+                        variable_paths.push((variable.clone(), rule[path.len()..].to_vec()));
+                    }
+                }
+            }
+            InputValue::Object(fields) => {
+                for (key, value) in fields.iter_mut() {
+                    let mut path = path.clone();
+                    path.push(key.to_string());
+                    self.redact_argument(value, path, variable_paths);
+                }
+            }
+            InputValue::List(values) => {
+                for value in values {
+                    self.redact_argument(value, path.clone(), variable_paths);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn redact_names(&self, value: &mut Value) {
+        match value {
+            Value::Object(fields) => {
+                for (key, value) in fields.iter_mut() {
+                    if self.is_redacted_name(key) {
+                        *value = Value::String(REDACTED.to_string());
+                    } else {
+                        self.redact_names(value);
+                    }
+                }
+            }
+            Value::List(values) => values.iter_mut().for_each(|value| self.redact_names(value)),
+            _ => {}
+        }
+    }
+
+    fn is_redacted_name(&self, name: &str) -> bool {
+        self.names
+            .iter()
+            .any(|redacted| name.eq_ignore_ascii_case(redacted))
+    }
+}
+
+fn redact_path(value: &mut Value, path: &[String]) {
+    let Some((key, rest)) = path.split_first() else {
+        *value = Value::String(REDACTED.to_string());
+        return;
+    };
+    match value {
+        Value::Object(fields) => {
+            if let Some(value) = fields.get_mut(key.as_str()) {
+                redact_path(value, rest);
+            }
+        }
+        Value::List(values) => values.iter_mut().for_each(|value| redact_path(value, path)),
+        _ => {}
+    }
+}
+
+/// Whether to log a span, see `OPERATION_LOG_SAMPLE_RATE`
+pub(crate) fn is_sampled() -> bool {
+    *SAMPLE_RATE > 0.0 && rand::random::<f64>() < *SAMPLE_RATE
+}
+
+/// Logs a share of operations (`OPERATION_LOG_SAMPLE_RATE`, none by default) as JSON lines
+/// with values redacted by `LOG_REDACTED_ARGUMENTS` and `LOG_REDACTED_NAMES`; a line is the span
+/// of the service in the trace of the request
+pub struct OperationLogger;
+
+impl ExtensionFactory for OperationLogger {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(OperationLoggerExtension {
+            query: Mutex::new(None),
+        })
+    }
+}
+
+struct OperationLoggerExtension {
+    /// The redacted operation if it's sampled
+    query: Mutex<Option<String>>,
+}
+
+#[async_trait::async_trait]
+impl Extension for OperationLoggerExtension {
+    async fn parse_query(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        query: &str,
+        variables: &Variables,
+        next: NextParseQuery<'_>,
+    ) -> ServerResult<ExecutableDocument> {
+        let document = next.run(ctx, query, variables).await?;
+        if is_sampled() {
+            let (redacted_document, redacted_variables) =
+                REDACTION_RULES.redact(&document, variables);
+            *self.query.lock().expect("Can't lock query") =
+                Some(ctx.stringify_execute_doc(&redacted_document, &redacted_variables));
+        }
+        Ok(document)
+    }
+
+    async fn execute(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        operation_name: Option<&str>,
+        next: NextExecute<'_>,
+    ) -> Response {
+        let Some(query) = self.query.lock().expect("Can't lock query").take() else {
+            return next.run(ctx, operation_name).await;
+        };
+
+        let start = Instant::now();
+        let response = next.run(ctx, operation_name).await;
+        let trace = http_client::current_context().trace;
+        println!(
+            "{}",
+            json!({
+                "operation": operation_name,
+                "traceId": trace.as_ref().map(|trace| &trace.trace_id),
+                "spanId": trace.as_ref().map(|trace| &trace.span_id),
+                "query": query,
+                "durationMs": start.elapsed().as_millis() as u64,
+                "errors": response.errors.len(),
+            })
+        );
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_graphql::parser::parse_query;
+    use async_graphql::value;
+
+    use super::*;
+
+    fn redact(rules: &RedactionRules, query: &str, variables: Value) -> (Vec<InputValue>, Value) {
+        let document = parse_query(query).expect("Can't parse query");
+        let (document, variables) = rules.redact(&document, &Variables::from_value(variables));
+        let mut arguments = vec![];
+        for (_, operation) in document.operations.iter() {
+            collect_arguments(&operation.node.selection_set.node, &mut arguments);
+        }
+        for fragment in document.fragments.values() {
+            collect_arguments(&fragment.node.selection_set.node, &mut arguments);
+        }
+        (arguments, variables.into_value())
+    }
+
+    fn redacted() -> InputValue {
+        InputValue::String(REDACTED.to_string())
+    }
+
+    fn collect_arguments(selection_set: &SelectionSet, arguments: &mut Vec<InputValue>) {
+        for selection in &selection_set.items {
+            match &selection.node {
+                Selection::Field(field) => {
+                    arguments.extend(
+                        field
+                            .node
+                            .arguments
+                            .iter()
+                            .map(|(_, value)| value.node.clone()),
+                    );
+                    collect_arguments(&field.node.selection_set.node, arguments);
+                }
+                Selection::InlineFragment(fragment) => {
+                    collect_arguments(&fragment.node.selection_set.node, arguments)
+                }
+                Selection::FragmentSpread(_) => {}
+            }
+        }
+    }
+
+    #[test]
+    fn arguments_are_redacted_by_path() {
+        let rules = RedactionRules::new("saveReport.query, registerWebhook.url", "");
+        let (arguments, variables) = redact(
+            &rules,
+            r#"mutation($url: String!) {
+                saveReport(name: "r", query: "{ getPlanets { name } }") { name }
+                registerWebhook(url: $url, secret: "s") { id }
+            }"#,
+            value!({ "url": "https://example.com" }),
+        );
+        assert_eq!(
+            vec![
+                InputValue::String("r".to_string()),
+                redacted(),
+                redacted(),
+                InputValue::String("s".to_string()),
+            ],
+            arguments
+        );
+        assert_eq!(value!({ "url": REDACTED }), variables);
+    }
+
+    #[test]
+    fn input_fields_are_redacted_in_variables() {
+        let rules = RedactionRules::new("createUser.user.profile.phone", "");
+        let (arguments, variables) = redact(
+            &rules,
+            r#"mutation($user: UserInput!) {
+                createUser(user: $user) { id }
+                inline: createUser(user: { name: "a", profile: { phone: "123" } }) { id }
+            }"#,
+            value!({ "user": { "name": "b", "profile": [{ "phone": "456" }] } }),
+        );
+        assert_eq!(
+            vec![
+                InputValue::Variable(Name::new("user")),
+                value!({ "name": "a", "profile": { "phone": REDACTED } }).into_value(),
+            ],
+            arguments
+        );
+        assert_eq!(
+            value!({ "user": { "name": "b", "profile": [{ "phone": REDACTED }] } }),
+            variables
+        );
+    }
+
+    #[test]
+    fn names_are_redacted_at_any_depth() {
+        let rules = RedactionRules::new("", DEFAULT_REDACTED_NAMES);
+        let (arguments, variables) = redact(
+            &rules,
+            r#"query($Token: String, $filter: Filter) {
+                ... on Query { login(password: "p", user: { EMAIL: "e", name: "n" }) }
+                ...fragment
+            }
+            fragment fragment on Query { users(filter: $filter, token: $Token) { name } }"#,
+            value!({ "Token": "t", "filter": { "email": "e", "names": ["n"] } }),
+        );
+        assert_eq!(
+            vec![
+                redacted(),
+                value!({ "EMAIL": REDACTED, "name": "n" }).into_value(),
+                InputValue::Variable(Name::new("filter")),
+                redacted(),
+            ],
+            arguments
+        );
+        assert_eq!(
+            value!({ "Token": REDACTED, "filter": { "email": REDACTED, "names": ["n"] } }),
+            variables
+        );
+    }
+
+    #[test]
+    fn field_name_redacts_all_arguments() {
+        let rules = RedactionRules::new("login", "");
+        let (arguments, _) = redact(
+            &rules,
+            r#"{ login(user: "u", code: 1) planets(name: "Earth") { name } }"#,
+            value!({}),
+        );
+        assert_eq!(
+            vec![
+                redacted(),
+                redacted(),
+                InputValue::String("Earth".to_string())
+            ],
+            arguments
+        );
+    }
+}
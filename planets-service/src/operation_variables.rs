@@ -0,0 +1,271 @@
+//! Checks variables of stored operations against their definitions before the operations run, so
+//! that callers get the name and the expected type of a wrong variable instead of a coercion error
+use std::collections::{HashMap, HashSet};
+
+use async_graphql::parser::types::{
+    BaseType, Type, TypeKind, TypeSystemDefinition, VariableDefinition,
+};
+use async_graphql::parser::{self, Positioned};
+use lazy_static::lazy_static;
+use serde_json::{Map, Value};
+
+lazy_static! {
+    static ref INPUT_TYPES: InputTypes =
+        InputTypes::from_sdl(&crate::schema_sdl()).expect("Can't get input types of the schema");
+}
+
+/// Enums and input objects of a schema; other named types are built-in or custom scalars
+#[derive(Default)]
+pub struct InputTypes {
+    enums: HashMap<String, HashSet<String>>,
+    input_objects: HashMap<String, Vec<InputField>>,
+}
+
+struct InputField {
+    name: String,
+    type_: Type,
+    has_default: bool,
+}
+
+impl InputTypes {
+    pub fn from_sdl(sdl: &str) -> Result<Self, String> {
+        let document = parser::parse_schema(sdl).map_err(|e| e.to_string())?;
+        let mut types = InputTypes::default();
+        for definition in document.definitions {
+            let TypeSystemDefinition::Type(definition) = definition else {
+                continue;
+            };
+            let name = definition.node.name.node.to_string();
+            match definition.node.kind {
+                TypeKind::Enum(enum_type) => {
+                    let values = enum_type
+                        .values
+                        .iter()
+                        .map(|value| value.node.value.node.to_string())
+                        .collect();
+                    types.enums.insert(name, values);
+                }
+                TypeKind::InputObject(input_object) => {
+                    let fields = input_object
+                        .fields
+                        .into_iter()
+                        .map(|field| InputField {
+                            name: field.node.name.node.to_string(),
+                            type_: field.node.ty.node,
+                            has_default: field.node.default_value.is_some(),
+                        })
+                        .collect();
+                    types.input_objects.insert(name, fields);
+                }
+                _ => {}
+            }
+        }
+        Ok(types)
+    }
+}
+
+/// Checks the variables against the definitions of the operation using types of this schema
+pub fn check(
+    definitions: &[Positioned<VariableDefinition>],
+    variables: &Map<String, Value>,
+) -> Result<(), Vec<String>> {
+    check_with_types(definitions, variables, &INPUT_TYPES)
+}
+
+/// Returns a message per wrong variable
+fn check_with_types(
+    definitions: &[Positioned<VariableDefinition>],
+    variables: &Map<String, Value>,
+    types: &InputTypes,
+) -> Result<(), Vec<String>> {
+    let mut errors = vec![];
+    for name in variables.keys() {
+        if !definitions
+            .iter()
+            .any(|definition| definition.node.name.node.as_str() == name)
+        {
+            errors.push(format!(
+                "Variable ${} is not defined by the operation",
+                name
+            ));
+        }
+    }
+    for definition in definitions {
+        let definition = &definition.node;
+        let name = format!("${}", definition.name.node);
+        let type_ = &definition.var_type.node;
+        match variables.get(definition.name.node.as_str()) {
+            Some(value) => check_value(&name, value, type_, types, &mut errors),
+            None if !type_.nullable && definition.default_value.is_none() => {
+                errors.push(format!("Variable {} of type {} is required", name, type_))
+            }
+            None => {}
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// `path` is the variable name followed by the names of input object fields
+fn check_value(
+    path: &str,
+    value: &Value,
+    type_: &Type,
+    types: &InputTypes,
+    errors: &mut Vec<String>,
+) {
+    let mismatch = || format!("Variable {} expects {}, got {}", path, type_, value);
+    if value.is_null() {
+        if !type_.nullable {
+            errors.push(mismatch());
+        }
+        return;
+    }
+    let name = match &type_.base {
+        BaseType::List(item_type) => {
+            match value {
+                Value::Array(items) => {
+                    for item in items {
+                        check_value(path, item, item_type, types, errors);
+                    }
+                }
+                // a single value is coerced to a list of one item
+                _ => check_value(path, value, item_type, types, errors),
+            }
+            return;
+        }
+        BaseType::Named(name) => name.as_str(),
+    };
+    let valid = match name {
+        "Int" => value
+            .as_i64()
+            .is_some_and(|value| i32::try_from(value).is_ok()),
+        "Float" => value.is_number(),
+        "String" => value.is_string(),
+        "Boolean" => value.is_boolean(),
+        "ID" => value.is_string() || value.is_i64() || value.is_u64(),
+        _ => {
+            if let Some(values) = types.enums.get(name) {
+                value.as_str().is_some_and(|value| values.contains(value))
+            } else if let Some(fields) = types.input_objects.get(name) {
+                let Value::Object(object) = value else {
+                    errors.push(mismatch());
+                    return;
+                };
+                check_input_object(path, object, fields, types, errors);
+                true
+            } else {
+                // custom scalars parse their values themselves
+                true
+            }
+        }
+    };
+    if !valid {
+        errors.push(mismatch());
+    }
+}
+
+fn check_input_object(
+    path: &str,
+    object: &Map<String, Value>,
+    fields: &[InputField],
+    types: &InputTypes,
+    errors: &mut Vec<String>,
+) {
+    for name in object.keys() {
+        if !fields.iter().any(|field| field.name == *name) {
+            errors.push(format!("Variable {} has no field {}", path, name));
+        }
+    }
+    for field in fields {
+        let field_path = format!("{}.{}", path, field.name);
+        match object.get(&field.name) {
+            Some(value) => check_value(&field_path, value, &field.type_, types, errors),
+            None if !field.type_.nullable && !field.has_default => errors.push(format!(
+                "Variable {} of type {} is required",
+                field_path, field.type_
+            )),
+            None => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    const SDL: &str = r#"
+        enum PlanetType { GAS_GIANT ICE_GIANT }
+        scalar BigDecimal
+        input PlanetFilter { nameContains: String, type: PlanetType }
+        input DetailsInput { meanRadius: BigDecimal!, mass: BigDecimal! }
+        input PlanetInput { name: String!, type: PlanetType!, details: DetailsInput! }
+    "#;
+
+    fn check_query(query: &str, variables: Value) -> Result<(), Vec<String>> {
+        let types = InputTypes::from_sdl(SDL).expect("Can't parse SDL");
+        let document = parser::parse_query(query).expect("Can't parse query");
+        let (_, operation) = document
+            .operations
+            .iter()
+            .next()
+            .expect("Can't get operation");
+        let Value::Object(variables) = variables else {
+            panic!("Variables should be an object");
+        };
+        check_with_types(&operation.node.variable_definitions, &variables, &types)
+    }
+
+    #[test]
+    fn valid_variables() {
+        let query = "query($id: ID!, $n: Int = 10, $filter: PlanetFilter, $ids: [ID!]) { a }";
+        assert_eq!(
+            Ok(()),
+            check_query(
+                query,
+                json!({ "id": 5, "filter": { "type": "GAS_GIANT" }, "ids": "1" })
+            )
+        );
+    }
+
+    #[test]
+    fn errors_name_variables_and_types() {
+        let query = "query($id: ID!, $n: Int, $ids: [ID!], $planet: PlanetInput!) { a }";
+        assert_eq!(
+            Err(vec![
+                "Variable $extra is not defined by the operation".to_string(),
+                "Variable $id of type ID! is required".to_string(),
+                "Variable $n expects Int, got \"ten\"".to_string(),
+                "Variable $ids expects ID!, got null".to_string(),
+                "Variable $planet has no field color".to_string(),
+                "Variable $planet.type expects PlanetType!, got \"STAR\"".to_string(),
+                "Variable $planet.details.mass of type BigDecimal! is required".to_string(),
+            ]),
+            check_query(
+                query,
+                json!({
+                    "n": "ten",
+                    "ids": ["1", null],
+                    "planet": {
+                        "name": "Pluto",
+                        "type": "STAR",
+                        "color": "grey",
+                        "details": { "meanRadius": "1188.3" }
+                    },
+                    "extra": 1
+                })
+            )
+        );
+    }
+
+    #[test]
+    fn schema_input_types() {
+        assert!(INPUT_TYPES.enums["PlanetType"].contains("GAS_GIANT"));
+        assert!(INPUT_TYPES.input_objects.contains_key("PlanetFilter"));
+    }
+}
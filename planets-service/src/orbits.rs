@@ -0,0 +1,149 @@
+use bigdecimal::{BigDecimal, ToPrimitive};
+use chrono::{DateTime, Utc};
+
+use crate::persistence::model::OrbitEntity;
+use crate::persistence::units::{METERS_PER_ASTRONOMICAL_UNIT, SECONDS_PER_DAY};
+
+pub const ASTRONOMICAL_UNIT_KILOMETERS: f64 = 149_597_870.7;
+pub const SPEED_OF_LIGHT_KILOMETERS_PER_SECOND: f64 = 299_792.458;
+
+// 2000-01-01T12:00:00Z
+const J2000_UNIX_SECONDS: i64 = 946_728_000;
+const KEPLER_EQUATION_TOLERANCE: f64 = 1e-12;
+const KEPLER_EQUATION_MAX_ITERATIONS: u32 = 50;
+
+/// Keplerian elements of an orbit at the J2000 epoch. Angles are in degrees
+pub struct OrbitalElements {
+    /// In astronomical units
+    pub semi_major_axis: f64,
+    pub eccentricity: f64,
+    /// In days
+    pub orbital_period: f64,
+    pub longitude_of_perihelion: f64,
+    pub mean_longitude: f64,
+}
+
+impl OrbitalElements {
+    /// Position relative to the star in astronomical units. Orbits are considered to lie in
+    /// the same plane, which is accurate enough for the Solar System's planets
+    pub fn position(&self, at: DateTime<Utc>) -> (f64, f64) {
+        let days_since_epoch = (at.timestamp_millis() as f64 / 1000.0 - J2000_UNIX_SECONDS as f64)
+            / SECONDS_PER_DAY as f64;
+        let mean_longitude = self.mean_longitude + 360.0 * days_since_epoch / self.orbital_period;
+        let mean_anomaly = (mean_longitude - self.longitude_of_perihelion).to_radians();
+        let eccentric_anomaly = solve_kepler_equation(mean_anomaly, self.eccentricity);
+
+        // relative to the direction to the perihelion
+        let x = self.semi_major_axis * (eccentric_anomaly.cos() - self.eccentricity);
+        let y = self.semi_major_axis
+            * (1.0 - self.eccentricity.powi(2)).sqrt()
+            * eccentric_anomaly.sin();
+
+        let (sin, cos) = self.longitude_of_perihelion.to_radians().sin_cos();
+        (x * cos - y * sin, x * sin + y * cos)
+    }
+}
+
+/// Converts the semi-major axis and the period from the stored meters and seconds
+impl From<&OrbitEntity> for OrbitalElements {
+    fn from(entity: &OrbitEntity) -> Self {
+        let to_f64 = |value: &BigDecimal| value.to_f64().expect("Can't convert orbital element");
+        OrbitalElements {
+            semi_major_axis: to_f64(&entity.semi_major_axis) / METERS_PER_ASTRONOMICAL_UNIT as f64,
+            eccentricity: to_f64(&entity.eccentricity),
+            orbital_period: to_f64(&entity.orbital_period) / SECONDS_PER_DAY as f64,
+            longitude_of_perihelion: to_f64(&entity.longitude_of_perihelion),
+            mean_longitude: to_f64(&entity.mean_longitude),
+        }
+    }
+}
+
+/// Distance in astronomical units between bodies orbiting the same star
+pub fn distance(from: &OrbitalElements, to: &OrbitalElements, at: DateTime<Utc>) -> f64 {
+    let (from_x, from_y) = from.position(at);
+    let (to_x, to_y) = to.position(at);
+    (to_x - from_x).hypot(to_y - from_y)
+}
+
+/// Solves `E - e * sin(E) = M` for the eccentric anomaly `E` by Newton's method
+fn solve_kepler_equation(mean_anomaly: f64, eccentricity: f64) -> f64 {
+    let mut eccentric_anomaly = mean_anomaly;
+    for _ in 0..KEPLER_EQUATION_MAX_ITERATIONS {
+        let delta = (eccentric_anomaly - eccentricity * eccentric_anomaly.sin() - mean_anomaly)
+            / (1.0 - eccentricity * eccentric_anomaly.cos());
+        eccentric_anomaly -= delta;
+        if delta.abs() < KEPLER_EQUATION_TOLERANCE {
+            break;
+        }
+    }
+    eccentric_anomaly
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn earth() -> OrbitalElements {
+        OrbitalElements {
+            semi_major_axis: 1.00000261,
+            eccentricity: 0.01671123,
+            orbital_period: 365.256,
+            longitude_of_perihelion: 102.93768193,
+            mean_longitude: 100.46457166,
+        }
+    }
+
+    fn mars() -> OrbitalElements {
+        OrbitalElements {
+            semi_major_axis: 1.52371034,
+            eccentricity: 0.09339410,
+            orbital_period: 686.980,
+            longitude_of_perihelion: 336.05637041,
+            mean_longitude: 355.44656795,
+        }
+    }
+
+    fn distance_to_star(elements: &OrbitalElements, at: DateTime<Utc>) -> f64 {
+        let (x, y) = elements.position(at);
+        x.hypot(y)
+    }
+
+    #[test]
+    fn earth_is_closest_to_sun_in_early_january() {
+        let perihelion = Utc.with_ymd_and_hms(2024, 1, 3, 0, 0, 0).unwrap();
+        let aphelion = Utc.with_ymd_and_hms(2024, 7, 5, 0, 0, 0).unwrap();
+
+        assert!((distance_to_star(&earth(), perihelion) - 0.9833).abs() < 0.001);
+        assert!((distance_to_star(&earth(), aphelion) - 1.0167).abs() < 0.001);
+    }
+
+    #[test]
+    fn mars_great_opposition() {
+        let opposition = Utc.with_ymd_and_hms(2003, 8, 27, 10, 0, 0).unwrap();
+
+        // 0.3727 AU actually, the difference comes from the neglected inclination
+        assert!((distance(&earth(), &mars(), opposition) - 0.3727).abs() < 0.005);
+    }
+
+    #[test]
+    fn distance_is_symmetric() {
+        let at = Utc.with_ymd_and_hms(2030, 5, 1, 0, 0, 0).unwrap();
+
+        assert_eq!(
+            distance(&earth(), &mars(), at),
+            distance(&mars(), &earth(), at)
+        );
+        assert_eq!(0.0, distance(&earth(), &earth(), at));
+    }
+
+    #[test]
+    fn kepler_equation() {
+        for eccentricity in [0.0, 0.2, 0.9] {
+            let eccentric_anomaly = solve_kepler_equation(1.0, eccentricity);
+            let mean_anomaly = eccentric_anomaly - eccentricity * eccentric_anomaly.sin();
+            assert!((mean_anomaly - 1.0).abs() < 1e-9);
+        }
+    }
+}
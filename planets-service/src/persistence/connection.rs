@@ -1,14 +1,245 @@
 use std::env;
+use std::fs;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use arc_swap::ArcSwap;
+use diesel::connection::SimpleConnection;
 use diesel::pg::PgConnection;
-use diesel::r2d2::{ConnectionManager, Pool};
+use diesel::r2d2::{ConnectionManager, CustomizeConnection, Pool, PoolError, PooledConnection};
+use serde::Deserialize;
+use url::Url;
+
+use crate::secrets::SecretCache;
+use crate::{load_shedding, metrics};
 
 pub type PgPool = Pool<ConnectionManager<PgConnection>>;
 
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(10);
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Connection settings; they are read from `DATABASE_CONFIG_FILE` (JSON) if it's set, so that
+/// they can be changed at runtime, otherwise from `DATABASE_URL` and `DATABASE_PASSWORD_SECRET`
+#[derive(Clone, PartialEq, Deserialize)]
+pub struct DbConfig {
+    pub url: String,
+    #[serde(default = "default_max_size")]
+    pub max_size: u32,
+    /// Name of the secret storing the password, which replaces the one in the URL; the user of
+    /// the URL is kept, so the secret must be static
+    #[serde(default)]
+    pub password_secret: Option<String>,
+}
+
+fn default_max_size() -> u32 {
+    10
+}
+
+impl DbConfig {
+    pub fn from_env() -> Self {
+        match env::var("DATABASE_CONFIG_FILE") {
+            Ok(path) => Self::from_file(&path).expect("Can't read DB config"),
+            Err(_) => Self {
+                url: env::var("DATABASE_URL").expect("Can't get DB URL"),
+                max_size: default_max_size(),
+                password_secret: env::var("DATABASE_PASSWORD_SECRET").ok(),
+            },
+        }
+    }
+
+    fn from_file(path: &str) -> Result<Self, String> {
+        let json = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        serde_json::from_str(&json).map_err(|e| e.to_string())
+    }
+
+    async fn resolve_url(&self, secrets: Option<&SecretCache>) -> Result<String, String> {
+        let password_secret = match &self.password_secret {
+            Some(password_secret) => password_secret,
+            None => return Ok(self.url.clone()),
+        };
+        let password = secrets
+            .ok_or("Secrets provider is not configured")?
+            .get(password_secret)
+            .await?;
+        let mut url = Url::parse(&self.url).map_err(|e| e.to_string())?;
+        url.set_password(Some(&password))
+            .map_err(|_| "Can't set DB password")?;
+        Ok(url.to_string())
+    }
+}
+
+/// Doesn't support passwords stored in secret storages, see [create_configured_pool]
 pub fn create_connection_pool() -> PgPool {
-    let db_url = env::var("DATABASE_URL").expect("Can't get DB URL");
-    let manager = ConnectionManager::<PgConnection>::new(db_url);
+    let config = DbConfig::from_env();
+    create_pool(&config.url, config.max_size).expect("Failed to create pool")
+}
+
+/// Same as [create_connection_pool], but the password can be fetched from the secret storage
+pub async fn create_configured_pool(secrets: Option<&SecretCache>) -> PgPool {
+    let config = DbConfig::from_env();
+    let url = config
+        .resolve_url(secrets)
+        .await
+        .expect("Can't resolve DB URL");
+    create_pool(&url, config.max_size).expect("Failed to create pool")
+}
+
+fn create_pool(url: &str, max_size: u32) -> Result<PgPool, PoolError> {
     Pool::builder()
-        .build(manager)
-        .expect("Failed to create pool")
+        .max_size(max_size)
+        .build(ConnectionManager::<PgConnection>::new(url))
+}
+
+/// For serverless instances: connections are opened on the first use to not slow down cold starts.
+/// An instance handles one request at a time, but resolvers of a request (e.g. DataLoader batches)
+/// hold connections concurrently, so the pool has the configured size
+pub fn create_lazy_connection_pool() -> PgPool {
+    let config = DbConfig::from_env();
+    Pool::builder()
+        .max_size(config.max_size)
+        .min_idle(Some(0))
+        .build_unchecked(ConnectionManager::<PgConnection>::new(config.url))
+}
+
+/// A schema created for a smoke test, so that its migrations and canary writes don't touch the
+/// tables of the service and don't hold their locks; connections of the pool use only this schema
+pub struct DisposableSchema {
+    pub pool: PgPool,
+    name: String,
+}
+
+impl DisposableSchema {
+    pub async fn create(secrets: Option<&SecretCache>) -> Self {
+        let config = DbConfig::from_env();
+        let url = config
+            .resolve_url(secrets)
+            .await
+            .expect("Can't resolve DB URL");
+        let name = format!("smoke_{:016x}", rand::random::<u64>());
+        // the search path can name a schema which doesn't exist yet
+        let pool = Pool::builder()
+            .max_size(config.max_size)
+            .connection_customizer(Box::new(SearchPath(name.clone())))
+            .build(ConnectionManager::<PgConnection>::new(url))
+            .expect("Failed to create pool");
+        pool.get()
+            .expect("Can't get DB connection")
+            .batch_execute(&format!("CREATE SCHEMA {}", name))
+            .expect("Can't create schema");
+        DisposableSchema { pool, name }
+    }
+
+    /// Drops the schema with everything created in it
+    pub fn discard(self) {
+        let mut conn = self.pool.get().expect("Can't get DB connection");
+        conn.batch_execute(&format!("DROP SCHEMA {} CASCADE", self.name))
+            .expect("Can't drop schema");
+    }
+}
+
+#[derive(Debug)]
+struct SearchPath(String);
+
+impl CustomizeConnection<PgConnection, diesel::r2d2::Error> for SearchPath {
+    fn on_acquire(&self, conn: &mut PgConnection) -> Result<(), diesel::r2d2::Error> {
+        conn.batch_execute(&format!("SET search_path TO {}", self.0))
+            .map_err(diesel::r2d2::Error::QueryError)
+    }
+}
+
+/// Pool that can be replaced at runtime, e.g. on credentials rotation, without a restart
+pub struct ReloadablePool {
+    current: ArcSwap<PgPool>,
+}
+
+impl ReloadablePool {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            current: ArcSwap::from_pointee(pool),
+        }
+    }
+
+    pub fn get(&self) -> Result<PooledConnection<ConnectionManager<PgConnection>>, PoolError> {
+        let pool = self.current.load();
+        let start = Instant::now();
+        let connection = pool.get();
+        let wait = start.elapsed();
+        metrics::DB_CONNECTION_WAIT.record(&[], wait.as_secs_f64());
+        load_shedding::record_checkout(wait, connection.is_ok());
+        let state = pool.state();
+        metrics::DB_CONNECTIONS.set(&["idle"], state.idle_connections as f64);
+        metrics::DB_CONNECTIONS.set(
+            &["in_use"],
+            (state.connections - state.idle_connections) as f64,
+        );
+        connection
+    }
+
+    /// New checkouts are served by the new pool at once; the old one is closed when connections
+    /// checked out from it are returned (or after a timeout)
+    pub async fn replace(&self, pool: PgPool) {
+        let old_pool = self.current.swap(Arc::new(pool));
+        let deadline = Instant::now() + DRAIN_TIMEOUT;
+        loop {
+            let state = old_pool.state();
+            if state.connections == state.idle_connections || Instant::now() >= deadline {
+                break;
+            }
+            tokio::time::sleep(DRAIN_POLL_INTERVAL).await;
+        }
+    }
+}
+
+/// Rebuilds the pool when `DATABASE_CONFIG_FILE` or the rotated password changes; if the new
+/// pool can't connect, the current one is kept
+pub async fn watch_config(pool: Arc<ReloadablePool>, secrets: Option<Arc<SecretCache>>) {
+    let config_file = env::var("DATABASE_CONFIG_FILE").ok();
+    let config = DbConfig::from_env();
+    if config_file.is_none() && config.password_secret.is_none() {
+        return;
+    }
+    let mut current = (
+        config
+            .resolve_url(secrets.as_deref())
+            .await
+            .expect("Can't resolve DB URL"),
+        config.max_size,
+    );
+
+    loop {
+        tokio::time::sleep(CONFIG_POLL_INTERVAL).await;
+
+        let config = match &config_file {
+            Some(path) => DbConfig::from_file(path),
+            None => Ok(config.clone()),
+        };
+        let url = match &config {
+            Ok(config) => config.resolve_url(secrets.as_deref()).await,
+            Err(e) => Err(e.clone()),
+        };
+        let (config, url) = match (config, url) {
+            (Ok(config), Ok(url)) => (config, url),
+            (Err(e), _) | (_, Err(e)) => {
+                println!("Can't read DB config: {}", e);
+                continue;
+            }
+        };
+        if (&url, config.max_size) == (&current.0, current.1) {
+            continue;
+        }
+
+        let new_url = url.clone();
+        match tokio::task::spawn_blocking(move || create_pool(&new_url, config.max_size))
+            .await
+            .expect("Can't create pool")
+        {
+            Ok(new_pool) => {
+                current = (url, config.max_size);
+                pool.replace(new_pool).await;
+                println!("DB pool was replaced");
+            }
+            Err(e) => println!("DB pool wasn't replaced: {}", e),
+        }
+    }
 }
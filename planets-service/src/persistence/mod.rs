@@ -2,3 +2,5 @@ pub mod connection;
 pub mod model;
 pub mod repository;
 mod schema;
+pub mod schema_check;
+pub mod units;
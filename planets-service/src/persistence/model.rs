@@ -1,14 +1,40 @@
+use std::io::Write;
+use std::str::FromStr;
+
 use bigdecimal::BigDecimal;
+use chrono::{DateTime, Utc};
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::{Pg, PgValue};
 use diesel::prelude::*;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::{Integer, Text, Varchar};
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumIter, EnumString};
+use uuid::Uuid;
 
-use crate::persistence::schema::{details, planets};
+use crate::persistence::schema::{
+    atmosphere_components, classification_rules, details, orbits, outbox_events, planets, reports,
+    stars, webhook_deliveries, webhooks,
+};
 
-#[derive(Identifiable, Queryable)]
+#[derive(Identifiable, Queryable, Serialize, Deserialize)]
 #[diesel(table_name = planets)]
 pub struct PlanetEntity {
     pub id: i32,
     pub name: String,
-    pub type_: String,
+    pub type_: PlanetType,
+    // events stored before the column was added don't contain it
+    #[serde(default)]
+    pub uuid: Uuid,
+    #[serde(default)]
+    pub star_id: Option<i32>,
+    #[serde(default = "published")]
+    pub status: String,
+}
+
+fn published() -> String {
+    PlanetStatus::Published.to_string()
 }
 
 #[derive(Identifiable, Queryable, Associations)]
@@ -23,18 +49,413 @@ pub struct DetailsEntity {
     pub planet_id: i32,
 }
 
-#[derive(Insertable)]
+/// See [crate::orbits::OrbitalElements]
+#[derive(Identifiable, Queryable, Associations, Insertable, Clone)]
+#[diesel(table_name = orbits)]
+#[diesel(primary_key(planet_id))]
+#[diesel(belongs_to(PlanetEntity, foreign_key = planet_id))]
+pub struct OrbitEntity {
+    pub planet_id: i32,
+    pub semi_major_axis: BigDecimal,
+    pub eccentricity: BigDecimal,
+    pub orbital_period: BigDecimal,
+    pub longitude_of_perihelion: BigDecimal,
+    pub mean_longitude: BigDecimal,
+}
+
+/// A gas of the atmosphere of a planet
+#[derive(Identifiable, Queryable, Associations, Insertable, Clone)]
+#[diesel(table_name = atmosphere_components)]
+#[diesel(primary_key(planet_id, gas))]
+#[diesel(belongs_to(PlanetEntity, foreign_key = planet_id))]
+pub struct GasComponentEntity {
+    pub planet_id: i32,
+    pub gas: String,
+    /// By volume
+    pub percentage: BigDecimal,
+}
+
+#[derive(Insertable, AsChangeset)]
 #[diesel(table_name = planets)]
 pub struct NewPlanetEntity {
     pub name: String,
-    pub type_: String,
+    pub type_: PlanetType,
+    /// The star isn't changed on update if absent, `Some(None)` detaches the planet from its star
+    pub star_id: Option<Option<i32>>,
+    /// Published on creation if absent; not changed on update
+    pub status: Option<String>,
 }
 
-#[derive(Insertable)]
+/// A planet to create or update. Its star and, if absent, its type are resolved in the
+/// transaction of the write, see [NewPlanetEntity]
+pub struct PlanetWrite {
+    pub name: String,
+    /// Determined by the classification rules if absent
+    pub type_: Option<PlanetType>,
+    /// Created if there is no star with its name. The star isn't changed on update if absent,
+    /// `Some(None)` detaches the planet from its star
+    pub star: Option<Option<NewStarEntity>>,
+    /// Published on creation if absent; not changed on update
+    pub status: Option<String>,
+}
+
+/// A planet without a type which no classification rule matches; it isn't written
+#[derive(Debug)]
+pub struct UnclassifiedPlanet;
+
+#[derive(Identifiable, Queryable)]
+#[diesel(table_name = stars)]
+pub struct StarEntity {
+    pub id: i32,
+    pub name: String,
+    pub spectral_class: String,
+    pub mass: BigDecimal,
+}
+
+#[derive(Insertable, AsChangeset, Clone)]
+#[diesel(table_name = stars)]
+pub struct NewStarEntity {
+    pub name: String,
+    pub spectral_class: String,
+    pub mass: BigDecimal,
+}
+
+#[derive(Insertable, AsChangeset, Clone)]
 #[diesel(table_name = details)]
+#[diesel(treat_none_as_null = true)]
 pub struct NewDetailsEntity {
     pub mean_radius: BigDecimal,
     pub mass: BigDecimal,
     pub population: Option<BigDecimal>,
     pub planet_id: i32,
 }
+
+/// Contents of the tables exported to a snapshot
+pub struct SnapshotEntities {
+    pub stars: Vec<StarEntity>,
+    /// In any status
+    pub planets: Vec<PlanetEntity>,
+    pub details: Vec<DetailsEntity>,
+    pub orbits: Vec<OrbitEntity>,
+    pub atmosphere_components: Vec<GasComponentEntity>,
+}
+
+/// A planet of a snapshot with its details and orbit; the star is referenced by name
+pub struct ImportedPlanet {
+    pub uuid: Uuid,
+    pub name: String,
+    pub type_: PlanetType,
+    pub status: String,
+    pub star_name: Option<String>,
+    pub details: NewDetailsEntity,
+    pub orbit: Option<OrbitEntity>,
+    pub atmosphere: Vec<GasComponentEntity>,
+}
+
+pub struct ImportedSnapshot {
+    /// An event per created or overwritten planet
+    pub events: Vec<OutboxEventEntity>,
+    pub skipped: usize,
+}
+
+/// Number of planets of a snapshot imported with [ConflictPolicy::Fail] which already exist
+pub struct ExistingPlanets(pub usize);
+
+/// What happens to existing planets (with the same name or UUID) on import
+#[derive(Copy, Clone, Eq, PartialEq, Display, EnumString)]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+pub enum ConflictPolicy {
+    /// Existing planets are kept as is
+    Skip,
+    Overwrite,
+    /// The import fails if any planet exists
+    Fail,
+}
+
+/// Planets are ordered by the column and then by ID, so the order is stable when values repeat
+#[derive(Default)]
+pub struct PlanetsOrder {
+    pub column: PlanetsOrderColumn,
+    pub descending: bool,
+}
+
+#[derive(Copy, Clone, Default)]
+pub enum PlanetsOrderColumn {
+    #[default]
+    Id,
+    Name,
+    Type,
+}
+
+#[derive(Clone, Default)]
+pub struct PlanetsFilter {
+    pub name_contains: Option<String>,
+    pub type_: Option<PlanetType>,
+}
+
+impl PlanetsFilter {
+    pub fn is_empty(&self) -> bool {
+        self.name_contains.is_none() && self.type_.is_none()
+    }
+}
+
+/// Versions which started from `from` inclusive to `to` exclusive, ordered by the start.
+/// `after` is the start of the last version of the previous page in that order
+pub struct HistoryRange {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub after: Option<DateTime<Utc>>,
+    pub descending: bool,
+    pub limit: i64,
+    /// Only versions of the planet when it was published, e.g. for users other than admins
+    pub published_only: bool,
+}
+
+/// Start and end of a version of `planets_history` or `details_history`; the end is absent
+/// for the current version
+pub type Validity = (DateTime<Utc>, Option<DateTime<Utc>>);
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = outbox_events)]
+pub struct OutboxEventEntity {
+    pub id: i64,
+    pub topic: String,
+    pub kind: String,
+    pub payload: serde_json::Value,
+    pub traceparent: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = outbox_events)]
+pub struct NewOutboxEventEntity {
+    pub topic: String,
+    pub kind: String,
+    pub payload: serde_json::Value,
+    pub traceparent: Option<String>,
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = reports)]
+pub struct ReportEntity {
+    pub id: i32,
+    pub owner: String,
+    pub name: String,
+    pub query: String,
+    pub variables: serde_json::Value,
+}
+
+#[derive(Insertable, AsChangeset)]
+#[diesel(table_name = reports)]
+pub struct NewReportEntity {
+    pub owner: String,
+    pub name: String,
+    pub query: String,
+    pub variables: serde_json::Value,
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = webhooks)]
+pub struct WebhookEntity {
+    pub id: i32,
+    pub url: String,
+    /// All kinds if empty
+    pub event_kinds: Vec<String>,
+    pub secret: String,
+    /// Events of transactions from this one on aren't enqueued yet
+    pub next_transaction_id: i64,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = webhooks)]
+pub struct NewWebhookEntity {
+    pub url: String,
+    pub event_kinds: Vec<String>,
+    pub secret: String,
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = webhook_deliveries)]
+pub struct WebhookDeliveryEntity {
+    pub id: i64,
+    pub webhook_id: i32,
+    pub event_id: i64,
+    pub status: String,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub next_attempt_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = webhook_deliveries)]
+pub struct NewWebhookDeliveryEntity {
+    pub webhook_id: i32,
+    pub event_id: i64,
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_at: DateTime<Utc>,
+}
+
+/// Result of a delivery attempt
+#[derive(AsChangeset)]
+#[diesel(table_name = webhook_deliveries)]
+#[diesel(treat_none_as_null = true)]
+pub struct WebhookDeliveryAttempt {
+    pub status: String,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub next_attempt_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+/// Failed deliveries are retried until the number of attempts reaches the limit
+#[derive(Copy, Clone, Eq, PartialEq, Display, EnumString, async_graphql::Enum)]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+#[graphql(name = "WebhookDeliveryStatus")]
+pub enum DeliveryStatus {
+    Pending,
+    Delivered,
+    Failed,
+}
+
+#[derive(Copy, Clone, Display)]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+pub enum EventKind {
+    Created,
+    Updated,
+    Deleted,
+    Published,
+    Archived,
+}
+
+/// Values stored in `planets.status`, which has a check constraint allowing only them
+#[derive(Copy, Clone, Eq, PartialEq, Display, EnumString)]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+pub enum PlanetStatus {
+    Draft,
+    Published,
+    Archived,
+}
+
+/// Values stored in `planets.type` and `classification_rules.planet_type` are spelled out, so
+/// renaming a variant doesn't change them; the columns have check constraints allowing only them
+#[derive(
+    Copy,
+    Clone,
+    Debug,
+    Eq,
+    PartialEq,
+    Serialize,
+    Deserialize,
+    Display,
+    EnumString,
+    EnumIter,
+    AsExpression,
+    FromSqlRow,
+)]
+#[diesel(sql_type = Varchar)]
+pub enum PlanetType {
+    #[strum(serialize = "TERRESTRIAL_PLANET")]
+    #[serde(rename = "TERRESTRIAL_PLANET")]
+    TerrestrialPlanet,
+    #[strum(serialize = "GAS_GIANT")]
+    #[serde(rename = "GAS_GIANT")]
+    GasGiant,
+    #[strum(serialize = "ICE_GIANT")]
+    #[serde(rename = "ICE_GIANT")]
+    IceGiant,
+    #[strum(serialize = "DWARF_PLANET")]
+    #[serde(rename = "DWARF_PLANET")]
+    DwarfPlanet,
+}
+
+impl ToSql<Varchar, Pg> for PlanetType {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        out.write_all(self.to_string().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Varchar, Pg> for PlanetType {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        let value = <String as FromSql<Varchar, Pg>>::from_sql(bytes)?;
+        PlanetType::from_str(&value).map_err(|_| format!("Unknown planet type {}", value).into())
+    }
+}
+
+#[derive(QueryableByName)]
+pub struct ColumnCommentEntity {
+    #[diesel(sql_type = Text)]
+    pub table_name: String,
+    #[diesel(sql_type = Text)]
+    pub column_name: String,
+    #[diesel(sql_type = Text)]
+    pub comment: String,
+}
+
+/// An anomaly found by a data quality check
+#[derive(QueryableByName)]
+pub struct DataQualityFindingEntity {
+    /// Name of the check in SCREAMING_SNAKE_CASE
+    #[diesel(sql_type = Text)]
+    pub check_name: String,
+    #[diesel(sql_type = Integer)]
+    pub planet_id: i32,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    pub planet_uuid: Uuid,
+    #[diesel(sql_type = Text)]
+    pub planet_name: String,
+    #[diesel(sql_type = Text)]
+    pub message: String,
+}
+
+#[derive(Queryable)]
+pub struct ClassificationRuleEntity {
+    pub id: i32,
+    pub planet_type: PlanetType,
+    pub min_mass: Option<BigDecimal>,
+    pub max_mass: Option<BigDecimal>,
+    pub min_mean_radius: Option<BigDecimal>,
+    pub max_mean_radius: Option<BigDecimal>,
+    pub priority: i32,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = classification_rules)]
+pub struct NewClassificationRuleEntity {
+    pub planet_type: PlanetType,
+    pub min_mass: Option<BigDecimal>,
+    pub max_mass: Option<BigDecimal>,
+    pub min_mean_radius: Option<BigDecimal>,
+    pub max_mean_radius: Option<BigDecimal>,
+    pub priority: i32,
+}
+
+#[cfg(test)]
+mod tests {
+    use strum::IntoEnumIterator;
+
+    use super::*;
+
+    #[test]
+    fn planet_type_values() {
+        let values: Vec<String> = PlanetType::iter().map(|type_| type_.to_string()).collect();
+        assert_eq!(
+            vec![
+                "TERRESTRIAL_PLANET",
+                "GAS_GIANT",
+                "ICE_GIANT",
+                "DWARF_PLANET"
+            ],
+            values
+        );
+        for type_ in PlanetType::iter() {
+            assert_eq!(Ok(type_), PlanetType::from_str(&type_.to_string()));
+            assert_eq!(
+                serde_json::json!(type_.to_string()),
+                serde_json::to_value(type_).expect("Can't serialize planet type")
+            );
+        }
+        assert!(PlanetType::from_str("GasGiant").is_err());
+    }
+}
@@ -1,40 +1,1135 @@
+use std::collections::HashMap;
+use std::thread;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use diesel::dsl::sql;
+use diesel::pg::Pg;
 use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error};
+use diesel::sql_types::{BigInt, Bool, Text, Timestamptz};
+use rand::Rng;
+use uuid::Uuid;
+
+use crate::classification;
+use crate::http_client;
+use crate::persistence::model::{
+    ClassificationRuleEntity, ColumnCommentEntity, ConflictPolicy, DataQualityFindingEntity,
+    DeliveryStatus, DetailsEntity, EventKind, ExistingPlanets, GasComponentEntity, HistoryRange,
+    ImportedPlanet, ImportedSnapshot, NewClassificationRuleEntity, NewDetailsEntity,
+    NewOutboxEventEntity, NewPlanetEntity, NewReportEntity, NewStarEntity,
+    NewWebhookDeliveryEntity, NewWebhookEntity, OrbitEntity, OutboxEventEntity, PlanetEntity,
+    PlanetStatus, PlanetWrite, PlanetsFilter, PlanetsOrder, PlanetsOrderColumn, ReportEntity,
+    SnapshotEntities, StarEntity, UnclassifiedPlanet, Validity, WebhookDeliveryAttempt,
+    WebhookDeliveryEntity, WebhookEntity,
+};
+use crate::persistence::schema::{
+    atmosphere_components, classification_rules, details, details_history, orbits, outbox_events,
+    planet_redirects, planets, planets_history, reports, stars, webhook_deliveries, webhooks,
+};
+
+pub const PLANETS_TOPIC: &str = "planets";
+
+/// Columns of `planets_history` in the order of [PlanetEntity] fields
+const PLANET_VERSION_COLUMNS: (
+    planets_history::planet_id,
+    planets_history::name,
+    planets_history::type_,
+    planets_history::uuid,
+    planets_history::star_id,
+    planets_history::status,
+) = (
+    planets_history::planet_id,
+    planets_history::name,
+    planets_history::type_,
+    planets_history::uuid,
+    planets_history::star_id,
+    planets_history::status,
+);
+
+const MAX_TRANSACTION_ATTEMPTS: u32 = 5;
+const RETRY_BASE_DELAY_MILLIS: u64 = 10;
 
-use crate::persistence::model::{DetailsEntity, NewDetailsEntity, NewPlanetEntity, PlanetEntity};
-use crate::persistence::schema::{details, planets};
+pub fn get_all(
+    status: PlanetStatus,
+    order: &PlanetsOrder,
+    conn: &mut PgConnection,
+) -> QueryResult<Vec<PlanetEntity>> {
+    let query = planets::table
+        .filter(planets::status.eq(status.to_string()))
+        .into_boxed();
+    let query = match (order.column, order.descending) {
+        (PlanetsOrderColumn::Id, false) => query.order(planets::id.asc()),
+        (PlanetsOrderColumn::Id, true) => query.order(planets::id.desc()),
+        (PlanetsOrderColumn::Name, false) => query.order(planets::name.asc()),
+        (PlanetsOrderColumn::Name, true) => query.order(planets::name.desc()),
+        (PlanetsOrderColumn::Type, false) => query.order(planets::type_.asc()),
+        (PlanetsOrderColumn::Type, true) => query.order(planets::type_.desc()),
+    };
+    // the tiebreaker, IDs are unique
+    query.then_order_by(planets::id.asc()).load(conn)
+}
+
+/// Like [get_all], but with the versions of the planets valid at the moment; the versions of
+/// merged planets are only in the history of the target
+pub fn get_all_as_of(
+    status: PlanetStatus,
+    order: &PlanetsOrder,
+    as_of: DateTime<Utc>,
+    conn: &mut PgConnection,
+) -> QueryResult<Vec<PlanetEntity>> {
+    let query = planets_history::table
+        .filter(planets_history::status.eq(status.to_string()))
+        .filter(planets_history::valid_from.le(as_of))
+        .filter(
+            planets_history::valid_to
+                .gt(as_of)
+                .or(planets_history::valid_to.is_null()),
+        )
+        .filter(planets_history::merged_from.is_null())
+        .select(PLANET_VERSION_COLUMNS)
+        .into_boxed();
+    let query = match (order.column, order.descending) {
+        (PlanetsOrderColumn::Id, false) => query.order(planets_history::planet_id.asc()),
+        (PlanetsOrderColumn::Id, true) => query.order(planets_history::planet_id.desc()),
+        (PlanetsOrderColumn::Name, false) => query.order(planets_history::name.asc()),
+        (PlanetsOrderColumn::Name, true) => query.order(planets_history::name.desc()),
+        (PlanetsOrderColumn::Type, false) => query.order(planets_history::type_.asc()),
+        (PlanetsOrderColumn::Type, true) => query.order(planets_history::type_.desc()),
+    };
+    query
+        .then_order_by(planets_history::planet_id.asc())
+        .load(conn)
+}
 
-pub fn get_all(conn: &mut PgConnection) -> QueryResult<Vec<PlanetEntity>> {
-    use crate::persistence::schema::planets::dsl::*;
+pub fn get_as_of(
+    id: i32,
+    as_of: DateTime<Utc>,
+    conn: &mut PgConnection,
+) -> QueryResult<PlanetEntity> {
+    planets_history::table
+        .filter(planets_history::planet_id.eq(id))
+        .filter(planets_history::valid_from.le(as_of))
+        .filter(
+            planets_history::valid_to
+                .gt(as_of)
+                .or(planets_history::valid_to.is_null()),
+        )
+        .filter(planets_history::merged_from.is_null())
+        .select(PLANET_VERSION_COLUMNS)
+        .first(conn)
+}
 
-    planets.load(conn)
+pub fn get_by_uuid_as_of(
+    uuid: Uuid,
+    as_of: DateTime<Utc>,
+    conn: &mut PgConnection,
+) -> QueryResult<PlanetEntity> {
+    planets_history::table
+        .filter(planets_history::uuid.eq(uuid))
+        .filter(planets_history::valid_from.le(as_of))
+        .filter(
+            planets_history::valid_to
+                .gt(as_of)
+                .or(planets_history::valid_to.is_null()),
+        )
+        .filter(planets_history::merged_from.is_null())
+        .select(PLANET_VERSION_COLUMNS)
+        .first(conn)
 }
 
 pub fn get(id: i32, conn: &mut PgConnection) -> QueryResult<PlanetEntity> {
     planets::table.find(id).get_result(conn)
 }
 
+pub fn get_by_ids(ids: &[i32], conn: &mut PgConnection) -> QueryResult<Vec<PlanetEntity>> {
+    planets::table.filter(planets::id.eq_any(ids)).load(conn)
+}
+
+/// Returns up to `limit` published planets in an order determined by the seed, so the same seed
+/// gives the same planets
+pub fn sample(limit: i64, seed: i64, conn: &mut PgConnection) -> QueryResult<Vec<PlanetEntity>> {
+    planets::table
+        .filter(planets::status.eq(PlanetStatus::Published.to_string()))
+        .order(
+            sql::<Text>("md5(planets.id::text || ")
+                .bind::<BigInt, _>(seed)
+                .sql("::text)"),
+        )
+        .limit(limit)
+        .load(conn)
+}
+
+pub fn get_by_uuid(uuid: Uuid, conn: &mut PgConnection) -> QueryResult<PlanetEntity> {
+    planets::table.filter(planets::uuid.eq(uuid)).first(conn)
+}
+
+pub fn get_by_uuids(uuids: &[Uuid], conn: &mut PgConnection) -> QueryResult<Vec<PlanetEntity>> {
+    planets::table
+        .filter(planets::uuid.eq_any(uuids))
+        .load(conn)
+}
+
+/// ID of the planet which the merged planet with the ID was merged into
+pub fn get_redirect(old_id: i32, conn: &mut PgConnection) -> QueryResult<i32> {
+    planet_redirects::table
+        .find(old_id)
+        .select(planet_redirects::planet_id)
+        .first(conn)
+}
+
+pub fn get_redirect_by_uuid(old_uuid: Uuid, conn: &mut PgConnection) -> QueryResult<i32> {
+    planet_redirects::table
+        .filter(planet_redirects::old_uuid.eq(old_uuid))
+        .select(planet_redirects::planet_id)
+        .first(conn)
+}
+
+pub fn get_details_as_of(
+    planet_ids: &[i32],
+    as_of: DateTime<Utc>,
+    conn: &mut PgConnection,
+) -> QueryResult<Vec<DetailsEntity>> {
+    details_history::table
+        .filter(details_history::planet_id.eq_any(planet_ids))
+        .filter(details_history::valid_from.le(as_of))
+        .filter(
+            details_history::valid_to
+                .gt(as_of)
+                .or(details_history::valid_to.is_null()),
+        )
+        .filter(details_history::merged_from.is_null())
+        .select((
+            details_history::details_id,
+            details_history::mean_radius,
+            details_history::mass,
+            details_history::population,
+            details_history::planet_id,
+        ))
+        .load(conn)
+}
+
+/// Versions of the planet in the range; the primary key covers the query
+pub fn get_planet_history(
+    planet_id: i32,
+    range: &HistoryRange,
+    conn: &mut PgConnection,
+) -> QueryResult<Vec<(PlanetEntity, Validity)>> {
+    let mut query = planets_history::table
+        .filter(planets_history::planet_id.eq(planet_id))
+        .select((
+            PLANET_VERSION_COLUMNS,
+            (planets_history::valid_from, planets_history::valid_to),
+        ))
+        .into_boxed();
+    if let Some(from) = range.from {
+        query = query.filter(planets_history::valid_from.ge(from));
+    }
+    if let Some(to) = range.to {
+        query = query.filter(planets_history::valid_from.lt(to));
+    }
+    if range.published_only {
+        query = query.filter(planets_history::status.eq(PlanetStatus::Published.to_string()));
+    }
+    query = match (range.after, range.descending) {
+        (Some(after), false) => query.filter(planets_history::valid_from.gt(after)),
+        (Some(after), true) => query.filter(planets_history::valid_from.lt(after)),
+        (None, _) => query,
+    };
+    query = if range.descending {
+        query.order(planets_history::valid_from.desc())
+    } else {
+        query.order(planets_history::valid_from.asc())
+    };
+    query.limit(range.limit).load(conn)
+}
+
+/// Whether a version of `details_history` was current while the planet was published. Versions
+/// changed by one transaction start one after another, so the overlap which both starts and ends
+/// in the same transaction has never been visible; the end of a version is the start of the next
+/// one, unless the row was deleted
+const OVERLAPS_PUBLISHED_VERSION: &str = "exists (
+    select 1 from planets_history p
+    left join planets_history next_p
+        on next_p.planet_id = p.planet_id and next_p.uuid = p.uuid
+        and next_p.valid_from = p.valid_to
+    left join details_history next_d
+        on next_d.details_id = details_history.details_id
+        and next_d.valid_from = details_history.valid_to
+    where p.planet_id = details_history.planet_id
+        and p.merged_from is not distinct from details_history.merged_from
+        and p.status = 'PUBLISHED'
+        and p.valid_from < coalesce(details_history.valid_to, 'infinity')
+        and details_history.valid_from < coalesce(p.valid_to, 'infinity')
+        and (case when p.valid_from > details_history.valid_from
+                then p.transaction_id else details_history.transaction_id end)
+            is distinct from
+            (case when coalesce(p.valid_to, 'infinity')
+                    < coalesce(details_history.valid_to, 'infinity')
+                then next_p.transaction_id else next_d.transaction_id end)
+)";
+
+/// Versions of the details of the planet in the range, see [get_planet_history];
+/// `details_history_planet_id_idx` covers the query
+pub fn get_details_history(
+    planet_id: i32,
+    range: &HistoryRange,
+    conn: &mut PgConnection,
+) -> QueryResult<Vec<(DetailsEntity, Validity)>> {
+    let mut query = details_history::table
+        .filter(details_history::planet_id.eq(planet_id))
+        .select((
+            (
+                details_history::details_id,
+                details_history::mean_radius,
+                details_history::mass,
+                details_history::population,
+                details_history::planet_id,
+            ),
+            (details_history::valid_from, details_history::valid_to),
+        ))
+        .into_boxed();
+    if let Some(from) = range.from {
+        query = query.filter(details_history::valid_from.ge(from));
+    }
+    if let Some(to) = range.to {
+        query = query.filter(details_history::valid_from.lt(to));
+    }
+    if range.published_only {
+        query = query.filter(sql::<Bool>(OVERLAPS_PUBLISHED_VERSION));
+    }
+    query = match (range.after, range.descending) {
+        (Some(after), false) => query.filter(details_history::valid_from.gt(after)),
+        (Some(after), true) => query.filter(details_history::valid_from.lt(after)),
+        (None, _) => query,
+    };
+    query = if range.descending {
+        query.order(details_history::valid_from.desc())
+    } else {
+        query.order(details_history::valid_from.asc())
+    };
+    query.limit(range.limit).load(conn)
+}
+
 pub fn get_details(planet_ids: &[i32], conn: &mut PgConnection) -> QueryResult<Vec<DetailsEntity>> {
     details::table
         .filter(details::planet_id.eq_any(planet_ids))
         .load::<DetailsEntity>(conn)
 }
 
+pub fn get_orbits(planet_ids: &[i32], conn: &mut PgConnection) -> QueryResult<Vec<OrbitEntity>> {
+    orbits::table
+        .filter(orbits::planet_id.eq_any(planet_ids))
+        .load(conn)
+}
+
+/// Replaces the orbit of a planet; the orbit is removed if absent
+pub fn set_orbit(
+    planet_id: i32,
+    orbit: Option<OrbitEntity>,
+    conn: &mut PgConnection,
+) -> QueryResult<(PlanetEntity, OutboxEventEntity)> {
+    in_serializable_transaction(conn, |conn| {
+        let planet: PlanetEntity = planets::table.find(planet_id).get_result(conn)?;
+        diesel::delete(orbits::table.filter(orbits::planet_id.eq(planet_id))).execute(conn)?;
+        if let Some(orbit) = &orbit {
+            diesel::insert_into(orbits::table)
+                .values(&OrbitEntity {
+                    planet_id,
+                    ..orbit.clone()
+                })
+                .execute(conn)?;
+        }
+
+        let event = create_planet_event(EventKind::Updated, &planet, conn)?;
+
+        Ok((planet, event))
+    })
+}
+
+/// Gases of the planets from the most abundant
+pub fn get_atmospheres(
+    planet_ids: &[i32],
+    conn: &mut PgConnection,
+) -> QueryResult<Vec<GasComponentEntity>> {
+    atmosphere_components::table
+        .filter(atmosphere_components::planet_id.eq_any(planet_ids))
+        .order((
+            atmosphere_components::planet_id,
+            atmosphere_components::percentage.desc(),
+            atmosphere_components::gas,
+        ))
+        .load(conn)
+}
+
+pub fn get_stars(ids: &[i32], conn: &mut PgConnection) -> QueryResult<Vec<StarEntity>> {
+    stars::table.filter(stars::id.eq_any(ids)).load(conn)
+}
+
+/// Returns the star with the same name if there is one (its other properties are kept),
+/// otherwise creates it
+pub fn get_or_create_star(
+    new_star: NewStarEntity,
+    conn: &mut PgConnection,
+) -> QueryResult<StarEntity> {
+    diesel::insert_into(stars::table)
+        .values(&new_star)
+        .on_conflict(stars::name)
+        .do_nothing()
+        .execute(conn)?;
+    stars::table
+        .filter(stars::name.eq(&new_star.name))
+        .first(conn)
+}
+
 pub fn create(
-    new_planet: NewPlanetEntity,
+    planet: PlanetWrite,
     mut new_details_entity: NewDetailsEntity,
+    atmosphere: &[GasComponentEntity],
     conn: &mut PgConnection,
-) -> QueryResult<PlanetEntity> {
-    use crate::persistence::schema::{details::dsl::*, planets::dsl::*};
+) -> QueryResult<Result<(PlanetEntity, OutboxEventEntity), UnclassifiedPlanet>> {
+    in_serializable_transaction(conn, |conn| {
+        let Some(new_planet) = to_new_planet_entity(&planet, &new_details_entity, conn)? else {
+            return Ok(Err(UnclassifiedPlanet));
+        };
+        let created_planet: PlanetEntity = diesel::insert_into(planets::table)
+            .values(&new_planet)
+            .get_result(conn)?;
+
+        new_details_entity.planet_id = created_planet.id;
+
+        diesel::insert_into(details::table)
+            .values(&new_details_entity)
+            .execute(conn)?;
+        insert_atmosphere(created_planet.id, atmosphere, conn)?;
+
+        let event = create_planet_event(EventKind::Created, &created_planet, conn)?;
+
+        Ok(Ok((created_planet, event)))
+    })
+}
+
+/// The atmosphere isn't changed if absent
+pub fn update(
+    id: i32,
+    planet: PlanetWrite,
+    mut details_entity: NewDetailsEntity,
+    atmosphere: Option<&[GasComponentEntity]>,
+    conn: &mut PgConnection,
+) -> QueryResult<Result<(PlanetEntity, OutboxEventEntity), UnclassifiedPlanet>> {
+    in_serializable_transaction(conn, |conn| {
+        let Some(new_planet) = to_new_planet_entity(&planet, &details_entity, conn)? else {
+            return Ok(Err(UnclassifiedPlanet));
+        };
+        let updated_planet: PlanetEntity = diesel::update(planets::table.find(id))
+            .set(&new_planet)
+            .get_result(conn)?;
+
+        details_entity.planet_id = id;
+
+        diesel::update(details::table.filter(details::planet_id.eq(id)))
+            .set(&details_entity)
+            .execute(conn)?;
+        if let Some(atmosphere) = atmosphere {
+            diesel::delete(
+                atmosphere_components::table.filter(atmosphere_components::planet_id.eq(id)),
+            )
+            .execute(conn)?;
+            insert_atmosphere(id, atmosphere, conn)?;
+        }
+
+        let event = create_planet_event(EventKind::Updated, &updated_planet, conn)?;
+
+        Ok(Ok((updated_planet, event)))
+    })
+}
+
+// the type is classified before the star is created, so that nothing is written for a planet
+// which can't be classified
+fn to_new_planet_entity(
+    planet: &PlanetWrite,
+    details_entity: &NewDetailsEntity,
+    conn: &mut PgConnection,
+) -> QueryResult<Option<NewPlanetEntity>> {
+    let type_ = match planet.type_ {
+        Some(type_) => type_,
+        None => match classification::classify(
+            &get_classification_rules(conn)?,
+            &details_entity.mass,
+            &details_entity.mean_radius,
+        ) {
+            Some(type_) => type_,
+            None => return Ok(None),
+        },
+    };
+    let star_id = match &planet.star {
+        Some(Some(new_star)) => Some(Some(get_or_create_star(new_star.clone(), conn)?.id)),
+        Some(None) => Some(None),
+        None => None,
+    };
+    Ok(Some(NewPlanetEntity {
+        name: planet.name.clone(),
+        type_,
+        star_id,
+        status: planet.status.clone(),
+    }))
+}
+
+fn insert_atmosphere(
+    planet_id: i32,
+    atmosphere: &[GasComponentEntity],
+    conn: &mut PgConnection,
+) -> QueryResult<usize> {
+    let components: Vec<GasComponentEntity> = atmosphere
+        .iter()
+        .map(|component| GasComponentEntity {
+            planet_id,
+            ..component.clone()
+        })
+        .collect();
+    diesel::insert_into(atmosphere_components::table)
+        .values(&components)
+        .execute(conn)
+}
+
+/// Changes the status of the planet, e.g. publishes a draft
+pub fn set_status(
+    id: i32,
+    status: PlanetStatus,
+    kind: EventKind,
+    conn: &mut PgConnection,
+) -> QueryResult<(PlanetEntity, OutboxEventEntity)> {
+    in_serializable_transaction(conn, |conn| {
+        let updated_planet: PlanetEntity = diesel::update(planets::table.find(id))
+            .set(planets::status.eq(status.to_string()))
+            .get_result(conn)?;
+
+        let event = create_planet_event(kind, &updated_planet, conn)?;
 
-    let created_planet: PlanetEntity = diesel::insert_into(planets)
-        .values(new_planet)
+        Ok((updated_planet, event))
+    })
+}
+
+pub fn count(filter: &PlanetsFilter, conn: &mut PgConnection) -> QueryResult<i64> {
+    filter_planets(filter).count().get_result(conn)
+}
+
+/// Deletes up to `batch_size` planets matching the filter along with their details, orbits and
+/// atmospheres.
+/// Returns an event per deleted planet, so an empty result means that nothing matches anymore
+pub fn delete_batch(
+    filter: &PlanetsFilter,
+    batch_size: i64,
+    conn: &mut PgConnection,
+) -> QueryResult<Vec<OutboxEventEntity>> {
+    in_serializable_transaction(conn, |conn| {
+        let ids: Vec<i32> = filter_planets(filter)
+            .select(planets::id)
+            .order(planets::id)
+            .limit(batch_size)
+            .load(conn)?;
+
+        diesel::delete(details::table.filter(details::planet_id.eq_any(&ids))).execute(conn)?;
+        diesel::delete(orbits::table.filter(orbits::planet_id.eq_any(&ids))).execute(conn)?;
+        diesel::delete(
+            atmosphere_components::table.filter(atmosphere_components::planet_id.eq_any(&ids)),
+        )
+        .execute(conn)?;
+        let deleted_planets: Vec<PlanetEntity> =
+            diesel::delete(planets::table.filter(planets::id.eq_any(&ids))).get_results(conn)?;
+
+        deleted_planets
+            .iter()
+            .map(|planet| create_planet_event(EventKind::Deleted, planet, conn))
+            .collect()
+    })
+}
+
+/// Merges a duplicate into the target planet: the target keeps its own data, but takes the star,
+/// the orbit and the atmosphere of the source if it has none. The source is deleted, and
+/// a redirect from it (and from planets merged into it before) to the target is recorded.
+/// The history of the source moves to the target, see `planets_history.merged_from`. Returns the
+/// target and the events of deletion of the source and update of the target
+pub fn merge(
+    source_id: i32,
+    target_id: i32,
+    conn: &mut PgConnection,
+) -> QueryResult<(PlanetEntity, Vec<OutboxEventEntity>)> {
+    in_serializable_transaction(conn, |conn| {
+        let source: PlanetEntity = planets::table.find(source_id).first(conn)?;
+        let mut target: PlanetEntity = planets::table.find(target_id).first(conn)?;
+
+        if target.star_id.is_none() && source.star_id.is_some() {
+            target = diesel::update(planets::table.find(target_id))
+                .set(planets::star_id.eq(source.star_id))
+                .get_result(conn)?;
+        }
+        let target_has_orbit: bool = diesel::select(diesel::dsl::exists(
+            orbits::table.filter(orbits::planet_id.eq(target_id)),
+        ))
+        .get_result(conn)?;
+        if !target_has_orbit {
+            diesel::update(orbits::table.filter(orbits::planet_id.eq(source_id)))
+                .set(orbits::planet_id.eq(target_id))
+                .execute(conn)?;
+        }
+        let target_has_atmosphere: bool = diesel::select(diesel::dsl::exists(
+            atmosphere_components::table.filter(atmosphere_components::planet_id.eq(target_id)),
+        ))
         .get_result(conn)?;
+        if !target_has_atmosphere {
+            diesel::update(
+                atmosphere_components::table.filter(atmosphere_components::planet_id.eq(source_id)),
+            )
+            .set(atmosphere_components::planet_id.eq(target_id))
+            .execute(conn)?;
+        }
 
-    new_details_entity.planet_id = created_planet.id;
+        diesel::update(planet_redirects::table.filter(planet_redirects::planet_id.eq(source_id)))
+            .set(planet_redirects::planet_id.eq(target_id))
+            .execute(conn)?;
+        diesel::insert_into(planet_redirects::table)
+            .values((
+                planet_redirects::old_id.eq(source.id),
+                planet_redirects::old_uuid.eq(source.uuid),
+                planet_redirects::planet_id.eq(target_id),
+            ))
+            .execute(conn)?;
+
+        diesel::delete(details::table.filter(details::planet_id.eq(source_id))).execute(conn)?;
+        diesel::delete(orbits::table.filter(orbits::planet_id.eq(source_id))).execute(conn)?;
+        diesel::delete(
+            atmosphere_components::table.filter(atmosphere_components::planet_id.eq(source_id)),
+        )
+        .execute(conn)?;
+        diesel::delete(planets::table.find(source_id)).execute(conn)?;
 
-    diesel::insert_into(details)
-        .values(new_details_entity)
+        // versions of planets merged into the source before keep their `merged_from`
+        diesel::update(
+            planets_history::table
+                .filter(planets_history::planet_id.eq(source_id))
+                .filter(planets_history::merged_from.is_null()),
+        )
+        .set(planets_history::merged_from.eq(source_id))
         .execute(conn)?;
+        diesel::update(planets_history::table.filter(planets_history::planet_id.eq(source_id)))
+            .set(planets_history::planet_id.eq(target_id))
+            .execute(conn)?;
+        diesel::update(
+            details_history::table
+                .filter(details_history::planet_id.eq(source_id))
+                .filter(details_history::merged_from.is_null()),
+        )
+        .set(details_history::merged_from.eq(source_id))
+        .execute(conn)?;
+        diesel::update(details_history::table.filter(details_history::planet_id.eq(source_id)))
+            .set(details_history::planet_id.eq(target_id))
+            .execute(conn)?;
+
+        let events = vec![
+            create_planet_event(EventKind::Deleted, &source, conn)?,
+            create_planet_event(EventKind::Updated, &target, conn)?,
+        ];
+        Ok((target, events))
+    })
+}
+
+/// Stars and planets with their details, orbits and atmospheres, read in one transaction
+pub fn get_snapshot(conn: &mut PgConnection) -> QueryResult<SnapshotEntities> {
+    conn.build_transaction()
+        .repeatable_read()
+        .read_only()
+        .run(|conn| {
+            Ok(SnapshotEntities {
+                stars: stars::table.order(stars::id).load(conn)?,
+                planets: planets::table.order(planets::id).load(conn)?,
+                details: details::table.order(details::planet_id).load(conn)?,
+                orbits: orbits::table.order(orbits::planet_id).load(conn)?,
+                atmosphere_components: atmosphere_components::table
+                    .order((
+                        atmosphere_components::planet_id,
+                        atmosphere_components::percentage.desc(),
+                        atmosphere_components::gas,
+                    ))
+                    .load(conn)?,
+            })
+        })
+}
+
+/// Restores stars and planets by names, which are unique unlike IDs and UUIDs of another
+/// database; a planet renamed since the snapshot was made is found by UUID. Importing the same
+/// snapshot again changes nothing. Existing stars are overwritten only with
+/// [ConflictPolicy::Overwrite]; with [ConflictPolicy::Fail] nothing is imported if any planet
+/// exists, which is checked in the transaction
+pub fn import_snapshot(
+    new_stars: &[NewStarEntity],
+    imported_planets: &[ImportedPlanet],
+    conflict_policy: ConflictPolicy,
+    conn: &mut PgConnection,
+) -> QueryResult<Result<ImportedSnapshot, ExistingPlanets>> {
+    in_serializable_transaction(conn, |conn| {
+        let names: Vec<&String> = imported_planets.iter().map(|planet| &planet.name).collect();
+        let uuids: Vec<Uuid> = imported_planets.iter().map(|planet| planet.uuid).collect();
+        let existing_planets: Vec<(i32, Uuid, String)> = planets::table
+            .filter(planets::name.eq_any(names).or(planets::uuid.eq_any(uuids)))
+            .select((planets::id, planets::uuid, planets::name))
+            .load(conn)?;
+        let ids_by_name: HashMap<&String, i32> = existing_planets
+            .iter()
+            .map(|(id, _, name)| (name, *id))
+            .collect();
+        let ids_by_uuid: HashMap<&Uuid, i32> = existing_planets
+            .iter()
+            .map(|(id, uuid, _)| (uuid, *id))
+            .collect();
+        let existing_ids: Vec<Option<i32>> = imported_planets
+            .iter()
+            .map(|planet| {
+                ids_by_name
+                    .get(&planet.name)
+                    .or_else(|| ids_by_uuid.get(&planet.uuid))
+                    .copied()
+            })
+            .collect();
+        let existing_count = existing_ids.iter().flatten().count();
+        if conflict_policy == ConflictPolicy::Fail && existing_count > 0 {
+            return Ok(Err(ExistingPlanets(existing_count)));
+        }
+
+        for new_star in new_stars {
+            let insert = diesel::insert_into(stars::table)
+                .values(new_star)
+                .on_conflict(stars::name);
+            if conflict_policy == ConflictPolicy::Overwrite {
+                insert.do_update().set(new_star).execute(conn)?;
+            } else {
+                insert.do_nothing().execute(conn)?;
+            }
+        }
+        let star_names: Vec<&String> = new_stars.iter().map(|star| &star.name).collect();
+        let star_ids: HashMap<String, i32> = stars::table
+            .filter(stars::name.eq_any(star_names))
+            .select((stars::name, stars::id))
+            .load::<(String, i32)>(conn)?
+            .into_iter()
+            .collect();
+
+        let mut imported = ImportedSnapshot {
+            events: vec![],
+            skipped: 0,
+        };
+        for (planet, existing_id) in imported_planets.iter().zip(existing_ids) {
+            let star_id = match &planet.star_name {
+                Some(name) => Some(*star_ids.get(name).ok_or(Error::NotFound)?),
+                None => None,
+            };
+            let values = (
+                planets::name.eq(&planet.name),
+                planets::type_.eq(&planet.type_),
+                planets::status.eq(&planet.status),
+                planets::star_id.eq(star_id),
+            );
+
+            let (saved_planet, event_kind): (PlanetEntity, _) = match existing_id {
+                Some(_) if conflict_policy == ConflictPolicy::Skip => {
+                    imported.skipped += 1;
+                    continue;
+                }
+                // the existing planet keeps its UUID
+                Some(existing_id) => {
+                    let updated_planet: PlanetEntity =
+                        diesel::update(planets::table.find(existing_id))
+                            .set(values)
+                            .get_result(conn)?;
+                    diesel::delete(details::table.filter(details::planet_id.eq(updated_planet.id)))
+                        .execute(conn)?;
+                    diesel::delete(orbits::table.filter(orbits::planet_id.eq(updated_planet.id)))
+                        .execute(conn)?;
+                    diesel::delete(
+                        atmosphere_components::table
+                            .filter(atmosphere_components::planet_id.eq(updated_planet.id)),
+                    )
+                    .execute(conn)?;
+                    (updated_planet, EventKind::Updated)
+                }
+                None => {
+                    let created_planet = diesel::insert_into(planets::table)
+                        .values((planets::uuid.eq(planet.uuid), values))
+                        .get_result(conn)?;
+                    (created_planet, EventKind::Created)
+                }
+            };
+
+            let mut details = planet.details.clone();
+            details.planet_id = saved_planet.id;
+            diesel::insert_into(details::table)
+                .values(&details)
+                .execute(conn)?;
+            if let Some(orbit) = &planet.orbit {
+                let mut orbit = orbit.clone();
+                orbit.planet_id = saved_planet.id;
+                diesel::insert_into(orbits::table)
+                    .values(&orbit)
+                    .execute(conn)?;
+            }
+            insert_atmosphere(saved_planet.id, &planet.atmosphere, conn)?;
+
+            imported
+                .events
+                .push(create_planet_event(event_kind, &saved_planet, conn)?);
+        }
+        Ok(Ok(imported))
+    })
+}
+
+/// Returns events of the topic that go after the token (event id) in order of their creation
+pub fn get_events_after(
+    topic: &str,
+    token: i64,
+    conn: &mut PgConnection,
+) -> QueryResult<Vec<OutboxEventEntity>> {
+    outbox_events::table
+        .filter(outbox_events::topic.eq(topic))
+        .filter(outbox_events::id.gt(token))
+        .order(outbox_events::id)
+        .select(OutboxEventEntity::as_select())
+        .load(conn)
+}
+
+pub fn get_classification_rules(
+    conn: &mut PgConnection,
+) -> QueryResult<Vec<ClassificationRuleEntity>> {
+    classification_rules::table
+        .order((classification_rules::priority, classification_rules::id))
+        .load(conn)
+}
+
+/// Replaces all classification rules at once
+pub fn replace_classification_rules(
+    rules: Vec<NewClassificationRuleEntity>,
+    conn: &mut PgConnection,
+) -> QueryResult<Vec<ClassificationRuleEntity>> {
+    in_serializable_transaction(conn, |conn| {
+        diesel::delete(classification_rules::table).execute(conn)?;
+        diesel::insert_into(classification_rules::table)
+            .values(&rules)
+            .execute(conn)?;
+        get_classification_rules(conn)
+    })
+}
+
+/// Saves a report replacing the owner's report with the same name
+pub fn save_report(
+    new_report: NewReportEntity,
+    conn: &mut PgConnection,
+) -> QueryResult<ReportEntity> {
+    diesel::insert_into(reports::table)
+        .values(&new_report)
+        .on_conflict((reports::owner, reports::name))
+        .do_update()
+        .set(&new_report)
+        .returning(ReportEntity::as_returning())
+        .get_result(conn)
+}
+
+pub fn get_report(owner: &str, name: &str, conn: &mut PgConnection) -> QueryResult<ReportEntity> {
+    reports::table
+        .filter(reports::owner.eq(owner))
+        .filter(reports::name.eq(name))
+        .select(ReportEntity::as_select())
+        .first(conn)
+}
+
+pub fn get_reports(owner: &str, conn: &mut PgConnection) -> QueryResult<Vec<ReportEntity>> {
+    reports::table
+        .filter(reports::owner.eq(owner))
+        .order(reports::name)
+        .select(ReportEntity::as_select())
+        .load(conn)
+}
+
+/// Deliveries are created only for events of transactions running at the registration or later
+pub fn create_webhook(
+    new_webhook: NewWebhookEntity,
+    conn: &mut PgConnection,
+) -> QueryResult<WebhookEntity> {
+    in_serializable_transaction(conn, |conn| {
+        let next_transaction_id = get_oldest_running_transaction_id(conn)?;
+        diesel::insert_into(webhooks::table)
+            .values((
+                &new_webhook,
+                webhooks::next_transaction_id.eq(next_transaction_id),
+            ))
+            .returning(WebhookEntity::as_returning())
+            .get_result(conn)
+    })
+}
+
+pub fn get_webhooks(conn: &mut PgConnection) -> QueryResult<Vec<WebhookEntity>> {
+    webhooks::table
+        .order(webhooks::id)
+        .select(WebhookEntity::as_select())
+        .load(conn)
+}
+
+/// Returns the latest deliveries of the webhook first
+pub fn get_webhook_deliveries(
+    webhook_id: i32,
+    limit: i64,
+    conn: &mut PgConnection,
+) -> QueryResult<Vec<WebhookDeliveryEntity>> {
+    webhook_deliveries::table
+        .filter(webhook_deliveries::webhook_id.eq(webhook_id))
+        .order(webhook_deliveries::id.desc())
+        .limit(limit)
+        .select(WebhookDeliveryEntity::as_select())
+        .load(conn)
+}
+
+/// Events of transactions older than the returned one are committed or rolled back, even if
+/// their IDs are higher than the IDs of events of running transactions
+fn get_oldest_running_transaction_id(conn: &mut PgConnection) -> QueryResult<i64> {
+    diesel::select(sql::<BigInt>("txid_snapshot_xmin(txid_current_snapshot())")).get_result(conn)
+}
+
+/// Creates pending deliveries of the events of transactions finished since the previous call,
+/// taking into account the kinds of events each webhook is registered for. Events are taken in
+/// order of transactions rather than IDs, since IDs are assigned on insert and a transaction
+/// committing late has lower ones. Returns the number of created deliveries
+pub fn enqueue_webhook_deliveries(conn: &mut PgConnection) -> QueryResult<usize> {
+    in_serializable_transaction(conn, |conn| {
+        // the snapshot of the transaction, so the events of the older transactions are visible
+        let oldest_running_transaction_id = get_oldest_running_transaction_id(conn)?;
+        let webhooks: Vec<WebhookEntity> = webhooks::table
+            .select(WebhookEntity::as_select())
+            .for_update()
+            .load(conn)?;
+
+        let mut created = 0;
+        for webhook in webhooks {
+            let events: Vec<OutboxEventEntity> = outbox_events::table
+                .filter(outbox_events::topic.eq(PLANETS_TOPIC))
+                .filter(outbox_events::transaction_id.ge(webhook.next_transaction_id))
+                .filter(outbox_events::transaction_id.lt(oldest_running_transaction_id))
+                .order(outbox_events::id)
+                .select(OutboxEventEntity::as_select())
+                .load(conn)?;
+            if events.is_empty() {
+                continue;
+            }
+            let deliveries = events
+                .iter()
+                .filter(|event| {
+                    webhook.event_kinds.is_empty() || webhook.event_kinds.contains(&event.kind)
+                })
+                .map(|event| NewWebhookDeliveryEntity {
+                    webhook_id: webhook.id,
+                    event_id: event.id,
+                    status: DeliveryStatus::Pending.to_string(),
+                    attempts: 0,
+                    next_attempt_at: Utc::now(),
+                })
+                .collect::<Vec<_>>();
+            created += diesel::insert_into(webhook_deliveries::table)
+                .values(&deliveries)
+                .on_conflict_do_nothing()
+                .execute(conn)?;
+            diesel::update(webhooks::table.find(webhook.id))
+                .set(webhooks::next_transaction_id.eq(oldest_running_transaction_id))
+                .execute(conn)?;
+        }
+        Ok(created)
+    })
+}
+
+/// A delivery along with what is needed to make it
+pub type WebhookDeliveryTask = (
+    WebhookDeliveryEntity,
+    WebhookEntity,
+    OutboxEventEntity,
+    DateTime<Utc>,
+);
+
+/// Claims up to `limit` pending deliveries which are due, postponing their next attempt until
+/// `lease_until`, so that other instances don't make them concurrently
+pub fn claim_due_webhook_deliveries(
+    limit: i64,
+    lease_until: DateTime<Utc>,
+    conn: &mut PgConnection,
+) -> QueryResult<Vec<WebhookDeliveryTask>> {
+    conn.transaction(|conn| {
+        let ids: Vec<i64> = webhook_deliveries::table
+            .filter(webhook_deliveries::status.eq(DeliveryStatus::Pending.to_string()))
+            .filter(webhook_deliveries::next_attempt_at.le(Utc::now()))
+            .order(webhook_deliveries::next_attempt_at)
+            .limit(limit)
+            .select(webhook_deliveries::id)
+            .for_update()
+            .skip_locked()
+            .load(conn)?;
+
+        diesel::update(webhook_deliveries::table.filter(webhook_deliveries::id.eq_any(&ids)))
+            .set(webhook_deliveries::next_attempt_at.eq(lease_until))
+            .execute(conn)?;
+
+        webhook_deliveries::table
+            .inner_join(webhooks::table)
+            .inner_join(outbox_events::table)
+            .filter(webhook_deliveries::id.eq_any(&ids))
+            .select((
+                WebhookDeliveryEntity::as_select(),
+                WebhookEntity::as_select(),
+                OutboxEventEntity::as_select(),
+                outbox_events::created_at,
+            ))
+            .load(conn)
+    })
+}
+
+pub fn record_webhook_delivery_attempt(
+    id: i64,
+    attempt: &WebhookDeliveryAttempt,
+    conn: &mut PgConnection,
+) -> QueryResult<usize> {
+    diesel::update(webhook_deliveries::table.find(id))
+        .set(attempt)
+        .execute(conn)
+}
+
+/// Returns comments of the columns of the current schema's tables
+pub fn get_column_comments(conn: &mut PgConnection) -> QueryResult<Vec<ColumnCommentEntity>> {
+    diesel::sql_query(
+        "select table_name::text, column_name::text, comment from ( \
+             select table_name, column_name, \
+                 col_description(format('%I.%I', table_schema, table_name)::regclass, ordinal_position) as comment \
+             from information_schema.columns where table_schema = current_schema() \
+         ) as columns where comment is not null",
+    )
+    .load(conn)
+}
+
+/// Runs the data quality checks: planets without details, non-positive masses or mean radii,
+/// types differing from the ones given by the classification rules, and planets which haven't
+/// changed since `stale_before` or whose changes weren't recorded
+pub fn get_data_quality_findings(
+    stale_before: DateTime<Utc>,
+    conn: &mut PgConnection,
+) -> QueryResult<Vec<DataQualityFindingEntity>> {
+    // versions without a transaction ID (0) were made by the migrations backfilling the history
+    // and converting units, not by changes of the planets, so their time is unknown
+    let mut findings: Vec<DataQualityFindingEntity> = diesel::sql_query(
+        "select 'MISSING_DETAILS' as check_name, p.id as planet_id, p.uuid as planet_uuid, \
+             p.name as planet_name, 'The planet has no details' as message \
+         from planets p where not exists (select 1 from details d where d.planet_id = p.id) \
+         union all \
+         select 'NON_POSITIVE_MASS', p.id, p.uuid, p.name, format('Mass is %s', d.mass) \
+         from planets p join details d on d.planet_id = p.id where d.mass <= 0 \
+         union all \
+         select 'NON_POSITIVE_MEAN_RADIUS', p.id, p.uuid, p.name, \
+             format('Mean radius is %s m', d.mean_radius) \
+         from planets p join details d on d.planet_id = p.id where d.mean_radius <= 0 \
+         union all \
+         select 'STALE', p.id, p.uuid, p.name, \
+             coalesce('Last updated at ' || h.updated_at, 'No changes were recorded') \
+         from planets p cross join lateral ( \
+             select greatest( \
+                 (select max(valid_from) from planets_history \
+                  where planet_id = p.id and transaction_id <> 0), \
+                 (select max(valid_from) from details_history \
+                  where planet_id = p.id and transaction_id <> 0) \
+             ) as updated_at \
+         ) h \
+         where h.updated_at is null or h.updated_at < $1",
+    )
+    .bind::<Timestamptz, _>(stale_before)
+    .load(conn)?;
+
+    let rules = get_classification_rules(conn)?;
+    let planets: Vec<(PlanetEntity, DetailsEntity)> =
+        planets::table.inner_join(details::table).load(conn)?;
+    findings.extend(planets.into_iter().filter_map(|(planet, details)| {
+        let classified_type =
+            classification::classify(&rules, &details.mass, &details.mean_radius)?;
+        (classified_type != planet.type_).then(|| DataQualityFindingEntity {
+            check_name: "TYPE_MISMATCH".to_string(),
+            planet_id: planet.id,
+            planet_uuid: planet.uuid,
+            planet_name: planet.name,
+            message: format!(
+                "Type is {}, but classification rules give {}",
+                planet.type_, classified_type
+            ),
+        })
+    }));
+    findings.sort_by(|a, b| (a.planet_id, &a.check_name).cmp(&(b.planet_id, &b.check_name)));
+    Ok(findings)
+}
+
+// the event is stored in the same transaction as the change itself, along with the trace context
+// of the request which made the change
+fn create_planet_event(
+    kind: EventKind,
+    planet: &PlanetEntity,
+    conn: &mut PgConnection,
+) -> QueryResult<OutboxEventEntity> {
+    let new_event = NewOutboxEventEntity {
+        topic: PLANETS_TOPIC.to_string(),
+        kind: kind.to_string(),
+        payload: serde_json::to_value(planet).expect("Can't serialize a planet"),
+        traceparent: http_client::current_context()
+            .trace
+            .map(|trace| trace.to_string()),
+    };
+
+    diesel::insert_into(outbox_events::table)
+        .values(new_event)
+        .returning(OutboxEventEntity::as_returning())
+        .get_result(conn)
+}
+
+pub fn filter_planets(filter: &PlanetsFilter) -> planets::BoxedQuery<'_, Pg> {
+    let mut query = planets::table.into_boxed();
+
+    if let Some(name_part) = &filter.name_contains {
+        query = query.filter(planets::name.ilike(format!("%{}%", escape_like_pattern(name_part))));
+    }
+    if let Some(type_) = &filter.type_ {
+        query = query.filter(planets::type_.eq(type_));
+    }
+
+    query
+}
+
+// user input must not be able to add wildcards to a LIKE pattern
+fn escape_like_pattern(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// Runs `f` in a serializable transaction. If Postgres aborts the transaction because of a
+/// concurrent write, it is retried after a jittered exponential backoff (bounded number of attempts).
+/// The backoff blocks the thread, so async callers run it with `spawn_blocking`
+pub fn in_serializable_transaction<T, F>(conn: &mut PgConnection, mut f: F) -> QueryResult<T>
+where
+    F: FnMut(&mut PgConnection) -> QueryResult<T>,
+{
+    let mut attempt = 1;
+    loop {
+        match conn.build_transaction().serializable().run(&mut f) {
+            Err(Error::DatabaseError(DatabaseErrorKind::SerializationFailure, _))
+                if attempt < MAX_TRANSACTION_ATTEMPTS =>
+            {
+                thread::sleep(get_retry_delay(attempt));
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
 
-    Ok(created_planet)
+fn get_retry_delay(attempt: u32) -> Duration {
+    let max_delay = RETRY_BASE_DELAY_MILLIS * 2u64.pow(attempt - 1);
+    Duration::from_millis(rand::thread_rng().gen_range(0..=max_delay))
 }
@@ -1,3 +1,23 @@
+diesel::table! {
+    atmosphere_components (planet_id, gas) {
+        planet_id -> Int4,
+        gas -> Varchar,
+        percentage -> Numeric,
+    }
+}
+
+diesel::table! {
+    classification_rules (id) {
+        id -> Int4,
+        planet_type -> Varchar,
+        min_mass -> Nullable<Numeric>,
+        max_mass -> Nullable<Numeric>,
+        min_mean_radius -> Nullable<Numeric>,
+        max_mean_radius -> Nullable<Numeric>,
+        priority -> Int4,
+    }
+}
+
 diesel::table! {
     details (id) {
         id -> Int4,
@@ -8,15 +28,142 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    details_history (details_id, valid_from) {
+        details_id -> Int4,
+        mean_radius -> Numeric,
+        mass -> Numeric,
+        population -> Nullable<Numeric>,
+        planet_id -> Int4,
+        valid_from -> Timestamptz,
+        valid_to -> Nullable<Timestamptz>,
+        transaction_id -> Int8,
+        merged_from -> Nullable<Int4>,
+    }
+}
+
+diesel::table! {
+    orbits (planet_id) {
+        planet_id -> Int4,
+        semi_major_axis -> Numeric,
+        eccentricity -> Numeric,
+        orbital_period -> Numeric,
+        longitude_of_perihelion -> Numeric,
+        mean_longitude -> Numeric,
+    }
+}
+
+diesel::table! {
+    outbox_events (id) {
+        id -> Int8,
+        topic -> Varchar,
+        kind -> Varchar,
+        payload -> Jsonb,
+        created_at -> Timestamptz,
+        traceparent -> Nullable<Varchar>,
+        transaction_id -> Int8,
+    }
+}
+
+diesel::table! {
+    planet_redirects (old_id) {
+        old_id -> Int4,
+        old_uuid -> Uuid,
+        planet_id -> Int4,
+        created_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     planets (id) {
         id -> Int4,
         name -> Varchar,
         #[sql_name = "type"]
         type_ -> Varchar,
+        uuid -> Uuid,
+        star_id -> Nullable<Int4>,
+        status -> Varchar,
+    }
+}
+
+diesel::table! {
+    planets_history (planet_id, valid_from, uuid) {
+        planet_id -> Int4,
+        name -> Varchar,
+        #[sql_name = "type"]
+        type_ -> Varchar,
+        uuid -> Uuid,
+        star_id -> Nullable<Int4>,
+        status -> Varchar,
+        valid_from -> Timestamptz,
+        valid_to -> Nullable<Timestamptz>,
+        transaction_id -> Int8,
+        merged_from -> Nullable<Int4>,
+    }
+}
+
+diesel::table! {
+    reports (id) {
+        id -> Int4,
+        owner -> Varchar,
+        name -> Varchar,
+        query -> Text,
+        variables -> Jsonb,
+    }
+}
+
+diesel::table! {
+    stars (id) {
+        id -> Int4,
+        name -> Varchar,
+        spectral_class -> Varchar,
+        mass -> Numeric,
+    }
+}
+
+diesel::table! {
+    webhook_deliveries (id) {
+        id -> Int8,
+        webhook_id -> Int4,
+        event_id -> Int8,
+        status -> Varchar,
+        attempts -> Int4,
+        last_error -> Nullable<Varchar>,
+        next_attempt_at -> Timestamptz,
+        delivered_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    webhooks (id) {
+        id -> Int4,
+        url -> Varchar,
+        event_kinds -> Array<Varchar>,
+        secret -> Varchar,
+        next_transaction_id -> Int8,
     }
 }
 
+diesel::joinable!(atmosphere_components -> planets (planet_id));
 diesel::joinable!(details -> planets (planet_id));
+diesel::joinable!(orbits -> planets (planet_id));
+diesel::joinable!(planet_redirects -> planets (planet_id));
+diesel::joinable!(planets -> stars (star_id));
+diesel::joinable!(webhook_deliveries -> outbox_events (event_id));
+diesel::joinable!(webhook_deliveries -> webhooks (webhook_id));
 
-diesel::allow_tables_to_appear_in_same_query!(details, planets,);
+diesel::allow_tables_to_appear_in_same_query!(
+    atmosphere_components,
+    classification_rules,
+    details,
+    details_history,
+    orbits,
+    outbox_events,
+    planet_redirects,
+    planets,
+    planets_history,
+    reports,
+    stars,
+    webhook_deliveries,
+    webhooks,
+);
@@ -0,0 +1,439 @@
+use std::fmt::{self, Display, Formatter};
+
+use diesel::prelude::*;
+use diesel::sql_types::Text;
+use diesel::Column;
+
+use crate::persistence::schema::{
+    atmosphere_components, classification_rules, details, details_history, orbits, outbox_events,
+    planet_redirects, planets, planets_history, reports, stars, webhook_deliveries, webhooks,
+};
+
+/// A column the repository relies on
+struct ExpectedColumn {
+    table: &'static str,
+    column: &'static str,
+    /// As in `information_schema.columns.data_type`
+    data_type: &'static str,
+    nullable: bool,
+}
+
+// should be kept in sync with schema.rs
+const EXPECTED_COLUMNS: &[ExpectedColumn] = &[
+    column("planets", planets::id::NAME, "integer", false),
+    column("planets", planets::name::NAME, "character varying", false),
+    column("planets", planets::type_::NAME, "character varying", false),
+    column("planets", planets::uuid::NAME, "uuid", false),
+    column("planets", planets::star_id::NAME, "integer", true),
+    column("planets", planets::status::NAME, "character varying", false),
+    column(
+        "planet_redirects",
+        planet_redirects::old_id::NAME,
+        "integer",
+        false,
+    ),
+    column(
+        "planet_redirects",
+        planet_redirects::old_uuid::NAME,
+        "uuid",
+        false,
+    ),
+    column(
+        "planet_redirects",
+        planet_redirects::planet_id::NAME,
+        "integer",
+        false,
+    ),
+    column(
+        "planet_redirects",
+        planet_redirects::created_at::NAME,
+        "timestamp with time zone",
+        false,
+    ),
+    column("reports", reports::id::NAME, "integer", false),
+    column("reports", reports::owner::NAME, "character varying", false),
+    column("reports", reports::name::NAME, "character varying", false),
+    column("reports", reports::query::NAME, "text", false),
+    column("reports", reports::variables::NAME, "jsonb", false),
+    column("stars", stars::id::NAME, "integer", false),
+    column("stars", stars::name::NAME, "character varying", false),
+    column(
+        "stars",
+        stars::spectral_class::NAME,
+        "character varying",
+        false,
+    ),
+    column("stars", stars::mass::NAME, "numeric", false),
+    column("details", details::id::NAME, "integer", false),
+    column("details", details::mean_radius::NAME, "numeric", false),
+    column("details", details::mass::NAME, "numeric", false),
+    column("details", details::population::NAME, "numeric", true),
+    column("details", details::planet_id::NAME, "integer", false),
+    column(
+        "planets_history",
+        planets_history::planet_id::NAME,
+        "integer",
+        false,
+    ),
+    column(
+        "planets_history",
+        planets_history::name::NAME,
+        "character varying",
+        false,
+    ),
+    column(
+        "planets_history",
+        planets_history::type_::NAME,
+        "character varying",
+        false,
+    ),
+    column(
+        "planets_history",
+        planets_history::uuid::NAME,
+        "uuid",
+        false,
+    ),
+    column(
+        "planets_history",
+        planets_history::star_id::NAME,
+        "integer",
+        true,
+    ),
+    column(
+        "planets_history",
+        planets_history::status::NAME,
+        "character varying",
+        false,
+    ),
+    column(
+        "planets_history",
+        planets_history::valid_from::NAME,
+        "timestamp with time zone",
+        false,
+    ),
+    column(
+        "planets_history",
+        planets_history::valid_to::NAME,
+        "timestamp with time zone",
+        true,
+    ),
+    column(
+        "planets_history",
+        planets_history::transaction_id::NAME,
+        "bigint",
+        false,
+    ),
+    column(
+        "planets_history",
+        planets_history::merged_from::NAME,
+        "integer",
+        true,
+    ),
+    column(
+        "details_history",
+        details_history::details_id::NAME,
+        "integer",
+        false,
+    ),
+    column(
+        "details_history",
+        details_history::mean_radius::NAME,
+        "numeric",
+        false,
+    ),
+    column(
+        "details_history",
+        details_history::mass::NAME,
+        "numeric",
+        false,
+    ),
+    column(
+        "details_history",
+        details_history::population::NAME,
+        "numeric",
+        true,
+    ),
+    column(
+        "details_history",
+        details_history::planet_id::NAME,
+        "integer",
+        false,
+    ),
+    column(
+        "details_history",
+        details_history::valid_from::NAME,
+        "timestamp with time zone",
+        false,
+    ),
+    column(
+        "details_history",
+        details_history::valid_to::NAME,
+        "timestamp with time zone",
+        true,
+    ),
+    column(
+        "details_history",
+        details_history::transaction_id::NAME,
+        "bigint",
+        false,
+    ),
+    column(
+        "details_history",
+        details_history::merged_from::NAME,
+        "integer",
+        true,
+    ),
+    column("orbits", orbits::planet_id::NAME, "integer", false),
+    column("orbits", orbits::semi_major_axis::NAME, "numeric", false),
+    column("orbits", orbits::eccentricity::NAME, "numeric", false),
+    column("orbits", orbits::orbital_period::NAME, "numeric", false),
+    column(
+        "orbits",
+        orbits::longitude_of_perihelion::NAME,
+        "numeric",
+        false,
+    ),
+    column("orbits", orbits::mean_longitude::NAME, "numeric", false),
+    column(
+        "atmosphere_components",
+        atmosphere_components::planet_id::NAME,
+        "integer",
+        false,
+    ),
+    column(
+        "atmosphere_components",
+        atmosphere_components::gas::NAME,
+        "character varying",
+        false,
+    ),
+    column(
+        "atmosphere_components",
+        atmosphere_components::percentage::NAME,
+        "numeric",
+        false,
+    ),
+    column("outbox_events", outbox_events::id::NAME, "bigint", false),
+    column(
+        "outbox_events",
+        outbox_events::topic::NAME,
+        "character varying",
+        false,
+    ),
+    column(
+        "outbox_events",
+        outbox_events::kind::NAME,
+        "character varying",
+        false,
+    ),
+    column(
+        "outbox_events",
+        outbox_events::payload::NAME,
+        "jsonb",
+        false,
+    ),
+    column(
+        "outbox_events",
+        outbox_events::created_at::NAME,
+        "timestamp with time zone",
+        false,
+    ),
+    column(
+        "outbox_events",
+        outbox_events::traceparent::NAME,
+        "character varying",
+        true,
+    ),
+    column(
+        "outbox_events",
+        outbox_events::transaction_id::NAME,
+        "bigint",
+        false,
+    ),
+    column(
+        "classification_rules",
+        classification_rules::id::NAME,
+        "integer",
+        false,
+    ),
+    column(
+        "classification_rules",
+        classification_rules::planet_type::NAME,
+        "character varying",
+        false,
+    ),
+    column(
+        "classification_rules",
+        classification_rules::min_mass::NAME,
+        "numeric",
+        true,
+    ),
+    column(
+        "classification_rules",
+        classification_rules::max_mass::NAME,
+        "numeric",
+        true,
+    ),
+    column(
+        "classification_rules",
+        classification_rules::min_mean_radius::NAME,
+        "numeric",
+        true,
+    ),
+    column(
+        "classification_rules",
+        classification_rules::max_mean_radius::NAME,
+        "numeric",
+        true,
+    ),
+    column(
+        "classification_rules",
+        classification_rules::priority::NAME,
+        "integer",
+        false,
+    ),
+    column("webhooks", webhooks::id::NAME, "integer", false),
+    column("webhooks", webhooks::url::NAME, "character varying", false),
+    column("webhooks", webhooks::event_kinds::NAME, "ARRAY", false),
+    column(
+        "webhooks",
+        webhooks::secret::NAME,
+        "character varying",
+        false,
+    ),
+    column(
+        "webhooks",
+        webhooks::next_transaction_id::NAME,
+        "bigint",
+        false,
+    ),
+    column(
+        "webhook_deliveries",
+        webhook_deliveries::id::NAME,
+        "bigint",
+        false,
+    ),
+    column(
+        "webhook_deliveries",
+        webhook_deliveries::webhook_id::NAME,
+        "integer",
+        false,
+    ),
+    column(
+        "webhook_deliveries",
+        webhook_deliveries::event_id::NAME,
+        "bigint",
+        false,
+    ),
+    column(
+        "webhook_deliveries",
+        webhook_deliveries::status::NAME,
+        "character varying",
+        false,
+    ),
+    column(
+        "webhook_deliveries",
+        webhook_deliveries::attempts::NAME,
+        "integer",
+        false,
+    ),
+    column(
+        "webhook_deliveries",
+        webhook_deliveries::last_error::NAME,
+        "character varying",
+        true,
+    ),
+    column(
+        "webhook_deliveries",
+        webhook_deliveries::next_attempt_at::NAME,
+        "timestamp with time zone",
+        false,
+    ),
+    column(
+        "webhook_deliveries",
+        webhook_deliveries::delivered_at::NAME,
+        "timestamp with time zone",
+        true,
+    ),
+];
+
+const fn column(
+    table: &'static str,
+    column: &'static str,
+    data_type: &'static str,
+    nullable: bool,
+) -> ExpectedColumn {
+    ExpectedColumn {
+        table,
+        column,
+        data_type,
+        nullable,
+    }
+}
+
+#[derive(QueryableByName)]
+struct ActualColumn {
+    #[diesel(sql_type = Text)]
+    table_name: String,
+    #[diesel(sql_type = Text)]
+    column_name: String,
+    #[diesel(sql_type = Text)]
+    data_type: String,
+    #[diesel(sql_type = Text)]
+    is_nullable: String,
+}
+
+/// Differences between the Diesel schema and the database found by [check_schema]
+#[derive(Debug)]
+pub struct SchemaMismatches(pub Vec<String>);
+
+impl Display for SchemaMismatches {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Database schema doesn't match the application's one:")?;
+        for mismatch in &self.0 {
+            writeln!(f, "  - {}", mismatch)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compares the tables and columns used by the repository with the live database, so that
+/// a partially applied migration is reported at startup rather than in the middle of a request
+pub fn check_schema(conn: &mut PgConnection) -> QueryResult<Result<(), SchemaMismatches>> {
+    let actual_columns = diesel::sql_query(
+        "select table_name::text, column_name::text, data_type::text, is_nullable::text \
+         from information_schema.columns where table_schema = current_schema()",
+    )
+    .load::<ActualColumn>(conn)?;
+
+    let mismatches = EXPECTED_COLUMNS
+        .iter()
+        .filter_map(|expected| {
+            let actual = actual_columns.iter().find(|actual| {
+                actual.table_name == expected.table && actual.column_name == expected.column
+            });
+            let qualified_name = format!("{}.{}", expected.table, expected.column);
+
+            match actual {
+                None => Some(format!("column {} is missing", qualified_name)),
+                Some(actual) if actual.data_type != expected.data_type => Some(format!(
+                    "column {} has type '{}', but '{}' is expected",
+                    qualified_name, actual.data_type, expected.data_type
+                )),
+                Some(actual) if (actual.is_nullable == "YES") != expected.nullable => {
+                    Some(format!(
+                        "column {} should{} be nullable",
+                        qualified_name,
+                        if expected.nullable { "" } else { " not" }
+                    ))
+                }
+                Some(_) => None,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    Ok(if mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(SchemaMismatches(mismatches))
+    })
+}
@@ -0,0 +1,91 @@
+//! Physical quantities are stored in SI units; the schema and snapshots keep the conventional
+//! ones: radii in kilometers, semi-major axes in astronomical units and periods in days
+use bigdecimal::BigDecimal;
+
+pub const METERS_PER_ASTRONOMICAL_UNIT: i64 = 149_597_870_700;
+pub const SECONDS_PER_DAY: i64 = 86_400;
+
+/// Exact, not rounded to the precision of the column
+pub fn kilometers_to_meters(kilometers: &BigDecimal) -> BigDecimal {
+    shift(kilometers, 3)
+}
+
+/// Keeps at least one fractional digit, as radii were formatted when stored in kilometers
+pub fn meters_to_kilometers(meters: &BigDecimal) -> BigDecimal {
+    let kilometers = shift(meters, -3).normalized();
+    if kilometers.as_bigint_and_exponent().1 < 1 {
+        kilometers.with_scale(1)
+    } else {
+        kilometers
+    }
+}
+
+/// Rounded to a meter
+pub fn astronomical_units_to_meters(astronomical_units: &BigDecimal) -> BigDecimal {
+    (astronomical_units * BigDecimal::from(METERS_PER_ASTRONOMICAL_UNIT)).round(0)
+}
+
+/// Rounded to 10 fractional digits, which is below a meter
+pub fn meters_to_astronomical_units(meters: &BigDecimal) -> BigDecimal {
+    (meters / BigDecimal::from(METERS_PER_ASTRONOMICAL_UNIT))
+        .round(10)
+        .normalized()
+}
+
+pub fn days_to_seconds(days: &BigDecimal) -> BigDecimal {
+    days * BigDecimal::from(SECONDS_PER_DAY)
+}
+
+/// Rounded to 6 fractional digits, which is below a second
+pub fn seconds_to_days(seconds: &BigDecimal) -> BigDecimal {
+    (seconds / BigDecimal::from(SECONDS_PER_DAY))
+        .round(6)
+        .normalized()
+}
+
+/// Multiplies by a power of 10 without expanding the digits
+fn shift(value: &BigDecimal, exponent: i64) -> BigDecimal {
+    let (unscaled, scale) = value.as_bigint_and_exponent();
+    BigDecimal::new(unscaled, scale - exponent)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn decimal(value: &str) -> BigDecimal {
+        BigDecimal::from_str(value).expect("Can't parse decimal")
+    }
+
+    #[test]
+    fn radii() {
+        assert_eq!(decimal("6371000"), kilometers_to_meters(&decimal("6371.0")));
+        assert_eq!(decimal("10.7"), kilometers_to_meters(&decimal("0.0107")));
+        assert_eq!(
+            "6371.0",
+            meters_to_kilometers(&decimal("6371000")).to_string()
+        );
+        assert_eq!(
+            "2439.7",
+            meters_to_kilometers(&decimal("2439700")).to_string()
+        );
+        assert_eq!("0.0107", meters_to_kilometers(&decimal("10.7")).to_string());
+        assert_eq!(
+            "6371.0",
+            meters_to_kilometers(&kilometers_to_meters(&decimal("6.371e3"))).to_string()
+        );
+    }
+
+    #[test]
+    fn orbits() {
+        let meters = astronomical_units_to_meters(&decimal("1.52371034"));
+        assert_eq!(decimal("227943822428"), meters);
+        assert_eq!(decimal("1.52371034"), meters_to_astronomical_units(&meters));
+
+        let seconds = days_to_seconds(&decimal("686.980"));
+        assert_eq!(decimal("59355072"), seconds);
+        assert_eq!(decimal("686.98"), seconds_to_days(&seconds));
+    }
+}
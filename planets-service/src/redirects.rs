@@ -0,0 +1,67 @@
+use std::sync::{Arc, Mutex};
+
+use async_graphql::extensions::{
+    Extension, ExtensionContext, ExtensionFactory, NextExecute, NextPrepareRequest,
+};
+use async_graphql::{value, Request, Response, ServerResult, Value, ID};
+
+/// IDs of merged planets resolved to the planets they were merged into during an operation
+#[derive(Default)]
+pub struct ResolvedRedirects(Mutex<Vec<(ID, ID)>>);
+
+impl ResolvedRedirects {
+    pub fn add(&self, from: ID, to: ID) {
+        self.0
+            .lock()
+            .expect("Can't lock redirects")
+            .push((from, to));
+    }
+}
+
+/// Lists resolved redirects in the `redirects` extension of the response, so that a client
+/// holding an old ID can replace it with the canonical one
+pub struct RedirectTracker;
+
+impl ExtensionFactory for RedirectTracker {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(RedirectTrackerExtension {
+            redirects: Default::default(),
+        })
+    }
+}
+
+struct RedirectTrackerExtension {
+    redirects: Arc<ResolvedRedirects>,
+}
+
+#[async_trait::async_trait]
+impl Extension for RedirectTrackerExtension {
+    async fn prepare_request(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        request: Request,
+        next: NextPrepareRequest<'_>,
+    ) -> ServerResult<Request> {
+        next.run(ctx, request.data(Arc::clone(&self.redirects)))
+            .await
+    }
+
+    async fn execute(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        operation_name: Option<&str>,
+        next: NextExecute<'_>,
+    ) -> Response {
+        let response = next.run(ctx, operation_name).await;
+        let redirects =
+            std::mem::take(&mut *self.redirects.0.lock().expect("Can't lock redirects"));
+        if redirects.is_empty() {
+            return response;
+        }
+        let redirects = redirects
+            .into_iter()
+            .map(|(from, to)| value!({ "from": from.0, "to": to.0 }))
+            .collect();
+        response.extension("redirects", Value::List(redirects))
+    }
+}
@@ -0,0 +1,283 @@
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::mem::size_of;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use async_graphql::extensions::{
+    Extension, ExtensionContext, ExtensionFactory, NextRequest, NextResolve, ResolveInfo,
+};
+use async_graphql::parser::parse_schema;
+use async_graphql::parser::types::{TypeKind, TypeSystemDefinition};
+use async_graphql::{Response, ServerResult, Value};
+use lazy_static::lazy_static;
+
+use crate::graphql::UserAgent;
+use crate::memory::CacheMemory;
+
+/// A field being renamed: during the transition both names are exposed, the old one being
+/// deprecated and resolved by the new one's resolver
+pub struct RenamedField {
+    pub type_name: String,
+    pub old_name: String,
+    pub new_name: String,
+}
+
+/// Deprecation reason of the old name, e.g. "Use `planetType` instead"
+const RENAMED_PREFIX: &str = "Use `";
+const RENAMED_SUFFIX: &str = "` instead";
+
+/// Further clients are counted together, so that arbitrary headers can't exhaust memory
+const MAX_USER_AGENTS: usize = 100;
+const OTHER_USER_AGENTS: &str = "(other)";
+const UNKNOWN_USER_AGENT: &str = "(unknown)";
+
+lazy_static! {
+    /// Taken from the schema, so that the deprecated fields are the only list of renames
+    pub static ref RENAMED_FIELDS: Vec<RenamedField> = from_sdl(&crate::schema_sdl());
+    static ref USAGES: Vec<Usage> = RENAMED_FIELDS.iter().map(|_| Usage::default()).collect();
+}
+
+/// Fields deprecated in favour of another field of the same type
+fn from_sdl(sdl: &str) -> Vec<RenamedField> {
+    let document = parse_schema(sdl).expect("Can't parse SDL");
+    let mut renamed_fields = vec![];
+    for definition in document.definitions {
+        let TypeSystemDefinition::Type(definition) = definition else {
+            continue;
+        };
+        let TypeKind::Object(object) = &definition.node.kind else {
+            continue;
+        };
+        let field_names: HashSet<&str> = object
+            .fields
+            .iter()
+            .map(|field| field.node.name.node.as_str())
+            .collect();
+        for field in &object.fields {
+            let new_name = field
+                .node
+                .directives
+                .iter()
+                .find(|directive| directive.node.name.node == "deprecated")
+                .and_then(|directive| directive.node.get_argument("reason"))
+                .and_then(|reason| match &reason.node {
+                    Value::String(reason) => reason
+                        .strip_prefix(RENAMED_PREFIX)?
+                        .strip_suffix(RENAMED_SUFFIX)
+                        .map(str::to_string),
+                    _ => None,
+                });
+            if let Some(new_name) = new_name.filter(|name| field_names.contains(name.as_str())) {
+                renamed_fields.push(RenamedField {
+                    type_name: definition.node.name.node.to_string(),
+                    old_name: field.node.name.node.to_string(),
+                    new_name,
+                });
+            }
+        }
+    }
+    renamed_fields
+}
+
+#[derive(Default)]
+struct Usage {
+    operations: AtomicU64,
+    last_used_at: AtomicU64,
+    user_agents: Mutex<HashMap<String, UserAgentUsage>>,
+}
+
+impl Usage {
+    fn record(&self, field: &RenamedField, user_agent: &str, now: u64) {
+        self.operations.fetch_add(1, Ordering::Relaxed);
+        self.last_used_at.store(now, Ordering::Relaxed);
+
+        let mut user_agents = self.user_agents.lock().expect("Can't lock user agents");
+        let user_agent = if user_agents.contains_key(user_agent) {
+            user_agent
+        } else if user_agents.len() < MAX_USER_AGENTS {
+            println!(
+                "Deprecated {}.{} is used by a new client: {}",
+                field.type_name, field.old_name, user_agent
+            );
+            user_agent
+        } else {
+            OTHER_USER_AGENTS
+        };
+        let usage = user_agents
+            .entry(user_agent.to_string())
+            .or_insert_with(|| UserAgentUsage {
+                user_agent: user_agent.to_string(),
+                operations: 0,
+                last_used_at: now,
+            });
+        usage.operations += 1;
+        usage.last_used_at = now;
+    }
+}
+
+/// Operations of a client identified by the `User-Agent` header that requested an old name
+#[derive(Clone)]
+pub struct UserAgentUsage {
+    pub user_agent: String,
+    pub operations: u64,
+    /// Seconds since the Unix epoch
+    pub last_used_at: u64,
+}
+
+/// How many operations requested the old name of a field since the start; once clients stop
+/// using it, the old name can be removed
+pub struct RenamedFieldUsage {
+    pub field: &'static RenamedField,
+    pub operations: u64,
+    /// Seconds since the Unix epoch
+    pub last_used_at: Option<u64>,
+    /// The most recent first
+    pub user_agents: Vec<UserAgentUsage>,
+}
+
+/// Memory taken by the clients of all the renamed fields
+pub fn get_user_agents_memory() -> CacheMemory {
+    let (entries, estimated_bytes) = USAGES.iter().fold((0, 0), |(entries, bytes), usage| {
+        let user_agents = usage.user_agents.lock().expect("Can't lock user agents");
+        let size: usize = user_agents
+            .keys()
+            .map(|user_agent| 2 * user_agent.capacity() + size_of::<(String, UserAgentUsage)>())
+            .sum();
+        (entries + user_agents.len(), bytes + size)
+    });
+    CacheMemory {
+        name: "renamedFieldClients",
+        entries,
+        estimated_bytes,
+    }
+}
+
+pub fn get_usages() -> Vec<RenamedFieldUsage> {
+    RENAMED_FIELDS
+        .iter()
+        .zip(USAGES.iter())
+        .map(|(field, usage)| {
+            let last_used_at = usage.last_used_at.load(Ordering::Relaxed);
+            let mut user_agents: Vec<_> = usage
+                .user_agents
+                .lock()
+                .expect("Can't lock user agents")
+                .values()
+                .cloned()
+                .collect();
+            user_agents.sort_by_key(|usage| Reverse(usage.last_used_at));
+            RenamedFieldUsage {
+                field,
+                operations: usage.operations.load(Ordering::Relaxed),
+                last_used_at: (last_used_at > 0).then_some(last_used_at),
+                user_agents,
+            }
+        })
+        .collect()
+}
+
+/// Counts operations that use old names of [RENAMED_FIELDS] per client
+pub struct RenamedFieldTracker;
+
+impl ExtensionFactory for RenamedFieldTracker {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(RenamedFieldTrackerExtension::default())
+    }
+}
+
+#[derive(Default)]
+struct RenamedFieldTrackerExtension {
+    // indexes in RENAMED_FIELDS
+    used_fields: Mutex<HashSet<usize>>,
+    user_agent: Mutex<Option<String>>,
+}
+
+#[async_trait::async_trait]
+impl Extension for RenamedFieldTrackerExtension {
+    async fn request(&self, ctx: &ExtensionContext<'_>, next: NextRequest<'_>) -> Response {
+        let response = next.run(ctx).await;
+
+        let used_fields = self.used_fields.lock().expect("Can't lock used fields");
+        if !used_fields.is_empty() {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Can't get current time")
+                .as_secs();
+            let user_agent = self.user_agent.lock().expect("Can't lock user agent");
+            let user_agent = user_agent.as_deref().unwrap_or(UNKNOWN_USER_AGENT);
+            for index in used_fields.iter() {
+                USAGES[*index].record(&RENAMED_FIELDS[*index], user_agent, now);
+            }
+        }
+
+        response
+    }
+
+    async fn resolve(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        info: ResolveInfo<'_>,
+        next: NextResolve<'_>,
+    ) -> ServerResult<Option<Value>> {
+        if let Some(index) = RENAMED_FIELDS
+            .iter()
+            .position(|field| field.old_name == info.name && field.type_name == info.parent_type)
+        {
+            self.used_fields
+                .lock()
+                .expect("Can't lock used fields")
+                .insert(index);
+            // request data isn't available in the `request` hook
+            *self.user_agent.lock().expect("Can't lock user agent") = ctx
+                .data_opt::<UserAgent>()
+                .map(|user_agent| user_agent.0.clone());
+        }
+        next.run(ctx, info).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renamed_fields_are_taken_from_deprecations() {
+        let renamed_fields = from_sdl(
+            r#"
+            type Planet {
+                planetType: String!
+                type: String! @deprecated(reason: "Use `planetType` instead")
+                isRotatingAroundSun: Boolean! @deprecated(reason: "Now it is not in doubt")
+                kind: String @deprecated(reason: "Use `category` instead")
+            }
+            "#,
+        );
+
+        assert_eq!(1, renamed_fields.len());
+        assert_eq!("Planet", renamed_fields[0].type_name);
+        assert_eq!("type", renamed_fields[0].old_name);
+        assert_eq!("planetType", renamed_fields[0].new_name);
+    }
+
+    #[test]
+    fn clients_over_limit_are_counted_together() {
+        let usage = Usage::default();
+        let field = &RENAMED_FIELDS[0];
+        for index in 0..MAX_USER_AGENTS + 2 {
+            usage.record(field, &format!("client/{}", index), 1);
+        }
+        usage.record(field, "client/0", 2);
+
+        let user_agents = usage.user_agents.lock().expect("Can't lock user agents");
+        assert_eq!(MAX_USER_AGENTS + 1, user_agents.len());
+        assert_eq!(2, user_agents[OTHER_USER_AGENTS].operations);
+        assert_eq!(2, user_agents["client/0"].operations);
+        assert_eq!(2, user_agents["client/0"].last_used_at);
+        assert_eq!(
+            MAX_USER_AGENTS as u64 + 3,
+            usage.operations.load(Ordering::Relaxed)
+        );
+    }
+}
@@ -0,0 +1,219 @@
+//! Operations captured by the operation log (see `OperationLogger`) and the comparison of their
+//! captured latencies and errors with the ones of a replay, used by the `replay` bin
+use std::collections::BTreeMap;
+
+use async_graphql::parser::parse_query;
+use async_graphql::parser::types::OperationType;
+use serde::{Deserialize, Serialize};
+
+use crate::operation_log::REDACTED;
+
+/// A line of the operation log
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Capture {
+    pub operation: Option<String>,
+    /// Variables are inlined
+    pub query: String,
+    pub duration_ms: u64,
+    pub errors: usize,
+}
+
+/// `None` for other lines of the log
+pub fn parse_capture(line: &str) -> Option<Capture> {
+    serde_json::from_str(line).ok()
+}
+
+/// Why a capture isn't replayed: redacted values would be sent as is, mutations would change
+/// the data of the target, and subscriptions don't complete
+pub fn skip_reason(capture: &Capture, include_mutations: bool) -> Option<&'static str> {
+    if capture.query.contains(REDACTED) {
+        return Some("redacted");
+    }
+    let Ok(document) = parse_query(&capture.query) else {
+        return Some("invalid");
+    };
+    let operation = match &capture.operation {
+        Some(name) => document
+            .operations
+            .iter()
+            .find(|(operation_name, _)| operation_name.map(|name| name.as_str()) == Some(name)),
+        None => document.operations.iter().next(),
+    };
+    match operation.map(|(_, operation)| operation.node.ty) {
+        None => Some("invalid"),
+        Some(OperationType::Mutation) if !include_mutations => Some("mutation"),
+        Some(OperationType::Subscription) => Some("subscription"),
+        Some(_) => None,
+    }
+}
+
+/// A replayed capture
+pub struct Outcome {
+    pub capture: Capture,
+    pub duration_ms: u64,
+    /// A failed request counts as an error
+    pub errors: usize,
+}
+
+/// Latencies are the median and the 95th percentile; diffs are the replayed values minus
+/// the captured ones. Errors are numbers of operations with errors
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OperationReport {
+    pub operation: String,
+    pub count: usize,
+    pub captured_p50_ms: u64,
+    pub replayed_p50_ms: u64,
+    pub p50_diff_ms: i64,
+    pub captured_p95_ms: u64,
+    pub replayed_p95_ms: u64,
+    pub p95_diff_ms: i64,
+    pub captured_errors: usize,
+    pub replayed_errors: usize,
+}
+
+/// A report per operation name, in order of names; anonymous operations are reported together
+pub fn report(outcomes: &[Outcome]) -> Vec<OperationReport> {
+    let mut by_operation: BTreeMap<&str, Vec<&Outcome>> = BTreeMap::new();
+    for outcome in outcomes {
+        let name = outcome.capture.operation.as_deref().unwrap_or("anonymous");
+        by_operation.entry(name).or_default().push(outcome);
+    }
+    by_operation
+        .into_iter()
+        .map(|(operation, outcomes)| {
+            let captured: Vec<u64> = outcomes
+                .iter()
+                .map(|outcome| outcome.capture.duration_ms)
+                .collect();
+            let replayed: Vec<u64> = outcomes.iter().map(|outcome| outcome.duration_ms).collect();
+            let (captured_p50_ms, captured_p95_ms) =
+                (percentile(&captured, 50), percentile(&captured, 95));
+            let (replayed_p50_ms, replayed_p95_ms) =
+                (percentile(&replayed, 50), percentile(&replayed, 95));
+            OperationReport {
+                operation: operation.to_string(),
+                count: outcomes.len(),
+                captured_p50_ms,
+                replayed_p50_ms,
+                p50_diff_ms: replayed_p50_ms as i64 - captured_p50_ms as i64,
+                captured_p95_ms,
+                replayed_p95_ms,
+                p95_diff_ms: replayed_p95_ms as i64 - captured_p95_ms as i64,
+                captured_errors: outcomes
+                    .iter()
+                    .filter(|outcome| outcome.capture.errors > 0)
+                    .count(),
+                replayed_errors: outcomes.iter().filter(|outcome| outcome.errors > 0).count(),
+            }
+        })
+        .collect()
+}
+
+/// By the nearest rank; `values` aren't empty
+fn percentile(values: &[u64], percent: usize) -> u64 {
+    let mut values = values.to_vec();
+    values.sort_unstable();
+    let rank = (values.len() as f64 * percent as f64 / 100.0).ceil() as usize;
+    values[rank.max(1) - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capture(operation: Option<&str>, query: &str, duration_ms: u64, errors: usize) -> Capture {
+        Capture {
+            operation: operation.map(String::from),
+            query: query.to_string(),
+            duration_ms,
+            errors,
+        }
+    }
+
+    #[test]
+    fn only_operation_lines_are_captures() {
+        let line = r#"{"operation":"planets","traceId":null,"spanId":null,"query":"query planets { getPlanets { name } }","durationMs":12,"errors":0}"#;
+        assert_eq!(
+            Some(capture(
+                Some("planets"),
+                "query planets { getPlanets { name } }",
+                12,
+                0
+            )),
+            parse_capture(line)
+        );
+        assert_eq!(None, parse_capture(r#"{"event":"startup"}"#));
+        assert_eq!(None, parse_capture("Server started"));
+    }
+
+    #[test]
+    fn skip_reasons() {
+        let skip_reason = |operation, query, include_mutations| {
+            skip_reason(&capture(operation, query, 1, 0), include_mutations)
+        };
+        assert_eq!(None, skip_reason(None, "{ getPlanets { name } }", false));
+        let document = "query a { getPlanets { name } } mutation b { deletePlanets { affected } }";
+        assert_eq!(None, skip_reason(Some("a"), document, false));
+        assert_eq!(Some("mutation"), skip_reason(Some("b"), document, false));
+        assert_eq!(None, skip_reason(Some("b"), document, true));
+        assert_eq!(
+            Some("subscription"),
+            skip_reason(None, "subscription { planetEvents { token } }", true)
+        );
+        assert_eq!(
+            Some("redacted"),
+            skip_reason(None, r#"{ login(password: "[REDACTED]") }"#, false)
+        );
+        assert_eq!(Some("invalid"), skip_reason(None, "{", false));
+        assert_eq!(Some("invalid"), skip_reason(Some("c"), document, false));
+    }
+
+    #[test]
+    fn reports_per_operation() {
+        let outcome = |operation, captured, replayed, errors| Outcome {
+            capture: capture(operation, "{ a }", captured, errors),
+            duration_ms: replayed,
+            errors: 0,
+        };
+        let mut outcomes: Vec<Outcome> = (1..=20)
+            .map(|i| outcome(Some("planets"), i * 10, i * 5, 0))
+            .collect();
+        outcomes.push(outcome(None, 100, 150, 1));
+        outcomes.push(Outcome {
+            errors: 2,
+            ..outcome(None, 100, 50, 0)
+        });
+
+        assert_eq!(
+            vec![
+                OperationReport {
+                    operation: "anonymous".to_string(),
+                    count: 2,
+                    captured_p50_ms: 100,
+                    replayed_p50_ms: 50,
+                    p50_diff_ms: -50,
+                    captured_p95_ms: 100,
+                    replayed_p95_ms: 150,
+                    p95_diff_ms: 50,
+                    captured_errors: 1,
+                    replayed_errors: 1,
+                },
+                OperationReport {
+                    operation: "planets".to_string(),
+                    count: 20,
+                    captured_p50_ms: 100,
+                    replayed_p50_ms: 50,
+                    p50_diff_ms: -50,
+                    captured_p95_ms: 190,
+                    replayed_p95_ms: 95,
+                    p95_diff_ms: -95,
+                    captured_errors: 0,
+                    replayed_errors: 0,
+                },
+            ],
+            report(&outcomes)
+        );
+    }
+}
@@ -0,0 +1,655 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::env;
+use std::hash::{Hash, Hasher};
+use std::mem::size_of;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_graphql::extensions::{
+    Extension, ExtensionContext, ExtensionFactory, NextExecute, NextParseQuery, NextResolve,
+    ResolveInfo,
+};
+use async_graphql::parser::types::{ExecutableDocument, OperationType};
+use async_graphql::{Response, ServerResult, Value, Variables};
+use common_utils::{CustomError, Role};
+use lazy_static::lazy_static;
+
+use crate::decimal_format::BigDecimalFormat;
+use crate::graphql::CurrentUser;
+use crate::masking;
+use crate::memory::{self, CacheMemory};
+use crate::metrics;
+use crate::renames::RENAMED_FIELDS;
+use crate::validation::ValidateOnly;
+
+lazy_static! {
+    static ref CAPACITY: usize = env::var("RESPONSE_CACHE_CAPACITY")
+        .map(|value| value.parse().expect("Can't parse response cache capacity"))
+        .unwrap_or(1000);
+    static ref TTL: Duration = Duration::from_secs(
+        env::var("RESPONSE_CACHE_TTL_SECONDS")
+            .map(|value| value.parse().expect("Can't parse response cache TTL"))
+            .unwrap_or(60)
+    );
+}
+
+/// Tables whose versions are tracked
+const TABLES: &[&str] = &[
+    "planets",
+    "details",
+    "atmosphere_components",
+    "stars",
+    "orbits",
+    "classification_rules",
+    "reports",
+    "webhooks",
+];
+
+/// Tables read to resolve fields of a type or fields returning it
+const TYPE_TABLES: &[(&str, &[&str])] = &[
+    (
+        "Planet",
+        &["planets", "details", "atmosphere_components", "stars"],
+    ),
+    ("Details", &["details", "atmosphere_components"]),
+    (
+        "InhabitedPlanetDetails",
+        &["details", "atmosphere_components"],
+    ),
+    (
+        "UninhabitedPlanetDetails",
+        &["details", "atmosphere_components"],
+    ),
+    ("GasComponent", &["atmosphere_components"]),
+    ("PlanetVersion", &["planets"]),
+    ("DetailsVersion", &["details"]),
+    // pages without items don't resolve fields of the versions
+    ("PlanetVersionPage", &["planets"]),
+    ("DetailsVersionPage", &["details"]),
+    ("Star", &["stars"]),
+    ("PlanetDistance", &["planets", "stars", "orbits"]),
+    ("ClassificationRule", &["classification_rules"]),
+    ("Report", &["reports"]),
+    ("Webhook", &["webhooks"]),
+];
+
+/// Tables written by mutations; a mutation which is not listed bumps all the tables
+const MUTATION_TABLES: &[(&str, &[&str])] = &[
+    (
+        "createPlanet",
+        &["planets", "details", "atmosphere_components", "stars"],
+    ),
+    (
+        "updatePlanet",
+        &["planets", "details", "atmosphere_components", "stars"],
+    ),
+    (
+        "deletePlanets",
+        &["planets", "details", "atmosphere_components", "orbits"],
+    ),
+    ("setPlanetOrbit", &["orbits"]),
+    (
+        "mergePlanets",
+        &["planets", "details", "atmosphere_components", "orbits"],
+    ),
+    ("publishPlanet", &["planets"]),
+    ("archivePlanet", &["planets"]),
+    ("exportSnapshot", &[]),
+    (
+        "importSnapshot",
+        &[
+            "planets",
+            "details",
+            "atmosphere_components",
+            "stars",
+            "orbits",
+        ],
+    ),
+    ("setClassificationRules", &["classification_rules"]),
+    ("saveReport", &["reports"]),
+    ("registerWebhook", &["webhooks"]),
+    ("setHeapProfiling", &[]),
+];
+
+/// Query fields whose results depend on more than the tracked tables: randomness, configuration,
+/// in-memory state, or webhook deliveries updated by the worker
+const UNCACHEABLE_FIELDS: &[&str] = &[
+    "randomPlanet",
+    "samplePlanets",
+    "enabledFeatures",
+    "renamedFieldUsage",
+    "responseCacheStats",
+    "memoryStats",
+    "brokerStats",
+    "webhooks",
+    "runReport",
+    "dataQualityReport",
+];
+
+/// Caches data of successful queries in memory. An entry is served until a mutation changes
+/// one of the tables read by the query (each mutation bumps versions of the tables it writes)
+/// or until `RESPONSE_CACHE_TTL_SECONDS` pass. Versions are kept per instance, so the TTL bounds
+/// how long changes made by other instances or directly in the database go unnoticed; set
+/// `RESPONSE_CACHE_CAPACITY` to 0 to disable caching
+#[derive(Clone)]
+pub struct ResponseCache {
+    state: Arc<CacheState>,
+}
+
+/// Numbers of queries served from the cache and executed since the start of the instance
+pub struct ResponseCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+}
+
+impl ResponseCacheStats {
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            total => self.hits as f64 / total as f64,
+        }
+    }
+}
+
+struct CacheState {
+    capacity: usize,
+    ttl: Duration,
+    versions: Vec<AtomicU64>,
+    entries: Mutex<Entries>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[derive(Default)]
+struct Entries {
+    responses: HashMap<CacheKey, CachedResponse>,
+    // insertion order for eviction
+    keys: VecDeque<CacheKey>,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    operation_hash: u64,
+    variables: String,
+    role: Option<String>,
+    user: Option<String>,
+    profile: Option<String>,
+    decimal_format: BigDecimalFormat,
+}
+
+impl CacheKey {
+    fn size(&self) -> usize {
+        size_of::<Self>()
+            + self.variables.capacity()
+            + self.role.as_ref().map_or(0, String::capacity)
+            + self.user.as_ref().map_or(0, String::capacity)
+            + self.profile.as_ref().map_or(0, String::capacity)
+    }
+}
+
+struct CachedResponse {
+    data: Value,
+    cached_at: Instant,
+    // indexes in TABLES with their versions at the start of the execution
+    versions: Vec<(usize, u64)>,
+}
+
+impl ResponseCache {
+    pub fn from_env() -> Self {
+        Self::new(*CAPACITY, *TTL)
+    }
+
+    fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            state: Arc::new(CacheState {
+                capacity,
+                ttl,
+                versions: TABLES.iter().map(|_| AtomicU64::new(0)).collect(),
+                entries: Mutex::default(),
+                hits: AtomicU64::new(0),
+                misses: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    pub fn stats(&self) -> ResponseCacheStats {
+        ResponseCacheStats {
+            hits: self.state.hits.load(Ordering::Relaxed),
+            misses: self.state.misses.load(Ordering::Relaxed),
+            entries: self.state.lock_entries().responses.len(),
+        }
+    }
+
+    pub fn memory(&self) -> CacheMemory {
+        let entries = self.state.lock_entries();
+        let estimated_bytes = entries
+            .responses
+            .iter()
+            .map(|(key, cached)| {
+                2 * key.size()
+                    + memory::estimate_value_size(&cached.data)
+                    + cached.versions.len() * size_of::<(usize, u64)>()
+            })
+            .sum();
+        CacheMemory {
+            name: "responses",
+            entries: entries.responses.len(),
+            estimated_bytes,
+        }
+    }
+}
+
+impl CacheState {
+    fn lock_entries(&self) -> std::sync::MutexGuard<'_, Entries> {
+        self.entries.lock().expect("Can't lock cached responses")
+    }
+
+    fn get_versions(&self) -> Vec<u64> {
+        self.versions
+            .iter()
+            .map(|version| version.load(Ordering::SeqCst))
+            .collect()
+    }
+
+    fn bump(&self, tables: &HashSet<usize>) {
+        for index in tables {
+            self.versions[*index].fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn get(&self, key: &CacheKey) -> Option<Value> {
+        let versions = self.get_versions();
+        let mut entries = self.lock_entries();
+        let cached = entries.responses.get(key)?;
+        if cached.cached_at.elapsed() < self.ttl
+            && cached
+                .versions
+                .iter()
+                .all(|(index, version)| versions[*index] == *version)
+        {
+            Some(cached.data.clone())
+        } else {
+            entries.responses.remove(key);
+            None
+        }
+    }
+
+    fn put(&self, key: CacheKey, cached: CachedResponse) {
+        let mut entries = self.lock_entries();
+        if entries.responses.insert(key.clone(), cached).is_none() {
+            entries.keys.push_back(key);
+        }
+        while entries.responses.len() > self.capacity {
+            let Some(oldest) = entries.keys.pop_front() else {
+                break;
+            };
+            entries.responses.remove(&oldest);
+        }
+        // keys of entries removed as stale
+        if entries.keys.len() > 2 * self.capacity {
+            let Entries { responses, keys } = &mut *entries;
+            keys.retain(|key| responses.contains_key(key));
+        }
+        metrics::CACHE_ENTRIES.set(&["response"], entries.responses.len() as f64);
+    }
+}
+
+impl ExtensionFactory for ResponseCache {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(ResponseCacheExtension {
+            state: Arc::clone(&self.state),
+            operation: Mutex::default(),
+            uncacheable: AtomicBool::new(false),
+            tables: Mutex::default(),
+        })
+    }
+}
+
+struct ResponseCacheExtension {
+    state: Arc<CacheState>,
+    operation: Mutex<Option<ParsedOperation>>,
+    uncacheable: AtomicBool,
+    // indexes in TABLES read or written by the operation
+    tables: Mutex<HashSet<usize>>,
+}
+
+struct ParsedOperation {
+    is_query: bool,
+    document_hash: u64,
+    variables: String,
+}
+
+impl ResponseCacheExtension {
+    fn add_tables(&self, tables: &[&str]) {
+        self.tables
+            .lock()
+            .expect("Can't lock operation tables")
+            .extend(tables.iter().filter_map(|table| get_table_index(table)));
+    }
+
+    fn take_tables(&self) -> HashSet<usize> {
+        std::mem::take(&mut *self.tables.lock().expect("Can't lock operation tables"))
+    }
+}
+
+#[async_trait::async_trait]
+impl Extension for ResponseCacheExtension {
+    async fn parse_query(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        query: &str,
+        variables: &Variables,
+        next: NextParseQuery<'_>,
+    ) -> ServerResult<ExecutableDocument> {
+        let document = next.run(ctx, query, variables).await?;
+        let mut hasher = DefaultHasher::new();
+        ctx.stringify_execute_doc(&document, variables)
+            .hash(&mut hasher);
+        *self.operation.lock().expect("Can't lock operation") = Some(ParsedOperation {
+            is_query: document
+                .operations
+                .iter()
+                .all(|(_, operation)| operation.node.ty == OperationType::Query),
+            document_hash: hasher.finish(),
+            variables: serde_json::to_string(variables).expect("Can't serialize variables"),
+        });
+        Ok(document)
+    }
+
+    async fn execute(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        operation_name: Option<&str>,
+        next: NextExecute<'_>,
+    ) -> Response {
+        let operation = self.operation.lock().expect("Can't lock operation").take();
+        let operation = match operation {
+            Some(operation) if operation.is_query => operation,
+            Some(_) => {
+                let response = next.run(ctx, operation_name).await;
+                self.state.bump(&self.take_tables());
+                return response;
+            }
+            None => return next.run(ctx, operation_name).await,
+        };
+        if self.state.capacity == 0 || ctx.data_opt::<ValidateOnly>().is_some() {
+            return next.run(ctx, operation_name).await;
+        }
+        #[cfg(feature = "chaos")]
+        if crate::chaos::is_enabled(ctx) {
+            return next.run(ctx, operation_name).await;
+        }
+
+        let key = CacheKey {
+            operation_hash: hash_operation(operation.document_hash, operation_name),
+            variables: operation.variables,
+            role: match ctx.data_opt::<Result<Option<Role>, CustomError>>() {
+                Some(Ok(Some(role))) => Some(role.to_string()),
+                _ => None,
+            },
+            user: ctx.data_opt::<CurrentUser>().map(|user| user.0.clone()),
+            profile: masking::get_profile(ctx).map(|(name, _)| name.clone()),
+            decimal_format: BigDecimalFormat::get(ctx),
+        };
+        if let Some(data) = self.state.get(&key) {
+            self.state.hits.fetch_add(1, Ordering::Relaxed);
+            metrics::CACHE_REQUESTS.increment(&["response", "hit"], 1);
+            return Response::new(data);
+        }
+        self.state.misses.fetch_add(1, Ordering::Relaxed);
+        metrics::CACHE_REQUESTS.increment(&["response", "miss"], 1);
+
+        let versions = self.state.get_versions();
+        let response = next.run(ctx, operation_name).await;
+        // only data is kept, so responses with extensions of inner extensions aren't cached
+        if response.is_ok()
+            && response.extensions.is_empty()
+            && !self.uncacheable.load(Ordering::Relaxed)
+        {
+            let cached = CachedResponse {
+                data: response.data.clone(),
+                cached_at: Instant::now(),
+                versions: self
+                    .take_tables()
+                    .into_iter()
+                    .map(|index| (index, versions[index]))
+                    .collect(),
+            };
+            self.state.put(key, cached);
+        }
+        response
+    }
+
+    async fn resolve(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        info: ResolveInfo<'_>,
+        next: NextResolve<'_>,
+    ) -> ServerResult<Option<Value>> {
+        match info.parent_type {
+            "Query" if UNCACHEABLE_FIELDS.contains(&info.name) => {
+                self.uncacheable.store(true, Ordering::Relaxed)
+            }
+            "Mutation" => self.add_tables(
+                MUTATION_TABLES
+                    .iter()
+                    .find(|(field, _)| *field == info.name)
+                    .map(|(_, tables)| *tables)
+                    .unwrap_or(TABLES),
+            ),
+            _ => {}
+        }
+        // usage of old names is tracked during execution
+        if RENAMED_FIELDS
+            .iter()
+            .any(|field| field.old_name == info.name && field.type_name == info.parent_type)
+        {
+            self.uncacheable.store(true, Ordering::Relaxed);
+        }
+        for type_name in [info.parent_type, get_named_type(info.return_type)] {
+            if let Some((_, tables)) = TYPE_TABLES.iter().find(|(name, _)| *name == type_name) {
+                self.add_tables(tables);
+            }
+        }
+        next.run(ctx, info).await
+    }
+}
+
+fn get_table_index(table: &str) -> Option<usize> {
+    TABLES.iter().position(|name| *name == table)
+}
+
+fn hash_operation(document_hash: u64, operation_name: Option<&str>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    document_hash.hash(&mut hasher);
+    operation_name.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// `Planet` for `[Planet!]!`
+fn get_named_type(type_name: &str) -> &str {
+    type_name.trim_matches(|c| c == '[' || c == ']' || c == '!')
+}
+
+#[cfg(test)]
+mod tests {
+    use async_graphql::value;
+
+    use crate::schema_coordinates::{self, Coordinate};
+
+    use super::*;
+
+    /// Types which don't need tables of their own: their fields are computed from the parent or
+    /// from in-memory state, they are returned only by uncacheable fields, or only by mutations and
+    /// subscriptions, which aren't cached
+    const UNTRACKED_TYPES: &[&str] = &[
+        "AllocatorStats",
+        "BrokerTopicStats",
+        "CacheMemory",
+        "DataQualityFinding",
+        "DataQualityReport",
+        "DeletePlanetsResult",
+        "DeletionProgress",
+        "Distance",
+        "EnabledFeature",
+        "MemoryStats",
+        "PlanetEvent",
+        "PlanetEventDigest",
+        "PlanetEventKindCount",
+        "RenamedFieldUsage",
+        "ResponseCacheStats",
+        "ServerInfo",
+        "SnapshotImport",
+        "TravelTime",
+        "UserAgentUsage",
+        "WebhookDelivery",
+        "_Service",
+    ];
+
+    fn key(operation_hash: u64) -> CacheKey {
+        CacheKey {
+            operation_hash,
+            variables: "{}".to_string(),
+            role: None,
+            user: None,
+            profile: None,
+            decimal_format: BigDecimalFormat::String,
+        }
+    }
+
+    fn cached(state: &CacheState, tables: &[&str]) -> CachedResponse {
+        let versions = state.get_versions();
+        CachedResponse {
+            data: value!({ "getPlanets": [] }),
+            cached_at: Instant::now(),
+            versions: tables
+                .iter()
+                .filter_map(|table| get_table_index(table))
+                .map(|index| (index, versions[index]))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn named_type() {
+        assert_eq!("Planet", get_named_type("[Planet!]!"));
+        assert_eq!("Planet", get_named_type("Planet"));
+    }
+
+    #[test]
+    fn bumped_table_invalidates_entries_reading_it() {
+        let cache = ResponseCache::new(10, Duration::from_secs(60));
+        let state = &cache.state;
+        state.put(key(1), cached(state, &["planets"]));
+        state.put(key(2), cached(state, &["reports"]));
+
+        state.bump(&HashSet::from([get_table_index("planets").unwrap()]));
+
+        assert!(state.get(&key(1)).is_none());
+        assert!(state.get(&key(2)).is_some());
+        assert_eq!(1, cache.stats().entries);
+    }
+
+    #[test]
+    fn oldest_entries_are_evicted() {
+        let cache = ResponseCache::new(2, Duration::from_secs(60));
+        let state = &cache.state;
+        for operation_hash in 1..=3 {
+            state.put(key(operation_hash), cached(state, &["planets"]));
+        }
+
+        assert!(state.get(&key(1)).is_none());
+        assert!(state.get(&key(2)).is_some());
+        assert!(state.get(&key(3)).is_some());
+    }
+
+    #[test]
+    fn entries_expire() {
+        let cache = ResponseCache::new(10, Duration::ZERO);
+        let state = &cache.state;
+        state.put(key(1), cached(state, &["planets"]));
+
+        assert!(state.get(&key(1)).is_none());
+    }
+
+    #[test]
+    fn schema_is_covered() {
+        let coordinates = schema_coordinates::from_sdl(&crate::schema_sdl());
+        let fields_of = |type_name: &str| -> Vec<String> {
+            let prefix = format!("{}.", type_name);
+            coordinates
+                .iter()
+                .filter(|(coordinate, kind)| {
+                    coordinate.starts_with(&prefix)
+                        && matches!(
+                            kind,
+                            Coordinate::ObjectField { .. } | Coordinate::InterfaceField
+                        )
+                })
+                .map(|(coordinate, _)| coordinate[prefix.len()..].to_string())
+                .collect()
+        };
+
+        // object and interface types, i.e. types with fields
+        let mut uncovered: Vec<&String> = coordinates
+            .iter()
+            .filter(|(type_name, kind)| {
+                **kind == Coordinate::Type
+                    && !["Query", "Mutation", "Subscription"].contains(&type_name.as_str())
+                    && !fields_of(type_name).is_empty()
+                    && !TYPE_TABLES.iter().any(|(name, _)| name == type_name)
+                    && !UNTRACKED_TYPES.contains(&type_name.as_str())
+            })
+            .map(|(type_name, _)| type_name)
+            .collect();
+        uncovered.sort();
+        assert!(
+            uncovered.is_empty(),
+            "Types missing from TYPE_TABLES or UNTRACKED_TYPES: {:?}",
+            uncovered
+        );
+
+        let mut unlisted_mutations: Vec<String> = fields_of("Mutation")
+            .into_iter()
+            .filter(|field| !MUTATION_TABLES.iter().any(|(name, _)| name == field))
+            .collect();
+        unlisted_mutations.sort();
+        assert!(
+            unlisted_mutations.is_empty(),
+            "Mutations missing from MUTATION_TABLES: {:?}",
+            unlisted_mutations
+        );
+
+        let query_fields = fields_of("Query");
+        for field in UNCACHEABLE_FIELDS {
+            assert!(query_fields.contains(&field.to_string()), "{}", field);
+        }
+        for (_, tables) in TYPE_TABLES.iter().chain(MUTATION_TABLES) {
+            for table in *tables {
+                assert!(get_table_index(table).is_some(), "{}", table);
+            }
+        }
+    }
+
+    #[test]
+    fn hit_rate() {
+        let stats = ResponseCacheStats {
+            hits: 3,
+            misses: 1,
+            entries: 1,
+        };
+        assert_eq!(0.75, stats.hit_rate());
+        assert_eq!(
+            0.0,
+            ResponseCacheStats {
+                hits: 0,
+                misses: 0,
+                entries: 0
+            }
+            .hit_rate()
+        );
+    }
+}
@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+
+use async_graphql::parser::parse_schema;
+use async_graphql::parser::types::{FieldDefinition, TypeKind, TypeSystemDefinition};
+use async_graphql::Positioned;
+
+/// What a schema coordinate points to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Coordinate {
+    Type,
+    ObjectField { nullable: bool },
+    InterfaceField,
+    InputField,
+    Argument,
+    EnumValue,
+}
+
+/// Keyed by schema coordinates: `Type`, `Type.field`, `Type.field(argument:)` or `Enum.VALUE`;
+/// configuration keyed by them is validated against these at startup
+pub type Coordinates = HashMap<String, Coordinate>;
+
+pub fn from_sdl(sdl: &str) -> Coordinates {
+    let document = parse_schema(sdl).expect("Can't parse SDL");
+    let mut coordinates = Coordinates::new();
+    for definition in document.definitions {
+        let TypeSystemDefinition::Type(definition) = definition else {
+            continue;
+        };
+        let type_name = definition.node.name.node.to_string();
+        match &definition.node.kind {
+            TypeKind::Object(object) => {
+                add_fields(&mut coordinates, &type_name, &object.fields, |field| {
+                    Coordinate::ObjectField {
+                        nullable: field.ty.node.nullable,
+                    }
+                })
+            }
+            TypeKind::Interface(interface) => {
+                add_fields(&mut coordinates, &type_name, &interface.fields, |_| {
+                    Coordinate::InterfaceField
+                })
+            }
+            TypeKind::InputObject(input) => {
+                for field in &input.fields {
+                    coordinates.insert(
+                        format!("{}.{}", type_name, field.node.name.node),
+                        Coordinate::InputField,
+                    );
+                }
+            }
+            TypeKind::Enum(enum_type) => {
+                for value in &enum_type.values {
+                    coordinates.insert(
+                        format!("{}.{}", type_name, value.node.value.node),
+                        Coordinate::EnumValue,
+                    );
+                }
+            }
+            TypeKind::Scalar | TypeKind::Union(_) => {}
+        }
+        coordinates.insert(type_name, Coordinate::Type);
+    }
+    coordinates
+}
+
+fn add_fields(
+    coordinates: &mut Coordinates,
+    type_name: &str,
+    fields: &[Positioned<FieldDefinition>],
+    coordinate: impl Fn(&FieldDefinition) -> Coordinate,
+) {
+    for field in fields {
+        let field_coordinate = format!("{}.{}", type_name, field.node.name.node);
+        for argument in &field.node.arguments {
+            coordinates.insert(
+                format!("{}({}:)", field_coordinate, argument.node.name.node),
+                Coordinate::Argument,
+            );
+        }
+        coordinates.insert(field_coordinate, coordinate(&field.node));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coordinates() {
+        let coordinates = from_sdl(
+            "
+            interface Details { meanRadius: String! }
+            type InhabitedPlanetDetails implements Details {
+                meanRadius: String!
+                population(unit: Unit): String
+            }
+            input DetailsInput { meanRadius: String! }
+            enum Unit { MILLIONS }
+            ",
+        );
+
+        let get = |coordinate: &str| coordinates.get(coordinate).copied();
+        assert_eq!(Some(Coordinate::Type), get("InhabitedPlanetDetails"));
+        assert_eq!(
+            Some(Coordinate::ObjectField { nullable: false }),
+            get("InhabitedPlanetDetails.meanRadius")
+        );
+        assert_eq!(
+            Some(Coordinate::ObjectField { nullable: true }),
+            get("InhabitedPlanetDetails.population")
+        );
+        assert_eq!(
+            Some(Coordinate::Argument),
+            get("InhabitedPlanetDetails.population(unit:)")
+        );
+        assert_eq!(Some(Coordinate::InterfaceField), get("Details.meanRadius"));
+        assert_eq!(Some(Coordinate::InputField), get("DetailsInput.meanRadius"));
+        assert_eq!(Some(Coordinate::EnumValue), get("Unit.MILLIONS"));
+        assert_eq!(None, get("Planet"));
+    }
+}
@@ -0,0 +1,232 @@
+//! Publishing of the subgraph SDL to a schema registry at startup, so that gateways pick up
+//! schema changes without a manual step
+use std::env;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use crate::build_info::BUILD_INFO;
+use crate::http_client::{self, RetryPolicy};
+
+const APOLLO_API_URL: &str = "https://api.apollographql.com/api/graphql";
+const RETRY_POLICY: RetryPolicy = RetryPolicy {
+    max_attempts: 3,
+    base_delay: Duration::from_secs(5),
+    max_delay: Duration::from_secs(5),
+};
+
+const APOLLO_PUBLISH_MUTATION: &str = r#"
+    mutation PublishSubgraph(
+        $graphId: ID!
+        $variant: String!
+        $name: String!
+        $url: String
+        $revision: String!
+        $sdl: String!
+    ) {
+        graph(id: $graphId) {
+            publishSubgraph(
+                graphVariant: $variant
+                name: $name
+                url: $url
+                revision: $revision
+                activePartialSchema: { sdl: $sdl }
+            ) {
+                errors { message }
+            }
+        }
+    }
+"#;
+
+#[derive(Debug, PartialEq)]
+pub enum Registry {
+    /// Apollo GraphOS; the graph ref is `graph@variant`
+    Apollo { key: String, graph_ref: String },
+    /// A self-hosted registry which accepts the subgraph as JSON POSTed to the URL
+    Http { url: String },
+}
+
+#[derive(Debug, PartialEq)]
+pub struct RegistryConfig {
+    pub registry: Registry,
+    pub subgraph_name: String,
+    /// The URL at which gateways reach the service
+    pub routing_url: Option<String>,
+    pub version: String,
+    pub git_sha: String,
+}
+
+impl RegistryConfig {
+    /// Apollo GraphOS if `APOLLO_KEY` and `APOLLO_GRAPH_REF` are set, otherwise a registry at
+    /// `SCHEMA_REGISTRY_URL`; publishing is off if neither is configured
+    pub fn from_env() -> Option<Self> {
+        let registry = match (env::var("APOLLO_KEY"), env::var("APOLLO_GRAPH_REF")) {
+            (Ok(key), Ok(graph_ref)) => Registry::Apollo { key, graph_ref },
+            _ => Registry::Http {
+                url: env::var("SCHEMA_REGISTRY_URL").ok()?,
+            },
+        };
+        Some(RegistryConfig {
+            registry,
+            subgraph_name: env::var("SUBGRAPH_NAME").unwrap_or_else(|_| "planets".to_string()),
+            routing_url: env::var("SUBGRAPH_URL").ok(),
+            version: BUILD_INFO.version.to_string(),
+            git_sha: BUILD_INFO.git_sha.to_string(),
+        })
+    }
+}
+
+/// Makes a few attempts, since the registry may be unavailable for a while during a deployment;
+/// the service keeps running if publishing fails
+pub async fn publish(config: RegistryConfig, sdl: String) {
+    match publish_once(&config, &sdl).await {
+        Ok(()) => println!(
+            "Schema of {} {} ({}) is published",
+            config.subgraph_name, config.version, config.git_sha
+        ),
+        Err(e) => println!("Can't publish schema: {}", e),
+    }
+}
+
+async fn publish_once(config: &RegistryConfig, sdl: &str) -> Result<(), String> {
+    let client = http_client::client();
+    let request = match &config.registry {
+        Registry::Apollo { key, .. } => client
+            .post(APOLLO_API_URL)
+            .header("x-api-key", key)
+            .header("apollographql-client-name", "planets-service"),
+        Registry::Http { url } => client.post(url),
+    };
+    let response: Value =
+        http_client::send(request.json(&to_request_body(config, sdl)), &RETRY_POLICY)
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| e.to_string())?
+            .json()
+            .await
+            .unwrap_or(Value::Null);
+
+    match config.registry {
+        Registry::Apollo { .. } => check_apollo_response(&response),
+        Registry::Http { .. } => Ok(()),
+    }
+}
+
+fn to_request_body(config: &RegistryConfig, sdl: &str) -> Value {
+    match &config.registry {
+        Registry::Apollo { graph_ref, .. } => {
+            let (graph_id, variant) = graph_ref
+                .split_once('@')
+                .unwrap_or((graph_ref.as_str(), "current"));
+            json!({
+                "query": APOLLO_PUBLISH_MUTATION,
+                "variables": {
+                    "graphId": graph_id,
+                    "variant": variant,
+                    "name": config.subgraph_name,
+                    "url": config.routing_url,
+                    "revision": config.git_sha,
+                    "sdl": sdl,
+                },
+            })
+        }
+        Registry::Http { .. } => json!({
+            "name": config.subgraph_name,
+            "url": config.routing_url,
+            "version": config.version,
+            "gitSha": config.git_sha,
+            "sdl": sdl,
+        }),
+    }
+}
+
+fn check_apollo_response(response: &Value) -> Result<(), String> {
+    let errors = match response.get("errors") {
+        Some(errors) => errors,
+        None => &response["data"]["graph"]["publishSubgraph"]["errors"],
+    };
+    let messages: Vec<&str> = errors
+        .as_array()
+        .map(|errors| {
+            errors
+                .iter()
+                .filter_map(|error| error["message"].as_str())
+                .collect()
+        })
+        .unwrap_or_default();
+    if !messages.is_empty() {
+        return Err(messages.join("; "));
+    }
+    if response["data"]["graph"].is_null() {
+        return Err("Graph is not found".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(registry: Registry) -> RegistryConfig {
+        RegistryConfig {
+            registry,
+            subgraph_name: "planets".to_string(),
+            routing_url: Some("http://planets-service:8001".to_string()),
+            version: "0.1.0".to_string(),
+            git_sha: "3f2a1c9".to_string(),
+        }
+    }
+
+    #[test]
+    fn apollo_request() {
+        let config = config(Registry::Apollo {
+            key: "service:graph:key".to_string(),
+            graph_ref: "solar-system@production".to_string(),
+        });
+        let body = to_request_body(&config, "type Query { a: Int }");
+        assert_eq!(
+            json!({
+                "graphId": "solar-system",
+                "variant": "production",
+                "name": "planets",
+                "url": "http://planets-service:8001",
+                "revision": "3f2a1c9",
+                "sdl": "type Query { a: Int }",
+            }),
+            body["variables"]
+        );
+    }
+
+    #[test]
+    fn http_request() {
+        let config = config(Registry::Http {
+            url: "http://registry/schemas".to_string(),
+        });
+        let body = to_request_body(&config, "type Query { a: Int }");
+        assert_eq!("0.1.0", body["version"]);
+        assert_eq!("3f2a1c9", body["gitSha"]);
+        assert_eq!("type Query { a: Int }", body["sdl"]);
+    }
+
+    #[test]
+    fn apollo_errors() {
+        let published = json!({ "data": { "graph": { "publishSubgraph": { "errors": [] } } } });
+        assert_eq!(Ok(()), check_apollo_response(&published));
+
+        let composition_failed = json!({ "data": { "graph": { "publishSubgraph": {
+            "errors": [{ "message": "Field Planet.id is missing" }]
+        } } } });
+        assert_eq!(
+            Err("Field Planet.id is missing".to_string()),
+            check_apollo_response(&composition_failed)
+        );
+
+        let unauthorized = json!({ "errors": [{ "message": "Invalid API key" }] });
+        assert_eq!(
+            Err("Invalid API key".to_string()),
+            check_apollo_response(&unauthorized)
+        );
+
+        assert!(check_apollo_response(&json!({ "data": { "graph": null } })).is_err());
+    }
+}
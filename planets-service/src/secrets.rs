@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+use std::env;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+
+use crate::http_client::{self, RetryPolicy};
+use crate::metrics;
+
+/// A secret value and, if the provider leases it, how long it's valid
+pub struct FetchedSecret {
+    pub value: String,
+    pub ttl: Option<Duration>,
+}
+
+/// External secret storage; secret names are provider-specific
+#[async_trait::async_trait]
+pub trait SecretProvider: Send + Sync {
+    async fn fetch(&self, name: &str) -> Result<FetchedSecret, String>;
+}
+
+/// Reads secrets from HashiCorp Vault over its HTTP API; a name is a path and a key,
+/// e.g. `secret/data/planets-db#password` (KV v2). Only static secrets are supported: dynamic
+/// database credentials issue a new user with each lease, but only the password is replaced
+pub struct VaultSecretProvider {
+    address: String,
+    token: String,
+}
+
+impl VaultSecretProvider {
+    pub fn new(address: String, token: String) -> Self {
+        Self { address, token }
+    }
+}
+
+#[async_trait::async_trait]
+impl SecretProvider for VaultSecretProvider {
+    async fn fetch(&self, name: &str) -> Result<FetchedSecret, String> {
+        let (path, key) = split_name(name);
+        let key = key.ok_or_else(|| format!("Key of secret {} is not specified", name))?;
+        let request = http_client::client()
+            .get(format!(
+                "{}/v1/{}",
+                self.address.trim_end_matches('/'),
+                path
+            ))
+            .header("X-Vault-Token", &self.token);
+        let response: Value = http_client::send(request, &RetryPolicy::default())
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| e.to_string())?
+            .json()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        // KV v2 nests secret data into one more `data` object
+        let data = match response["data"].get("data") {
+            Some(data) if data.is_object() => data,
+            _ => &response["data"],
+        };
+        let value = data[key]
+            .as_str()
+            .ok_or_else(|| format!("Secret {} is not found", name))?;
+        let ttl = response["lease_duration"]
+            .as_u64()
+            .filter(|lease_duration| *lease_duration > 0)
+            .map(Duration::from_secs);
+        Ok(FetchedSecret {
+            value: value.to_string(),
+            ttl,
+        })
+    }
+}
+
+/// Reads secrets from AWS Secrets Manager; a name is a secret ID optionally followed by a key
+/// of the JSON secret, e.g. `planets-db#password`
+#[cfg(feature = "aws-secrets-manager")]
+pub struct AwsSecretProvider {
+    client: aws_sdk_secretsmanager::Client,
+}
+
+#[cfg(feature = "aws-secrets-manager")]
+impl AwsSecretProvider {
+    pub async fn from_env() -> Self {
+        let config = aws_config::load_from_env().await;
+        Self {
+            client: aws_sdk_secretsmanager::Client::new(&config),
+        }
+    }
+}
+
+#[cfg(feature = "aws-secrets-manager")]
+#[async_trait::async_trait]
+impl SecretProvider for AwsSecretProvider {
+    async fn fetch(&self, name: &str) -> Result<FetchedSecret, String> {
+        let (secret_id, key) = split_name(name);
+        let output = self
+            .client
+            .get_secret_value()
+            .secret_id(secret_id)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        let secret = output
+            .secret_string()
+            .ok_or_else(|| format!("Secret {} is not a string", name))?;
+        let value = match key {
+            Some(key) => serde_json::from_str::<Value>(secret).map_err(|e| e.to_string())?[key]
+                .as_str()
+                .ok_or_else(|| format!("Secret {} is not found", name))?
+                .to_string(),
+            None => secret.to_string(),
+        };
+        // rotation is scheduled on the AWS side, so the value is refreshed periodically
+        Ok(FetchedSecret { value, ttl: None })
+    }
+}
+
+fn split_name(name: &str) -> (&str, Option<&str>) {
+    match name.split_once('#') {
+        Some((path, key)) => (path, Some(key)),
+        None => (name, None),
+    }
+}
+
+/// Caches secrets until their lease or the refresh interval expires, so rotated values are
+/// picked up; if a secret can't be refreshed, the stale value is used
+pub struct SecretCache {
+    provider: Box<dyn SecretProvider>,
+    refresh_interval: Duration,
+    secrets: Mutex<HashMap<String, (String, Instant)>>,
+}
+
+impl SecretCache {
+    pub fn new(provider: Box<dyn SecretProvider>, refresh_interval: Duration) -> Self {
+        Self {
+            provider,
+            refresh_interval,
+            secrets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Uses the provider set by `SECRETS_PROVIDER` (`vault` with `VAULT_ADDR` and `VAULT_TOKEN`,
+    /// or `aws`); secrets are refreshed every `SECRETS_REFRESH_INTERVAL` seconds (300 by default)
+    pub async fn from_env() -> Option<Self> {
+        let provider: Box<dyn SecretProvider> = match env::var("SECRETS_PROVIDER").ok()?.as_str() {
+            "vault" => Box::new(VaultSecretProvider::new(
+                env::var("VAULT_ADDR").expect("Can't get Vault address"),
+                env::var("VAULT_TOKEN").expect("Can't get Vault token"),
+            )),
+            #[cfg(feature = "aws-secrets-manager")]
+            "aws" => Box::new(AwsSecretProvider::from_env().await),
+            provider => panic!("Unsupported secrets provider: {}", provider),
+        };
+        let refresh_interval = env::var("SECRETS_REFRESH_INTERVAL")
+            .map(|value| value.parse().expect("Can't parse secrets refresh interval"))
+            .unwrap_or(300);
+        Some(Self::new(provider, Duration::from_secs(refresh_interval)))
+    }
+
+    pub async fn get(&self, name: &str) -> Result<String, String> {
+        let cached = self
+            .secrets
+            .lock()
+            .expect("Can't lock secrets")
+            .get(name)
+            .cloned();
+        if let Some((value, expires_at)) = &cached {
+            if Instant::now() < *expires_at {
+                metrics::CACHE_REQUESTS.increment(&["secret", "hit"], 1);
+                return Ok(value.clone());
+            }
+        }
+        metrics::CACHE_REQUESTS.increment(&["secret", "miss"], 1);
+
+        match self.provider.fetch(name).await {
+            Ok(secret) => {
+                let ttl = secret
+                    .ttl
+                    .map_or(self.refresh_interval, |ttl| ttl.min(self.refresh_interval));
+                self.secrets.lock().expect("Can't lock secrets").insert(
+                    name.to_string(),
+                    (secret.value.clone(), Instant::now() + ttl),
+                );
+                Ok(secret.value)
+            }
+            Err(e) => match cached {
+                Some((value, _)) => {
+                    println!("Secret {} wasn't refreshed: {}", name, e);
+                    Ok(value)
+                }
+                None => Err(e),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+
+    struct RotatingProvider {
+        fetches: Arc<AtomicUsize>,
+    }
+
+    #[async_trait::async_trait]
+    impl SecretProvider for RotatingProvider {
+        async fn fetch(&self, _name: &str) -> Result<FetchedSecret, String> {
+            match self.fetches.fetch_add(1, Ordering::SeqCst) {
+                0 => Ok(FetchedSecret {
+                    value: "first".to_string(),
+                    ttl: Some(Duration::ZERO),
+                }),
+                1 => Ok(FetchedSecret {
+                    value: "second".to_string(),
+                    ttl: None,
+                }),
+                _ => Err("Unavailable".to_string()),
+            }
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_secret_renewal() {
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let cache = SecretCache::new(
+            Box::new(RotatingProvider {
+                fetches: Arc::clone(&fetches),
+            }),
+            Duration::from_secs(300),
+        );
+
+        // the first lease is expired at once
+        assert_eq!(Ok("first".to_string()), cache.get("db#password").await);
+        assert_eq!(Ok("second".to_string()), cache.get("db#password").await);
+        assert_eq!(Ok("second".to_string()), cache.get("db#password").await);
+        assert_eq!(2, fetches.load(Ordering::SeqCst));
+
+        fetches.store(1, Ordering::SeqCst);
+        let cache = SecretCache::new(Box::new(RotatingProvider { fetches }), Duration::ZERO);
+        // the stale value is used when the provider is unavailable
+        assert_eq!(Ok("second".to_string()), cache.get("db#password").await);
+        assert_eq!(Ok("second".to_string()), cache.get("db#password").await);
+    }
+}
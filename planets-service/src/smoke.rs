@@ -0,0 +1,71 @@
+//! Self-test run by `planets-service --smoke` before rollout: canary operations are executed
+//! against the schema as an admin, and the process exits with a non-zero code if any fails. They
+//! run in a disposable database schema with the planets added by migrations; none of them creates
+//! a planet, since that sends a Kafka message
+use std::time::Instant;
+
+use async_graphql::{Request, Variables};
+use serde_json::json;
+
+use crate::graphql::AppSchema;
+
+struct CanaryOperation {
+    name: &'static str,
+    query: &'static str,
+}
+
+/// Executed in order; `$id` is the ID of the first planet listed by a previous operation
+const CANARY_OPERATIONS: &[CanaryOperation] = &[
+    CanaryOperation {
+        name: "list planets",
+        query: "{ getPlanets { id name planetType status details { meanRadius mass atmosphere { gas } } star { name } } }",
+    },
+    CanaryOperation {
+        name: "update planet",
+        query: r#"mutation($id: ID!) {
+            updatePlanet(
+                id: $id
+                planet: { name: "Smoke test", type: DWARF_PLANET, details: { meanRadius: "2.0", mass: "1e20" } }
+            ) { id }
+        }"#,
+    },
+    CanaryOperation {
+        name: "get planet",
+        query: "query($id: ID!) { getPlanet(id: $id) { name details { meanRadius } } }",
+    },
+];
+
+/// Prints a line per operation and returns whether all of them succeeded; operations after a
+/// failure are skipped, since they may depend on it
+pub async fn run(schema: &AppSchema) -> bool {
+    let mut planet_id = None;
+    for (index, operation) in CANARY_OPERATIONS.iter().enumerate() {
+        let request = Request::new(operation.query)
+            .variables(Variables::from_json(json!({ "id": planet_id })));
+        let start = Instant::now();
+        let response = crate::execute(schema, request, Some("ADMIN")).await;
+        let duration = start.elapsed().as_millis();
+
+        if response.is_err() {
+            let messages: Vec<String> = response.errors.iter().map(|e| e.to_string()).collect();
+            println!(
+                "FAILED {} ({} ms): {}",
+                operation.name,
+                duration,
+                messages.join("; ")
+            );
+            for skipped in &CANARY_OPERATIONS[index + 1..] {
+                println!("SKIPPED {}", skipped.name);
+            }
+            return false;
+        }
+        println!("OK {} ({} ms)", operation.name, duration);
+
+        if let Ok(data) = response.data.into_json() {
+            if let Some(id) = data.pointer("/getPlanets/0/id") {
+                planet_id = Some(id.clone());
+            }
+        }
+    }
+    true
+}
@@ -0,0 +1,370 @@
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use bigdecimal::BigDecimal;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::persistence::model::{
+    DetailsEntity, GasComponentEntity, ImportedPlanet, NewDetailsEntity, NewStarEntity,
+    OrbitEntity, PlanetType, SnapshotEntities,
+};
+use crate::persistence::units;
+
+const VERSION: u32 = 1;
+/// Snapshots compress well, so a small request can expand into a lot of memory
+const MAX_DECOMPRESSED_BYTES: u64 = 256 * 1024 * 1024;
+
+/// A line of a snapshot. Records are independent of IDs, so a snapshot can be imported into
+/// another database: planets and stars are identified by names
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(tag = "record", rename_all = "snake_case")]
+pub enum SnapshotRecord {
+    /// The first line
+    Header {
+        version: u32,
+    },
+    Star(StarRecord),
+    /// Goes after the star it orbits
+    Planet(Box<PlanetRecord>),
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct StarRecord {
+    name: String,
+    spectral_class: String,
+    mass: BigDecimal,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct PlanetRecord {
+    uuid: Uuid,
+    name: String,
+    #[serde(rename = "type")]
+    type_: PlanetType,
+    status: String,
+    star: Option<String>,
+    /// In kilometers, as in the schema
+    mean_radius: BigDecimal,
+    mass: BigDecimal,
+    population: Option<BigDecimal>,
+    orbit: Option<OrbitRecord>,
+    // snapshots made before atmospheres were modeled don't contain it
+    #[serde(default)]
+    atmosphere: Vec<GasRecord>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct OrbitRecord {
+    /// In astronomical units
+    semi_major_axis: BigDecimal,
+    eccentricity: BigDecimal,
+    /// In days
+    orbital_period: BigDecimal,
+    longitude_of_perihelion: BigDecimal,
+    mean_longitude: BigDecimal,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct GasRecord {
+    gas: String,
+    percentage: BigDecimal,
+}
+
+pub fn to_records(entities: SnapshotEntities) -> Vec<SnapshotRecord> {
+    let SnapshotEntities {
+        stars,
+        planets,
+        details,
+        orbits,
+        atmosphere_components,
+    } = entities;
+    let star_names: HashMap<i32, &String> =
+        stars.iter().map(|star| (star.id, &star.name)).collect();
+    let mut details: HashMap<i32, DetailsEntity> = details
+        .into_iter()
+        .map(|details| (details.planet_id, details))
+        .collect();
+    let mut orbits: HashMap<i32, OrbitEntity> = orbits
+        .into_iter()
+        .map(|orbit| (orbit.planet_id, orbit))
+        .collect();
+
+    let mut atmospheres: HashMap<i32, Vec<GasRecord>> = HashMap::new();
+    for component in atmosphere_components {
+        atmospheres
+            .entry(component.planet_id)
+            .or_default()
+            .push(GasRecord {
+                gas: component.gas,
+                percentage: component.percentage,
+            });
+    }
+
+    let planet_records: Vec<SnapshotRecord> = planets
+        .into_iter()
+        .filter_map(|planet| {
+            // details are created along with a planet, so only a concurrent deletion can miss them
+            let details = details.remove(&planet.id)?;
+            Some(SnapshotRecord::Planet(Box::new(PlanetRecord {
+                uuid: planet.uuid,
+                name: planet.name,
+                type_: planet.type_,
+                status: planet.status,
+                star: planet
+                    .star_id
+                    .and_then(|star_id| star_names.get(&star_id))
+                    .map(|name| name.to_string()),
+                mean_radius: units::meters_to_kilometers(&details.mean_radius),
+                mass: details.mass,
+                population: details.population,
+                orbit: orbits.remove(&planet.id).map(|orbit| OrbitRecord {
+                    semi_major_axis: units::meters_to_astronomical_units(&orbit.semi_major_axis),
+                    eccentricity: orbit.eccentricity,
+                    orbital_period: units::seconds_to_days(&orbit.orbital_period),
+                    longitude_of_perihelion: orbit.longitude_of_perihelion,
+                    mean_longitude: orbit.mean_longitude,
+                }),
+                atmosphere: atmospheres.remove(&planet.id).unwrap_or_default(),
+            })))
+        })
+        .collect();
+
+    let star_records = stars.into_iter().map(|star| {
+        SnapshotRecord::Star(StarRecord {
+            name: star.name,
+            spectral_class: star.spectral_class,
+            mass: star.mass,
+        })
+    });
+
+    std::iter::once(SnapshotRecord::Header { version: VERSION })
+        .chain(star_records)
+        .chain(planet_records)
+        .collect()
+}
+
+/// Stars and planets to import
+pub fn from_records(records: Vec<SnapshotRecord>) -> (Vec<NewStarEntity>, Vec<ImportedPlanet>) {
+    let mut stars = vec![];
+    let mut planets = vec![];
+    for record in records {
+        match record {
+            SnapshotRecord::Header { .. } => {}
+            SnapshotRecord::Star(star) => stars.push(NewStarEntity {
+                name: star.name,
+                spectral_class: star.spectral_class,
+                mass: star.mass,
+            }),
+            SnapshotRecord::Planet(planet) => planets.push(ImportedPlanet {
+                uuid: planet.uuid,
+                name: planet.name,
+                type_: planet.type_,
+                status: planet.status,
+                star_name: planet.star,
+                details: NewDetailsEntity {
+                    mean_radius: units::kilometers_to_meters(&planet.mean_radius),
+                    mass: planet.mass,
+                    population: planet.population,
+                    planet_id: 0,
+                },
+                orbit: planet.orbit.map(|orbit| OrbitEntity {
+                    planet_id: 0,
+                    semi_major_axis: units::astronomical_units_to_meters(&orbit.semi_major_axis),
+                    eccentricity: orbit.eccentricity,
+                    orbital_period: units::days_to_seconds(&orbit.orbital_period),
+                    longitude_of_perihelion: orbit.longitude_of_perihelion,
+                    mean_longitude: orbit.mean_longitude,
+                }),
+                atmosphere: planet
+                    .atmosphere
+                    .into_iter()
+                    .map(|component| GasComponentEntity {
+                        planet_id: 0,
+                        gas: component.gas,
+                        percentage: component.percentage,
+                    })
+                    .collect(),
+            }),
+        }
+    }
+    (stars, planets)
+}
+
+/// Gzipped NDJSON encoded in Base64, so that it can be passed as a GraphQL string
+pub fn encode(records: &[SnapshotRecord]) -> String {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    for record in records {
+        serde_json::to_writer(&mut encoder, record).expect("Can't serialize a snapshot record");
+        encoder.write_all(b"\n").expect("Can't compress a snapshot");
+    }
+    BASE64.encode(encoder.finish().expect("Can't compress a snapshot"))
+}
+
+pub fn decode(snapshot: &str) -> Result<Vec<SnapshotRecord>, String> {
+    decode_at_most(snapshot, MAX_DECOMPRESSED_BYTES)
+}
+
+fn decode_at_most(snapshot: &str, max_bytes: u64) -> Result<Vec<SnapshotRecord>, String> {
+    let compressed = BASE64
+        .decode(snapshot.trim())
+        .map_err(|e| format!("Snapshot isn't valid Base64: {}", e))?;
+    let mut decompressed = String::new();
+    GzDecoder::new(compressed.as_slice())
+        .take(max_bytes + 1)
+        .read_to_string(&mut decompressed)
+        .map_err(|e| format!("Can't decompress snapshot: {}", e))?;
+    if decompressed.len() as u64 > max_bytes {
+        return Err(format!(
+            "Snapshot exceeds {} bytes when decompressed",
+            max_bytes
+        ));
+    }
+
+    let mut records = vec![];
+    for (index, line) in decompressed.lines().enumerate() {
+        if line.is_empty() {
+            continue;
+        }
+        let record = serde_json::from_str(line)
+            .map_err(|e| format!("Invalid record on line {}: {}", index + 1, e))?;
+        records.push(record);
+    }
+
+    match records.first() {
+        Some(SnapshotRecord::Header { version }) if *version == VERSION => Ok(records),
+        Some(SnapshotRecord::Header { version }) => {
+            Err(format!("Unsupported snapshot version {}", version))
+        }
+        _ => Err("Snapshot doesn't start with a header".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::persistence::model::{PlanetEntity, StarEntity};
+
+    use super::*;
+
+    fn decimal(value: &str) -> BigDecimal {
+        BigDecimal::from_str(value).expect("Can't parse decimal")
+    }
+
+    #[test]
+    fn records_are_encoded_and_decoded() {
+        let records = vec![
+            SnapshotRecord::Header { version: VERSION },
+            SnapshotRecord::Star(StarRecord {
+                name: "Sun".to_string(),
+                spectral_class: "G2V".to_string(),
+                mass: decimal("1.989e30"),
+            }),
+            SnapshotRecord::Planet(Box::new(PlanetRecord {
+                uuid: Uuid::nil(),
+                name: "Earth".to_string(),
+                type_: PlanetType::TerrestrialPlanet,
+                status: "PUBLISHED".to_string(),
+                star: Some("Sun".to_string()),
+                mean_radius: decimal("6371.0"),
+                mass: decimal("5.97e24"),
+                population: Some(decimal("7.53")),
+                orbit: None,
+                atmosphere: vec![GasRecord {
+                    gas: "N2".to_string(),
+                    percentage: decimal("78.08"),
+                }],
+            })),
+        ];
+        assert_eq!(records, decode(&encode(&records)).expect("Can't decode"));
+    }
+
+    #[test]
+    fn planets_reference_stars_by_name() {
+        let records = to_records(SnapshotEntities {
+            stars: vec![StarEntity {
+                id: 7,
+                name: "Sun".to_string(),
+                spectral_class: "G2V".to_string(),
+                mass: decimal("1.989e30"),
+            }],
+            planets: vec![PlanetEntity {
+                id: 3,
+                name: "Earth".to_string(),
+                type_: PlanetType::TerrestrialPlanet,
+                uuid: Uuid::nil(),
+                star_id: Some(7),
+                status: "DRAFT".to_string(),
+            }],
+            details: vec![DetailsEntity {
+                id: 1,
+                mean_radius: decimal("6371000"),
+                mass: decimal("5.97e24"),
+                population: None,
+                planet_id: 3,
+            }],
+            orbits: vec![OrbitEntity {
+                planet_id: 3,
+                semi_major_axis: decimal("149598261150"),
+                eccentricity: decimal("0.01671123"),
+                orbital_period: decimal("31558118.4"),
+                longitude_of_perihelion: decimal("102.93768193"),
+                mean_longitude: decimal("100.46457166"),
+            }],
+            atmosphere_components: vec![GasComponentEntity {
+                planet_id: 3,
+                gas: "N2".to_string(),
+                percentage: decimal("78.08"),
+            }],
+        });
+        assert_eq!(3, records.len());
+        // snapshots keep the units of the schema
+        let SnapshotRecord::Planet(planet) = &records[2] else {
+            panic!("The last record should be a planet");
+        };
+        assert_eq!(decimal("6371"), planet.mean_radius);
+        let orbit = planet.orbit.as_ref().expect("Can't get orbit");
+        assert_eq!(decimal("1.00000261"), orbit.semi_major_axis);
+        assert_eq!(decimal("365.256"), orbit.orbital_period);
+
+        let (stars, planets) = from_records(records);
+        assert_eq!("Sun", stars[0].name);
+        assert_eq!(Some("Sun".to_string()), planets[0].star_name);
+        assert_eq!("DRAFT", planets[0].status);
+        assert_eq!(decimal("6371000"), planets[0].details.mean_radius);
+        let orbit = planets[0].orbit.as_ref().expect("Can't get orbit");
+        assert_eq!(decimal("149598261150"), orbit.semi_major_axis);
+        assert_eq!(decimal("31558118.4"), orbit.orbital_period);
+        assert_eq!("N2", planets[0].atmosphere[0].gas);
+    }
+
+    #[test]
+    fn invalid_snapshots() {
+        assert!(decode("not base64!").is_err());
+        assert_eq!(
+            Err("Snapshot doesn't start with a header".to_string()),
+            decode(&encode(&[]))
+        );
+        assert_eq!(
+            Err("Unsupported snapshot version 2".to_string()),
+            decode(&encode(&[SnapshotRecord::Header { version: 2 }]))
+        );
+    }
+
+    #[test]
+    fn decompressed_size_is_limited() {
+        let snapshot = encode(&[SnapshotRecord::Header { version: VERSION }]);
+        // `{"record":"header","version":1}` and a line break
+        assert!(decode_at_most(&snapshot, 32).is_ok());
+        assert_eq!(
+            Err("Snapshot exceeds 31 bytes when decompressed".to_string()),
+            decode_at_most(&snapshot, 31)
+        );
+    }
+}
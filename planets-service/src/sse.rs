@@ -0,0 +1,193 @@
+use std::time::Duration;
+
+use actix_web::http::header::CACHE_CONTROL;
+use actix_web::web::Bytes;
+use actix_web::HttpResponse;
+use async_graphql::parser::parse_query;
+use async_graphql::parser::types::Selection;
+use async_graphql::{Request, Response, ServerError, Value};
+use futures::future::{self, Either};
+use futures::{stream, StreamExt};
+use tokio::sync::oneshot;
+
+use crate::graphql::{AppSchema, ResumeFrom, RESUMABLE_SUBSCRIPTIONS};
+use crate::http_client::{self, RequestContext};
+use crate::subscription::{self, TOO_MANY_CONNECTIONS_MESSAGE};
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+const HEARTBEAT: &str = ":\n\n";
+const COMPLETE_EVENT: &str = "event: complete\ndata:\n\n";
+
+/// Executes a subscription as GraphQL over SSE in the distinct connections mode: each result
+/// is sent as a `next` event, then `complete` is sent once the subscription ends. Results that
+/// contain a token (e.g. of planet events) are sent with it as the event ID, so a client can resume;
+/// a reconnect with `Last-Event-ID` is rejected unless the operation can resume. Connections count
+/// towards the same limits as WebSocket ones: a connection evicted by newer ones of its principal
+/// gets an error before `complete`
+pub fn stream_response(
+    schema: &AppSchema,
+    request: Request,
+    principal: String,
+    context: RequestContext,
+    last_event_id: Option<&str>,
+) -> HttpResponse {
+    let request = match last_event_id {
+        Some(last_event_id) => match resume(request, last_event_id) {
+            Ok(request) => request,
+            Err(e) => return HttpResponse::BadRequest().body(e),
+        },
+        None => request,
+    };
+
+    let (close, mut closed) = oneshot::channel();
+    let registration = subscription::register_connection(principal, move || {
+        close.send(()).ok();
+    });
+    let responses = http_client::with_stream_context(context, schema.execute_stream(request));
+    let mut responses = subscription::limit_rate(responses).boxed_local();
+    let responses = async_stream::stream! {
+        // unregisters once the response ends
+        let _registration = registration;
+        loop {
+            match future::select(responses.next(), &mut closed).await {
+                Either::Left((Some(response), _)) => yield response,
+                Either::Left((None, _)) => break,
+                Either::Right(_) => {
+                    yield Response::from_errors(vec![ServerError::new(
+                        TOO_MANY_CONNECTIONS_MESSAGE,
+                        None,
+                    )]);
+                    break;
+                }
+            }
+        }
+    };
+
+    let events = responses
+        .map(|response| Some(format_next_event(&response)))
+        .chain(stream::once(future::ready(None)));
+    let heartbeats = stream::unfold((), |_| async {
+        tokio::time::sleep(HEARTBEAT_INTERVAL).await;
+        Some((Some(HEARTBEAT.to_string()), ()))
+    });
+    // heartbeats are sent until the results end
+    let body = stream::select(events, heartbeats)
+        .scan((), |_, message| future::ready(message))
+        .chain(stream::once(future::ready(COMPLETE_EVENT.to_string())))
+        .map(|message| Ok::<_, actix_web::Error>(Bytes::from(message)));
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((CACHE_CONTROL, "no-cache"))
+        .streaming(body)
+}
+
+/// Resumes the operation after the event whose token is `last_event_id`, if all root fields of
+/// the operation are [RESUMABLE_SUBSCRIPTIONS]
+fn resume(request: Request, last_event_id: &str) -> Result<Request, String> {
+    let token = last_event_id
+        .parse::<i64>()
+        .map_err(|_| "Last-Event-ID must be the token of an event".to_string())?;
+    let document = parse_query(&request.query).map_err(|e| e.to_string())?;
+    let operation = match &request.operation_name {
+        Some(name) => document
+            .operations
+            .iter()
+            .find(|(operation_name, _)| operation_name.map(|name| name.as_str()) == Some(name)),
+        None => document.operations.iter().next(),
+    };
+    let is_resumable = operation.is_some_and(|(_, operation)| {
+        operation
+            .node
+            .selection_set
+            .node
+            .items
+            .iter()
+            .all(|selection| match &selection.node {
+                Selection::Field(field) => {
+                    RESUMABLE_SUBSCRIPTIONS.contains(&field.node.name.node.as_str())
+                }
+                _ => false,
+            })
+    });
+    if !is_resumable {
+        return Err("The operation can't be resumed from Last-Event-ID".to_string());
+    }
+    Ok(request.data(ResumeFrom(token)))
+}
+
+fn format_next_event(response: &Response) -> String {
+    let data = serde_json::to_string(response).expect("Can't serialize a response");
+    match get_token(response) {
+        Some(token) => format!("id: {}\nevent: next\ndata: {}\n\n", token, data),
+        None => format!("event: next\ndata: {}\n\n", data),
+    }
+}
+
+/// `token` (or `lastToken` of a digest) of the subscription's root field
+fn get_token(response: &Response) -> Option<i64> {
+    let Value::Object(data) = &response.data else {
+        return None;
+    };
+    let Some(Value::Object(root_field)) = data.values().next() else {
+        return None;
+    };
+    match root_field
+        .get("token")
+        .or_else(|| root_field.get("lastToken"))
+    {
+        Some(Value::Number(token)) => token.as_i64(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_graphql::value;
+
+    use super::*;
+
+    #[test]
+    fn next_event_with_token() {
+        let response =
+            Response::new(value!({ "planetEvents": { "token": 42, "kind": "CREATED" } }));
+
+        assert_eq!(
+            "id: 42\nevent: next\ndata: {\"data\":{\"planetEvents\":{\"token\":42,\"kind\":\"CREATED\"}}}\n\n",
+            format_next_event(&response)
+        );
+    }
+
+    #[test]
+    fn next_event_of_digest() {
+        let response = Response::new(value!({ "planetEventDigests": { "lastToken": 42 } }));
+
+        assert!(format_next_event(&response).starts_with("id: 42\n"));
+    }
+
+    #[test]
+    fn next_event_without_token() {
+        let response = Response::new(value!({ "latestPlanet": { "name": "Earth" } }));
+
+        assert!(format_next_event(&response).starts_with("event: next\ndata: "));
+    }
+
+    #[test]
+    fn only_resumable_operations_are_resumed() {
+        let resume = |query: &str, last_event_id| resume(Request::new(query), last_event_id);
+
+        assert!(resume("subscription { planetEvents { token } }", "7").is_ok());
+        assert!(resume(
+            "subscription { planetEventDigests(batchIntervalSeconds: 1) { lastToken } }",
+            "7"
+        )
+        .is_ok());
+        assert!(resume("subscription { planetEvents { token } }", "last").is_err());
+        assert!(resume("subscription { latestPlanet { name } }", "7").is_err());
+        assert!(resume(
+            "subscription { planetEvents { token } latestPlanet { name } }",
+            "7"
+        )
+        .is_err());
+    }
+}
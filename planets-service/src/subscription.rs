@@ -0,0 +1,390 @@
+use std::collections::{HashMap, VecDeque};
+use std::env;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use actix::{
+    Actor, ActorContext, ActorFutureExt, ActorStreamExt, AsyncContext, ContextFutureSpawner,
+    Handler, Message as ActixMessage, Recipient, StreamHandler, WrapFuture, WrapStream,
+};
+use actix_http::ws::Item;
+use actix_web::{HttpRequest, HttpResponse};
+use actix_web_actors::ws::{
+    self, CloseCode, CloseReason, Message, ProtocolError, WebsocketContext,
+};
+use async_graphql::http::{WebSocket, WebSocketProtocols, WsMessage, ALL_WEBSOCKET_PROTOCOLS};
+use async_graphql::Data;
+use futures::future::{self, Either};
+use futures::{Stream, StreamExt};
+use lazy_static::lazy_static;
+use tokio::sync::mpsc;
+
+use crate::graphql::AppSchema;
+use crate::http;
+use crate::http_client::{self, RequestContext};
+use crate::masking::ApiKey;
+
+/// Sent to a connection closed because its principal opened too many of them
+pub const TOO_MANY_CONNECTIONS_CLOSE_CODE: u16 = 4429;
+pub const TOO_MANY_CONNECTIONS_MESSAGE: &str = "Too many subscription connections";
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+lazy_static! {
+    static ref SUBSCRIPTION_LIMITS: SubscriptionLimits = SubscriptionLimits {
+        max_connections_per_principal: get_limit("MAX_SUBSCRIPTION_CONNECTIONS_PER_PRINCIPAL")
+            .unwrap_or(10),
+        max_events_per_second: get_limit("MAX_SUBSCRIPTION_EVENTS_PER_SECOND").unwrap_or(100),
+    };
+    static ref CONNECTIONS: Mutex<ConnectionRegistry<Box<dyn FnOnce() + Send>>> =
+        Mutex::new(ConnectionRegistry::default());
+}
+
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(0);
+
+struct SubscriptionLimits {
+    max_connections_per_principal: usize,
+    /// Per connection; events over the limit are delayed, and left in the broker once a second's
+    /// worth of them is waiting
+    max_events_per_second: u32,
+}
+
+fn get_limit<T: FromStr>(name: &str) -> Option<T> {
+    env::var(name).ok().map(|value| {
+        value
+            .parse()
+            .unwrap_or_else(|_| panic!("Can't parse {}", name))
+    })
+}
+
+/// Open connections of each principal from the oldest to the newest
+struct ConnectionRegistry<T> {
+    connections: HashMap<String, VecDeque<(u64, T)>>,
+}
+
+impl<T> Default for ConnectionRegistry<T> {
+    fn default() -> Self {
+        Self {
+            connections: HashMap::new(),
+        }
+    }
+}
+
+impl<T> ConnectionRegistry<T> {
+    /// Returns the principal's oldest connections that exceed the limit
+    fn register(&mut self, principal: &str, id: u64, connection: T, limit: usize) -> Vec<T> {
+        let connections = self.connections.entry(principal.to_string()).or_default();
+        connections.push_back((id, connection));
+        let excess = connections.len().saturating_sub(limit);
+        connections
+            .drain(..excess)
+            .map(|(_, connection)| connection)
+            .collect()
+    }
+
+    fn unregister(&mut self, principal: &str, id: u64) {
+        if let Some(connections) = self.connections.get_mut(principal) {
+            connections.retain(|(connection_id, _)| *connection_id != id);
+            if connections.is_empty() {
+                self.connections.remove(principal);
+            }
+        }
+    }
+}
+
+/// A subscription connection (WebSocket or SSE) counted towards the limit of its principal until
+/// dropped
+pub struct ConnectionRegistration {
+    principal: String,
+    id: u64,
+}
+
+impl Drop for ConnectionRegistration {
+    fn drop(&mut self) {
+        CONNECTIONS
+            .lock()
+            .expect("Can't lock connections")
+            .unregister(&self.principal, self.id);
+    }
+}
+
+/// Registers a connection of the principal; `close` is called once the principal opens more than
+/// `MAX_SUBSCRIPTION_CONNECTIONS_PER_PRINCIPAL` newer connections
+pub fn register_connection(
+    principal: String,
+    close: impl FnOnce() + Send + 'static,
+) -> ConnectionRegistration {
+    let id = NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed);
+    let evicted_connections = CONNECTIONS
+        .lock()
+        .expect("Can't lock connections")
+        .register(
+            &principal,
+            id,
+            Box::new(close),
+            SUBSCRIPTION_LIMITS.max_connections_per_principal,
+        );
+    for close in evicted_connections {
+        close();
+    }
+    ConnectionRegistration { principal, id }
+}
+
+/// Throttles results of subscriptions of a connection to `MAX_SUBSCRIPTION_EVENTS_PER_SECOND`
+pub fn limit_rate<T: 'static>(messages: impl Stream<Item = T> + 'static) -> impl Stream<Item = T> {
+    throttle(messages, SUBSCRIPTION_LIMITS.max_events_per_second)
+}
+
+/// Starts a WebSocket connection serving subscriptions (`graphql-ws` and `graphql-transport-ws`
+/// protocols) which is closed with [TOO_MANY_CONNECTIONS_CLOSE_CODE] when its principal opens
+/// more than `MAX_SUBSCRIPTION_CONNECTIONS_PER_PRINCIPAL` newer connections
+pub fn start_connection(
+    schema: AppSchema,
+    principal: String,
+    request: &HttpRequest,
+    payload: actix_web::web::Payload,
+) -> actix_web::Result<HttpResponse> {
+    let protocol = request
+        .headers()
+        .get("sec-websocket-protocol")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|protocols| {
+            protocols
+                .split(',')
+                .find_map(|protocol| WebSocketProtocols::from_str(protocol.trim()).ok())
+        })
+        .ok_or_else(|| actix_web::error::ErrorBadRequest("Unsupported WebSocket protocol"))?;
+
+    let connection = SubscriptionConnection {
+        principal,
+        context: http::get_request_context(request),
+        registration: None,
+        role: request
+            .headers()
+            .get("role")
+            .and_then(|value| value.to_str().ok())
+            .map(String::from),
+        api_key: request
+            .headers()
+            .get("x-api-key")
+            .and_then(|value| value.to_str().ok())
+            .map(String::from),
+        schema,
+        protocol,
+        last_heartbeat: Instant::now(),
+        messages: None,
+        continuation: Vec::new(),
+    };
+
+    ws::WsResponseBuilder::new(connection, request, payload)
+        .protocols(&ALL_WEBSOCKET_PROTOCOLS)
+        .start()
+}
+
+#[derive(ActixMessage)]
+#[rtype(result = "()")]
+struct CloseConnection;
+
+struct SubscriptionConnection {
+    principal: String,
+    registration: Option<ConnectionRegistration>,
+    /// Value of the header, an invalid one is reported by operations which check the role
+    role: Option<String>,
+    /// Selects the masking profile of the subscriptions
+    api_key: Option<String>,
+    /// Of the upgrade request; operations of the connection join its trace
+    context: RequestContext,
+    schema: AppSchema,
+    protocol: WebSocketProtocols,
+    last_heartbeat: Instant,
+    messages: Option<async_channel::Sender<Vec<u8>>>,
+    continuation: Vec<u8>,
+}
+
+impl Actor for SubscriptionConnection {
+    type Context = WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        let connection: Recipient<CloseConnection> = ctx.address().recipient();
+        self.registration = Some(register_connection(self.principal.clone(), move || {
+            connection.do_send(CloseConnection)
+        }));
+
+        ctx.run_interval(HEARTBEAT_INTERVAL, |connection, ctx| {
+            if Instant::now().duration_since(connection.last_heartbeat) > CLIENT_TIMEOUT {
+                ctx.stop();
+            }
+            ctx.ping(b"");
+        });
+
+        let (tx, rx) = async_channel::unbounded();
+        // subscriptions filter events by the role
+        let mut data = Data::default();
+        data.insert(common_utils::parse_role(self.role.as_deref()));
+        if let Some(api_key) = &self.api_key {
+            data.insert(ApiKey(api_key.clone()));
+        }
+        let messages = WebSocket::new(self.schema.clone(), rx, self.protocol).connection_data(data);
+        let messages = http_client::with_stream_context(self.context.clone(), messages);
+
+        limit_rate(messages)
+            .into_actor(self)
+            .map(|message, _connection, ctx| match message {
+                WsMessage::Text(text) => ctx.text(text),
+                WsMessage::Close(code, description) => ctx.close(Some(CloseReason {
+                    code: code.into(),
+                    description: Some(description),
+                })),
+            })
+            .finish()
+            .spawn(ctx);
+
+        self.messages = Some(tx);
+    }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        self.registration.take();
+    }
+}
+
+impl Handler<CloseConnection> for SubscriptionConnection {
+    type Result = ();
+
+    fn handle(&mut self, _message: CloseConnection, ctx: &mut Self::Context) {
+        ctx.close(Some(CloseReason {
+            code: CloseCode::Other(TOO_MANY_CONNECTIONS_CLOSE_CODE),
+            description: Some(TOO_MANY_CONNECTIONS_MESSAGE.to_string()),
+        }));
+        ctx.stop();
+    }
+}
+
+impl StreamHandler<Result<Message, ProtocolError>> for SubscriptionConnection {
+    fn handle(&mut self, message: Result<Message, ProtocolError>, ctx: &mut Self::Context) {
+        let message = match message {
+            Ok(message) => message,
+            Err(_) => {
+                ctx.stop();
+                return;
+            }
+        };
+
+        let message = match message {
+            Message::Ping(bytes) => {
+                self.last_heartbeat = Instant::now();
+                ctx.pong(&bytes);
+                None
+            }
+            Message::Pong(_) => {
+                self.last_heartbeat = Instant::now();
+                None
+            }
+            Message::Continuation(item) => match item {
+                Item::FirstText(bytes) | Item::FirstBinary(bytes) => {
+                    self.continuation = bytes.to_vec();
+                    None
+                }
+                Item::Continue(bytes) => {
+                    self.continuation.extend_from_slice(&bytes);
+                    None
+                }
+                Item::Last(bytes) => {
+                    self.continuation.extend_from_slice(&bytes);
+                    Some(std::mem::take(&mut self.continuation))
+                }
+            },
+            Message::Text(text) => Some(text.into_bytes().to_vec()),
+            Message::Binary(bytes) => Some(bytes.to_vec()),
+            Message::Close(_) => {
+                ctx.stop();
+                None
+            }
+            Message::Nop => None,
+        };
+
+        if let Some(message) = message {
+            let sender = self.messages.as_ref().expect("Can't get sender").clone();
+            async move { sender.send(message).await }
+                .into_actor(self)
+                .map(|result, _connection, ctx| {
+                    if result.is_err() {
+                        ctx.stop();
+                    }
+                })
+                .spawn(ctx);
+        }
+    }
+}
+
+/// Delays messages so that no more than `max_per_second` are sent. Up to a second's worth of
+/// messages wait to be sent; once there are more, `messages` isn't read until some are sent, so
+/// that a subscription producing faster than the limit can't make memory grow unboundedly. Events
+/// then wait in the broker, which tells a subscriber falling too far behind how many it skipped
+fn throttle<T: 'static>(
+    messages: impl Stream<Item = T> + 'static,
+    max_per_second: u32,
+) -> impl Stream<Item = T> {
+    let max_per_second = max_per_second.max(1);
+    let (sender, mut receiver) = mpsc::channel(max_per_second as usize);
+    // messages are read while the previous ones wait, so that bursts don't wait for the reading
+    actix_rt::spawn(async move {
+        futures::pin_mut!(messages);
+        loop {
+            let message = {
+                let closed = sender.closed();
+                futures::pin_mut!(closed);
+                match future::select(messages.next(), closed).await {
+                    Either::Left((Some(message), _)) => message,
+                    _ => break,
+                }
+            };
+            if sender.send(message).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let interval = Duration::from_secs(1) / max_per_second;
+    async_stream::stream! {
+        let mut next_slot = Instant::now();
+        while let Some(message) = receiver.recv().await {
+            let now = Instant::now();
+            if next_slot > now {
+                actix_rt::time::sleep(next_slot - now).await;
+            }
+            next_slot = next_slot.max(now) + interval;
+            yield message;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_oldest_connections_are_evicted() {
+        let mut registry = ConnectionRegistry::default();
+
+        assert!(registry.register("key:a", 1, "a1", 2).is_empty());
+        assert!(registry.register("key:a", 2, "a2", 2).is_empty());
+        assert!(registry.register("key:b", 3, "b1", 2).is_empty());
+        assert_eq!(vec!["a1"], registry.register("key:a", 4, "a3", 2));
+
+        registry.unregister("key:a", 2);
+        assert!(registry.register("key:a", 5, "a4", 2).is_empty());
+        assert_eq!(vec!["a3"], registry.register("key:a", 6, "a5", 2));
+    }
+
+    #[actix_rt::test]
+    async fn test_excess_messages_wait() {
+        let messages = futures::stream::iter(0..5);
+
+        let sent: Vec<i32> = throttle(messages, 2).collect().await;
+
+        assert_eq!(vec![0, 1, 2, 3, 4], sent);
+    }
+}
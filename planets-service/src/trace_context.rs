@@ -0,0 +1,128 @@
+//! W3C Trace Context (https://www.w3.org/TR/trace-context/): the service joins the trace of
+//! the caller (e.g. the gateway) as a span of its own, so that its logs and outbound calls belong
+//! to the same distributed trace
+use std::fmt;
+
+const VERSION: &str = "00";
+const SAMPLED_FLAG: u8 = 0x01;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceContext {
+    /// 32 lowercase hex digits
+    pub trace_id: String,
+    /// 16 lowercase hex digits; the ID of the span of the service
+    pub span_id: String,
+    pub flags: u8,
+}
+
+impl TraceContext {
+    /// A span of the trace of the `traceparent` header, or of a new trace if the header is missing
+    /// or invalid
+    pub fn continue_from(traceparent: Option<&str>) -> Self {
+        match traceparent.and_then(parse) {
+            Some(parent) => TraceContext {
+                span_id: new_span_id(),
+                ..parent
+            },
+            None => TraceContext {
+                trace_id: format!("{:032x}", rand::random::<u128>().max(1)),
+                span_id: new_span_id(),
+                flags: SAMPLED_FLAG,
+            },
+        }
+    }
+}
+
+/// The `traceparent` header for calls made within the span
+impl fmt::Display for TraceContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}-{}-{}-{:02x}",
+            VERSION, self.trace_id, self.span_id, self.flags
+        )
+    }
+}
+
+fn new_span_id() -> String {
+    format!("{:016x}", rand::random::<u64>().max(1))
+}
+
+/// The trace and the flags of a `traceparent` header; the span is the caller's one. Fields of
+/// future versions after the known ones are ignored
+fn parse(traceparent: &str) -> Option<TraceContext> {
+    let mut parts = traceparent.trim().split('-');
+    let version = parts.next()?;
+    let trace_id = parts.next()?;
+    let parent_id = parts.next()?;
+    let flags = parts.next()?;
+    let is_hex = |value: &str, len: usize| {
+        value.len() == len
+            && value
+                .bytes()
+                .all(|c| matches!(c, b'0'..=b'9' | b'a'..=b'f'))
+    };
+    let is_valid_id = |id: &str, len: usize| is_hex(id, len) && id.bytes().any(|c| c != b'0');
+    if !is_hex(version, 2)
+        || version == "ff"
+        || (version == VERSION && parts.next().is_some())
+        || !is_valid_id(trace_id, 32)
+        || !is_valid_id(parent_id, 16)
+        || !is_hex(flags, 2)
+    {
+        return None;
+    }
+    Some(TraceContext {
+        trace_id: trace_id.to_string(),
+        span_id: parent_id.to_string(),
+        flags: u8::from_str_radix(flags, 16).ok()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn caller_trace_is_continued() {
+        let context = TraceContext::continue_from(Some(TRACEPARENT));
+        assert_eq!("4bf92f3577b34da6a3ce929d0e0e4736", context.trace_id);
+        assert_ne!("00f067aa0ba902b7", context.span_id);
+        assert_eq!(16, context.span_id.len());
+        assert_eq!(
+            format!("00-4bf92f3577b34da6a3ce929d0e0e4736-{}-01", context.span_id),
+            context.to_string()
+        );
+    }
+
+    #[test]
+    fn new_trace_is_started_without_valid_header() {
+        for traceparent in [
+            None,
+            Some("00-00000000000000000000000000000000-00f067aa0ba902b7-01"),
+            Some("00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01"),
+            Some("00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01"),
+            Some("ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"),
+            Some("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra"),
+            Some("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7"),
+            Some("garbage"),
+        ] {
+            let context = TraceContext::continue_from(traceparent);
+            assert_ne!("4bf92f3577b34da6a3ce929d0e0e4736", context.trace_id);
+            assert_eq!(32, context.trace_id.len());
+            assert_eq!(SAMPLED_FLAG, context.flags);
+        }
+    }
+
+    #[test]
+    fn future_versions_are_accepted() {
+        let context = TraceContext::continue_from(Some(
+            "01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00-extra",
+        ));
+        assert_eq!("4bf92f3577b34da6a3ce929d0e0e4736", context.trace_id);
+        assert_eq!(0, context.flags);
+        assert!(context.to_string().starts_with("00-"));
+    }
+}
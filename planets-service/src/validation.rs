@@ -0,0 +1,504 @@
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fmt::{self, Display, Formatter};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use async_graphql::extensions::{
+    Extension, ExtensionContext, ExtensionFactory, NextExecute, NextParseQuery, NextPrepareRequest,
+    NextRequest, NextValidation,
+};
+use async_graphql::indexmap::IndexMap;
+use async_graphql::parser::types::{
+    DocumentOperations, ExecutableDocument, OperationDefinition, Selection, SelectionSet,
+};
+use async_graphql::{
+    value, Error, ErrorExtensions, Name, Positioned, Request, Response, ServerError, ServerResult,
+    ValidationResult, Value, Variables,
+};
+use futures::lock::Mutex;
+use lazy_static::lazy_static;
+
+lazy_static! {
+    pub static ref QUERY_LIMITS: QueryLimits = QueryLimits {
+        max_complexity: get_limit("MAX_QUERY_COMPLEXITY"),
+        max_depth: get_limit("MAX_QUERY_DEPTH"),
+        max_aliases: get_limit("MAX_QUERY_ALIASES").unwrap_or(30),
+        max_root_fields: get_limit("MAX_QUERY_ROOT_FIELDS").unwrap_or(20),
+        max_directives: get_limit("MAX_QUERY_DIRECTIVES").unwrap_or(50),
+    };
+}
+
+/// Server's budget for a single operation. Complexity and depth limits are not set by default,
+/// because otherwise the introspection query won't work
+pub struct QueryLimits {
+    pub max_complexity: Option<usize>,
+    pub max_depth: Option<usize>,
+    pub max_aliases: usize,
+    pub max_root_fields: usize,
+    pub max_directives: usize,
+}
+
+fn get_limit(name: &str) -> Option<usize> {
+    env::var(name)
+        .ok()
+        .map(|value| value.parse().expect("Can't parse query limit"))
+}
+
+/// Marks a request that should only be parsed and validated
+pub struct ValidateOnly;
+
+/// Adds a `validation` entry with the operation's complexity, depth, and normalized text to
+/// responses of requests marked with [ValidateOnly]; such requests are never executed. Complexity
+/// and depth are omitted if they aren't computed, i.e. the operation fails the validation rules
+pub struct Validator;
+
+impl ExtensionFactory for Validator {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(ValidatorExtension::default())
+    }
+}
+
+#[derive(Default)]
+struct ValidatorExtension {
+    validate_only: AtomicBool,
+    normalized_query: Mutex<Option<String>>,
+    validation_result: Mutex<Option<ValidationResult>>,
+}
+
+#[async_trait::async_trait]
+impl Extension for ValidatorExtension {
+    async fn request(&self, ctx: &ExtensionContext<'_>, next: NextRequest<'_>) -> Response {
+        let response = next.run(ctx).await;
+        if !self.validate_only.load(Ordering::Relaxed) {
+            return response;
+        }
+
+        let valid = response.errors.is_empty();
+        let normalized_query = self.normalized_query.lock().await.take();
+        let mut validation = IndexMap::new();
+        validation.insert(Name::new("valid"), Value::from(valid));
+        if let Some(result) = self.validation_result.lock().await.take() {
+            validation.insert(Name::new("complexity"), Value::from(result.complexity));
+            validation.insert(Name::new("depth"), Value::from(result.depth));
+        }
+        validation.insert(
+            Name::new("maxComplexity"),
+            value!(QUERY_LIMITS.max_complexity),
+        );
+        validation.insert(Name::new("maxDepth"), value!(QUERY_LIMITS.max_depth));
+        validation.insert(Name::new("normalizedQuery"), value!(normalized_query));
+
+        response.extension("validation", Value::Object(validation))
+    }
+
+    async fn prepare_request(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        request: Request,
+        next: NextPrepareRequest<'_>,
+    ) -> ServerResult<Request> {
+        // request data isn't available in the `request` hook yet
+        let validate_only = ctx.data_opt::<ValidateOnly>().is_some();
+        self.validate_only.store(validate_only, Ordering::Relaxed);
+        next.run(ctx, request).await
+    }
+
+    async fn parse_query(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        query: &str,
+        variables: &Variables,
+        next: NextParseQuery<'_>,
+    ) -> ServerResult<ExecutableDocument> {
+        let document = next.run(ctx, query, variables).await?;
+        if self.validate_only.load(Ordering::Relaxed) {
+            *self.normalized_query.lock().await =
+                Some(ctx.stringify_execute_doc(&document, variables));
+        }
+        Ok(document)
+    }
+
+    async fn validation(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        next: NextValidation<'_>,
+    ) -> Result<ValidationResult, Vec<ServerError>> {
+        let result = next.run(ctx).await?;
+        *self.validation_result.lock().await = Some(result);
+        Ok(result)
+    }
+
+    async fn execute(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        operation_name: Option<&str>,
+        next: NextExecute<'_>,
+    ) -> Response {
+        if self.validate_only.load(Ordering::Relaxed) {
+            Response::new(Value::Null)
+        } else {
+            next.run(ctx, operation_name).await
+        }
+    }
+}
+
+/// Rejects operations with too many aliases, root fields, or directives. Such operations are cheap
+/// in terms of depth and complexity, but each alias or root field is resolved separately. Only the
+/// operation selected by `operationName` is checked, since the others aren't executed
+pub struct OperationLimiter;
+
+impl ExtensionFactory for OperationLimiter {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(OperationLimiterExtension::default())
+    }
+}
+
+#[derive(Default)]
+struct OperationLimiterExtension {
+    operation_name: Mutex<Option<String>>,
+}
+
+#[async_trait::async_trait]
+impl Extension for OperationLimiterExtension {
+    async fn prepare_request(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        request: Request,
+        next: NextPrepareRequest<'_>,
+    ) -> ServerResult<Request> {
+        *self.operation_name.lock().await = request.operation_name.clone();
+        next.run(ctx, request).await
+    }
+
+    async fn parse_query(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        query: &str,
+        variables: &Variables,
+        next: NextParseQuery<'_>,
+    ) -> ServerResult<ExecutableDocument> {
+        let document = next.run(ctx, query, variables).await?;
+
+        let operation_name = self.operation_name.lock().await.clone();
+        // an unknown or missing operation name is reported by the execution itself
+        if let Some(operation) = select_operation(&document, operation_name.as_deref()) {
+            check_operation_limits(&document, &operation.node, &QUERY_LIMITS)
+                .map_err(|e| e.extend().into_server_error(operation.pos))?;
+        }
+
+        Ok(document)
+    }
+}
+
+/// The operation which is executed for the name, selected the same way as by the executor
+fn select_operation<'a>(
+    document: &'a ExecutableDocument,
+    operation_name: Option<&str>,
+) -> Option<&'a Positioned<OperationDefinition>> {
+    match (&document.operations, operation_name) {
+        (DocumentOperations::Single(operation), None) => Some(operation),
+        (DocumentOperations::Single(_), Some(_)) => None,
+        (DocumentOperations::Multiple(operations), Some(name)) => operations.get(name),
+        (DocumentOperations::Multiple(operations), None) if operations.len() == 1 => {
+            operations.values().next()
+        }
+        (DocumentOperations::Multiple(_), None) => None,
+    }
+}
+
+/// An operation limit that was exceeded
+#[derive(Debug, PartialEq)]
+pub enum OperationLimitError {
+    Aliases { count: usize, limit: usize },
+    RootFields { count: usize, limit: usize },
+    Directives { count: usize, limit: usize },
+}
+
+impl Display for OperationLimitError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            OperationLimitError::Aliases { count, limit } => {
+                write!(f, "Operation has {} aliases, the limit is {}", count, limit)
+            }
+            OperationLimitError::RootFields { count, limit } => {
+                write!(
+                    f,
+                    "Operation has {} root fields, the limit is {}",
+                    count, limit
+                )
+            }
+            OperationLimitError::Directives { count, limit } => {
+                write!(
+                    f,
+                    "Operation has {} directives, the limit is {}",
+                    count, limit
+                )
+            }
+        }
+    }
+}
+
+impl ErrorExtensions for OperationLimitError {
+    fn extend(&self) -> Error {
+        let (code, count, limit) = match self {
+            OperationLimitError::Aliases { count, limit } => ("TOO_MANY_ALIASES", count, limit),
+            OperationLimitError::RootFields { count, limit } => {
+                ("TOO_MANY_ROOT_FIELDS", count, limit)
+            }
+            OperationLimitError::Directives { count, limit } => {
+                ("TOO_MANY_DIRECTIVES", count, limit)
+            }
+        };
+
+        Error::new(self.to_string()).extend_with(|_, e| {
+            e.set("code", code);
+            e.set("count", *count);
+            e.set("limit", *limit);
+        })
+    }
+}
+
+pub fn check_operation_limits(
+    document: &ExecutableDocument,
+    operation: &OperationDefinition,
+    limits: &QueryLimits,
+) -> Result<(), OperationLimitError> {
+    let stats = SelectionStats::of_operation(document, operation);
+
+    if stats.fields > limits.max_root_fields {
+        return Err(OperationLimitError::RootFields {
+            count: stats.fields,
+            limit: limits.max_root_fields,
+        });
+    }
+    if stats.aliases > limits.max_aliases {
+        return Err(OperationLimitError::Aliases {
+            count: stats.aliases,
+            limit: limits.max_aliases,
+        });
+    }
+    if stats.directives > limits.max_directives {
+        return Err(OperationLimitError::Directives {
+            count: stats.directives,
+            limit: limits.max_directives,
+        });
+    }
+
+    Ok(())
+}
+
+/// Counts of a selection set; `fields` are counted only on its top level. Fragments are counted
+/// each time they are spread, so nested spreads multiply
+#[derive(Clone, Copy, Default)]
+struct SelectionStats {
+    fields: usize,
+    aliases: usize,
+    directives: usize,
+}
+
+impl SelectionStats {
+    fn of_operation(document: &ExecutableDocument, operation: &OperationDefinition) -> Self {
+        let mut stats = Self::of_selection_set(
+            document,
+            &operation.selection_set.node,
+            &mut HashMap::new(),
+            &mut HashSet::new(),
+        );
+        stats.directives = stats
+            .directives
+            .saturating_add(operation.directives.len())
+            .saturating_add(
+                operation
+                    .variable_definitions
+                    .iter()
+                    .map(|definition| definition.node.directives.len())
+                    .sum(),
+            );
+        stats
+    }
+
+    // statistics of each fragment are computed once, so deeply nested spreads can't make this slow
+    fn of_selection_set<'a>(
+        document: &'a ExecutableDocument,
+        selection_set: &'a SelectionSet,
+        fragments: &mut HashMap<&'a Name, SelectionStats>,
+        visiting: &mut HashSet<&'a Name>,
+    ) -> Self {
+        let mut stats = SelectionStats::default();
+
+        for selection in &selection_set.items {
+            match &selection.node {
+                Selection::Field(field) => {
+                    let nested = Self::of_selection_set(
+                        document,
+                        &field.node.selection_set.node,
+                        fragments,
+                        visiting,
+                    );
+                    stats.fields = stats.fields.saturating_add(1);
+                    stats.aliases = stats
+                        .aliases
+                        .saturating_add(field.node.alias.is_some() as usize)
+                        .saturating_add(nested.aliases);
+                    stats.directives = stats
+                        .directives
+                        .saturating_add(field.node.directives.len())
+                        .saturating_add(nested.directives);
+                }
+                Selection::InlineFragment(fragment) => {
+                    let nested = Self::of_selection_set(
+                        document,
+                        &fragment.node.selection_set.node,
+                        fragments,
+                        visiting,
+                    );
+                    stats.add(&nested);
+                    stats.directives = stats
+                        .directives
+                        .saturating_add(fragment.node.directives.len());
+                }
+                Selection::FragmentSpread(spread) => {
+                    let name = &spread.node.fragment_name.node;
+                    stats.directives = stats
+                        .directives
+                        .saturating_add(spread.node.directives.len());
+                    // cycles are reported by the validation itself
+                    if visiting.contains(name) {
+                        continue;
+                    }
+                    let nested = match fragments.get(name) {
+                        Some(nested) => *nested,
+                        None => match document.fragments.get(name) {
+                            Some(fragment) => {
+                                visiting.insert(name);
+                                let mut nested = Self::of_selection_set(
+                                    document,
+                                    &fragment.node.selection_set.node,
+                                    fragments,
+                                    visiting,
+                                );
+                                visiting.remove(name);
+                                nested.directives = nested
+                                    .directives
+                                    .saturating_add(fragment.node.directives.len());
+                                fragments.insert(name, nested);
+                                nested
+                            }
+                            None => continue,
+                        },
+                    };
+                    stats.add(&nested);
+                }
+            }
+        }
+
+        stats
+    }
+
+    fn add(&mut self, other: &SelectionStats) {
+        self.fields = self.fields.saturating_add(other.fields);
+        self.aliases = self.aliases.saturating_add(other.aliases);
+        self.directives = self.directives.saturating_add(other.directives);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_graphql::parser::parse_query;
+
+    use super::*;
+
+    const LIMITS: QueryLimits = QueryLimits {
+        max_complexity: None,
+        max_depth: None,
+        max_aliases: 3,
+        max_root_fields: 2,
+        max_directives: 2,
+    };
+
+    fn check(query: &str) -> Result<(), OperationLimitError> {
+        let document = parse_query(query).expect("Can't parse query");
+        let (_, operation) = document
+            .operations
+            .iter()
+            .next()
+            .expect("Can't get operation");
+        check_operation_limits(&document, &operation.node, &LIMITS)
+    }
+
+    #[test]
+    fn operation_within_limits() {
+        assert_eq!(
+            Ok(()),
+            check("{ a: getPlanets { b: name c: type } getPlanet(id: 1) { id } }")
+        );
+    }
+
+    #[test]
+    fn too_many_root_fields() {
+        assert_eq!(
+            Err(OperationLimitError::RootFields { count: 3, limit: 2 }),
+            check("{ getPlanets { id } ... on Query { getPlanet(id: 1) { id } } ...root } fragment root on Query { getPlanets { name } }")
+        );
+    }
+
+    #[test]
+    fn aliases_in_fragments_are_counted_per_spread() {
+        assert_eq!(
+            Err(OperationLimitError::Aliases { count: 4, limit: 3 }),
+            check(
+                "{ getPlanets { ...names ...names } } fragment names on Planet { a: name b: name }"
+            )
+        );
+    }
+
+    #[test]
+    fn too_many_directives() {
+        assert_eq!(
+            Err(OperationLimitError::Directives { count: 3, limit: 2 }),
+            check("query($skip: Boolean!) @a { getPlanets @skip(if: $skip) { name @include(if: true) } }")
+        );
+    }
+
+    #[test]
+    fn only_selected_operation_is_checked() {
+        let document = parse_query(
+            "query Small { getPlanets { id } } query Large { a: getPlanets { b: id c: name d: type } }",
+        )
+        .expect("Can't parse query");
+        let check_named = |name| {
+            let operation = select_operation(&document, Some(name)).expect("Can't get operation");
+            check_operation_limits(&document, &operation.node, &LIMITS)
+        };
+
+        assert_eq!(Ok(()), check_named("Small"));
+        assert_eq!(
+            Err(OperationLimitError::Aliases { count: 4, limit: 3 }),
+            check_named("Large")
+        );
+        assert!(select_operation(&document, None).is_none());
+    }
+
+    #[test]
+    fn fragment_bomb_is_counted_without_expanding() {
+        let mut query = "{ getPlanets { ...f0 } } fragment f0 on Planet { a: name }".to_string();
+        for index in 1..70 {
+            query += &format!(
+                " fragment f{} on Planet {{ ...f{} ...f{} }}",
+                index,
+                index - 1,
+                index - 1
+            );
+        }
+        query = query.replacen("...f0 }", "...f69 }", 1);
+
+        assert_eq!(
+            Err(OperationLimitError::Aliases {
+                count: usize::MAX,
+                limit: 3
+            }),
+            check(&query)
+        );
+    }
+}
@@ -0,0 +1,268 @@
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use diesel::{PgConnection, QueryResult};
+use futures::future;
+use hmac::{Hmac, Mac};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::Client;
+use serde_json::json;
+use sha2::Sha256;
+
+use common_utils::ids;
+
+use crate::graphql;
+use crate::http_client::{self, RequestContext, RetryPolicy};
+use crate::persistence::connection::ReloadablePool;
+use crate::persistence::model::{
+    DeliveryStatus, OutboxEventEntity, PlanetEntity, WebhookDeliveryAttempt, WebhookDeliveryEntity,
+    WebhookEntity,
+};
+use crate::persistence::repository::{self, WebhookDeliveryTask};
+use crate::trace_context::TraceContext;
+
+/// Contains `sha256=` followed by the hex-encoded HMAC of the body keyed by the webhook's secret
+pub const SIGNATURE_HEADER: &str = "x-webhook-signature";
+
+const CLOUD_EVENTS_CONTENT_TYPE: &str = "application/cloudevents+json";
+const CLOUD_EVENT_SOURCE: &str = "/planets-service";
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+const BATCH_SIZE: i64 = 50;
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_ATTEMPTS: i32 = 8;
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(10);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60 * 60);
+
+type WorkerResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+/// Delivers planet events to the registered webhooks until the process stops
+pub async fn run(pool: Arc<ReloadablePool>) {
+    loop {
+        if let Err(e) = process(&pool, http_client::client()).await {
+            println!("Can't deliver webhooks: {}", e);
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// A single pass of the worker: creates deliveries of new events and makes the due attempts.
+/// Returns the number of attempts made
+pub async fn process(pool: &Arc<ReloadablePool>, client: &Client) -> WorkerResult<usize> {
+    let lease_until = Utc::now() + chrono::Duration::from_std(DELIVERY_TIMEOUT * 2)?;
+    let tasks = run_blocking(pool, move |conn| {
+        repository::enqueue_webhook_deliveries(conn)?;
+        repository::claim_due_webhook_deliveries(BATCH_SIZE, lease_until, conn)
+    })
+    .await?;
+
+    let attempts = future::join_all(tasks.into_iter().map(
+        |(delivery, webhook, event, created_at): WebhookDeliveryTask| async move {
+            // the delivery joins the trace of the request which caused the event
+            let context = RequestContext {
+                request_id: None,
+                trace: event
+                    .traceparent
+                    .as_deref()
+                    .map(|traceparent| TraceContext::continue_from(Some(traceparent))),
+            };
+            let result =
+                http_client::with_context(context, send(client, &webhook, &event, created_at))
+                    .await;
+            (delivery.id, get_attempt(&delivery, result, Utc::now()))
+        },
+    ))
+    .await;
+
+    let count = attempts.len();
+    run_blocking(pool, move |conn| {
+        for (id, attempt) in &attempts {
+            repository::record_webhook_delivery_attempt(*id, attempt, conn)?;
+        }
+        Ok(())
+    })
+    .await?;
+    Ok(count)
+}
+
+async fn run_blocking<T, F>(pool: &Arc<ReloadablePool>, f: F) -> WorkerResult<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&mut PgConnection) -> QueryResult<T> + Send + 'static,
+{
+    let pool = Arc::clone(pool);
+    tokio::task::spawn_blocking(move || {
+        let mut conn = pool.get()?;
+        Ok(f(&mut conn)?)
+    })
+    .await?
+}
+
+async fn send(
+    client: &Client,
+    webhook: &WebhookEntity,
+    event: &OutboxEventEntity,
+    created_at: DateTime<Utc>,
+) -> Result<(), String> {
+    let body = to_cloud_event(event, created_at)?.to_string();
+    let request = client
+        .post(&webhook.url)
+        .timeout(DELIVERY_TIMEOUT)
+        .header(CONTENT_TYPE, CLOUD_EVENTS_CONTENT_TYPE)
+        .header(SIGNATURE_HEADER, sign(&webhook.secret, body.as_bytes()))
+        .body(body);
+    // failed deliveries are retried with a longer backoff, see get_retry_delay
+    http_client::send(request, &RetryPolicy::NONE)
+        .await
+        .and_then(|response| response.error_for_status())
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+/// Structured mode of the CloudEvents HTTP binding; the trace of the event is passed in the
+/// attribute of the Distributed Tracing extension
+fn to_cloud_event(
+    event: &OutboxEventEntity,
+    created_at: DateTime<Utc>,
+) -> Result<serde_json::Value, String> {
+    let mut cloud_event = json!({
+        "specversion": "1.0",
+        "id": event.id.to_string(),
+        "source": CLOUD_EVENT_SOURCE,
+        "type": format!("{}.{}", event.topic, event.kind.to_lowercase()),
+        "time": created_at.to_rfc3339(),
+        "datacontenttype": "application/json",
+        "data": to_public_planet(&event.payload)?,
+    });
+    if let Some(traceparent) = &event.traceparent {
+        cloud_event["traceparent"] = json!(traceparent);
+    }
+    Ok(cloud_event)
+}
+
+/// The planet of an event with the IDs exposed to clients instead of the primary keys, so that
+/// receivers can pass them to `getPlanet`
+fn to_public_planet(payload: &serde_json::Value) -> Result<serde_json::Value, String> {
+    let planet = serde_json::from_value::<PlanetEntity>(payload.clone())
+        .map_err(|e| format!("Can't deserialize a planet: {}", e))?;
+    Ok(json!({
+        "id": graphql::to_planet_id(planet.id, planet.uuid),
+        "name": planet.name,
+        "planetType": planet.type_.to_string(),
+        "status": planet.status,
+        "star": planet.star_id.map(|star_id| json!({ "id": ids::encode_id(star_id) })),
+    }))
+}
+
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("Can't create HMAC from secret");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Failed deliveries are retried with exponential backoff until [MAX_ATTEMPTS] is reached
+fn get_attempt(
+    delivery: &WebhookDeliveryEntity,
+    result: Result<(), String>,
+    now: DateTime<Utc>,
+) -> WebhookDeliveryAttempt {
+    let attempts = delivery.attempts + 1;
+    let (status, last_error, next_attempt_at, delivered_at) = match result {
+        Ok(()) => (DeliveryStatus::Delivered, None, now, Some(now)),
+        Err(e) if attempts >= MAX_ATTEMPTS => (DeliveryStatus::Failed, Some(e), now, None),
+        Err(e) => {
+            let delay = chrono::Duration::from_std(get_retry_delay(attempts))
+                .expect("Can't convert retry delay");
+            (DeliveryStatus::Pending, Some(e), now + delay, None)
+        }
+    };
+    WebhookDeliveryAttempt {
+        status: status.to_string(),
+        attempts,
+        last_error,
+        next_attempt_at,
+        delivered_at,
+    }
+}
+
+fn get_retry_delay(attempts: i32) -> Duration {
+    RETRY_BASE_DELAY
+        .saturating_mul(2u32.saturating_pow(attempts.max(1) as u32 - 1))
+        .min(MAX_RETRY_DELAY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn delivery(attempts: i32) -> WebhookDeliveryEntity {
+        WebhookDeliveryEntity {
+            id: 1,
+            webhook_id: 1,
+            event_id: 1,
+            status: DeliveryStatus::Pending.to_string(),
+            attempts,
+            last_error: None,
+            next_attempt_at: Utc::now(),
+            delivered_at: None,
+        }
+    }
+
+    #[test]
+    fn signature() {
+        // RFC 4231, test case 2
+        assert_eq!(
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+            sign("Jefe", b"what do ya want for nothing?")
+        );
+    }
+
+    #[test]
+    fn retry_delay_grows_until_limit() {
+        assert_eq!(Duration::from_secs(10), get_retry_delay(1));
+        assert_eq!(Duration::from_secs(20), get_retry_delay(2));
+        assert_eq!(Duration::from_secs(40), get_retry_delay(3));
+        assert_eq!(MAX_RETRY_DELAY, get_retry_delay(30));
+    }
+
+    #[test]
+    fn attempts() {
+        let now = Utc::now();
+
+        let delivered = get_attempt(&delivery(2), Ok(()), now);
+        assert_eq!("DELIVERED", delivered.status);
+        assert_eq!(3, delivered.attempts);
+        assert_eq!(Some(now), delivered.delivered_at);
+
+        let retried = get_attempt(&delivery(0), Err("Timeout".to_string()), now);
+        assert_eq!("PENDING", retried.status);
+        assert_eq!(Some("Timeout".to_string()), retried.last_error);
+        assert_eq!(now + chrono::Duration::seconds(10), retried.next_attempt_at);
+
+        let failed = get_attempt(&delivery(MAX_ATTEMPTS - 1), Err("Timeout".to_string()), now);
+        assert_eq!("FAILED", failed.status);
+        assert_eq!(None, failed.delivered_at);
+    }
+
+    #[test]
+    fn data_contains_public_ids() {
+        let payload = json!({
+            "id": 3,
+            "name": "Earth",
+            "type_": "TERRESTRIAL_PLANET",
+            "uuid": "5f0c6e0e-0d5a-4f4e-9b1a-2d1c8f3e7a10",
+            "star_id": 1,
+            "status": "PUBLISHED",
+        });
+
+        let planet = to_public_planet(&payload).expect("Can't convert planet");
+
+        assert_eq!(ids::encode_id(3), planet["id"]);
+        assert_eq!(ids::encode_id(1), planet["star"]["id"]);
+        assert!(planet.get("star_id").is_none());
+        assert!(to_public_planet(&json!({ "id": 3 })).is_err());
+    }
+}
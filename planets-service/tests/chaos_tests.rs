@@ -0,0 +1,118 @@
+#![cfg(feature = "chaos")]
+
+use std::time::{Duration, Instant};
+
+use actix_web::{test, web, App};
+use futures::{future, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Map;
+use testcontainers::clients::Cli;
+
+use planets_service::{configure_service, create_schema_with_context};
+
+use crate::common::fixtures::PlanetFixture;
+
+mod common;
+
+#[actix_rt::test]
+async fn test_injected_faults() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let earth = PlanetFixture::earth().insert(&mut pool.get().expect("Can't get DB connection"));
+    let schema = create_schema_with_context(pool);
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(schema.clone())),
+    )
+    .await;
+
+    let query = &format!(
+        "{{ getPlanet(id: {}) {{ name details {{ meanRadius }} }} }}",
+        earth.id
+    );
+    let create_planet = |name: &str| {
+        format!(
+            r#"mutation {{ createPlanet(planet: {{ name: "{}", type: DWARF_PLANET, details: {{ meanRadius: "1188.3", mass: "1.303e22" }} }}) {{ name }} }}"#,
+            name
+        )
+    };
+
+    let started_at = Instant::now();
+    let delayed = execute(&service, query, "Query.getPlanet=latency:300").await;
+    assert!(started_at.elapsed() >= Duration::from_millis(300));
+    assert!(delayed.errors.is_none());
+    assert_eq!("Earth fixture", delayed.data["getPlanet"]["name"]);
+
+    let failed = execute(&service, query, "Planet.details=db_error").await;
+    let errors = failed.errors.expect("Response doesn't contain errors");
+    assert_eq!(
+        "Can't get DB connection: injected fault",
+        errors[0]["message"]
+    );
+    assert!(failed.data["getPlanet"].is_null());
+
+    // messages which weren't sent don't fail the mutation
+    let created = execute(
+        &service,
+        &create_planet("Chaotic planet"),
+        "Mutation.createPlanet=broker_error",
+    )
+    .await;
+    assert!(created.errors.is_none());
+    assert_eq!("Chaotic planet", created.data["createPlanet"]["name"]);
+
+    // once the broker recovers, the event which wasn't published is replayed from the outbox
+    let recovered = execute(&service, &create_planet("Recovered planet"), "").await;
+    assert!(recovered.errors.is_none());
+    let names: Vec<_> = schema
+        .execute_stream("subscription { planetEvents(resumeFrom: 0) { planet { name } } }")
+        .map(|response| {
+            response
+                .data
+                .into_json()
+                .expect("Can't convert response to JSON")["planetEvents"]["planet"]["name"]
+                .clone()
+        })
+        .filter(|name| future::ready(*name != "Earth fixture"))
+        .take(2)
+        .collect()
+        .await;
+    assert_eq!(["Chaotic planet", "Recovered planet"], names.as_slice());
+
+    let invalid = execute(&service, query, "Query.getPlanet=meteor").await;
+    assert!(invalid.errors.is_some());
+}
+
+async fn execute<S>(service: &S, query: &str, faults: &str) -> GraphQLCustomResponse
+where
+    S: actix_web::dev::Service<
+        actix_http::Request,
+        Response = actix_web::dev::ServiceResponse,
+        Error = actix_web::Error,
+    >,
+{
+    let request = test::TestRequest::post()
+        .uri("/")
+        .insert_header(("role", "ADMIN"))
+        .insert_header(("chaos-faults", faults))
+        .set_json(&GraphQLCustomRequest {
+            query: query.to_string(),
+            variables: Map::new(),
+        })
+        .to_request();
+    test::call_and_read_body_json(service, request).await
+}
+
+#[derive(Serialize)]
+struct GraphQLCustomRequest {
+    query: String,
+    variables: Map<String, serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct GraphQLCustomResponse {
+    data: serde_json::Value,
+    errors: Option<Vec<serde_json::Value>>,
+}
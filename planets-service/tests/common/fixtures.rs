@@ -0,0 +1,176 @@
+#![allow(dead_code)]
+
+use std::str::FromStr;
+
+use bigdecimal::BigDecimal;
+use diesel::PgConnection;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use planets_service::persistence::model::{
+    GasComponentEntity, NewDetailsEntity, NewStarEntity, OrbitEntity, PlanetEntity, PlanetType,
+    PlanetWrite,
+};
+use planets_service::persistence::{repository, units};
+
+const PLANET_TYPES: &[&str] = &[
+    "TERRESTRIAL_PLANET",
+    "GAS_GIANT",
+    "ICE_GIANT",
+    "DWARF_PLANET",
+];
+
+/// Planet with details inserted through the repository, so tests don't depend on the data
+/// added by migrations; names of the predefined planets differ from the migration ones, as
+/// names are unique
+pub struct PlanetFixture {
+    name: String,
+    type_: String,
+    /// In kilometers, as in the schema
+    mean_radius: BigDecimal,
+    mass: BigDecimal,
+    population: Option<BigDecimal>,
+    star: Option<String>,
+    /// As in the schema: semi-major axis in astronomical units, eccentricity, orbital period in
+    /// days, longitude of perihelion and mean longitude in degrees
+    orbit: Option<[BigDecimal; 5]>,
+    /// Gas and its percentage by volume
+    atmosphere: Vec<(String, BigDecimal)>,
+}
+
+impl PlanetFixture {
+    pub fn earth() -> Self {
+        Self::new("Earth fixture", "TERRESTRIAL_PLANET", "6371.0", "5.97e24")
+            .population(Some("7.53"))
+    }
+
+    pub fn jupiter() -> Self {
+        Self::new("Jupiter fixture", "GAS_GIANT", "69911.0", "1.898e27")
+    }
+
+    /// The same seed gives the same planet
+    pub fn random(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let type_ = PLANET_TYPES[rng.gen_range(0..PLANET_TYPES.len())];
+        Self::new(
+            &format!("Planet {}", rng.gen::<u32>()),
+            type_,
+            &format!("{:.1}", rng.gen_range(1000.0..70000.0)),
+            &format!("{:.3}e{}", rng.gen_range(1.0..10.0), rng.gen_range(22..28)),
+        )
+    }
+
+    fn new(name: &str, type_: &str, mean_radius: &str, mass: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            type_: type_.to_string(),
+            mean_radius: parse_decimal(mean_radius),
+            mass: parse_decimal(mass),
+            population: None,
+            star: None,
+            orbit: None,
+            atmosphere: vec![],
+        }
+    }
+
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
+    pub fn type_(mut self, type_: &str) -> Self {
+        self.type_ = type_.to_string();
+        self
+    }
+
+    pub fn mean_radius(mut self, mean_radius: &str) -> Self {
+        self.mean_radius = parse_decimal(mean_radius);
+        self
+    }
+
+    pub fn mass(mut self, mass: &str) -> Self {
+        self.mass = parse_decimal(mass);
+        self
+    }
+
+    pub fn population(mut self, population: Option<&str>) -> Self {
+        self.population = population.map(parse_decimal);
+        self
+    }
+
+    /// The star with the name is created if there is none
+    pub fn star(mut self, name: &str) -> Self {
+        self.star = Some(name.to_string());
+        self
+    }
+
+    pub fn orbit(mut self, elements: [&str; 5]) -> Self {
+        self.orbit = Some(elements.map(parse_decimal));
+        self
+    }
+
+    pub fn gas(mut self, gas: &str, percentage: &str) -> Self {
+        self.atmosphere
+            .push((gas.to_string(), parse_decimal(percentage)));
+        self
+    }
+
+    pub fn insert(self, conn: &mut PgConnection) -> PlanetEntity {
+        let star = self.star.map(|name| NewStarEntity {
+            name,
+            spectral_class: "G".to_string(),
+            mass: parse_decimal("1.989e30"),
+        });
+        let planet = PlanetWrite {
+            name: self.name,
+            type_: Some(PlanetType::from_str(&self.type_).expect("Can't parse planet type")),
+            star: Some(star),
+            status: None,
+        };
+        let details = NewDetailsEntity {
+            mean_radius: units::kilometers_to_meters(&self.mean_radius).round(0),
+            mass: self.mass,
+            population: self.population,
+            planet_id: 0,
+        };
+        let atmosphere: Vec<GasComponentEntity> = self
+            .atmosphere
+            .into_iter()
+            .map(|(gas, percentage)| GasComponentEntity {
+                planet_id: 0,
+                gas,
+                percentage,
+            })
+            .collect();
+        let (planet, _event) = repository::create(planet, details, &atmosphere, conn)
+            .expect("Can't insert planet fixture")
+            .expect("Can't classify planet fixture");
+        if let Some(
+            [semi_major_axis, eccentricity, orbital_period, longitude_of_perihelion, mean_longitude],
+        ) = self.orbit
+        {
+            let orbit = OrbitEntity {
+                planet_id: planet.id,
+                semi_major_axis: units::astronomical_units_to_meters(&semi_major_axis),
+                eccentricity,
+                orbital_period: units::days_to_seconds(&orbital_period).round(3),
+                longitude_of_perihelion,
+                mean_longitude,
+            };
+            repository::set_orbit(planet.id, Some(orbit), conn)
+                .expect("Can't insert orbit fixture");
+        }
+        planet
+    }
+}
+
+/// Inserts `count` random planets; the same seed gives the same planets
+pub fn insert_random_planets(count: u64, seed: u64, conn: &mut PgConnection) -> Vec<PlanetEntity> {
+    (0..count)
+        .map(|index| PlanetFixture::random(seed.wrapping_add(index)).insert(conn))
+        .collect()
+}
+
+fn parse_decimal(value: &str) -> BigDecimal {
+    BigDecimal::from_str(value).expect("Can't parse decimal")
+}
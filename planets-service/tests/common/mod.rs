@@ -9,6 +9,8 @@ use testcontainers::{Container, RunnableImage};
 use planets_service::persistence::connection::{create_connection_pool, PgPool};
 use planets_service::run_migrations;
 
+pub mod fixtures;
+
 pub fn setup(docker: &Cli) -> (Container<Postgres>, PgPool) {
     dotenv().ok();
     let pg_container = setup_database(docker);
@@ -34,6 +36,7 @@ fn get_pg_image() -> RunnableImage<Postgres> {
 }
 
 // TODO: check population
+#[allow(dead_code)]
 pub fn check_planet(
     planet_json: &serde_json::Value,
     id: i32,
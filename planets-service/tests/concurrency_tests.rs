@@ -0,0 +1,84 @@
+use chrono::{DateTime, Utc};
+use diesel::sql_types::Timestamptz;
+use diesel::{sql_query, QueryableByName, RunQueryDsl};
+use testcontainers::clients::Cli;
+
+use planets_service::{create_schema_with_context, DETAILS_BATCH_SIZE};
+
+use crate::common::fixtures;
+
+mod common;
+
+/// A read of details, recorded by the view replacing the table
+#[derive(QueryableByName)]
+struct DetailsRead {
+    #[diesel(sql_type = Timestamptz)]
+    started_at: DateTime<Utc>,
+    #[diesel(sql_type = Timestamptz)]
+    finished_at: DateTime<Utc>,
+}
+
+#[actix_rt::test]
+async fn test_details_are_loaded_concurrently() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let mut conn = pool.get().expect("Can't get DB connection");
+    fixtures::insert_random_planets(32, 1, &mut conn);
+
+    // each read of details takes 200 ms and records when it started and finished
+    sql_query("alter table details rename to details_data")
+        .execute(&mut conn)
+        .expect("Can't rename details");
+    sql_query("create table details_reads (started_at timestamptz, finished_at timestamptz)")
+        .execute(&mut conn)
+        .expect("Can't create details reads");
+    sql_query(
+        "create function read_details() returns int as $$
+        declare started_at timestamptz := clock_timestamp();
+        begin
+            perform pg_sleep(0.2);
+            insert into details_reads values (started_at, clock_timestamp());
+            return 1;
+        end $$ language plpgsql",
+    )
+    .execute(&mut conn)
+    .expect("Can't create details read function");
+    sql_query(
+        "create view details as select * from details_data where (select read_details()) = 1",
+    )
+    .execute(&mut conn)
+    .expect("Can't create recorded details");
+    drop(conn);
+
+    let schema = create_schema_with_context(pool.clone());
+
+    let response = schema
+        .execute("{ getPlanets { details { meanRadius } } }")
+        .await;
+
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let planets_count = response
+        .data
+        .into_json()
+        .expect("Can't convert response to JSON")["getPlanets"]
+        .as_array()
+        .expect("Can't get planets")
+        .len();
+    let mut reads: Vec<DetailsRead> =
+        sql_query("select started_at, finished_at from details_reads order by started_at")
+            .load(&mut pool.get().expect("Can't get DB connection"))
+            .expect("Can't get details reads");
+
+    // details are loaded in full batches, which are read at the same time
+    assert_eq!(
+        (planets_count as f64 / DETAILS_BATCH_SIZE as f64).ceil() as usize,
+        reads.len()
+    );
+    let last_read = reads.pop().expect("Can't get details read");
+    assert!(
+        reads
+            .iter()
+            .all(|read| read.finished_at > last_read.started_at),
+        "Details were read one after another"
+    );
+}
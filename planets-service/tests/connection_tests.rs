@@ -0,0 +1,98 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use testcontainers::clients::Cli;
+
+use diesel::sql_types::BigInt;
+use diesel::{QueryableByName, RunQueryDsl};
+
+use planets_service::persistence::connection::{
+    create_connection_pool, create_lazy_connection_pool, DisposableSchema, ReloadablePool,
+};
+use planets_service::persistence::model::{PlanetStatus, PlanetsOrder};
+use planets_service::persistence::repository;
+use planets_service::{create_schema, run_migrations, smoke};
+
+mod common;
+
+#[actix_rt::test]
+async fn test_replace_pool() {
+    let docker = Cli::default();
+    let (_pg_container, old_pool) = common::setup(&docker);
+    let pool = ReloadablePool::new(old_pool);
+
+    let in_flight_conn = pool.get().expect("Can't get DB connection");
+
+    let replacing = pool.replace(create_connection_pool());
+    futures::pin_mut!(replacing);
+    // the old pool is drained until the checked out connection is returned
+    assert!(
+        tokio::time::timeout(Duration::from_millis(500), &mut replacing)
+            .await
+            .is_err()
+    );
+    pool.get()
+        .expect("Can't get DB connection from the new pool");
+
+    drop(in_flight_conn);
+    tokio::time::timeout(Duration::from_secs(1), replacing)
+        .await
+        .expect("Old pool wasn't drained");
+}
+
+#[actix_rt::test]
+async fn test_lazy_pool_serves_concurrent_resolvers() {
+    let docker = Cli::default();
+    let (_pg_container, _pool) = common::setup(&docker);
+
+    let pool = create_lazy_connection_pool();
+    assert_eq!(0, pool.state().connections);
+    // e.g. a resolver holding a connection while a DataLoader batch runs
+    let _resolver_conn = pool.get().expect("Can't get DB connection");
+    pool.get_timeout(Duration::from_secs(1))
+        .expect("Can't get a second DB connection");
+}
+
+#[actix_rt::test]
+async fn test_smoke_test_changes_are_discarded() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let get_planet_names = || {
+        repository::get_all(
+            PlanetStatus::Published,
+            &PlanetsOrder::default(),
+            &mut pool.get().expect("Can't get DB connection"),
+        )
+        .expect("Can't get planets")
+        .into_iter()
+        .map(|planet| planet.name)
+        .collect::<Vec<String>>()
+    };
+    let planet_names = get_planet_names();
+
+    let disposable_schema = DisposableSchema::create(None).await;
+    run_migrations(
+        &mut disposable_schema
+            .pool
+            .get()
+            .expect("Can't get DB connection"),
+    );
+    let schema = create_schema(Arc::new(ReloadablePool::new(
+        disposable_schema.pool.clone(),
+    )));
+    assert!(smoke::run(&schema).await);
+    disposable_schema.discard();
+
+    assert_eq!(planet_names, get_planet_names());
+    #[derive(QueryableByName)]
+    struct Count {
+        #[diesel(sql_type = BigInt)]
+        count: i64,
+    }
+    let schemas = diesel::sql_query(
+        "select count(*) as count from information_schema.schemata where schema_name like 'smoke\\_%'",
+    )
+    .get_result::<Count>(&mut pool.get().expect("Can't get DB connection"))
+    .expect("Can't count schemas");
+    assert_eq!(0, schemas.count);
+}
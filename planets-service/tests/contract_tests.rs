@@ -0,0 +1,154 @@
+//! Operations of the official UI kept in `contracts/`: ones in `public` are executed without
+//! a role and ones in `admin` as an admin. Each operation must pass validation and execute
+//! without errors, and the shape of its response (types of values instead of the values) must
+//! match the `.shape.json` snapshot next to it. Run with `UPDATE_CONTRACT_SHAPES=true` to
+//! accept changed shapes. String variables starting with `$` are replaced with ids of the
+//! fixtures with the same names (`$earth` and `$jupiter`)
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use async_graphql::{Request, Variables};
+use serde_json::{json, Map, Value};
+use testcontainers::clients::Cli;
+
+use planets_service::{create_schema_with_context, execute};
+
+use crate::common::fixtures::PlanetFixture;
+
+mod common;
+
+const CONTRACT_DIRS: &[(&str, Option<&str>)] = &[("public", None), ("admin", Some("ADMIN"))];
+
+#[actix_rt::test]
+async fn test_contracts() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let mut conn = pool.get().expect("Can't get DB connection");
+    let earth = PlanetFixture::earth()
+        .star("Sun fixture")
+        .gas("N2", "78.08")
+        .gas("O2", "20.95")
+        .insert(&mut conn);
+    let jupiter = PlanetFixture::jupiter().insert(&mut conn);
+    let fixture_ids = HashMap::from([
+        ("$earth", earth.id.to_string()),
+        ("$jupiter", jupiter.id.to_string()),
+    ]);
+    let schema = create_schema_with_context(pool);
+    let update_shapes = env::var("UPDATE_CONTRACT_SHAPES").is_ok_and(|value| value == "true");
+
+    let mut failures = vec![];
+    for (dir, role) in CONTRACT_DIRS {
+        for path in get_operation_paths(dir) {
+            let name = path
+                .strip_prefix(env!("CARGO_MANIFEST_DIR"))
+                .unwrap_or(&path)
+                .display()
+                .to_string();
+            let query = fs::read_to_string(&path).expect("Can't read operation");
+            let variables = fs::read_to_string(path.with_extension("variables.json"))
+                .map(|json| serde_json::from_str(&json).expect("Can't parse variables"))
+                .map(|variables| replace_fixture_ids(variables, &fixture_ids))
+                .unwrap_or(json!({}));
+
+            let request = Request::new(query).variables(Variables::from_json(variables));
+            let response = execute(&schema, request, *role).await;
+            if response.is_err() {
+                let messages: Vec<String> = response.errors.iter().map(|e| e.to_string()).collect();
+                failures.push(format!("{} failed: {}", name, messages.join("; ")));
+                continue;
+            }
+
+            let data = response.data.into_json().expect("Can't convert data");
+            let shape = to_shape(&data);
+            let shape_path = path.with_extension("shape.json");
+            if update_shapes {
+                let json = serde_json::to_string_pretty(&shape).expect("Can't serialize shape");
+                fs::write(&shape_path, json + "\n").expect("Can't write shape");
+                continue;
+            }
+            match fs::read_to_string(&shape_path) {
+                Ok(json) => {
+                    let expected: Value = serde_json::from_str(&json).expect("Can't parse shape");
+                    if expected != shape {
+                        failures.push(format!(
+                            "Shape of {} changed, expected:\n{}\nactual:\n{}",
+                            name, expected, shape
+                        ));
+                    }
+                }
+                Err(_) => failures.push(format!("Shape of {} is missing", name)),
+            }
+        }
+    }
+
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+/// Operations of a directory in alphabetical order, since mutations may affect later operations
+fn get_operation_paths(dir: &str) -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("contracts")
+        .join(dir);
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .expect("Can't read contracts")
+        .map(|entry| entry.expect("Can't read contract").path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "graphql")
+        })
+        .collect();
+    paths.sort();
+    paths
+}
+
+fn replace_fixture_ids(value: Value, fixture_ids: &HashMap<&str, String>) -> Value {
+    match value {
+        Value::String(name) if name.starts_with('$') => {
+            let id = fixture_ids
+                .get(name.as_str())
+                .unwrap_or_else(|| panic!("Fixture {} is unknown", name));
+            Value::String(id.clone())
+        }
+        Value::Array(items) => Value::Array(
+            items
+                .into_iter()
+                .map(|item| replace_fixture_ids(item, fixture_ids))
+                .collect(),
+        ),
+        Value::Object(fields) => Value::Object(
+            fields
+                .into_iter()
+                .map(|(name, value)| (name, replace_fixture_ids(value, fixture_ids)))
+                .collect(),
+        ),
+        value => value,
+    }
+}
+
+/// Values are replaced with their types, except for `__typename`, and arrays with their first
+/// element, so that the shape doesn't depend on the data
+fn to_shape(value: &Value) -> Value {
+    match value {
+        Value::Null => json!("null"),
+        Value::Bool(_) => json!("boolean"),
+        Value::Number(_) => json!("number"),
+        Value::String(_) => json!("string"),
+        Value::Array(items) => Value::Array(items.first().map(to_shape).into_iter().collect()),
+        Value::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(name, value)| {
+                    let shape = if name == "__typename" {
+                        value.clone()
+                    } else {
+                        to_shape(value)
+                    };
+                    (name.clone(), shape)
+                })
+                .collect::<Map<_, _>>(),
+        ),
+    }
+}
@@ -0,0 +1,84 @@
+//! Share of the public schema elements (types, fields, input fields and enum values) having
+//! a description, as seen by clients through introspection, so it includes the descriptions
+//! maintained in the database. Raise [MIN_COVERAGE] as the schema gets documented
+use async_graphql::Request;
+use serde_json::Value;
+use testcontainers::clients::Cli;
+
+use planets_service::{create_schema_with_context, execute};
+
+mod common;
+
+const MIN_COVERAGE: f64 = 0.41;
+
+const BUILT_IN_SCALARS: &[&str] = &["String", "Int", "Float", "Boolean", "ID"];
+
+const INTROSPECTION_QUERY: &str = r#"
+    {
+        __schema {
+            types {
+                name
+                description
+                fields(includeDeprecated: true) { name description }
+                inputFields { name description }
+                enumValues(includeDeprecated: true) { name description }
+            }
+        }
+    }
+"#;
+
+#[actix_rt::test]
+async fn test_description_coverage() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let schema = create_schema_with_context(pool);
+
+    let response = execute(&schema, Request::new(INTROSPECTION_QUERY), None).await;
+    assert!(response.is_ok(), "{:?}", response.errors);
+    let data = response.data.into_json().expect("Can't convert data");
+
+    let elements = get_schema_elements(&data);
+    let undocumented: Vec<&str> = elements
+        .iter()
+        .filter(|(_, description)| !description)
+        .map(|(name, _)| name.as_str())
+        .collect();
+    let coverage = 1.0 - undocumented.len() as f64 / elements.len() as f64;
+    assert!(
+        coverage >= MIN_COVERAGE,
+        "Description coverage {:.1}% is below {:.1}%, undocumented: {}",
+        coverage * 100.0,
+        MIN_COVERAGE * 100.0,
+        undocumented.join(", ")
+    );
+}
+
+/// Names of the elements and whether they have a non-empty description; introspection, built-in
+/// and federation (starting with `_`) types and fields are skipped
+fn get_schema_elements(data: &Value) -> Vec<(String, bool)> {
+    let is_documented = |item: &Value| {
+        item["description"]
+            .as_str()
+            .is_some_and(|description| !description.trim().is_empty())
+    };
+    let mut elements = vec![];
+    let types = data["__schema"]["types"]
+        .as_array()
+        .expect("Can't get types");
+    for type_ in types {
+        let type_name = type_["name"].as_str().expect("Can't get type name");
+        if type_name.starts_with('_') || BUILT_IN_SCALARS.contains(&type_name) {
+            continue;
+        }
+        elements.push((type_name.to_string(), is_documented(type_)));
+        for kind in ["fields", "inputFields", "enumValues"] {
+            for item in type_[kind].as_array().into_iter().flatten() {
+                let name = item["name"].as_str().expect("Can't get name");
+                if !name.starts_with('_') {
+                    elements.push((format!("{}.{}", type_name, name), is_documented(item)));
+                }
+            }
+        }
+    }
+    elements
+}
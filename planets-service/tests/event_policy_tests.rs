@@ -0,0 +1,103 @@
+use async_graphql::Request;
+use futures::StreamExt;
+use testcontainers::clients::Cli;
+
+use planets_service::create_schema_with_context;
+
+mod common;
+
+const PLANET_EVENTS: &str = "subscription { planetEvents(resumeFrom: 0) { token kind } }";
+
+#[actix_rt::test]
+async fn test_planet_events_by_role() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let schema = create_schema_with_context(pool);
+
+    let as_admin = |query: &str| Request::new(query).data(common_utils::parse_role(Some("ADMIN")));
+
+    let execute = |query: String| {
+        let response = schema.execute(as_admin(&query));
+        async move {
+            let response = response.await;
+            assert!(response.errors.is_empty(), "{:?}", response.errors);
+            response
+                .data
+                .into_json()
+                .expect("Can't convert response to JSON")
+        }
+    };
+    let create_planet = |name: &str, draft: bool| {
+        format!(
+            r#"mutation {{ createPlanet(planet: {{ name: "{}", type: ICE_GIANT, details: {{ meanRadius: "10.7", mass: "6.42e+23" }} }}, draft: {}) {{ id }} }}"#,
+            name, draft
+        )
+    };
+
+    let created = execute(create_planet("Test planet", false)).await;
+    execute(format!(
+        r#"mutation {{ updatePlanet(id: {}, planet: {{ name: "Renamed planet", type: ICE_GIANT, details: {{ meanRadius: "10.7", mass: "6.42e+23" }} }}) {{ id }} }}"#,
+        created["createPlanet"]["id"]
+    ))
+    .await;
+    execute(
+        r#"mutation { deletePlanets(filter: { nameContains: "Renamed" }) { affected } }"#
+            .to_string(),
+    )
+    .await;
+    let draft = execute(create_planet("Draft planet", true)).await;
+    let draft_id = &draft["createPlanet"]["id"];
+    execute(format!(
+        "mutation {{ publishPlanet(id: {}) {{ id }} }}",
+        draft_id
+    ))
+    .await;
+    execute(format!(
+        "mutation {{ archivePlanet(id: {}) {{ id }} }}",
+        draft_id
+    ))
+    .await;
+
+    let get_kinds = |request: Request, count: usize| {
+        let events = schema.execute_stream(request);
+        async move {
+            events
+                .take(count)
+                .map(|response| {
+                    let event = response
+                        .data
+                        .into_json()
+                        .expect("Can't convert response to JSON");
+                    event["planetEvents"]["kind"]
+                        .as_str()
+                        .expect("Can't get kind")
+                        .to_string()
+                })
+                .collect::<Vec<_>>()
+                .await
+        }
+    };
+
+    assert_eq!(
+        vec![
+            "CREATED",
+            "UPDATED",
+            "DELETED",
+            "CREATED",
+            "PUBLISHED",
+            "ARCHIVED"
+        ],
+        get_kinds(as_admin(PLANET_EVENTS), 6).await
+    );
+    let as_user = Request::new(PLANET_EVENTS).data(common_utils::parse_role(Some("USER")));
+    assert_eq!(
+        vec!["CREATED", "UPDATED", "PUBLISHED", "ARCHIVED"],
+        get_kinds(as_user, 4).await
+    );
+
+    // a public subscriber doesn't get the creation of the draft, only its publication and archiving
+    assert_eq!(
+        vec!["CREATED", "PUBLISHED", "ARCHIVED"],
+        get_kinds(Request::new(PLANET_EVENTS), 3).await
+    );
+}
@@ -0,0 +1,113 @@
+use std::env;
+
+use async_graphql::{value, Request, Variables};
+use diesel::debug_query;
+use diesel::pg::Pg;
+use proptest::prelude::*;
+use testcontainers::clients::Cli;
+
+use planets_service::create_schema_with_context;
+use planets_service::persistence::model::PlanetsFilter;
+use planets_service::persistence::repository::filter_planets;
+
+use crate::common::fixtures::{insert_random_planets, PlanetFixture};
+
+mod common;
+
+const MALICIOUS_NAMES: &[&str] = &[
+    "'; drop table planets; --",
+    "' or '1'='1",
+    "Earth' --",
+    "\" or \"\"=\"",
+    "%",
+    "_",
+    "%%",
+    "E_rth",
+    "\\",
+    "\\%",
+    "$1",
+    "😈 \u{202e}",
+    "",
+];
+
+fn get_sql(name_contains: &str) -> String {
+    let filter = PlanetsFilter {
+        name_contains: Some(name_contains.to_string()),
+        type_: None,
+    };
+    let query = debug_query::<Pg, _>(&filter_planets(&filter)).to_string();
+    let (sql, _binds) = query
+        .split_once(" -- binds: ")
+        .expect("Can't find bind parameters");
+    sql.to_string()
+}
+
+proptest! {
+    #[test]
+    fn test_name_filter_is_bound(name_contains in any::<String>()) {
+        // user input never makes its way into the SQL text
+        prop_assert_eq!(get_sql("Earth"), get_sql(&name_contains));
+    }
+}
+
+#[test]
+fn test_malicious_name_filters_are_bound() {
+    for name in MALICIOUS_NAMES {
+        assert_eq!(get_sql("Earth"), get_sql(name));
+    }
+}
+
+#[actix_rt::test]
+async fn test_malicious_name_filters() {
+    env::set_var("DISABLE_AUTH", true.to_string());
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let mut conn = pool.get().expect("Can't get DB connection");
+    let mut planets = vec![
+        PlanetFixture::earth().insert(&mut conn),
+        PlanetFixture::jupiter().insert(&mut conn),
+    ];
+    planets.extend(insert_random_planets(3, 1, &mut conn));
+    drop(conn);
+    let schema = create_schema_with_context(pool);
+
+    let mutation = "mutation($name: String) { deletePlanets(filter: { nameContains: $name }, dryRun: true) { affected } }";
+    let execute = |name: &str| {
+        let request = Request::new(mutation)
+            .variables(Variables::from_json(serde_json::json!({ "name": name })));
+        schema.execute(request)
+    };
+
+    for name in MALICIOUS_NAMES.iter().filter(|name| !name.is_empty()) {
+        let response = execute(name).await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        // wildcards are matched literally
+        assert_eq!(
+            value!({ "deletePlanets": { "affected": 0 } }),
+            response.data
+        );
+    }
+
+    // Postgres rejects NUL in text, which is reported as an error of the field
+    let response = execute("Earth\u{0}").await;
+    assert_eq!(1, response.errors.len());
+
+    let response = schema.execute("{ getPlanets { name } }").await;
+    let response = response
+        .data
+        .into_json()
+        .expect("Can't convert response to JSON");
+    let names = response["getPlanets"]
+        .as_array()
+        .expect("Can't get planets")
+        .iter()
+        .map(|planet| planet["name"].as_str().expect("Can't get name"))
+        .collect::<Vec<_>>();
+    for planet in &planets {
+        assert!(
+            names.contains(&planet.name.as_str()),
+            "{} is deleted",
+            planet.name
+        );
+    }
+}
@@ -0,0 +1,73 @@
+use std::env;
+use std::time::Duration;
+
+use actix_web::{test, web, App};
+use serde_json::Value;
+use testcontainers::clients::Cli;
+
+use planets_service::{configure_service, create_schema_with_context};
+
+mod common;
+
+#[actix_rt::test]
+async fn test_operations_shed_while_pool_is_overloaded() {
+    // any wait for a connection overloads the pool; the settings are read on first use,
+    // and this test binary doesn't share them
+    env::set_var("LOAD_SHEDDING_MAX_WAIT_MILLIS", "0");
+    env::set_var("LOAD_SHEDDING_PRIORITY_ROLES", "ADMIN");
+    env::set_var("LOAD_SHEDDING_PRIORITY_API_KEYS", "priority-key");
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool))),
+    )
+    .await;
+
+    let execute = |role: Option<&str>, api_key: Option<&str>| {
+        let mut request = test::TestRequest::post()
+            .uri("/")
+            .set_json(serde_json::json!({
+                "query": "query priority { getPlanets { name } }",
+                "operationName": "priority",
+            }));
+        if let Some(role) = role {
+            request = request.insert_header(("role", role));
+        }
+        if let Some(api_key) = api_key {
+            request = request.insert_header(("x-api-key", api_key));
+        }
+        test::call_service(&service, request.to_request())
+    };
+
+    let response = execute(None, None).await;
+    assert!(response.headers().get("retry-after").is_none());
+    let body: Value = test::read_body_json(response).await;
+    assert!(body["errors"].is_null());
+
+    // shedding starts when the window with the checkouts completes
+    actix_rt::time::sleep(Duration::from_secs(5)).await;
+
+    // neither the operation name nor an unknown API key makes an operation a priority one
+    let response = execute(Some("USER"), Some("priority")).await;
+    assert_eq!(
+        Some("5"),
+        response
+            .headers()
+            .get("retry-after")
+            .map(|value| value.to_str().expect("Can't get header value"))
+    );
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!("RETRY_LATER", body["errors"][0]["extensions"]["code"]);
+    assert_eq!(5, body["errors"][0]["extensions"]["retryAfter"]);
+
+    for (role, api_key) in [(Some("ADMIN"), None), (None, Some("priority-key"))] {
+        let response = execute(role, api_key).await;
+        assert!(response.headers().get("retry-after").is_none());
+        let body: Value = test::read_body_json(response).await;
+        assert!(body["errors"].is_null());
+        assert!(body["data"]["getPlanets"].is_array());
+    }
+}
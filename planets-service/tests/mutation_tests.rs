@@ -1,13 +1,24 @@
 use std::env;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
 
 use actix_web::{test, web, App};
+use bigdecimal::BigDecimal;
+use diesel::sql_types::{Integer, Numeric};
+use diesel::{QueryableByName, RunQueryDsl};
+use futures::StreamExt;
 use jsonpath_lib as jsonpath;
 use serde::{Deserialize, Serialize};
 use serde_json::Map;
 use testcontainers::clients::Cli;
 
+use planets_service::persistence::model::{NewDetailsEntity, PlanetType, PlanetWrite};
+use planets_service::persistence::repository;
 use planets_service::{configure_service, create_schema_with_context};
 
+use crate::common::fixtures::{insert_random_planets, PlanetFixture};
+
 mod common;
 
 #[actix_rt::test]
@@ -74,7 +85,1192 @@ async fn test_create_planet() {
     let created_planet_json = jsonpath::select(&response_data, "$.createPlanet")
         .expect("Can't get created planet by JSON path")[0];
 
-    common::check_planet(created_planet_json, 9, "Test planet", "ICE_GIANT", "10.7");
+    let created_planet_id = created_planet_json["id"]
+        .as_str()
+        .expect("Can't get id as str")
+        .parse()
+        .expect("Can't parse id");
+    common::check_planet(
+        created_planet_json,
+        created_planet_id,
+        "Test planet",
+        "ICE_GIANT",
+        "10.7",
+    );
+}
+
+#[actix_rt::test]
+async fn test_delete_planets() {
+    env::set_var("DISABLE_AUTH", true.to_string());
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let mut conn = pool.get().expect("Can't get DB connection");
+    let jupiter = PlanetFixture::jupiter().insert(&mut conn);
+    let saturn = PlanetFixture::jupiter()
+        .name("Saturn fixture")
+        .insert(&mut conn);
+    let earth = PlanetFixture::earth().insert(&mut conn);
+    drop(conn);
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool))),
+    )
+    .await;
+
+    // the name condition keeps planets added by migrations out of the deletion
+    let mutation = r#"
+        mutation($dryRun: Boolean) {
+            deletePlanets(filter: { nameContains: "fixture", type: GAS_GIANT }, dryRun: $dryRun) {
+                affected
+                dryRun
+            }
+        }
+        "#;
+
+    for dry_run in [true, false] {
+        let mut variables = Map::new();
+        variables.insert("dryRun".to_string(), dry_run.into());
+
+        let request = test::TestRequest::post()
+            .uri("/")
+            .set_json(&GraphQLCustomRequest {
+                query: mutation.to_string(),
+                variables,
+            })
+            .to_request();
+
+        let response: GraphQLCustomResponse =
+            test::call_and_read_body_json(&service, request).await;
+        let response_data = response.data.expect("Response doesn't contain data");
+
+        assert_eq!(
+            Some(2),
+            jsonpath::select(&response_data, "$.deletePlanets.affected")
+                .expect("Can't get affected count")[0]
+                .as_i64()
+        );
+    }
+
+    let request = test::TestRequest::post()
+        .uri("/")
+        .set_json(&GraphQLCustomRequest {
+            query: "{ getPlanets { id } }".to_string(),
+            variables: Map::new(),
+        })
+        .to_request();
+
+    let response: GraphQLCustomResponse = test::call_and_read_body_json(&service, request).await;
+    let planet_ids = jsonpath::select(
+        &response.data.expect("Response doesn't contain data"),
+        "$.getPlanets[*].id",
+    )
+    .expect("Can't get planet ids")
+    .into_iter()
+    .cloned()
+    .collect::<Vec<_>>();
+
+    assert!(!planet_ids.contains(&jupiter.id.to_string().into()));
+    assert!(!planet_ids.contains(&saturn.id.to_string().into()));
+    assert!(planet_ids.contains(&earth.id.to_string().into()));
+}
+
+#[actix_rt::test]
+async fn test_deletion_progress() {
+    env::set_var("DISABLE_AUTH", true.to_string());
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    // more than a batch of the deletion
+    let planets_count = 150;
+    insert_random_planets(
+        planets_count,
+        669,
+        &mut pool.get().expect("Can't get DB connection"),
+    );
+    let schema = create_schema_with_context(pool);
+
+    let mut progress = schema.execute_stream("subscription { deletionProgress { deleted total } }");
+    // the first poll subscribes, progress is published only by the deletion
+    assert!(
+        tokio::time::timeout(Duration::from_millis(500), progress.next())
+            .await
+            .is_err()
+    );
+
+    // names of random fixtures start with "Planet", unlike the ones added by migrations
+    let response = schema
+        .execute(r#"mutation { deletePlanets(filter: { nameContains: "Planet " }) { affected } }"#)
+        .await;
+    assert!(response.errors.is_empty());
+    assert_eq!(
+        planets_count,
+        response
+            .data
+            .into_json()
+            .expect("Can't convert response to JSON")["deletePlanets"]["affected"]
+    );
+
+    let mut deleted_counts = vec![];
+    while deleted_counts.last() != Some(&planets_count) {
+        let response = tokio::time::timeout(Duration::from_secs(10), progress.next())
+            .await
+            .expect("Can't get progress in time")
+            .expect("Can't get progress");
+        let progress = response
+            .data
+            .into_json()
+            .expect("Can't convert response to JSON")["deletionProgress"]
+            .clone();
+        assert_eq!(planets_count, progress["total"]);
+        deleted_counts.push(
+            progress["deleted"]
+                .as_u64()
+                .expect("Can't get deleted count"),
+        );
+    }
+    assert_eq!(vec![100, planets_count], deleted_counts);
+}
+
+#[actix_rt::test]
+async fn test_concurrent_planet_updates() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let earth = PlanetFixture::earth().insert(&mut pool.get().expect("Can't get DB connection"));
+
+    let writers_count = 4;
+    let writers = (0..writers_count)
+        .map(|index| {
+            let pool = pool.clone();
+            thread::spawn(move || {
+                let planet = PlanetWrite {
+                    name: format!("Earth {}", index),
+                    type_: Some(PlanetType::TerrestrialPlanet),
+                    star: None,
+                    status: None,
+                };
+                let details = NewDetailsEntity {
+                    mean_radius: BigDecimal::from_str("6371000").expect("Can't parse radius"),
+                    mass: BigDecimal::from_str("5.97e24").expect("Can't parse mass"),
+                    population: Some(BigDecimal::from(index)),
+                    planet_id: 0,
+                };
+                let mut conn = pool.get().expect("Can't get DB connection");
+                repository::update(earth.id, planet, details, None, &mut conn)
+            })
+        })
+        .collect::<Vec<_>>();
+
+    // each writer conflicts with the others; failed transactions should be retried, not surfaced
+    for writer in writers {
+        writer
+            .join()
+            .expect("Writer thread panicked")
+            .expect("Concurrent update failed")
+            .expect("Can't classify planet");
+    }
+
+    let mut conn = pool.get().expect("Can't get DB connection");
+    let earth_details = repository::get_details(&[earth.id], &mut conn).expect("Can't get details");
+    let earth = repository::get(earth.id, &mut conn).expect("Can't get planet");
+    let index = earth
+        .name
+        .strip_prefix("Earth ")
+        .expect("Unexpected planet name");
+    // name and details must come from the same writer
+    assert_eq!(
+        Some(BigDecimal::from_str(index).expect("Can't parse index")),
+        earth_details[0].population
+    );
+}
+
+#[actix_rt::test]
+async fn test_create_planet_orbiting_star() {
+    env::set_var("DISABLE_AUTH", true.to_string());
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    PlanetFixture::earth().insert(&mut pool.get().expect("Can't get DB connection"));
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool))),
+    )
+    .await;
+
+    let mutation = r#"
+        mutation($name: String!, $starMass: BigInt!) {
+            createPlanet(
+                planet: {
+                    name: $name
+                    type: GAS_GIANT
+                    details: { meanRadius: "52500.0", mass: "6.3e26" }
+                    star: { name: "Kepler-16A", spectralClass: "K", mass: $starMass }
+                }
+            ) {
+                id
+                star {
+                    id
+                    name
+                    spectralClass
+                    mass
+                }
+            }
+        }
+        "#;
+
+    let mut planet_ids = vec![];
+    let mut stars = vec![];
+    // the second planet refers to the star created with the first one
+    for (name, star_mass) in [("Kepler-16b", "1.37e30"), ("Kepler-16c", "1e30")] {
+        let mut variables = Map::new();
+        variables.insert("name".to_string(), name.into());
+        variables.insert("starMass".to_string(), star_mass.into());
+
+        let request = test::TestRequest::post()
+            .uri("/")
+            .set_json(&GraphQLCustomRequest {
+                query: mutation.to_string(),
+                variables,
+            })
+            .to_request();
+
+        let response: GraphQLCustomResponse =
+            test::call_and_read_body_json(&service, request).await;
+        let response_data = response.data.expect("Response doesn't contain data");
+        planet_ids.push(response_data["createPlanet"]["id"].clone());
+        stars.push(response_data["createPlanet"]["star"].clone());
+    }
+
+    assert_eq!(stars[0], stars[1]);
+    assert_eq!("Kepler-16A", stars[0]["name"]);
+    assert_eq!("K", stars[0]["spectralClass"]);
+    assert_eq!("1.37e30", stars[0]["mass"]);
+
+    let request = test::TestRequest::post()
+        .uri("/")
+        .set_json(&GraphQLCustomRequest {
+            query: "{ getPlanets { name star { name } } }".to_string(),
+            variables: Map::new(),
+        })
+        .to_request();
+
+    let response: GraphQLCustomResponse = test::call_and_read_body_json(&service, request).await;
+    let response_data = response.data.expect("Response doesn't contain data");
+    let get_star_name = |planet_name: &str| {
+        jsonpath::select(
+            &response_data,
+            &format!("$.getPlanets[?(@.name == '{}')].star", planet_name),
+        )
+        .expect("Can't get star")[0]
+            .get("name")
+            .cloned()
+    };
+
+    assert_eq!(Some("Kepler-16A".into()), get_star_name("Kepler-16b"));
+    assert_eq!(Some("Kepler-16A".into()), get_star_name("Kepler-16c"));
+    assert_eq!(None, get_star_name("Earth fixture"));
+
+    // an absent star isn't changed, null detaches the planet from its star
+    let mutation = r#"
+        mutation($id: ID!, $planet: PlanetInput!) {
+            updatePlanet(id: $id, planet: $planet) {
+                star {
+                    name
+                }
+            }
+        }
+        "#;
+    let mut planet = serde_json::json!({
+        "name": "Kepler-16b",
+        "type": "GAS_GIANT",
+        "details": { "meanRadius": "52500.0", "mass": "6.3e26" },
+    });
+    let mut updated_stars = vec![];
+    for star in [None, Some(serde_json::Value::Null)] {
+        if let Some(star) = star {
+            planet["star"] = star;
+        }
+        let mut variables = Map::new();
+        variables.insert("id".to_string(), planet_ids[0].clone());
+        variables.insert("planet".to_string(), planet.clone());
+
+        let request = test::TestRequest::post()
+            .uri("/")
+            .set_json(&GraphQLCustomRequest {
+                query: mutation.to_string(),
+                variables,
+            })
+            .to_request();
+
+        let response: GraphQLCustomResponse =
+            test::call_and_read_body_json(&service, request).await;
+        let response_data = response.data.expect("Response doesn't contain data");
+        updated_stars.push(response_data["updatePlanet"]["star"].clone());
+    }
+
+    assert_eq!("Kepler-16A", updated_stars[0]["name"]);
+    assert!(updated_stars[1].is_null());
+}
+
+#[actix_rt::test]
+async fn test_set_planet_orbit() {
+    env::set_var("DISABLE_AUTH", true.to_string());
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let schema = create_schema_with_context(pool);
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(schema.clone())),
+    )
+    .await;
+
+    let execute = |query: String| {
+        let request = test::TestRequest::post()
+            .uri("/")
+            .set_json(&GraphQLCustomRequest {
+                query,
+                variables: Map::new(),
+            })
+            .to_request();
+        test::call_and_read_body_json::<_, _, serde_json::Value>(&service, request)
+    };
+
+    let mut planet_ids = vec![];
+    for name in ["Earth orbit fixture", "Mars orbit fixture"] {
+        let response = execute(format!(
+            r#"mutation {{ createPlanet(planet: {{ name: "{}", type: TERRESTRIAL_PLANET, details: {{ meanRadius: "6371.0", mass: "5.97e24" }}, star: {{ name: "Orbit fixture star", spectralClass: "G", mass: "1.989e30" }} }}) {{ id }} }}"#,
+            name
+        ))
+        .await;
+        planet_ids.push(response["data"]["createPlanet"]["id"].clone());
+    }
+
+    // elements of the Earth and Mars
+    let set_orbit = |id: &serde_json::Value, orbit: &str| {
+        execute(format!(
+            "mutation {{ setPlanetOrbit(id: {}, orbit: {}) {{ id }} }}",
+            id, orbit
+        ))
+    };
+    set_orbit(&planet_ids[0], r#"{ semiMajorAxis: "1.00000261", eccentricity: "0.01671123", orbitalPeriod: "365.256", longitudeOfPerihelion: "102.93768193", meanLongitude: "100.46457166" }"#).await;
+    set_orbit(&planet_ids[1], r#"{ semiMajorAxis: "1.52371034", eccentricity: "0.09339410", orbitalPeriod: "686.980", longitudeOfPerihelion: "336.05637041", meanLongitude: "355.44656795" }"#).await;
+
+    // the great opposition of Mars
+    let distance_query = format!(
+        r#"{{ distance(from: {}, to: {}, at: "2003-08-27T10:00:00Z") {{ distance {{ astronomicalUnits }} }} }}"#,
+        planet_ids[0], planet_ids[1]
+    );
+    let response = execute(distance_query.clone()).await;
+    let astronomical_units = response["data"]["distance"]["distance"]["astronomicalUnits"]
+        .as_f64()
+        .expect("Can't get distance");
+    assert!((astronomical_units - 0.3727).abs() < 0.005);
+
+    let response = set_orbit(&planet_ids[1], r#"{ semiMajorAxis: "1.5", eccentricity: "1", orbitalPeriod: "686.980", longitudeOfPerihelion: "0", meanLongitude: "0" }"#).await;
+    assert_eq!(
+        "Eccentricity of an elliptic orbit must be from 0 to 1",
+        response["errors"][0]["message"]
+    );
+
+    set_orbit(&planet_ids[1], "null").await;
+    let response = execute(distance_query).await;
+    assert_eq!(
+        "Orbit of Mars orbit fixture is unknown",
+        response["errors"][0]["message"]
+    );
+
+    // orbit changes are planet updates, except for the rejected one
+    let kinds: Vec<_> = schema
+        .execute_stream("subscription { planetEvents(resumeFrom: 0) { kind } }")
+        .map(|response| {
+            response
+                .data
+                .into_json()
+                .expect("Can't convert response to JSON")["planetEvents"]["kind"]
+                .clone()
+        })
+        .take(5)
+        .collect()
+        .await;
+    assert_eq!(
+        ["CREATED", "CREATED", "UPDATED", "UPDATED", "UPDATED"],
+        kinds.as_slice()
+    );
+}
+
+#[actix_rt::test]
+async fn test_planet_atmosphere() {
+    env::set_var("DISABLE_AUTH", true.to_string());
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool))),
+    )
+    .await;
+
+    let execute = |query: String| {
+        test::TestRequest::post()
+            .uri("/")
+            .set_json(&GraphQLCustomRequest {
+                query,
+                variables: Map::new(),
+            })
+            .to_request()
+    };
+
+    let create = r#"
+        mutation {
+            createPlanet(
+                planet: {
+                    name: "Titan"
+                    type: DWARF_PLANET
+                    details: {
+                        meanRadius: "2574.7"
+                        mass: "1.345e23"
+                        atmosphere: [{ gas: "CH4", percentage: "1.4" }, { gas: "N2", percentage: "98.4" }]
+                    }
+                }
+            ) {
+                id
+                details { atmosphere { gas percentage } }
+            }
+        }
+        "#;
+    let response: GraphQLCustomResponse =
+        test::call_and_read_body_json(&service, execute(create.to_string())).await;
+    let created = &response.data.expect("Response doesn't contain data")["createPlanet"];
+    assert_eq!(
+        serde_json::json!([
+            { "gas": "N2", "percentage": "98.4000" },
+            { "gas": "CH4", "percentage": "1.4000" },
+        ]),
+        created["details"]["atmosphere"]
+    );
+    let id = created["id"].as_str().expect("Can't get ID");
+
+    // the atmosphere is kept if it's not specified
+    let update = |atmosphere: &str| {
+        format!(
+            r#"mutation {{
+                updatePlanet(
+                    id: "{}"
+                    planet: {{ name: "Titan", type: DWARF_PLANET, details: {{ meanRadius: "2574.7", mass: "1.345e23"{} }} }}
+                ) {{ details {{ atmosphere {{ gas }} }} }}
+            }}"#,
+            id, atmosphere
+        )
+    };
+    let response: GraphQLCustomResponse =
+        test::call_and_read_body_json(&service, execute(update(""))).await;
+    assert_eq!(
+        2,
+        response.data.expect("Response doesn't contain data")["updatePlanet"]["details"]
+            ["atmosphere"]
+            .as_array()
+            .expect("Can't get atmosphere")
+            .len()
+    );
+
+    let response: GraphQLCustomResponse =
+        test::call_and_read_body_json(&service, execute(update(", atmosphere: []"))).await;
+    assert_eq!(
+        serde_json::json!([]),
+        response.data.expect("Response doesn't contain data")["updatePlanet"]["details"]
+            ["atmosphere"]
+    );
+
+    let response: GraphQLCustomResponse = test::call_and_read_body_json(
+        &service,
+        execute(update(
+            r#", atmosphere: [{ gas: "N2", percentage: "60" }, { gas: "CH4", percentage: "50" }]"#,
+        )),
+    )
+    .await;
+    assert_eq!(
+        "Percentages of gases add up to more than 100",
+        response.errors.expect("Response doesn't contain errors")[0]["message"]
+    );
+}
+
+#[actix_rt::test]
+async fn test_merge_planets() {
+    env::set_var("DISABLE_AUTH", true.to_string());
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let mut conn = pool.get().expect("Can't get DB connection");
+    let target = PlanetFixture::jupiter().insert(&mut conn);
+    let target_id = target.id.to_string();
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool.clone()))),
+    )
+    .await;
+
+    let execute = |query: String| {
+        test::TestRequest::post()
+            .uri("/")
+            .set_json(&GraphQLCustomRequest {
+                query,
+                variables: Map::new(),
+            })
+            .to_request()
+    };
+    let create = |name: &str, atmosphere: &str| {
+        format!(
+            r#"mutation {{
+                createPlanet(
+                    planet: {{ name: "{}", type: ICE_GIANT, details: {{ meanRadius: "24764", mass: "1.024e26"{} }} }}
+                ) {{ id }}
+            }}"#,
+            name, atmosphere
+        )
+    };
+    let mut ids = vec![];
+    for query in [
+        create(
+            "Neptune (imported)",
+            r#", atmosphere: [{ gas: "H2", percentage: "80" }]"#,
+        ),
+        create("Neptune (imported twice)", ""),
+    ] {
+        let response: GraphQLCustomResponse =
+            test::call_and_read_body_json(&service, execute(query)).await;
+        ids.push(
+            response.data.expect("Response doesn't contain data")["createPlanet"]["id"]
+                .as_str()
+                .expect("Can't get ID")
+                .to_string(),
+        );
+    }
+    let merge = |source_id: &str, target_id: &str| {
+        format!(
+            r#"mutation {{
+                mergePlanets(sourceId: "{}", targetId: "{}") {{ name details {{ atmosphere {{ gas }} }} }}
+            }}"#,
+            source_id, target_id
+        )
+    };
+
+    // the target takes the atmosphere, since it has none
+    let response: GraphQLCustomResponse =
+        test::call_and_read_body_json(&service, execute(merge(&ids[0], &ids[1]))).await;
+    assert_eq!(
+        serde_json::json!({ "name": "Neptune (imported twice)", "details": { "atmosphere": [{ "gas": "H2" }] } }),
+        response.data.expect("Response doesn't contain data")["mergePlanets"]
+    );
+
+    // a planet merged into the target of another merge redirects to the final target
+    let response: GraphQLCustomResponse =
+        test::call_and_read_body_json(&service, execute(merge(&ids[1], &target_id))).await;
+    assert_eq!(
+        "Jupiter fixture",
+        response.data.expect("Response doesn't contain data")["mergePlanets"]["name"]
+    );
+    let redirects: Vec<Redirect> =
+        diesel::sql_query("select old_id, planet_id from planet_redirects order by old_id")
+            .load(&mut conn)
+            .expect("Can't get redirects");
+    let expected_ids: Vec<i32> = ids
+        .iter()
+        .map(|id| id.parse().expect("Can't parse ID"))
+        .collect();
+    assert_eq!(
+        vec![(expected_ids[0], target.id), (expected_ids[1], target.id)],
+        redirects
+            .iter()
+            .map(|redirect| (redirect.old_id, redirect.planet_id))
+            .collect::<Vec<_>>()
+    );
+
+    for (source_id, target_id, error) in [
+        (
+            target_id.as_str(),
+            target_id.as_str(),
+            "Can't merge a planet into itself",
+        ),
+        (ids[0].as_str(), target_id.as_str(), "Record not found"),
+    ] {
+        let response: GraphQLCustomResponse =
+            test::call_and_read_body_json(&service, execute(merge(source_id, target_id))).await;
+        assert_eq!(
+            error,
+            response.errors.expect("Response doesn't contain errors")[0]["message"]
+        );
+    }
+
+    // the history of the merged planets moves to the target
+    let query = format!(
+        r#"{{ getPlanet(id: "{}") {{ history {{ versions {{ name }} }} }} }}"#,
+        target_id
+    );
+    let response: GraphQLCustomResponse =
+        test::call_and_read_body_json(&service, execute(query)).await;
+    assert_eq!(
+        serde_json::json!([
+            { "name": "Jupiter fixture" },
+            { "name": "Neptune (imported)" },
+            { "name": "Neptune (imported twice)" },
+        ]),
+        response.data.expect("Response doesn't contain data")["getPlanet"]["history"]["versions"]
+    );
+}
+
+#[actix_rt::test]
+async fn test_saved_reports() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let mut conn = pool.get().expect("Can't get DB connection");
+    let jupiter = PlanetFixture::jupiter().insert(&mut conn);
+    let earth = PlanetFixture::earth().insert(&mut conn);
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool))),
+    )
+    .await;
+
+    let save_report = format!(
+        r#"
+        mutation($query: String!) {{
+            saveReport(name: "planet", query: $query, variables: {{ id: "{}" }}) {{
+                name
+            }}
+        }}
+        "#,
+        jupiter.id
+    );
+
+    for (query, error) in [
+        ("{ getPlanet(id: $id) { nope } }", Some("Invalid report")),
+        (
+            "mutation { deletePlanets(filter: { type: GAS_GIANT }) { affected } }",
+            Some("Only queries can be saved as reports"),
+        ),
+        ("query($id: ID!) { getPlanet(id: $id) { name } }", None),
+    ] {
+        let mut variables = Map::new();
+        variables.insert("query".to_string(), query.into());
+
+        let request = test::TestRequest::post()
+            .uri("/")
+            .insert_header(("user", "alice"))
+            .set_json(&GraphQLCustomRequest {
+                query: save_report.clone(),
+                variables,
+            })
+            .to_request();
+
+        let response: GraphQLCustomResponse =
+            test::call_and_read_body_json(&service, request).await;
+        match error {
+            Some(error) => {
+                let errors = response.errors.expect("Response doesn't contain errors");
+                let message = errors[0]["message"].as_str().expect("Can't get message");
+                assert!(message.starts_with(error), "{}", message);
+            }
+            None => assert_eq!(
+                "planet",
+                response.data.expect("Response doesn't contain data")["saveReport"]["name"]
+            ),
+        }
+    }
+
+    let run_report = r#"{ myReports { name } runReport(name: "planet") }"#;
+    for (user, expected) in [
+        ("alice", Some(r#"{"getPlanet":{"name":"Jupiter fixture"}}"#)),
+        ("bob", None),
+    ] {
+        let request = test::TestRequest::post()
+            .uri("/")
+            .insert_header(("user", user))
+            .set_json(&GraphQLCustomRequest {
+                query: run_report.to_string(),
+                variables: Map::new(),
+            })
+            .to_request();
+
+        let response: GraphQLCustomResponse =
+            test::call_and_read_body_json(&service, request).await;
+        match expected {
+            Some(expected) => {
+                let data = response.data.expect("Response doesn't contain data");
+                assert_eq!("planet", data["myReports"][0]["name"]);
+                assert_eq!(
+                    serde_json::from_str::<serde_json::Value>(expected)
+                        .expect("Can't parse expected data"),
+                    data["runReport"]
+                );
+            }
+            // reports are private
+            None => assert!(response.errors.is_some()),
+        }
+    }
+
+    // variables passed on run override the saved ones and are checked before the execution
+    for (variables, expected) in [
+        (
+            format!(r#"{{ id: "{}" }}"#, earth.id),
+            Ok(r#"{"getPlanet":{"name":"Earth fixture"}}"#),
+        ),
+        (
+            r#"{ id: true, limit: 1 }"#.to_string(),
+            Err(
+                "Invalid variables: Variable $limit is not defined by the operation; \
+                Variable $id expects ID!, got true",
+            ),
+        ),
+    ] {
+        let request = test::TestRequest::post()
+            .uri("/")
+            .insert_header(("user", "alice"))
+            .set_json(&GraphQLCustomRequest {
+                query: format!(
+                    r#"{{ runReport(name: "planet", variables: {}) }}"#,
+                    variables
+                ),
+                variables: Map::new(),
+            })
+            .to_request();
+
+        let response: GraphQLCustomResponse =
+            test::call_and_read_body_json(&service, request).await;
+        match expected {
+            Ok(expected) => assert_eq!(
+                serde_json::from_str::<serde_json::Value>(expected)
+                    .expect("Can't parse expected data"),
+                response.data.expect("Response doesn't contain data")["runReport"]
+            ),
+            Err(expected) => assert_eq!(
+                expected,
+                response.errors.expect("Response doesn't contain errors")[0]["message"]
+            ),
+        }
+    }
+}
+
+#[actix_rt::test]
+async fn test_response_cache() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool))),
+    )
+    .await;
+
+    let create_planet = r#"
+        mutation {
+            createPlanet(
+                planet: {
+                    name: "Cached planet"
+                    type: DWARF_PLANET
+                    details: { meanRadius: "1188.3", mass: "1.303e22" }
+                }
+            ) {
+                id
+            }
+        }
+        "#;
+    let planets_count = "{ getPlanets { name } }";
+    let stats = "{ responseCacheStats { hits misses hitRate } }";
+
+    let mut responses = vec![];
+    for query in [
+        planets_count,
+        planets_count,
+        create_planet,
+        planets_count,
+        stats,
+    ] {
+        let request = test::TestRequest::post()
+            .uri("/")
+            .insert_header(("role", "ADMIN"))
+            .set_json(&GraphQLCustomRequest {
+                query: query.to_string(),
+                variables: Map::new(),
+            })
+            .to_request();
+
+        let response: GraphQLCustomResponse =
+            test::call_and_read_body_json(&service, request).await;
+        responses.push(response.data.expect("Response doesn't contain data"));
+    }
+
+    let count = |index: usize| {
+        responses[index]["getPlanets"]
+            .as_array()
+            .expect("Can't get planets")
+            .len()
+    };
+    assert_eq!(count(0), count(1));
+    // the mutation busts the cached response
+    assert_eq!(count(0) + 1, count(3));
+
+    let stats = &responses[4]["responseCacheStats"];
+    assert_eq!(1, stats["hits"]);
+    // including the stats query itself
+    assert_eq!(3, stats["misses"]);
+    assert_eq!(0.25, stats["hitRate"]);
+}
+
+#[actix_rt::test]
+async fn test_entity_cache() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let mut conn = pool.get().expect("Can't get DB connection");
+    let earth = PlanetFixture::earth().insert(&mut conn);
+    let jupiter = PlanetFixture::jupiter().insert(&mut conn);
+    drop(conn);
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool))),
+    )
+    .await;
+
+    let execute = |query: &str, role: Option<&str>| {
+        let mut request = test::TestRequest::post()
+            .uri("/")
+            .set_json(&GraphQLCustomRequest {
+                query: query.to_string(),
+                variables: Map::new(),
+            });
+        if let Some(role) = role {
+            request = request.insert_header(("role", role));
+        }
+        test::call_and_read_body_json::<_, _, serde_json::Value>(&service, request.to_request())
+    };
+    // different queries for the same entities, so that responses aren't served by the response cache
+    let get_names = |ids: &[&str], role: Option<&str>| {
+        let representations: Vec<String> = ids
+            .iter()
+            .map(|id| format!(r#"{{ __typename: "Planet", id: "{}" }}"#, id))
+            .collect();
+        let query = format!(
+            "{{ _entities(representations: [{}]) {{ ... on Planet {{ name }} }} }}",
+            representations.join(", ")
+        );
+        let response = execute(&query, role);
+        async move {
+            let response = response.await;
+            response["data"]["_entities"]
+                .as_array()
+                .expect("Can't get entities")
+                .iter()
+                .map(|entity| entity["name"].as_str().map(String::from))
+                .collect::<Vec<_>>()
+        }
+    };
+
+    let earth_id = earth.id.to_string();
+    let jupiter_id = jupiter.id.to_string();
+    assert_eq!(
+        vec![Some("Earth fixture".to_string())],
+        get_names(&[&earth_id], None).await
+    );
+    execute(
+        &format!(
+            r#"mutation {{ updatePlanet(id: "{}", planet: {{ name: "Blue planet", type: TERRESTRIAL_PLANET, details: {{ meanRadius: "6371.0", mass: "5.97e24" }} }}) {{ id }} }}"#,
+            earth_id
+        ),
+        Some("ADMIN"),
+    )
+    .await;
+    // the mutation evicts the cached planet
+    assert_eq!(
+        vec![
+            Some("Blue planet".to_string()),
+            Some("Jupiter fixture".to_string())
+        ],
+        get_names(&[&earth_id, &jupiter_id], None).await
+    );
+
+    // drafts loaded by admins aren't served to others
+    let draft = execute(
+        r#"mutation { createPlanet(planet: { name: "Draft planet", type: ICE_GIANT, details: { meanRadius: "10.7", mass: "6.42e+23" } }, draft: true) { id } }"#,
+        Some("ADMIN"),
+    )
+    .await;
+    let draft_id = draft["data"]["createPlanet"]["id"]
+        .as_str()
+        .expect("Can't get ID");
+    assert_eq!(
+        vec![Some("Draft planet".to_string())],
+        get_names(&[draft_id], Some("ADMIN")).await
+    );
+    assert_eq!(vec![None], get_names(&[draft_id], None).await);
+}
+
+#[actix_rt::test]
+async fn test_snapshot() {
+    env::set_var("DISABLE_AUTH", true.to_string());
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool))),
+    )
+    .await;
+
+    let execute = |query: &str, variables: Map<String, serde_json::Value>| {
+        let request = test::TestRequest::post()
+            .uri("/")
+            .set_json(&GraphQLCustomRequest {
+                query: query.to_string(),
+                variables,
+            })
+            .to_request();
+        test::call_and_read_body_json::<_, _, GraphQLCustomResponse>(&service, request)
+    };
+
+    let response = execute("mutation { exportSnapshot }", Map::new()).await;
+    let snapshot = response.data.expect("Response doesn't contain data")["exportSnapshot"].clone();
+
+    execute(
+        "mutation { deletePlanets(filter: { type: GAS_GIANT }) { affected } }",
+        Map::new(),
+    )
+    .await;
+
+    let import = r#"
+        mutation($snapshot: String!, $onConflict: ConflictPolicy!) {
+            importSnapshot(snapshot: $snapshot, onConflict: $onConflict) {
+                created
+                updated
+                skipped
+            }
+        }
+        "#;
+    // the deleted planets are restored with their orbits (Jupiter gets ID 9), then nothing changes
+    for (on_conflict, expected) in [
+        ("SKIP", [2, 0, 6]),
+        ("SKIP", [0, 0, 8]),
+        ("OVERWRITE", [0, 8, 0]),
+    ] {
+        let mut variables = Map::new();
+        variables.insert("snapshot".to_string(), snapshot.clone());
+        variables.insert("onConflict".to_string(), on_conflict.into());
+        let response = execute(import, variables).await;
+        let result = &response.data.expect("Response doesn't contain data")["importSnapshot"];
+        assert_eq!(
+            serde_json::json!(expected),
+            serde_json::json!([result["created"], result["updated"], result["skipped"]])
+        );
+    }
+
+    let mut variables = Map::new();
+    variables.insert("snapshot".to_string(), snapshot);
+    variables.insert("onConflict".to_string(), "FAIL".into());
+    let response = execute(import, variables).await;
+    assert!(response.errors.is_some());
+
+    let response = execute(
+        r#"{ getPlanets { name } distance(from: 3, to: 9, at: "2023-11-03T00:00:00Z") { distance { astronomicalUnits } } }"#,
+        Map::new(),
+    )
+    .await;
+    let data = response.data.expect("Response doesn't contain data");
+    assert_eq!(
+        8,
+        data["getPlanets"]
+            .as_array()
+            .expect("Can't get planets")
+            .len()
+    );
+    assert!(data["distance"]["distance"]["astronomicalUnits"].is_number());
+}
+
+#[actix_rt::test]
+async fn test_snapshot_into_another_database() {
+    env::set_var("DISABLE_AUTH", true.to_string());
+    let docker = Cli::default();
+    let (_source_container, source_pool) = common::setup(&docker);
+    let source_planets = insert_random_planets(
+        3,
+        706,
+        &mut source_pool.get().expect("Can't get DB connection"),
+    );
+    // IDs and UUIDs of the same planets differ between the databases
+    let (_target_container, target_pool) = common::setup(&docker);
+    let mut target_conn = target_pool.get().expect("Can't get DB connection");
+    let target_earth = PlanetFixture::earth().insert(&mut target_conn);
+    let target_planet = PlanetFixture::random(706).insert(&mut target_conn);
+    assert_ne!(source_planets[0].uuid, target_planet.uuid);
+    drop(target_conn);
+
+    let execute = |pool, query: &str, variables: Map<String, serde_json::Value>| {
+        let query = query.to_string();
+        async move {
+            let service = test::init_service(
+                App::new()
+                    .configure(configure_service)
+                    .app_data(web::Data::new(create_schema_with_context(pool))),
+            )
+            .await;
+            let request = test::TestRequest::post()
+                .uri("/")
+                .set_json(&GraphQLCustomRequest { query, variables })
+                .to_request();
+            test::call_and_read_body_json::<_, _, GraphQLCustomResponse>(&service, request).await
+        }
+    };
+
+    let response = execute(source_pool, "mutation { exportSnapshot }", Map::new()).await;
+    let snapshot = response.data.expect("Response doesn't contain data")["exportSnapshot"].clone();
+
+    let import = |on_conflict: &str| {
+        let mut variables = Map::new();
+        variables.insert("snapshot".to_string(), snapshot.clone());
+        variables.insert("onConflict".to_string(), on_conflict.into());
+        execute(
+            target_pool.clone(),
+            r#"
+            mutation($snapshot: String!, $onConflict: ConflictPolicy!) {
+                importSnapshot(snapshot: $snapshot, onConflict: $onConflict) {
+                    created
+                    updated
+                    skipped
+                }
+            }
+            "#,
+            variables,
+        )
+    };
+    let counts = |response: GraphQLCustomResponse| {
+        let result = &response.data.expect("Response doesn't contain data")["importSnapshot"];
+        ["created", "updated", "skipped"]
+            .map(|count| result[count].as_u64().expect("Can't get count"))
+    };
+
+    // planets of the migrations and the first random one exist by name
+    let [created, updated, skipped] = counts(import("SKIP").await);
+    assert_eq!([2, 0], [created, updated]);
+
+    let response = import("FAIL").await;
+    let errors = response.errors.expect("Response doesn't contain errors");
+    assert_eq!(
+        format!(
+            "{} planets of the snapshot already exist",
+            skipped + created
+        ),
+        errors[0]["message"]
+    );
+
+    assert_eq!([0, skipped + created, 0], counts(import("OVERWRITE").await));
+
+    let response = execute(
+        target_pool.clone(),
+        &format!(
+            r#"{{ earth: getPlanet(id: {}) {{ name }} planet: getPlanet(id: {}) {{ name }} }}"#,
+            target_earth.id, target_planet.id
+        ),
+        Map::new(),
+    )
+    .await;
+    let data = response.data.expect("Response doesn't contain data");
+    // planets absent from the snapshot are kept, and the matched ones keep their IDs
+    assert_eq!("Earth fixture", data["earth"]["name"]);
+    assert_eq!(
+        serde_json::json!(source_planets[0].name),
+        data["planet"]["name"]
+    );
+}
+
+#[actix_rt::test]
+async fn test_quantities_stored_in_si_units() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool.clone()))),
+    )
+    .await;
+
+    let execute = |query: &str| {
+        let request = test::TestRequest::post()
+            .uri("/")
+            .insert_header(("role", "ADMIN"))
+            .set_json(&GraphQLCustomRequest {
+                query: query.to_string(),
+                variables: Map::new(),
+            })
+            .to_request();
+        test::call_and_read_body_json::<_, _, GraphQLCustomResponse>(&service, request)
+    };
+    let mut conn = pool.get().expect("Can't get DB connection");
+    let mut stored = |query: &str| {
+        diesel::sql_query(query)
+            .get_result::<StoredQuantity>(&mut conn)
+            .expect("Can't get stored quantity")
+            .value
+    };
+
+    let response = execute(
+        r#"mutation { createPlanet(planet: { name: "Titan", type: DWARF_PLANET, details: { meanRadius: "2574.7", mass: "1.345e23" } }) { id details { meanRadius } } }"#,
+    )
+    .await;
+    let created = &response.data.expect("Response doesn't contain data")["createPlanet"];
+    let created_id = created["id"].as_str().expect("Can't get ID");
+    assert_eq!("2574.7", created["details"]["meanRadius"]);
+    assert_eq!(
+        BigDecimal::from(2574700),
+        stored(&format!(
+            "select mean_radius as value from details where planet_id = {}",
+            created_id
+        ))
+    );
+
+    // elements of Mars, given in astronomical units and days
+    let response = execute(&format!(
+        r#"mutation {{ setPlanetOrbit(id: {}, orbit: {{ semiMajorAxis: "1.52371034", eccentricity: "0.09339410", orbitalPeriod: "686.980", longitudeOfPerihelion: "336.05637041", meanLongitude: "355.44656795" }}) {{ id }} }}"#,
+        created_id
+    ))
+    .await;
+    assert!(response.errors.is_none());
+    assert_eq!(
+        BigDecimal::from(227943822428_i64),
+        stored(&format!(
+            "select semi_major_axis as value from orbits where planet_id = {}",
+            created_id
+        ))
+    );
+    assert_eq!(
+        BigDecimal::from(59355072),
+        stored(&format!(
+            "select orbital_period as value from orbits where planet_id = {}",
+            created_id
+        ))
+    );
+
+    let response = execute(
+        r#"mutation { setClassificationRules(rules: [{ planetType: DWARF_PLANET, maxMeanRadius: "2000.5", priority: 1 }]) { maxMeanRadius } }"#,
+    )
+    .await;
+    assert_eq!(
+        "2000.5",
+        response.data.expect("Response doesn't contain data")["setClassificationRules"][0]
+            ["maxMeanRadius"]
+    );
+    assert_eq!(
+        BigDecimal::from(2000500),
+        stored("select max_mean_radius as value from classification_rules")
+    );
 }
 
 #[derive(Serialize)]
@@ -83,9 +1279,22 @@ struct GraphQLCustomRequest {
     variables: Map<String, serde_json::Value>,
 }
 
+#[derive(QueryableByName)]
+struct Redirect {
+    #[diesel(sql_type = Integer)]
+    old_id: i32,
+    #[diesel(sql_type = Integer)]
+    planet_id: i32,
+}
+
+#[derive(QueryableByName)]
+struct StoredQuantity {
+    #[diesel(sql_type = Numeric)]
+    value: BigDecimal,
+}
+
 #[derive(Deserialize)]
 struct GraphQLCustomResponse {
     data: Option<serde_json::Value>,
-    #[allow(dead_code)]
     errors: Option<serde_json::Value>,
 }
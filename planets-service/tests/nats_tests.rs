@@ -0,0 +1,48 @@
+#![cfg(feature = "nats")]
+
+use async_nats::HeaderMap;
+use testcontainers::clients::Cli;
+
+use planets_service::{create_schema_with_context, nats};
+
+use crate::common::fixtures::PlanetFixture;
+
+mod common;
+
+#[actix_rt::test]
+async fn test_execute_nats_message() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let earth = PlanetFixture::earth().insert(&mut pool.get().expect("Can't get DB connection"));
+    let schema = create_schema_with_context(pool);
+
+    let request = serde_json::json!({
+        "query": format!("{{ getPlanet(id: {}) {{ name }} }}", earth.id)
+    })
+    .to_string();
+    let response = nats::execute(&schema, request.as_bytes(), None).await;
+    let response: serde_json::Value =
+        serde_json::from_slice(&response).expect("Can't parse response");
+    assert_eq!("Earth fixture", response["data"]["getPlanet"]["name"]);
+
+    let request = br#"{"query": "{ classificationRules { planetType } }"}"#;
+    let response = nats::execute(&schema, request, None).await;
+    let response: serde_json::Value =
+        serde_json::from_slice(&response).expect("Can't parse response");
+    assert_eq!("Forbidden", response["errors"][0]["message"]);
+
+    let mut headers = HeaderMap::new();
+    headers.insert("role", "ADMIN");
+    let response = nats::execute(&schema, request, Some(&headers)).await;
+    let response: serde_json::Value =
+        serde_json::from_slice(&response).expect("Can't parse response");
+    assert!(response["errors"].is_null());
+
+    let response = nats::execute(&schema, b"not a request", None).await;
+    let response: serde_json::Value =
+        serde_json::from_slice(&response).expect("Can't parse response");
+    assert!(response["errors"][0]["message"]
+        .as_str()
+        .expect("Can't get error message")
+        .starts_with("Can't parse GraphQL request"));
+}
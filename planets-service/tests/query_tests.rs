@@ -1,11 +1,28 @@
+use std::env;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
 use actix_web::{test, web, App};
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, Duration, Utc};
+use diesel::connection::SimpleConnection;
+use diesel::r2d2::event::CheckoutEvent;
+use diesel::r2d2::{ConnectionManager, HandleEvent, Pool};
+use diesel::sql_types::{Integer, Timestamptz};
+use diesel::{PgConnection, QueryableByName, RunQueryDsl};
 use jsonpath_lib as jsonpath;
 use serde::{Deserialize, Serialize};
 use serde_json::Map;
+use sha2::Digest;
 use testcontainers::clients::Cli;
 
+use planets_service::persistence::model::{NewDetailsEntity, PlanetType, PlanetWrite};
+use planets_service::persistence::repository;
 use planets_service::{configure_service, create_schema_with_context};
 
+use crate::common::fixtures::PlanetFixture;
+
 mod common;
 
 const PLANET_FRAGMENT: &str = "
@@ -27,6 +44,10 @@ const PLANET_FRAGMENT: &str = "
 async fn test_get_planets() {
     let docker = Cli::default();
     let (_pg_container, pool) = common::setup(&docker);
+    let mut conn = pool.get().expect("Can't get DB connection");
+    let earth = PlanetFixture::earth().insert(&mut conn);
+    let random_planet = PlanetFixture::random(689).insert(&mut conn);
+    drop(conn);
 
     let service = test::init_service(
         App::new()
@@ -65,25 +86,43 @@ async fn test_get_planets() {
 
     let response: GraphQLCustomResponse = test::call_and_read_body_json(&service, request).await;
 
-    fn get_planet_as_json(all_planets: &serde_json::Value, index: i32) -> &serde_json::Value {
-        jsonpath::select(all_planets, &format!("$.getPlanets[{}]", index))
-            .expect("Can't get planet by JSON path")[0]
+    fn get_planet_as_json(all_planets: &serde_json::Value, id: i32) -> &serde_json::Value {
+        jsonpath::select(all_planets, "$.getPlanets[*]")
+            .expect("Can't get planets by JSON path")
+            .into_iter()
+            .find(|planet| planet["id"].as_str().and_then(|s| s.parse().ok()) == Some(id))
+            .expect("Can't find planet by id")
     }
 
-    let mercury_json = get_planet_as_json(&response.data, 0);
-    common::check_planet(mercury_json, 1, "Mercury", "TERRESTRIAL_PLANET", "2439.7");
+    let earth_json = get_planet_as_json(&response.data, earth.id);
+    common::check_planet(
+        earth_json,
+        earth.id,
+        "Earth fixture",
+        "TERRESTRIAL_PLANET",
+        "6371.0",
+    );
 
-    let earth_json = get_planet_as_json(&response.data, 2);
-    common::check_planet(earth_json, 3, "Earth", "TERRESTRIAL_PLANET", "6371.0");
-
-    let neptune_json = get_planet_as_json(&response.data, 7);
-    common::check_planet(neptune_json, 8, "Neptune", "ICE_GIANT", "24622.0");
+    let random_planet_json = get_planet_as_json(&response.data, random_planet.id);
+    assert_eq!(
+        random_planet.name,
+        random_planet_json["name"]
+            .as_str()
+            .expect("Can't get name as str")
+    );
+    assert_eq!(
+        random_planet.type_.to_string(),
+        random_planet_json["type"]
+            .as_str()
+            .expect("Can't get type as str")
+    );
 }
 
 #[actix_rt::test]
 async fn test_get_planet_by_id() {
     let docker = Cli::default();
     let (_pg_container, pool) = common::setup(&docker);
+    let earth = PlanetFixture::earth().insert(&mut pool.get().expect("Can't get DB connection"));
 
     let service = test::init_service(
         App::new()
@@ -92,15 +131,16 @@ async fn test_get_planet_by_id() {
     )
     .await;
 
-    let query = "
-        {
-            getPlanet(id: 3) {
-                ... planetFragment
-            }
-        }
+    let query = format!(
         "
-    .to_string()
-        + PLANET_FRAGMENT;
+        {{
+            getPlanet(id: {}) {{
+                ... planetFragment
+            }}
+        }}
+        ",
+        earth.id
+    ) + PLANET_FRAGMENT;
 
     let request_body = GraphQLCustomRequest {
         query,
@@ -116,13 +156,21 @@ async fn test_get_planet_by_id() {
 
     let earth_json =
         jsonpath::select(&response.data, "$.getPlanet").expect("Can't get planet by JSON path")[0];
-    common::check_planet(earth_json, 3, "Earth", "TERRESTRIAL_PLANET", "6371.0");
+    common::check_planet(
+        earth_json,
+        earth.id,
+        "Earth fixture",
+        "TERRESTRIAL_PLANET",
+        "6371.0",
+    );
 }
 
 #[actix_rt::test]
 async fn test_get_planet_by_id_with_variable() {
     let docker = Cli::default();
     let (_pg_container, pool) = common::setup(&docker);
+    let jupiter =
+        PlanetFixture::jupiter().insert(&mut pool.get().expect("Can't get DB connection"));
 
     let service = test::init_service(
         App::new()
@@ -140,9 +188,8 @@ async fn test_get_planet_by_id_with_variable() {
     .to_string()
         + PLANET_FRAGMENT;
 
-    let jupiter_id = 5;
     let mut variables = Map::new();
-    variables.insert("planetId".to_string(), jupiter_id.into());
+    variables.insert("planetId".to_string(), jupiter.id.to_string().into());
 
     let request_body = GraphQLCustomRequest { query, variables };
 
@@ -155,7 +202,1263 @@ async fn test_get_planet_by_id_with_variable() {
 
     let jupiter_json =
         jsonpath::select(&response.data, "$.getPlanet").expect("Can't get planet by JSON path")[0];
-    common::check_planet(jupiter_json, 5, "Jupiter", "GAS_GIANT", "69911.0");
+    common::check_planet(
+        jupiter_json,
+        jupiter.id,
+        "Jupiter fixture",
+        "GAS_GIANT",
+        "69911.0",
+    );
+}
+
+#[actix_rt::test]
+async fn test_get_merged_planet() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let mut conn = pool.get().expect("Can't get DB connection");
+    let duplicate = PlanetFixture::jupiter()
+        .name("Jupiter duplicate fixture")
+        .insert(&mut conn);
+    let jupiter = PlanetFixture::jupiter().insert(&mut conn);
+    repository::merge(duplicate.id, jupiter.id, &mut conn).expect("Can't merge planets");
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool))),
+    )
+    .await;
+
+    let query = |query: &str| {
+        test::TestRequest::post()
+            .uri("/")
+            .set_json(&GraphQLCustomRequest {
+                query: query.to_string(),
+                variables: Map::new(),
+            })
+            .to_request()
+    };
+
+    // the old ID resolves to the planet it was merged into, both in queries and as an entity
+    for operation in [
+        format!(r#"{{ getPlanet(id: "{}") {{ id name }} }}"#, duplicate.id),
+        format!(
+            r#"{{ _entities(representations: [{{ __typename: "Planet", id: "{}" }}]) {{ ... on Planet {{ id name }} }} }}"#,
+            duplicate.id
+        ),
+    ] {
+        let response: serde_json::Value =
+            test::call_and_read_body_json(&service, query(&operation)).await;
+        let planet = response["data"]
+            .as_object()
+            .and_then(|data| data.values().next())
+            .map(|planet| {
+                if planet.is_array() {
+                    &planet[0]
+                } else {
+                    planet
+                }
+            })
+            .expect("Can't get planet");
+        assert_eq!(
+            serde_json::json!({ "id": jupiter.id.to_string(), "name": "Jupiter fixture" }),
+            *planet
+        );
+        assert_eq!(
+            serde_json::json!([{ "from": duplicate.id.to_string(), "to": jupiter.id.to_string() }]),
+            response["extensions"]["redirects"]
+        );
+    }
+
+    let operation = format!(r#"{{ getPlanet(id: "{}") {{ id }} }}"#, jupiter.id);
+    let response: serde_json::Value =
+        test::call_and_read_body_json(&service, query(&operation)).await;
+    assert!(response.get("extensions").is_none());
+}
+
+#[actix_rt::test]
+async fn test_get_planet_as_of() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let mut conn = pool.get().expect("Can't get DB connection");
+    let jupiter = PlanetFixture::jupiter().insert(&mut conn);
+    let planet = PlanetWrite {
+        name: "Renamed Jupiter fixture".to_string(),
+        type_: Some(PlanetType::GasGiant),
+        star: None,
+        status: None,
+    };
+    let details = NewDetailsEntity {
+        mean_radius: BigDecimal::from_str("70000000").expect("Can't parse radius"),
+        mass: BigDecimal::from_str("1.898e27").expect("Can't parse mass"),
+        population: None,
+        planet_id: 0,
+    };
+    repository::update(jupiter.id, planet, details, None, &mut conn)
+        .expect("Can't update planet")
+        .expect("Can't classify planet");
+    // versions start when the rows are changed, so the details change after the planet
+    let get_versions = |table: &str, conn: &mut PgConnection| -> Vec<DateTime<Utc>> {
+        diesel::sql_query(format!(
+            "select valid_from from {table} where planet_id = $1 order by valid_from"
+        ))
+        .bind::<Integer, _>(jupiter.id)
+        .load::<Version>(conn)
+        .expect("Can't get versions")
+        .into_iter()
+        .map(|version| version.valid_from)
+        .collect()
+    };
+    let planet_versions = get_versions("planets_history", &mut conn);
+    let details_versions = get_versions("details_history", &mut conn);
+    assert!(planet_versions[0] < details_versions[0]);
+    assert!(planet_versions[1] < details_versions[1]);
+    drop(conn);
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool))),
+    )
+    .await;
+
+    let get_planet = |as_of: DateTime<Utc>| {
+        let query = format!(
+            r#"{{ getPlanet(id: "{}", asOf: "{}") {{ name details {{ meanRadius }} }} }}"#,
+            jupiter.id,
+            as_of.to_rfc3339()
+        );
+        test::TestRequest::post()
+            .uri("/")
+            .set_json(&GraphQLCustomRequest {
+                query,
+                variables: Map::new(),
+            })
+            .to_request()
+    };
+    let microsecond = Duration::microseconds(1);
+
+    // a version is valid from its start inclusive to the start of the next one exclusive
+    let response: serde_json::Value =
+        test::call_and_read_body_json(&service, get_planet(planet_versions[0] - microsecond)).await;
+    assert!(response["data"]["getPlanet"].is_null());
+
+    for as_of in [details_versions[0], planet_versions[1] - microsecond] {
+        let response: serde_json::Value =
+            test::call_and_read_body_json(&service, get_planet(as_of)).await;
+        assert_eq!("Jupiter fixture", response["data"]["getPlanet"]["name"]);
+        assert_eq!(
+            "69911.0",
+            response["data"]["getPlanet"]["details"]["meanRadius"]
+        );
+    }
+
+    let response: serde_json::Value =
+        test::call_and_read_body_json(&service, get_planet(details_versions[1])).await;
+    assert_eq!(
+        "Renamed Jupiter fixture",
+        response["data"]["getPlanet"]["name"]
+    );
+    assert_eq!(
+        "70000.0",
+        response["data"]["getPlanet"]["details"]["meanRadius"]
+    );
+
+    let response: serde_json::Value =
+        test::call_and_read_body_json(&service, get_planet(Utc::now() + Duration::hours(1))).await;
+    assert_eq!(
+        "asOf can't be in the future",
+        response["errors"][0]["message"]
+    );
+}
+
+#[actix_rt::test]
+async fn test_history_of_transaction_started_earlier() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let mut conn = pool.get().expect("Can't get DB connection");
+    let jupiter = PlanetFixture::jupiter().insert(&mut conn);
+
+    // the transaction starts before the other change, but changes the planet after it
+    let mut early_conn = pool.get().expect("Can't get DB connection");
+    early_conn
+        .batch_execute("begin; select txid_current()")
+        .expect("Can't start transaction");
+    diesel::sql_query("update planets set name = 'Jupiter 2' where id = $1")
+        .bind::<Integer, _>(jupiter.id)
+        .execute(&mut conn)
+        .expect("Can't update planet");
+    early_conn
+        .batch_execute(&format!(
+            "update planets set name = 'Jupiter 3' where id = {}; commit",
+            jupiter.id
+        ))
+        .expect("Can't update planet");
+
+    let versions: Vec<Version> = diesel::sql_query(
+        "select valid_from from planets_history where planet_id = $1 order by valid_from",
+    )
+    .bind::<Integer, _>(jupiter.id)
+    .load(&mut conn)
+    .expect("Can't get versions");
+    assert_eq!(3, versions.len());
+}
+
+#[actix_rt::test]
+async fn test_get_planets_by_ids() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let mut conn = pool.get().expect("Can't get DB connection");
+    let jupiter = PlanetFixture::jupiter().insert(&mut conn);
+    let earth = PlanetFixture::earth().insert(&mut conn);
+    drop(conn);
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool))),
+    )
+    .await;
+
+    let query = "
+        query($ids: [ID!]!) {
+            planetsByIds(ids: $ids) {
+                ... planetFragment
+            }
+        }"
+    .to_string()
+        + PLANET_FRAGMENT;
+
+    let mut variables = Map::new();
+    variables.insert(
+        "ids".to_string(),
+        serde_json::json!([
+            jupiter.id.to_string(),
+            "100000",
+            earth.id.to_string(),
+            "not an ID",
+            earth.id.to_string()
+        ]),
+    );
+
+    let request = test::TestRequest::post()
+        .uri("/")
+        .set_json(&GraphQLCustomRequest { query, variables })
+        .to_request();
+
+    let response: GraphQLCustomResponse = test::call_and_read_body_json(&service, request).await;
+    let planets = response.data["planetsByIds"]
+        .as_array()
+        .expect("Can't get planets");
+
+    assert_eq!(5, planets.len());
+    common::check_planet(
+        &planets[0],
+        jupiter.id,
+        "Jupiter fixture",
+        "GAS_GIANT",
+        "69911.0",
+    );
+    assert!(planets[1].is_null());
+    common::check_planet(
+        &planets[2],
+        earth.id,
+        "Earth fixture",
+        "TERRESTRIAL_PLANET",
+        "6371.0",
+    );
+    assert!(planets[3].is_null());
+    assert_eq!(planets[2], planets[4]);
+}
+
+#[actix_rt::test]
+async fn test_validate_query() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool))),
+    )
+    .await;
+
+    let query = "
+        {
+            getPlanet(id: 3) {
+                name
+                details {
+                    meanRadius
+                }
+            }
+        }
+        "
+    .to_string();
+
+    let request = test::TestRequest::post()
+        .uri("/validate")
+        .set_json(&GraphQLCustomRequest {
+            query,
+            variables: Map::new(),
+        })
+        .to_request();
+
+    let response: serde_json::Value = test::call_and_read_body_json(&service, request).await;
+
+    assert_eq!(serde_json::Value::Null, response["data"]);
+    let validation = &response["extensions"]["validation"];
+    assert_eq!(true, validation["valid"]);
+    assert_eq!(3, validation["depth"]);
+    assert_eq!(4, validation["complexity"]);
+    assert_eq!(
+        "query { getPlanet(id: 3) { name details { meanRadius } } }",
+        validation["normalizedQuery"]
+    );
+
+    let request = test::TestRequest::post()
+        .uri("/validate")
+        .set_json(&GraphQLCustomRequest {
+            query: "{ getPlanet(id: 3) { unknownField } }".to_string(),
+            variables: Map::new(),
+        })
+        .to_request();
+
+    let response: serde_json::Value = test::call_and_read_body_json(&service, request).await;
+
+    let validation = &response["extensions"]["validation"];
+    assert_eq!(false, validation["valid"]);
+    // they aren't computed for operations failing the validation rules
+    assert!(validation.get("complexity").is_none());
+    assert!(validation.get("depth").is_none());
+}
+
+#[actix_rt::test]
+async fn test_habitability_score_feature() {
+    env::set_var(
+        "FEATURE_FLAGS",
+        r#"{"HABITABILITY_SCORE": {"roles": ["USER"]}}"#,
+    );
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool))),
+    )
+    .await;
+
+    let request_body = GraphQLCustomRequest {
+        query: "{ getPlanet(id: 3) { habitabilityScore } }".to_string(),
+        variables: Map::new(),
+    };
+
+    let request = test::TestRequest::post()
+        .uri("/")
+        .insert_header(("role", "USER"))
+        .set_json(&request_body)
+        .to_request();
+
+    let response: GraphQLCustomResponse = test::call_and_read_body_json(&service, request).await;
+
+    let earth_score = response.data["getPlanet"]["habitabilityScore"]
+        .as_f64()
+        .expect("Can't get habitability score");
+    assert!(earth_score > 0.99);
+
+    let request = test::TestRequest::post()
+        .uri("/")
+        .set_json(&request_body)
+        .to_request();
+
+    let response: GraphQLCustomResponse = test::call_and_read_body_json(&service, request).await;
+
+    assert!(response.data["getPlanet"].is_null());
+}
+
+#[actix_rt::test]
+async fn test_sample_planets() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool))),
+    )
+    .await;
+
+    let request_body = GraphQLCustomRequest {
+        query: "{ samplePlanets(n: 3, seed: 7) { name } randomPlanet(seed: 7) { name } }"
+            .to_string(),
+        variables: Map::new(),
+    };
+
+    let mut responses = vec![];
+    for _ in 0..2 {
+        let request = test::TestRequest::post()
+            .uri("/")
+            .set_json(&request_body)
+            .to_request();
+        let response: GraphQLCustomResponse =
+            test::call_and_read_body_json(&service, request).await;
+        responses.push(response.data);
+    }
+
+    assert_eq!(responses[0], responses[1]);
+
+    let names =
+        jsonpath::select(&responses[0], "$.samplePlanets[*].name").expect("Can't get planet names");
+    let unique_names = names
+        .iter()
+        .map(|name| name.as_str().expect("Can't get name"))
+        .collect::<std::collections::HashSet<_>>();
+    assert_eq!(3, unique_names.len());
+    // a random planet is the first one of a sample with the same seed
+    assert_eq!(names[0], &responses[0]["randomPlanet"]["name"]);
+}
+
+#[actix_rt::test]
+async fn test_repeated_entity_representations() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let mut conn = pool.get().expect("Can't get DB connection");
+    let earth = PlanetFixture::earth().insert(&mut conn);
+    let jupiter = PlanetFixture::jupiter().insert(&mut conn);
+    drop(conn);
+
+    let checkouts = CheckoutCounter::default();
+    let counted_pool = Pool::builder()
+        .event_handler(Box::new(checkouts.clone()))
+        .build(ConnectionManager::new(
+            env::var("DATABASE_URL").expect("Can't get DB URL"),
+        ))
+        .expect("Can't create pool");
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(counted_pool))),
+    )
+    .await;
+    checkouts.0.store(0, Ordering::SeqCst);
+
+    let query = format!(
+        r#"
+        {{
+            _entities(representations: [
+                {{ __typename: "Planet", id: "{earth}" }},
+                {{ __typename: "Planet", id: "{earth}" }},
+                {{ __typename: "Planet", id: "{jupiter}" }},
+                {{ __typename: "Planet", id: "100000" }}
+            ]) {{
+                ... on Planet {{
+                    id
+                    name
+                }}
+            }}
+            getPlanet(id: {earth}) {{
+                name
+            }}
+        }}
+        "#,
+        earth = earth.id,
+        jupiter = jupiter.id
+    );
+
+    let request_body = GraphQLCustomRequest {
+        query,
+        variables: Map::new(),
+    };
+
+    let request = test::TestRequest::post()
+        .uri("/")
+        .set_json(&request_body)
+        .to_request();
+
+    let response: GraphQLCustomResponse = test::call_and_read_body_json(&service, request).await;
+
+    let entities = &response.data["_entities"];
+    assert_eq!("Earth fixture", entities[0]["name"]);
+    assert_eq!(entities[0], entities[1]);
+    assert_eq!("Jupiter fixture", entities[2]["name"]);
+    assert!(entities[3].is_null());
+    assert_eq!("Earth fixture", response.data["getPlanet"]["name"]);
+    // one batch loads the planets, the other query looks for a redirect of the missing one
+    assert_eq!(2, checkouts.0.load(Ordering::SeqCst));
+}
+
+#[actix_rt::test]
+async fn test_memory_stats() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool))),
+    )
+    .await;
+
+    let mut responses = vec![];
+    for query in [
+        "{ getPlanets { name } }",
+        "{ memoryStats { allocator { allocated } heapProfiling caches { name entries estimatedBytes } } }",
+    ] {
+        let request = test::TestRequest::post()
+            .uri("/")
+            .insert_header(("role", "ADMIN"))
+            .set_json(&GraphQLCustomRequest {
+                query: query.to_string(),
+                variables: Map::new(),
+            })
+            .to_request();
+
+        let response: GraphQLCustomResponse =
+            test::call_and_read_body_json(&service, request).await;
+        responses.push(response.data);
+    }
+
+    let stats = &responses[1]["memoryStats"];
+    // statistics of jemalloc are available only with the feature
+    if cfg!(feature = "jemalloc") {
+        assert!(stats["allocator"]["allocated"].is_number());
+    } else {
+        assert!(stats["allocator"].is_null());
+    }
+    assert!(stats["heapProfiling"].is_null());
+    let responses_cache = jsonpath::select(stats, "$.caches[?(@.name == 'responses')]")
+        .expect("Can't get cache by JSON path")[0];
+    assert_eq!(1, responses_cache["entries"]);
+    assert!(
+        responses_cache["estimatedBytes"]
+            .as_u64()
+            .expect("Can't get size")
+            > 0
+    );
+}
+
+#[actix_rt::test]
+async fn test_renamed_field() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool))),
+    )
+    .await;
+
+    let request_body = GraphQLCustomRequest {
+        query: "{ getPlanet(id: 1) { type planetType } }".to_string(),
+        variables: Map::new(),
+    };
+
+    let request = test::TestRequest::post()
+        .uri("/")
+        .insert_header(("user-agent", "legacy-client/1.0"))
+        .set_json(&request_body)
+        .to_request();
+
+    let response: GraphQLCustomResponse = test::call_and_read_body_json(&service, request).await;
+
+    assert_eq!(
+        "TERRESTRIAL_PLANET",
+        response.data["getPlanet"]["planetType"]
+    );
+    assert_eq!(
+        response.data["getPlanet"]["planetType"],
+        response.data["getPlanet"]["type"]
+    );
+
+    let request_body = GraphQLCustomRequest {
+        query: "{ renamedFieldUsage { oldName newName operations lastUsedAt userAgents { userAgent operations } } }"
+            .to_string(),
+        variables: Map::new(),
+    };
+
+    let request = test::TestRequest::post()
+        .uri("/")
+        .insert_header(("role", "ADMIN"))
+        .set_json(&request_body)
+        .to_request();
+
+    let response: GraphQLCustomResponse = test::call_and_read_body_json(&service, request).await;
+
+    let usage = &response.data["renamedFieldUsage"][0];
+    assert_eq!("type", usage["oldName"]);
+    assert_eq!("planetType", usage["newName"]);
+    assert!(usage["operations"].as_u64().expect("Can't get operations") >= 1);
+    assert!(usage["lastUsedAt"].is_u64());
+    let user_agents = usage["userAgents"]
+        .as_array()
+        .expect("Can't get user agents");
+    assert!(user_agents
+        .iter()
+        .any(|usage| usage["userAgent"] == "legacy-client/1.0" && usage["operations"] == 1));
+}
+
+#[actix_rt::test]
+async fn test_get_planet_with_graphql_body() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let earth = PlanetFixture::earth().insert(&mut pool.get().expect("Can't get DB connection"));
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool))),
+    )
+    .await;
+
+    let query = "
+        query planet($planetId: ID!) { getPlanet(id: $planetId) { name } }
+        query planets { getPlanets { name } }
+        ";
+
+    let request = test::TestRequest::post()
+        .uri(&format!(
+            "/?operationName=planet&variables=%7B%22planetId%22%3A%22{}%22%7D",
+            earth.id
+        ))
+        .insert_header(("content-type", "application/graphql"))
+        .set_payload(query)
+        .to_request();
+
+    let response: GraphQLCustomResponse = test::call_and_read_body_json(&service, request).await;
+
+    assert_eq!("Earth fixture", response.data["getPlanet"]["name"]);
+}
+
+#[actix_rt::test]
+async fn test_binary_response_formats() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool))),
+    )
+    .await;
+
+    let request_body = GraphQLCustomRequest {
+        query: "{ getPlanets { id name type details { meanRadius } } }".to_string(),
+        variables: Map::new(),
+    };
+
+    let request = test::TestRequest::post()
+        .uri("/")
+        .set_json(&request_body)
+        .to_request();
+    let json_response: serde_json::Value = test::call_and_read_body_json(&service, request).await;
+
+    let request = test::TestRequest::post()
+        .uri("/")
+        .insert_header(("accept", "application/msgpack"))
+        .set_json(&request_body)
+        .to_request();
+    let response = test::call_service(&service, request).await;
+    assert_eq!(
+        Some("application/msgpack"),
+        response
+            .headers()
+            .get("content-type")
+            .and_then(|value| value.to_str().ok())
+    );
+    let body = test::read_body(response).await;
+    let msgpack_response: serde_json::Value =
+        rmp_serde::from_slice(&body).expect("Can't decode MessagePack");
+    assert_eq!(json_response, msgpack_response);
+
+    let request = test::TestRequest::post()
+        .uri("/")
+        .insert_header(("accept", "application/cbor"))
+        .set_json(&request_body)
+        .to_request();
+    let body = test::call_and_read_body(&service, request).await;
+    let cbor_response: serde_json::Value =
+        ciborium::from_reader(body.as_ref()).expect("Can't decode CBOR");
+    assert_eq!(json_response, cbor_response);
+}
+
+#[actix_rt::test]
+async fn test_distance() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let mut conn = pool.get().expect("Can't get DB connection");
+    // elements of the Earth and Mars
+    let earth = PlanetFixture::earth()
+        .star("Distance fixture star")
+        .orbit([
+            "1.00000261",
+            "0.01671123",
+            "365.256",
+            "102.93768193",
+            "100.46457166",
+        ])
+        .insert(&mut conn);
+    let mars = PlanetFixture::earth()
+        .name("Mars fixture")
+        .star("Distance fixture star")
+        .orbit([
+            "1.52371034",
+            "0.09339410",
+            "686.980",
+            "336.05637041",
+            "355.44656795",
+        ])
+        .insert(&mut conn);
+    drop(conn);
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool))),
+    )
+    .await;
+
+    // the great opposition of Mars
+    let query = format!(
+        r#"
+        {{
+            distance(from: {}, to: {}, at: "2003-08-27T10:00:00Z") {{
+                distance {{
+                    kilometers
+                    astronomicalUnits
+                }}
+                lightTravelTime {{
+                    seconds
+                    minutes
+                }}
+            }}
+        }}
+        "#,
+        earth.id, mars.id
+    );
+
+    let request = test::TestRequest::post()
+        .uri("/")
+        .set_json(&GraphQLCustomRequest {
+            query,
+            variables: Map::new(),
+        })
+        .to_request();
+
+    let response: GraphQLCustomResponse = test::call_and_read_body_json(&service, request).await;
+
+    let distance = &response.data["distance"];
+    let get = |path: &str| {
+        jsonpath::select(distance, path).expect("Can't get value by JSON path")[0]
+            .as_f64()
+            .expect("Can't get number")
+    };
+    assert!((get("$.distance.astronomicalUnits") - 0.3727).abs() < 0.005);
+    assert!((get("$.distance.kilometers") - 55.76e6).abs() < 1e6);
+    assert!((get("$.lightTravelTime.minutes") - 3.1).abs() < 0.1);
+    assert_eq!(
+        get("$.lightTravelTime.seconds") / 60.0,
+        get("$.lightTravelTime.minutes")
+    );
+}
+
+#[actix_rt::test]
+async fn test_big_decimal_format() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let earth = PlanetFixture::earth().insert(&mut pool.get().expect("Can't get DB connection"));
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool))),
+    )
+    .await;
+
+    let query = format!(
+        "
+        {{
+            getPlanet(id: {}) {{
+                ... planetFragment
+            }}
+        }}
+        ",
+        earth.id
+    ) + PLANET_FRAGMENT;
+
+    let get_details = |format: Option<&'static str>| {
+        let mut request = test::TestRequest::post()
+            .uri("/")
+            .set_json(&GraphQLCustomRequest {
+                query: query.clone(),
+                variables: Map::new(),
+            });
+        if let Some(format) = format {
+            request = request.insert_header(("big-decimal-format", format));
+        }
+        request.to_request()
+    };
+
+    let response: GraphQLCustomResponse =
+        test::call_and_read_body_json(&service, get_details(Some("number"))).await;
+    let details = &response.data["getPlanet"]["details"];
+    assert_eq!(serde_json::json!(6371), details["meanRadius"]);
+    assert_eq!(serde_json::json!(7.53), details["population"]);
+    // BigInt isn't affected
+    assert!(details["mass"].is_string());
+
+    // the same operation isn't served from the cache in another format
+    let response: GraphQLCustomResponse =
+        test::call_and_read_body_json(&service, get_details(None)).await;
+    let details = &response.data["getPlanet"]["details"];
+    assert_eq!("6371.0", details["meanRadius"]);
+    assert_eq!("7.53", details["population"]);
+}
+
+#[actix_rt::test]
+async fn test_planet_status() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool))),
+    )
+    .await;
+
+    let execute = |query: &str, role: Option<&str>| {
+        let mut request = test::TestRequest::post()
+            .uri("/")
+            .set_json(&GraphQLCustomRequest {
+                query: query.to_string(),
+                variables: Map::new(),
+            });
+        if let Some(role) = role {
+            request = request.insert_header(("role", role));
+        }
+        test::call_and_read_body_json::<_, _, serde_json::Value>(&service, request.to_request())
+    };
+    let count = |response: &serde_json::Value| {
+        response["data"]["getPlanets"]
+            .as_array()
+            .expect("Can't get planets")
+            .len()
+    };
+
+    let published_count = count(&execute("{ getPlanets { id } }", None).await);
+    let response = execute(
+        r#"mutation { createPlanet(planet: { name: "Draft planet", type: ICE_GIANT, details: { meanRadius: "10.7", mass: "6.42e+23" } }, draft: true) { id status } }"#,
+        Some("ADMIN"),
+    )
+    .await;
+    assert_eq!("DRAFT", response["data"]["createPlanet"]["status"]);
+    let id = response["data"]["createPlanet"]["id"]
+        .as_str()
+        .expect("Can't get ID")
+        .to_string();
+    let get_planet = format!("{{ getPlanet(id: {}) {{ name }} }}", id);
+
+    // drafts are visible only to admins
+    assert_eq!(
+        published_count,
+        count(&execute("{ getPlanets { id } }", None).await)
+    );
+    let response = execute(&get_planet, None).await;
+    assert!(response["data"]["getPlanet"].is_null());
+    let response = execute(
+        &format!(
+            r#"{{ distance(from: {0}, to: {0}, at: "2003-08-27T10:00:00Z") {{ distance {{ kilometers }} }} }}"#,
+            id
+        ),
+        None,
+    )
+    .await;
+    assert_eq!("Record not found", response["errors"][0]["message"]);
+    let response = execute("{ getPlanets(status: DRAFT) { name } }", Some("ADMIN")).await;
+    assert_eq!("Draft planet", response["data"]["getPlanets"][0]["name"]);
+    let response = execute("{ getPlanets(status: DRAFT) { name } }", None).await;
+    assert!(response["errors"].is_array());
+    let archive_planet = format!("mutation {{ archivePlanet(id: {}) {{ status }} }}", id);
+    let response = execute(&archive_planet, Some("ADMIN")).await;
+    assert_eq!(
+        "Only published planets can be archived",
+        response["errors"][0]["message"]
+    );
+
+    let publish_planet = format!("mutation {{ publishPlanet(id: {}) {{ status }} }}", id);
+    let response = execute(&publish_planet, Some("ADMIN")).await;
+    assert_eq!("PUBLISHED", response["data"]["publishPlanet"]["status"]);
+    let response = execute(&get_planet, None).await;
+    assert_eq!("Draft planet", response["data"]["getPlanet"]["name"]);
+    let response = execute(&publish_planet, Some("ADMIN")).await;
+    assert_eq!(
+        "Planet is already published",
+        response["errors"][0]["message"]
+    );
+
+    execute(&archive_planet, Some("ADMIN")).await;
+    let response = execute(&get_planet, None).await;
+    assert!(response["data"]["getPlanet"].is_null());
+    assert_eq!(
+        published_count,
+        count(&execute("{ getPlanets { id } }", None).await)
+    );
+}
+
+#[actix_rt::test]
+async fn test_get_planets_order() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let mut conn = pool.get().expect("Can't get DB connection");
+    let beta = PlanetFixture::jupiter()
+        .name("Beta fixture")
+        .insert(&mut conn)
+        .id
+        .to_string();
+    let alpha = PlanetFixture::earth()
+        .name("Alpha fixture")
+        .insert(&mut conn)
+        .id
+        .to_string();
+    let gamma = PlanetFixture::jupiter()
+        .name("Gamma fixture")
+        .insert(&mut conn)
+        .id
+        .to_string();
+    drop(conn);
+    let fixture_ids = [&beta, &alpha, &gamma];
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool))),
+    )
+    .await;
+
+    // the order among the fixtures, planets added by migrations are left out
+    let get_ids = |order_by: &str| {
+        let request = test::TestRequest::post()
+            .uri("/")
+            .set_json(&GraphQLCustomRequest {
+                query: format!("{{ getPlanets{} {{ id }} }}", order_by),
+                variables: Map::new(),
+            })
+            .to_request();
+        let response = test::call_and_read_body_json(&service, request);
+        async move {
+            let response: GraphQLCustomResponse = response.await;
+            jsonpath::select(&response.data, "$.getPlanets[*].id")
+                .expect("Can't get IDs")
+                .into_iter()
+                .map(|id| id.as_str().expect("Can't get ID").to_string())
+                .filter(|id| fixture_ids.contains(&id))
+                .collect::<Vec<_>>()
+        }
+    };
+
+    assert_eq!(vec![beta.as_str(), &alpha, &gamma], get_ids("").await);
+    assert_eq!(
+        vec![gamma.as_str(), &alpha, &beta],
+        get_ids("(orderBy: { direction: DESC })").await
+    );
+    assert_eq!(
+        vec![alpha.as_str(), &beta, &gamma],
+        get_ids("(orderBy: { field: NAME })").await
+    );
+    // planets of the same type are ordered by ID ascending in both directions
+    assert_eq!(
+        vec![alpha.as_str(), &beta, &gamma],
+        get_ids("(orderBy: { field: TYPE, direction: DESC })").await
+    );
+}
+
+#[actix_rt::test]
+async fn test_data_quality_report() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let mut conn = pool.get().expect("Can't get DB connection");
+    let massless = PlanetFixture::earth()
+        .name("Massless fixture")
+        .mass("0")
+        .insert(&mut conn);
+    let fresh = PlanetFixture::jupiter().insert(&mut conn);
+    // a planet whose versions were made by migrations only
+    let unchanged = PlanetFixture::earth().insert(&mut conn);
+    for table in ["planets_history", "details_history"] {
+        diesel::sql_query(format!(
+            "update {} set transaction_id = 0 where planet_id = {}",
+            table, unchanged.id
+        ))
+        .execute(&mut conn)
+        .expect("Can't update history");
+    }
+    let bare = PlanetFixture::jupiter()
+        .name("Bare fixture")
+        .insert(&mut conn);
+    diesel::sql_query(format!("delete from details where planet_id = {}", bare.id))
+        .execute(&mut conn)
+        .expect("Can't delete details");
+    drop(conn);
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool))),
+    )
+    .await;
+
+    let query = "{ dataQualityReport { findings { check severity planetId message } } }";
+    let request = test::TestRequest::post()
+        .uri("/")
+        .insert_header(("role", "ADMIN"))
+        .set_json(&GraphQLCustomRequest {
+            query: query.to_string(),
+            variables: Map::new(),
+        })
+        .to_request();
+    let response: serde_json::Value = test::call_and_read_body_json(&service, request).await;
+
+    // the planets added by migrations are reported too
+    let fixture_ids = [massless.id, fresh.id, unchanged.id, bare.id].map(|id| id.to_string());
+    let findings: Vec<&serde_json::Value> = response["data"]["dataQualityReport"]["findings"]
+        .as_array()
+        .expect("Can't get findings")
+        .iter()
+        .filter(|finding| fixture_ids.iter().any(|id| finding["planetId"] == *id))
+        .collect();
+    // errors come first
+    assert_eq!(
+        vec![
+            &serde_json::json!({ "check": "NON_POSITIVE_MASS", "severity": "ERROR", "planetId": massless.id.to_string(), "message": "Mass is 0" }),
+            &serde_json::json!({ "check": "MISSING_DETAILS", "severity": "ERROR", "planetId": bare.id.to_string(), "message": "The planet has no details" }),
+            &serde_json::json!({ "check": "TYPE_MISMATCH", "severity": "WARNING", "planetId": massless.id.to_string(), "message": "Type is TERRESTRIAL_PLANET, but classification rules give DWARF_PLANET" }),
+            &serde_json::json!({ "check": "STALE", "severity": "INFO", "planetId": unchanged.id.to_string(), "message": "No changes were recorded" }),
+        ],
+        findings
+    );
+
+    let request = test::TestRequest::post()
+        .uri("/")
+        .set_json(&GraphQLCustomRequest {
+            query: query.to_string(),
+            variables: Map::new(),
+        })
+        .to_request();
+    let response: serde_json::Value = test::call_and_read_body_json(&service, request).await;
+    assert_eq!("Forbidden", response["errors"][0]["message"]);
+}
+
+#[actix_rt::test]
+async fn test_planet_history() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let mut conn = pool.get().expect("Can't get DB connection");
+    let jupiter = PlanetFixture::jupiter().insert(&mut conn);
+    for name in ["Jupiter 2", "Jupiter 3"] {
+        let planet = PlanetWrite {
+            name: name.to_string(),
+            type_: Some(PlanetType::GasGiant),
+            star: None,
+            status: None,
+        };
+        let details = NewDetailsEntity {
+            mean_radius: BigDecimal::from_str("70000000").expect("Can't parse radius"),
+            mass: BigDecimal::from_str("1.898e27").expect("Can't parse mass"),
+            population: None,
+            planet_id: 0,
+        };
+        repository::update(jupiter.id, planet, details, None, &mut conn)
+            .expect("Can't update planet")
+            .expect("Can't classify planet");
+    }
+    drop(conn);
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool))),
+    )
+    .await;
+
+    let get_history = |arguments: &str| {
+        let query = format!(
+            r#"{{ getPlanet(id: "{}") {{ history({}) {{ versions {{ name validFrom }} endCursor hasNextPage }} }} }}"#,
+            jupiter.id, arguments
+        );
+        let request = test::TestRequest::post()
+            .uri("/")
+            .set_json(&GraphQLCustomRequest {
+                query,
+                variables: Map::new(),
+            })
+            .to_request();
+        let response = test::call_and_read_body_json(&service, request);
+        async move {
+            let response: serde_json::Value = response.await;
+            response["data"]["getPlanet"]["history"].clone()
+        }
+    };
+    let names = |page: &serde_json::Value| {
+        page["versions"]
+            .as_array()
+            .expect("Can't get versions")
+            .iter()
+            .map(|version| {
+                version["name"]
+                    .as_str()
+                    .expect("Can't get name")
+                    .to_string()
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let page = get_history("limit: 2").await;
+    assert_eq!(vec!["Jupiter fixture", "Jupiter 2"], names(&page));
+    assert_eq!(true, page["hasNextPage"]);
+    assert_eq!(page["versions"][1]["validFrom"], page["endCursor"]);
+    let second_page = get_history(&format!("limit: 2, after: {}", page["endCursor"])).await;
+    assert_eq!(vec!["Jupiter 3"], names(&second_page));
+    assert_eq!(false, second_page["hasNextPage"]);
+
+    let page = get_history("direction: DESC").await;
+    assert_eq!(
+        vec!["Jupiter 3", "Jupiter 2", "Jupiter fixture"],
+        names(&page)
+    );
+
+    // `from` is inclusive, `to` is exclusive
+    let all = get_history("limit: 100").await;
+    let page = get_history(&format!(
+        "from: {}, to: {}",
+        all["versions"][1]["validFrom"], all["versions"][2]["validFrom"]
+    ))
+    .await;
+    assert_eq!(vec!["Jupiter 2"], names(&page));
+}
+
+#[actix_rt::test]
+async fn test_planet_history_of_drafts() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let mut conn = pool.get().expect("Can't get DB connection");
+    let jupiter = PlanetFixture::jupiter().insert(&mut conn);
+    for (name, status, mean_radius) in [
+        ("Jupiter draft", "DRAFT", 70_000_000),
+        ("Jupiter published", "PUBLISHED", 71_000_000),
+    ] {
+        conn.batch_execute(&format!(
+            "begin; \
+             update planets set name = '{name}', status = '{status}' where id = {id}; \
+             update details set mean_radius = {mean_radius} where planet_id = {id}; \
+             commit",
+            id = jupiter.id
+        ))
+        .expect("Can't update planet");
+    }
+    drop(conn);
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool))),
+    )
+    .await;
+
+    let get_history = |role: &str| {
+        let query = format!(
+            r#"{{ getPlanet(id: "{}") {{ history {{ versions {{ name }} }} detailsHistory {{ versions {{ meanRadius }} }} }} }}"#,
+            jupiter.id
+        );
+        let request = test::TestRequest::post()
+            .uri("/")
+            .insert_header(("role", role))
+            .set_json(&GraphQLCustomRequest {
+                query,
+                variables: Map::new(),
+            })
+            .to_request();
+        let response = test::call_and_read_body_json(&service, request);
+        async move {
+            let response: serde_json::Value = response.await;
+            let values = |path: &str| {
+                jsonpath::select(&response, path)
+                    .expect("Can't select values")
+                    .into_iter()
+                    .map(|value| value.as_str().expect("Can't get value").to_string())
+                    .collect::<Vec<_>>()
+            };
+            (
+                values("$.data.getPlanet.history.versions[*].name"),
+                values("$.data.getPlanet.detailsHistory.versions[*].meanRadius"),
+            )
+        }
+    };
+
+    let (names, mean_radii) = get_history("ADMIN").await;
+    assert_eq!(
+        vec!["Jupiter fixture", "Jupiter draft", "Jupiter published"],
+        names
+    );
+    assert_eq!(vec!["69911.0", "70000.0", "71000.0"], mean_radii);
+
+    // the draft details were current while the planet was published only within the
+    // transaction which published it
+    let (names, mean_radii) = get_history("USER").await;
+    assert_eq!(vec!["Jupiter fixture", "Jupiter published"], names);
+    assert_eq!(vec!["69911.0", "71000.0"], mean_radii);
+}
+
+#[actix_rt::test]
+async fn test_server_info() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool))),
+    )
+    .await;
+
+    let query = "{ serverInfo { version gitSha builtAt features schemaHash } }";
+    let request = test::TestRequest::post()
+        .uri("/")
+        .insert_header(("role", "ADMIN"))
+        .set_json(&GraphQLCustomRequest {
+            query: query.to_string(),
+            variables: Map::new(),
+        })
+        .to_request();
+    let response: serde_json::Value = test::call_and_read_body_json(&service, request).await;
+    let server_info = &response["data"]["serverInfo"];
+    assert_eq!(env!("CARGO_PKG_VERSION"), server_info["version"]);
+    assert!(server_info["features"]
+        .as_array()
+        .expect("Can't get features")
+        .contains(&"actix".into()));
+    // clients are generated from the same SDL
+    let sdl = planets_service::schema_sdl();
+    assert_eq!(
+        hex::encode(sha2::Sha256::digest(sdl)),
+        server_info["schemaHash"]
+    );
+
+    let request = test::TestRequest::post()
+        .uri("/")
+        .set_json(&GraphQLCustomRequest {
+            query: query.to_string(),
+            variables: Map::new(),
+        })
+        .to_request();
+    let response: serde_json::Value = test::call_and_read_body_json(&service, request).await;
+    assert_eq!("Forbidden", response["errors"][0]["message"]);
+}
+
+#[derive(QueryableByName)]
+struct Version {
+    #[diesel(sql_type = Timestamptz)]
+    valid_from: DateTime<Utc>,
 }
 
 #[derive(Serialize)]
@@ -168,3 +1471,13 @@ struct GraphQLCustomRequest {
 struct GraphQLCustomResponse {
     data: serde_json::Value,
 }
+
+/// Counts connections taken from a pool, i.e. database round trips of resolvers
+#[derive(Clone, Debug, Default)]
+struct CheckoutCounter(Arc<AtomicUsize>);
+
+impl HandleEvent for CheckoutCounter {
+    fn handle_checkout(&self, _event: CheckoutEvent) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
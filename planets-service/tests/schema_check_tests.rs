@@ -0,0 +1,36 @@
+use diesel::{sql_query, RunQueryDsl};
+use testcontainers::clients::Cli;
+
+use planets_service::persistence::schema_check::check_schema;
+
+mod common;
+
+#[test]
+fn test_schema_check() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let mut conn = pool.get().expect("Can't get DB connection");
+
+    check_schema(&mut conn)
+        .expect("Can't check schema")
+        .expect("Schema should match");
+
+    sql_query("alter table details drop column population")
+        .execute(&mut conn)
+        .expect("Can't drop column");
+    sql_query("alter table planets alter column name type text")
+        .execute(&mut conn)
+        .expect("Can't change column type");
+
+    let mismatches = check_schema(&mut conn)
+        .expect("Can't check schema")
+        .expect_err("Schema shouldn't match");
+
+    assert_eq!(
+        vec![
+            "column planets.name has type 'text', but 'character varying' is expected",
+            "column details.population is missing",
+        ],
+        mismatches.0
+    );
+}
@@ -0,0 +1,434 @@
+use std::env;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use actix_web::body::MessageBody;
+use actix_web::{test, web, App, HttpServer};
+use async_graphql::{Response, Schema};
+use futures::{future, SinkExt, Stream, StreamExt};
+use jsonpath_lib as jsonpath;
+use serde_json::{json, Value};
+use testcontainers::clients::Cli;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+use planets_service::graphql::AppSchema;
+use planets_service::{configure_service, create_schema_with_context};
+
+mod common;
+
+#[actix_rt::test]
+async fn test_resume_planet_events() {
+    env::set_var("DISABLE_AUTH", true.to_string());
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let schema = create_schema_with_context(pool);
+
+    let mutations = [
+        r#"mutation { createPlanet(planet: { name: "Test planet", type: ICE_GIANT, details: { meanRadius: "10.7", mass: "6.42e+23" } }) { id } }"#,
+        r#"mutation { deletePlanets(filter: { nameContains: "Test" }) { affected } }"#,
+    ];
+    for mutation in mutations {
+        let response = schema.execute(mutation).await;
+        assert!(response.errors.is_empty());
+    }
+
+    let mut events = schema.execute_stream(
+        "subscription { planetEvents(resumeFrom: 0) { token kind planet { name } } }",
+    );
+
+    let mut received_events = vec![];
+    for _ in 0..2 {
+        let response = events.next().await.expect("Can't get an event");
+        received_events.push(
+            response
+                .data
+                .into_json()
+                .expect("Can't convert response to JSON"),
+        );
+    }
+
+    let get_property = |event: &serde_json::Value, path: &str| {
+        jsonpath::select(event, path).expect("Can't get property")[0].clone()
+    };
+    assert_eq!(1, get_property(&received_events[0], "$.planetEvents.token"));
+    assert_eq!(
+        "CREATED",
+        get_property(&received_events[0], "$.planetEvents.kind")
+    );
+    assert_eq!(2, get_property(&received_events[1], "$.planetEvents.token"));
+    assert_eq!(
+        "DELETED",
+        get_property(&received_events[1], "$.planetEvents.kind")
+    );
+    assert_eq!(
+        "Test planet",
+        get_property(&received_events[1], "$.planetEvents.planet.name")
+    );
+}
+
+#[actix_rt::test]
+async fn test_planet_event_digests() {
+    env::set_var("DISABLE_AUTH", true.to_string());
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let schema = create_schema_with_context(pool);
+
+    for name in ["First", "Second", "Third"] {
+        let mutation = format!(
+            r#"mutation {{ createPlanet(planet: {{ name: "{}", type: ICE_GIANT, details: {{ meanRadius: "10.7", mass: "6.42e+23" }} }}) {{ id }} }}"#,
+            name
+        );
+        let response = schema.execute(mutation).await;
+        assert!(response.errors.is_empty());
+    }
+
+    let mut digests = schema.execute_stream(
+        "subscription { planetEventDigests(batchIntervalSeconds: 1, resumeFrom: 0) { events { token planet { name } } counts { kind count } lastToken } }",
+    );
+
+    // the replayed events arrive within the first interval
+    let digest = next_digest(&mut digests).await;
+    assert_eq!(
+        json!(["First", "Second", "Third"]),
+        json!(jsonpath::select(&digest, "$.events[*].planet.name").expect("Can't get names"))
+    );
+    assert_eq!(json!([{ "kind": "CREATED", "count": 3 }]), digest["counts"]);
+    assert_eq!(3, digest["lastToken"]);
+
+    let response = schema
+        .execute(r#"mutation { deletePlanets(filter: { nameContains: "First" }) { affected } }"#)
+        .await;
+    assert!(response.errors.is_empty());
+    let digest = next_digest(&mut digests).await;
+    assert_eq!(json!([{ "kind": "DELETED", "count": 1 }]), digest["counts"]);
+    assert_eq!(4, digest["lastToken"]);
+
+    let response = schema
+        .execute_stream(
+            "subscription { planetEventDigests(batchIntervalSeconds: 0) { lastToken } }",
+        )
+        .next()
+        .await
+        .expect("Can't get a response");
+    assert_eq!(
+        "batchIntervalSeconds must be from 1 to 60",
+        response.errors[0].message
+    );
+}
+
+#[actix_rt::test]
+async fn test_sse_subscription() {
+    env::set_var("DISABLE_AUTH", true.to_string());
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let schema = create_schema_with_context(pool);
+
+    for name in ["First", "Second"] {
+        let mutation = format!(
+            r#"mutation {{ createPlanet(planet: {{ name: "{}", type: ICE_GIANT, details: {{ meanRadius: "10.7", mass: "6.42e+23" }} }}) {{ id }} }}"#,
+            name
+        );
+        let response = schema.execute(mutation).await;
+        assert!(response.errors.is_empty());
+    }
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(schema)),
+    )
+    .await;
+
+    // a client reconnecting after the first event gets the events after it
+    let params = url::form_urlencoded::Serializer::new(String::new())
+        .append_pair(
+            "query",
+            "subscription { planetEvents { token planet { name } } }",
+        )
+        .finish();
+    let request = test::TestRequest::get()
+        .uri(&format!("/subscriptions/sse?{}", params))
+        .insert_header(("last-event-id", "1"))
+        .to_request();
+    let response = test::call_service(&service, request).await;
+    assert_eq!(
+        Some("text/event-stream"),
+        response
+            .headers()
+            .get("content-type")
+            .and_then(|value| value.to_str().ok())
+    );
+
+    let mut body = Box::pin(response.into_body());
+    let chunk = future::poll_fn(|cx| body.as_mut().poll_next(cx))
+        .await
+        .expect("Can't get an event")
+        .expect("Can't read an event");
+    assert_eq!(
+        "id: 2\nevent: next\ndata: {\"data\":{\"planetEvents\":{\"token\":2,\"planet\":{\"name\":\"Second\"}}}}\n\n",
+        String::from_utf8_lossy(&chunk)
+    );
+
+    // a query can't be resumed
+    let request = test::TestRequest::post()
+        .uri("/subscriptions/sse")
+        .insert_header(("last-event-id", "1"))
+        .set_json(serde_json::json!({ "query": "{ getPlanet(id: 1) { name } }" }))
+        .to_request();
+    let response = test::call_service(&service, request).await;
+    assert_eq!(400, response.status().as_u16());
+
+    // queries complete after the only result
+    let request = test::TestRequest::post()
+        .uri("/subscriptions/sse")
+        .set_json(serde_json::json!({ "query": "{ getPlanet(id: 1) { name } }" }))
+        .to_request();
+    let body = test::call_and_read_body(&service, request).await;
+    assert_eq!(
+        "event: next\ndata: {\"data\":{\"getPlanet\":{\"name\":\"Mercury\"}}}\n\nevent: complete\ndata:\n\n",
+        String::from_utf8_lossy(&body)
+    );
+}
+
+type WsClient = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+const PLANET_EVENTS_SUBSCRIPTION: &str =
+    "subscription { planetEvents(resumeFrom: 0) { token kind planet { name } } }";
+
+#[actix_rt::test]
+async fn test_graphql_transport_ws_protocol() {
+    env::set_var("DISABLE_AUTH", true.to_string());
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let schema = create_schema_with_context(pool);
+    let planet_id = create_test_planet(&schema).await;
+    let address = start_server(schema).await;
+
+    let (mut client, protocol) = connect(address, "graphql-transport-ws")
+        .await
+        .expect("Can't connect");
+    assert_eq!(Some("graphql-transport-ws".to_string()), protocol);
+
+    send(&mut client, json!({ "type": "connection_init" })).await;
+    assert_eq!(
+        json!({ "type": "connection_ack" }),
+        receive(&mut client).await
+    );
+
+    send(&mut client, json!({ "type": "ping" })).await;
+    assert_eq!(json!({ "type": "pong" }), receive(&mut client).await);
+
+    send(
+        &mut client,
+        json!({
+            "id": "1",
+            "type": "subscribe",
+            "payload": { "query": PLANET_EVENTS_SUBSCRIPTION },
+        }),
+    )
+    .await;
+    assert_eq!(
+        json!({
+            "id": "1",
+            "type": "next",
+            "payload": {
+                "data": {
+                    "planetEvents": { "token": 1, "kind": "CREATED", "planet": { "name": "Test planet" } }
+                }
+            },
+        }),
+        receive(&mut client).await
+    );
+
+    // a query completes after the only result
+    send(
+        &mut client,
+        json!({
+            "id": "2",
+            "type": "subscribe",
+            "payload": { "query": format!("{{ getPlanet(id: {}) {{ name }} }}", planet_id) },
+        }),
+    )
+    .await;
+    assert_eq!(
+        json!({
+            "id": "2",
+            "type": "next",
+            "payload": { "data": { "getPlanet": { "name": "Test planet" } } },
+        }),
+        receive(&mut client).await
+    );
+    assert_eq!(
+        json!({ "id": "2", "type": "complete" }),
+        receive(&mut client).await
+    );
+}
+
+#[actix_rt::test]
+async fn test_graphql_ws_protocol() {
+    env::set_var("DISABLE_AUTH", true.to_string());
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let schema = create_schema_with_context(pool);
+    let planet_id = create_test_planet(&schema).await;
+    let address = start_server(schema).await;
+
+    let (mut client, protocol) = connect(address, "graphql-ws").await.expect("Can't connect");
+    assert_eq!(Some("graphql-ws".to_string()), protocol);
+
+    send(&mut client, json!({ "type": "connection_init" })).await;
+    assert_eq!(
+        json!({ "type": "connection_ack" }),
+        receive(&mut client).await
+    );
+
+    send(
+        &mut client,
+        json!({
+            "id": "1",
+            "type": "start",
+            "payload": { "query": PLANET_EVENTS_SUBSCRIPTION },
+        }),
+    )
+    .await;
+    assert_eq!(
+        json!({
+            "id": "1",
+            "type": "data",
+            "payload": {
+                "data": {
+                    "planetEvents": { "token": 1, "kind": "CREATED", "planet": { "name": "Test planet" } }
+                }
+            },
+        }),
+        receive(&mut client).await
+    );
+
+    send(
+        &mut client,
+        json!({
+            "id": "2",
+            "type": "start",
+            "payload": { "query": format!("{{ getPlanet(id: {}) {{ name }} }}", planet_id) },
+        }),
+    )
+    .await;
+    assert_eq!(
+        json!({
+            "id": "2",
+            "type": "data",
+            "payload": { "data": { "getPlanet": { "name": "Test planet" } } },
+        }),
+        receive(&mut client).await
+    );
+    assert_eq!(
+        json!({ "id": "2", "type": "complete" }),
+        receive(&mut client).await
+    );
+}
+
+#[actix_rt::test]
+async fn test_websocket_protocol_negotiation() {
+    env::set_var("DISABLE_AUTH", true.to_string());
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let address = start_server(create_schema_with_context(pool)).await;
+
+    // the first protocol offered by the client which the server supports
+    for (offered, negotiated) in [
+        ("graphql-transport-ws, graphql-ws", "graphql-transport-ws"),
+        ("graphql-ws, graphql-transport-ws", "graphql-ws"),
+        ("unknown, graphql-ws", "graphql-ws"),
+    ] {
+        let (_, protocol) = connect(address, offered).await.expect("Can't connect");
+        assert_eq!(Some(negotiated.to_string()), protocol);
+    }
+
+    match connect(address, "unknown").await {
+        Err(WsError::Http(response)) => assert_eq!(400, response.status().as_u16()),
+        _ => panic!("Connection with an unsupported protocol isn't rejected"),
+    }
+}
+
+async fn next_digest(digests: &mut (impl Stream<Item = Response> + Unpin)) -> Value {
+    digests
+        .next()
+        .await
+        .expect("Can't get a digest")
+        .data
+        .into_json()
+        .expect("Can't convert response to JSON")["planetEventDigests"]
+        .clone()
+}
+
+/// ID of the created planet
+async fn create_test_planet(schema: &AppSchema) -> String {
+    let response = schema
+        .execute(r#"mutation { createPlanet(planet: { name: "Test planet", type: ICE_GIANT, details: { meanRadius: "10.7", mass: "6.42e+23" } }) { id } }"#)
+        .await;
+    assert!(response.errors.is_empty());
+    let data = response.data.into_json().expect("Can't convert data");
+    data["createPlanet"]["id"]
+        .as_str()
+        .expect("Can't get planet id")
+        .to_string()
+}
+
+async fn start_server(schema: AppSchema) -> SocketAddr {
+    let server = HttpServer::new(move || {
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(Schema::clone(&schema)))
+    })
+    .workers(1)
+    .bind(("127.0.0.1", 0))
+    .expect("Can't bind server");
+    let address = server.addrs()[0];
+    actix_rt::spawn(server.run());
+    address
+}
+
+/// The client and the protocol chosen by the server
+async fn connect(
+    address: SocketAddr,
+    protocols: &str,
+) -> Result<(WsClient, Option<String>), WsError> {
+    let mut request = format!("ws://{}/", address)
+        .into_client_request()
+        .expect("Can't create request");
+    request.headers_mut().insert(
+        "sec-websocket-protocol",
+        protocols.parse().expect("Can't create header value"),
+    );
+    let (client, response) = tokio_tungstenite::connect_async(request).await?;
+    let protocol = response
+        .headers()
+        .get("sec-websocket-protocol")
+        .and_then(|value| value.to_str().ok())
+        .map(String::from);
+    Ok((client, protocol))
+}
+
+async fn send(client: &mut WsClient, message: Value) {
+    client
+        .send(Message::Text(message.to_string()))
+        .await
+        .expect("Can't send message");
+}
+
+/// The next text message; heartbeats are skipped
+async fn receive(client: &mut WsClient) -> Value {
+    loop {
+        let message = tokio::time::timeout(Duration::from_secs(10), client.next())
+            .await
+            .expect("Timed out waiting for a message")
+            .expect("Connection is closed")
+            .expect("Can't receive message");
+        if let Message::Text(text) = message {
+            return serde_json::from_str(&text).expect("Can't parse message");
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use std::sync::{Arc, Mutex};
+
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+use diesel::connection::SimpleConnection;
+use testcontainers::clients::Cli;
+
+use planets_service::persistence::connection::ReloadablePool;
+use planets_service::persistence::model::NewWebhookEntity;
+use planets_service::persistence::repository;
+use planets_service::webhooks::{self, SIGNATURE_HEADER};
+
+use crate::common::fixtures::PlanetFixture;
+
+mod common;
+
+type Received = Arc<Mutex<Vec<(String, String)>>>;
+
+#[actix_rt::test]
+async fn test_webhook_delivery() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let pool = Arc::new(ReloadablePool::new(pool));
+    let mut conn = pool.get().expect("Can't get DB connection");
+
+    let received: Received = Arc::default();
+    let app_received = Arc::clone(&received);
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(web::Data::new(Arc::clone(&app_received)))
+            .route("/hook", web::post().to(receive))
+    })
+    .bind("127.0.0.1:0")
+    .expect("Can't bind webhook receiver");
+    let address = server.addrs()[0];
+    actix_rt::spawn(server.run());
+
+    let created_only = repository::create_webhook(
+        NewWebhookEntity {
+            url: format!("http://{}/hook", address),
+            event_kinds: vec!["CREATED".to_string()],
+            secret: "secret".to_string(),
+        },
+        &mut conn,
+    )
+    .expect("Can't create webhook");
+    let unreachable = repository::create_webhook(
+        NewWebhookEntity {
+            url: "http://127.0.0.1:1/hook".to_string(),
+            event_kinds: vec![],
+            secret: "secret".to_string(),
+        },
+        &mut conn,
+    )
+    .expect("Can't create webhook");
+
+    let earth = PlanetFixture::earth().insert(&mut conn);
+    drop(conn);
+
+    let attempts = webhooks::process(&pool, &reqwest::Client::new())
+        .await
+        .expect("Can't deliver webhooks");
+    assert_eq!(2, attempts);
+
+    let received = received.lock().expect("Can't lock received requests");
+    assert_eq!(1, received.len());
+    let (signature, body) = &received[0];
+    assert_eq!(&webhooks::sign("secret", body.as_bytes()), signature);
+    let event: serde_json::Value = serde_json::from_str(body).expect("Can't parse CloudEvent");
+    assert_eq!("planets.created", event["type"]);
+    // the ID exposed to clients, not the primary key
+    assert_eq!(earth.id.to_string(), event["data"]["id"]);
+
+    let mut conn = pool.get().expect("Can't get DB connection");
+    let delivered = repository::get_webhook_deliveries(created_only.id, 10, &mut conn)
+        .expect("Can't get deliveries");
+    assert_eq!("DELIVERED", delivered[0].status);
+    let failed = repository::get_webhook_deliveries(unreachable.id, 10, &mut conn)
+        .expect("Can't get deliveries");
+    assert_eq!("PENDING", failed[0].status);
+    assert_eq!(1, failed[0].attempts);
+    assert!(failed[0].last_error.is_some());
+}
+
+#[actix_rt::test]
+async fn test_late_commits_are_enqueued() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let mut conn = pool.get().expect("Can't get DB connection");
+    let webhook = repository::create_webhook(
+        NewWebhookEntity {
+            url: "http://127.0.0.1:1/hook".to_string(),
+            event_kinds: vec![],
+            secret: "secret".to_string(),
+        },
+        &mut conn,
+    )
+    .expect("Can't create webhook");
+
+    // the event gets a lower ID than the one of the planet, but is committed after it
+    let mut late_conn = pool.get().expect("Can't get DB connection");
+    late_conn
+        .batch_execute(
+            "begin; insert into outbox_events (topic, kind, payload) values ('planets', 'CREATED', '{}')",
+        )
+        .expect("Can't store event");
+    PlanetFixture::earth().insert(&mut conn);
+    assert_eq!(
+        0,
+        repository::enqueue_webhook_deliveries(&mut conn).expect("Can't enqueue deliveries")
+    );
+
+    late_conn.batch_execute("commit").expect("Can't commit");
+    assert_eq!(
+        2,
+        repository::enqueue_webhook_deliveries(&mut conn).expect("Can't enqueue deliveries")
+    );
+    let deliveries = repository::get_webhook_deliveries(webhook.id, 10, &mut conn)
+        .expect("Can't get deliveries");
+    assert_eq!(2, deliveries.len());
+}
+
+async fn receive(
+    received: web::Data<Received>,
+    request: HttpRequest,
+    body: String,
+) -> HttpResponse {
+    let signature = request
+        .headers()
+        .get(SIGNATURE_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    received
+        .lock()
+        .expect("Can't lock received requests")
+        .push((signature, body));
+    HttpResponse::NoContent().finish()
+}
@@ -4,6 +4,8 @@ use async_graphql::*;
 use chrono::NaiveDate;
 use strum_macros::EnumString;
 
+use common_utils::ids;
+
 use crate::get_conn_from_ctx;
 use crate::persistence::model::SatelliteEntity;
 use crate::persistence::repository;
@@ -22,14 +24,11 @@ impl Query {
             .collect()
     }
 
-    async fn get_satellite(&self, ctx: &Context<'_>, id: ID) -> Option<Satellite> {
-        let id = id
-            .to_string()
-            .parse::<i32>()
-            .expect("Can't get id from String");
-        repository::get(id, &mut get_conn_from_ctx(ctx))
+    async fn get_satellite(&self, ctx: &Context<'_>, id: ID) -> Result<Option<Satellite>> {
+        let id = ids::decode_id(&id).ok_or_else(|| format!("Invalid satellite ID {}", *id))?;
+        Ok(repository::get(id, &mut get_conn_from_ctx(ctx))
             .ok()
-            .map(|e| Satellite::from(&e))
+            .map(|e| Satellite::from(&e)))
     }
 
     #[graphql(entity)]
@@ -65,24 +64,24 @@ impl Planet {
         &self.id
     }
 
-    async fn satellites(&self, ctx: &Context<'_>) -> Vec<Satellite> {
-        let id = self
-            .id
-            .to_string()
-            .parse::<i32>()
-            .expect("Can't get id from String");
-        repository::get_by_planet_id(id, &mut get_conn_from_ctx(ctx))
-            .expect("Can't get satellites of planet")
-            .iter()
-            .map(Satellite::from)
-            .collect()
+    async fn satellites(&self, ctx: &Context<'_>) -> Result<Vec<Satellite>> {
+        // planets are exposed by integer IDs, see check_id_format
+        let id =
+            ids::decode_id(&self.id).ok_or_else(|| format!("Invalid planet ID {}", *self.id))?;
+        Ok(
+            repository::get_by_planet_id(id, &mut get_conn_from_ctx(ctx))
+                .expect("Can't get satellites of planet")
+                .iter()
+                .map(Satellite::from)
+                .collect(),
+        )
     }
 }
 
 impl From<&SatelliteEntity> for Satellite {
     fn from(entity: &SatelliteEntity) -> Self {
         Satellite {
-            id: entity.id.into(),
+            id: ids::encode_id(entity.id).into(),
             name: entity.name.clone(),
             life_exists: LifeExists::from_str(entity.life_exists.as_str())
                 .expect("Can't convert &str to LifeExists"),
@@ -1,3 +1,5 @@
+use std::env;
+
 use actix_web::{web, HttpResponse};
 use async_graphql::http::{playground_source, GraphQLPlaygroundConfig};
 use async_graphql::{Context, EmptyMutation, EmptySubscription, Schema};
@@ -39,6 +41,19 @@ pub fn create_schema_with_context(pool: PgPool) -> Schema<Query, EmptyMutation,
         .finish()
 }
 
+/// Satellites reference planets by integer IDs, so planets exposed by UUIDs (`ID_FORMAT` of the
+/// planets service other than `INTEGER`) can't be resolved; the format is set for both services
+pub fn check_id_format() {
+    if let Ok(format) = env::var("ID_FORMAT") {
+        if format != "INTEGER" {
+            panic!(
+                "ID format {} isn't supported: satellites reference planets by integer IDs",
+                format
+            );
+        }
+    }
+}
+
 pub fn run_migrations(conn: &mut PooledConnection<ConnectionManager<PgConnection>>) {
     conn.run_pending_migrations(MIGRATIONS)
         .expect("Failed to run database migrations");
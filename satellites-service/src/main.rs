@@ -6,11 +6,14 @@ use actix_web::{web, App, HttpServer};
 use dotenv::dotenv;
 
 use satellites_service::persistence::connection::create_connection_pool;
-use satellites_service::{configure_service, create_schema_with_context, run_migrations};
+use satellites_service::{
+    check_id_format, configure_service, create_schema_with_context, run_migrations,
+};
 
 #[actix_rt::main]
 async fn main() -> std::io::Result<()> {
     dotenv().ok();
+    check_id_format();
     let pool = create_connection_pool();
     run_migrations(&mut pool.get().expect("Can't get DB connection"));
 
@@ -124,6 +124,42 @@ async fn test_get_satellite() {
     );
 }
 
+#[actix_rt::test]
+async fn test_invalid_ids() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool))),
+    )
+    .await;
+
+    for (query, message) in [
+        (
+            r#"{ getSatellite(id: "moon") { name } }"#,
+            "Invalid satellite ID moon",
+        ),
+        (
+            r#"{ _entities(representations: [{ __typename: "Planet", id: "5f0c6e0e-0d5a-4f4e-9b1a-2d1c8f3e7a10" }]) { ... on Planet { satellites { name } } } }"#,
+            "Invalid planet ID 5f0c6e0e-0d5a-4f4e-9b1a-2d1c8f3e7a10",
+        ),
+    ] {
+        let request = test::TestRequest::post()
+            .uri("/")
+            .set_json(&GraphQLCustomRequest {
+                query: query.to_string(),
+                variables: Map::new(),
+            })
+            .to_request();
+        let response: GraphQLCustomResponse =
+            test::call_and_read_body_json(&service, request).await;
+        let errors = response.errors.expect("Response doesn't contain errors");
+        assert_eq!(message, errors[0]["message"]);
+    }
+}
+
 fn check_satellite(
     satellite_json: &serde_json::Value,
     name: &str,
@@ -173,6 +209,5 @@ struct GraphQLCustomRequest {
 #[derive(Deserialize)]
 struct GraphQLCustomResponse {
     data: Option<serde_json::Value>,
-    #[allow(dead_code)]
     errors: Option<serde_json::Value>,
 }